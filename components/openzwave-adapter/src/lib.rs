@@ -9,10 +9,10 @@ mod watchers;
 
 
 use taxonomy::util::Id as TaxoId;
-use taxonomy::services::{ Setter, Getter, AdapterId, ServiceId, Service, Channel, ChannelKind };
+use taxonomy::services::{ Setter, Getter, AdapterId, ServiceId, Service, Capabilities, Channel, ChannelKind };
 use taxonomy::values::*;
-use taxonomy::api::{ ResultMap, Error as TaxoError, InternalError, User };
-use taxonomy::adapter::{ AdapterManagerHandle, AdapterWatchGuard, WatchEvent };
+use taxonomy::api::{ AdapterStatus, ResultMap, Error as TaxoError, InternalError, User };
+use taxonomy::adapter::{ AdapterManagerHandle, AdapterWatchGuard, TransactionSupport, WatchEvent };
 use transformable_channels::mpsc::ExtSender;
 
 use openzwave::{ ConfigPath, InitOptions, ZWaveManager, ZWaveNotification };
@@ -125,12 +125,53 @@ fn taxo_kind_from_ozw_vid(vid: &ValueID) -> Option<ChannelKind> {
     match (vid.get_type(), vid.get_command_class(), vid.get_index()) {
         (ValueType::ValueType_Bool, Some(CommandClass::DoorLock),     0) => Some(ChannelKind::DoorLocked),
         (ValueType::ValueType_Bool, Some(CommandClass::SensorBinary), _) => Some(ChannelKind::OpenClosed),
+        (ValueType::ValueType_Decimal, Some(CommandClass::ThermostatSetpoint), _) => Some(ChannelKind::ThermostatSetpoint),
         // (ValueType::ValueType_Bool, Some(_)) => Some(ChannelKind::OnOff), TODO Find a proper type
         // Unrecognized command class or type - we don't know what to do with it.
         _ => None
     }
 }
 
+/// Z-Wave thermostats report their setpoint either in Celsius or in Fahrenheit.
+fn ozw_vid_as_temperature(vid: &ValueID, value: f64) -> Temperature {
+    if vid.get_units() == "F" {
+        Temperature::F(value)
+    } else {
+        Temperature::C(value)
+    }
+}
+
+fn taxo_capabilities_from_ozw_vid(vid: &ValueID) -> Option<Capabilities> {
+    match taxo_kind_from_ozw_vid(vid) {
+        Some(ChannelKind::OpenClosed) => Some(Capabilities {
+            choices: Some(vec![Value::OpenClosed(OpenClosed::Open), Value::OpenClosed(OpenClosed::Closed)]),
+            .. Capabilities::default()
+        }),
+        Some(ChannelKind::DoorLocked) => Some(Capabilities {
+            choices: Some(vec![Value::DoorLocked(DoorLocked::Locked), Value::DoorLocked(DoorLocked::Unlocked)]),
+            .. Capabilities::default()
+        }),
+        Some(ChannelKind::ThermostatSetpoint) => {
+            // Devices that do not know their bounds report 0 for both.
+            let (min, max) = (vid.get_min(), vid.get_max());
+            let (min, max) = if min < max {
+                (Some(Value::Temperature(ozw_vid_as_temperature(vid, min as f64))),
+                 Some(Value::Temperature(ozw_vid_as_temperature(vid, max as f64))))
+            } else {
+                (None, None)
+            };
+            Some(Capabilities {
+                min: min,
+                max: max,
+                step: Some(0.5),
+                unit: Some(if vid.get_units() == "F" { "F" } else { "C" }.to_owned()),
+                .. Capabilities::default()
+            })
+        }
+        _ => None
+    }
+}
+
 fn ozw_vid_as_taxo_value(vid: &ValueID) -> Option<Value> {
     if vid.get_command_class().is_none() {
         return None;
@@ -149,6 +190,16 @@ fn ozw_vid_as_taxo_value(vid: &ValueID) -> Option<Value> {
                 None
             }
         },
+        ValueType::ValueType_Decimal => {
+            if let Ok(value) = vid.as_float() {
+                match taxo_kind_from_ozw_vid(vid) {
+                    Some(ChannelKind::ThermostatSetpoint) => Some(Value::Temperature(ozw_vid_as_temperature(vid, value as f64))),
+                    _ => None,
+                }
+            } else {
+                None
+            }
+        },
         _ => None,   // TODO: Support more ValueType's
     }
 }
@@ -167,6 +218,15 @@ fn set_ozw_vid_from_taxo_value(vid: &ValueID, value: Value) -> Result<(), TaxoEr
                 _ => { return Err(TaxoError::InvalidValue(value)) } // TODO InvalidType would be better but we'll need to fix specific types for specific TaxoIds
             }
         }
+        ValueType::ValueType_Decimal => {
+            match value {
+                Value::Temperature(ref temperature) => {
+                    let value = if vid.get_units() == "F" { temperature.as_f() } else { temperature.as_c() };
+                    vid.set_float(value as f32)
+                }
+                _ => { return Err(TaxoError::InvalidValue(value)) }
+            }
+        }
         _ => { return Err(TaxoError::InternalError(InternalError::GenericError(format!("Unsupported OZW type: {:?}", vid.get_type())))) }
     };
 
@@ -204,7 +264,13 @@ fn ensure_directory<T: AsRef<Path> + ?Sized>(directory: &T) -> Result<(), Error>
 }
 
 impl OpenzwaveAdapter {
-    pub fn init<T: AdapterManagerHandle + Send + Sync + 'static> (box_manager: &Arc<T>, user_path: &str, device: Option<String>) -> Result<(), Error> {
+    pub fn id() -> TaxoId<AdapterId> {
+        TaxoId::new("OpenZwave Adapter")
+    }
+
+    /// Start the adapter. Returns the id of the adapter registered with `box_manager`,
+    /// or `None` if there is no Z-Wave device to drive.
+    pub fn init<T: AdapterManagerHandle + Send + Sync + 'static> (box_manager: &Arc<T>, user_path: &str, device: Option<String>) -> Result<Option<TaxoId<AdapterId>>, Error> {
 
         try!(ensure_directory(user_path));
 
@@ -219,19 +285,19 @@ impl OpenzwaveAdapter {
                 // early return: we should not impair foxbox startup for this error.
                 // TODO manage errors at adapter start: https://github.com/fxbox/RFC/issues/14
                 info!("[OpenzwaveAdapter] No ZWave device has been found.");
-                return Ok(());
+                return Ok(None);
             },
             Err(openzwave::Error::CannotReadDevice(device, cause)) => {
                 // early return for the same reason as above.
                 error!("[OpenzwaveAdapter] Could not read the device {}: {}.", device, cause);
-                return Ok(());
+                return Ok(None);
             }
             result => result
         });
 
         let name = String::from("OpenZwave Adapter");
         let adapter = Arc::new(OpenzwaveAdapter {
-            id: TaxoId::new(&name),
+            id: OpenzwaveAdapter::id(),
             name: name,
             vendor: String::from("Mozilla"),
             version: [1, 0, 0, 0],
@@ -243,12 +309,15 @@ impl OpenzwaveAdapter {
             value_cache: Arc::new(Mutex::new(HashMap::new())),
         });
 
+        try!(box_manager.add_adapter(adapter.clone()));
+        // Running once the controller is ready. Notifications are buffered until the
+        // thread is spawned, so this cannot overwrite the status that they report.
+        try!(box_manager.set_adapter_status(&adapter.id, AdapterStatus::Starting));
         adapter.spawn_notification_thread(rx, box_manager);
-        try!(box_manager.add_adapter(adapter));
 
         info!("[OpenzwaveAdapter] Started.");
 
-        Ok(())
+        Ok(Some(adapter.id.clone()))
     }
 
     fn spawn_notification_thread<T: AdapterManagerHandle + Send + Sync + 'static>(&self, rx: mpsc::Receiver<ZWaveNotification>, box_manager: &Arc<T>) {
@@ -264,7 +333,23 @@ impl OpenzwaveAdapter {
             for notification in rx {
                 //debug!("Received notification {:?}", notification);
                 match notification {
-                    ZWaveNotification::ControllerReady(_controller) => {}
+                    ZWaveNotification::ControllerReady(_controller) => {
+                        box_manager.set_adapter_status(&adapter_id, AdapterStatus::Running).unwrap_or_else(|e| {
+                            error!("Couldn't report the status of the adapter: {}", e);
+                        });
+                    }
+                    ZWaveNotification::ControllerFailed(_controller) => {
+                        box_manager.set_adapter_status(&adapter_id,
+                            AdapterStatus::Failed("The Z-Wave driver has failed".to_owned())).unwrap_or_else(|e| {
+                            error!("Couldn't report the status of the adapter: {}", e);
+                        });
+                    }
+                    ZWaveNotification::ControllerRemoved(_controller) => {
+                        box_manager.set_adapter_status(&adapter_id,
+                            AdapterStatus::Failed("The Z-Wave controller has been removed".to_owned())).unwrap_or_else(|e| {
+                            error!("Couldn't report the status of the adapter: {}", e);
+                        });
+                    }
                     ZWaveNotification::NodeNew(_node)               => {}
                     ZWaveNotification::NodeAdded(node)              => {
                         let service_name = format!("OpenZWave-{:08x}-{:02x}", node.get_home_id(), node.get_id());
@@ -309,6 +394,7 @@ impl OpenzwaveAdapter {
                                 service: node_id.clone(),
                                 adapter: adapter_id.clone(),
                                 last_seen: None,
+                                capabilities: taxo_capabilities_from_ozw_vid(&vid),
                                 tags: HashSet::new(),
                                 mechanism: Getter {
                                     kind: kind.clone(),
@@ -327,6 +413,7 @@ impl OpenzwaveAdapter {
                                 service: node_id.clone(),
                                 adapter: adapter_id.clone(),
                                 last_seen: None,
+                                capabilities: taxo_capabilities_from_ozw_vid(&vid),
                                 tags: HashSet::new(),
                                 mechanism: Setter {
                                     kind: kind,
@@ -339,8 +426,8 @@ impl OpenzwaveAdapter {
                     }
                     ZWaveNotification::ValueChanged(vid)          => {
                         match vid.get_type() {
-                            ValueType::ValueType_Bool => {},
-                            _ => continue // ignore non-bool vals for now
+                            ValueType::ValueType_Bool | ValueType::ValueType_Decimal => {},
+                            _ => continue // ignore other vals for now
                         };
 
                         let taxo_id = match getter_map.find_taxo_id_from_ozw(&vid) {
@@ -454,6 +541,25 @@ impl taxonomy::adapter::Adapter for OpenzwaveAdapter {
         }).collect()
     }
 
+    fn transaction_support(&self) -> TransactionSupport {
+        TransactionSupport::ReadBack
+    }
+
+    fn read_back_values(&self, mut setters: Vec<TaxoId<Setter>>, _: User) -> ResultMap<TaxoId<Setter>, Value, TaxoError> {
+        setters.drain(..).map(|id| {
+            let ozw_vid = match self.setter_map.find_ozw_from_taxo_id(&id) {
+                Some(ozw_vid) => ozw_vid,
+                None => return (id.clone(), Err(TaxoError::InternalError(InternalError::NoSuchSetter(id))))
+            };
+            // A value that the controller has not reported yet cannot be restored.
+            let taxo_value = if ozw_vid.is_set() { ozw_vid_as_taxo_value(&ozw_vid) } else { None };
+            let value_result = taxo_value.ok_or_else(|| {
+                TaxoError::InternalError(InternalError::GenericError(format!("The value of setter {} is unknown", id)))
+            });
+            (id, value_result)
+        }).collect()
+    }
+
     fn register_watch(&self, mut values: Vec<(TaxoId<Getter>, Option<Range>, Box<ExtSender<WatchEvent>>)>) -> Vec<(TaxoId<Getter>, Result<Box<AdapterWatchGuard>, TaxoError>)> {
         debug!("[OpenzwaveAdapter::register_watch] Should register some watchers");
         values.drain(..).map(|(id, range, sender)| {
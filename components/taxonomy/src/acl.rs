@@ -0,0 +1,541 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+///! This is the database of access control lists, which determines which users
+///! may read from getters and write to setters.
+///!
+///! Permissions are granted to users, either individually or through groups,
+///! on the channels matched by selectors. Grants are stored as their JSON source,
+///! so that they can be returned to the administrator as they were written.
+///! As selectors may match tags, users may only change the tags of the channels that
+///! they may already access, lest they grant themselves access to other channels.
+///!
+///! Access control is not enforced until an administrator starts managing grants,
+///! so that existing setups keep working. Recording the first grant enforces access
+///! control, which then remains enforced, even once all grants have been revoked,
+///! until an administrator explicitly turns it off. While it is enforced, anything
+///! that has not been granted is denied, including to `User::None`, unless requests
+///! are never authenticated (see `Acl::without_authentication`). Requests made on
+///! behalf of `User::System` (i.e. by the box itself) are never restricted.
+
+use api::User;
+use parse::*;
+use selector::{ GetterSelector, SetterSelector };
+use services::{ Channel, Getter, Setter };
+use util::{ Id, TagId };
+
+use rusqlite::{ Connection, Error as SqliteError };
+use serde_json;
+
+use std::collections::{ HashMap, HashSet };
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// A marker for Id.
+/// Only useful for writing `Id<GrantId>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Eq)]
+pub struct GrantId;
+
+/// A marker for Id.
+/// Only useful for writing `Id<GroupId>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Eq)]
+pub struct GroupId;
+
+/// An error while managing access control lists.
+#[derive(Debug, Serialize)]
+pub enum AclError {
+    /// The source of a grant could not be parsed.
+    ParseError(ParseError),
+
+    /// There is no grant with this id.
+    NoSuchGrant(Id<GrantId>),
+
+    /// There was an error executing some SQL.
+    SQLError(String),
+}
+
+impl From<SqliteError> for AclError {
+    fn from(err: SqliteError) -> Self {
+        AclError::SQLError(format!("{}", err))
+    }
+}
+
+impl From<ParseError> for AclError {
+    fn from(err: ParseError) -> Self {
+        AclError::ParseError(err)
+    }
+}
+
+/// The beneficiary of a grant.
+///
+/// # JSON
+///
+/// Either `{"user": id}`, where `id` is an integer, or `{"group": name}`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Grantee {
+    /// A single user.
+    User(i32),
+
+    /// All the members of a group.
+    Group(Id<GroupId>),
+}
+
+impl Parser<Grantee> for Grantee {
+    fn description() -> String {
+        "Grantee".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        if let Some(result) = path.push("user", |path| i32::take_opt(path, source, "user")) {
+            return result.map(Grantee::User);
+        }
+        if let Some(result) = path.push("group", |path| Id::take_opt(path, source, "group")) {
+            return result.map(Grantee::Group);
+        }
+        Err(ParseError::type_error("Grantee", &path, "an object with a field `user` or `group`"))
+    }
+}
+
+/// Permissions granted to a user or a group.
+///
+/// # JSON
+///
+/// An object with the following fields:
+///
+/// - `grantee` (see `Grantee`);
+/// - (optional) array of `GetterSelector` `read`: the grantee may fetch values from the
+///   getters matched by any of these selectors;
+/// - (optional) array of `SetterSelector` `write`: the grantee may send values to the
+///   setters matched by any of these selectors.
+///
+/// ```
+/// use foxbox_taxonomy::acl::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let source = r#"{
+///   "grantee": {"group": "family"},
+///   "read": [{"tags": ["door"]}],
+///   "write": [{"tags": ["door"], "kind": "DoorLocked"}]
+/// }"#;
+///
+/// let grant = Grant::from_str(source).unwrap();
+/// assert_eq!(grant.read.len(), 1);
+/// assert_eq!(grant.write.len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Grant {
+    pub grantee: Grantee,
+    pub read: Vec<GetterSelector>,
+    pub write: Vec<SetterSelector>,
+}
+
+impl Parser<Grant> for Grant {
+    fn description() -> String {
+        "Grant".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        let grantee = try!(path.push("grantee", |path| Grantee::take(path, source, "grantee")));
+        let read = match path.push("read", |path| GetterSelector::take_vec_opt(path, source, "read")) {
+            None => vec![],
+            Some(result) => try!(result)
+        };
+        let write = match path.push("write", |path| SetterSelector::take_vec_opt(path, source, "write")) {
+            None => vec![],
+            Some(result) => try!(result)
+        };
+        Ok(Grant {
+            grantee: grantee,
+            read: read,
+            write: write,
+        })
+    }
+}
+
+struct AclState {
+    db: Connection,
+
+    /// All the grants, by id, with their source.
+    grants: HashMap<Id<GrantId>, (Grant, String)>,
+
+    /// The members of each group.
+    groups: HashMap<Id<GroupId>, HashSet<i32>>,
+
+    /// `true` once access control is enforced.
+    enforced: bool,
+}
+
+impl AclState {
+    /// Determine whether a grant applies to a user.
+    fn applies_to(&self, grant: &Grant, user: i32) -> bool {
+        match grant.grantee {
+            Grantee::User(id) => id == user,
+            Grantee::Group(ref group) => match self.groups.get(group) {
+                None => false,
+                Some(members) => members.contains(&user)
+            }
+        }
+    }
+
+    fn is_allowed<F>(&self, user: &User, check: F) -> bool where F: Fn(&Grant) -> bool {
+        if *user == User::System || !self.enforced {
+            return true;
+        }
+        let user = match *user {
+            User::Id(id) => id,
+            // Nothing is granted to anonymous users.
+            _ => return false
+        };
+        self.grants.values().any(|&(ref grant, _)| self.applies_to(grant, user) && check(grant))
+    }
+
+    fn set_enforced(&mut self, enforced: bool) -> Result<(), AclError> {
+        try!(self.db.execute("INSERT OR REPLACE INTO settings (name, value) VALUES ('enforced', $1)",
+            &[&(enforced as i32)]));
+        self.enforced = enforced;
+        Ok(())
+    }
+}
+
+/// The access control lists, backed by a database.
+///
+/// The contents of the database are kept in memory, so checking permissions never
+/// touches the disk.
+pub struct Acl {
+    state: Mutex<AclState>,
+
+    /// `false` if requests are never authenticated, in which case `User::None` is
+    /// never restricted.
+    authenticated: bool,
+}
+
+impl Acl {
+    /// Open or create the database at `path` and load its contents.
+    pub fn new(path: &PathBuf) -> Result<Self, AclError> {
+        debug!("Opening taxonomy acl database at {}", path.display());
+        let db = try!(Connection::open(path.clone()));
+        try!(db.execute("CREATE TABLE IF NOT EXISTS grants (
+                    id      TEXT NOT NULL PRIMARY KEY,
+                    source  TEXT NOT NULL
+            )", &[]));
+        try!(db.execute("CREATE TABLE IF NOT EXISTS group_members (
+                    grp     TEXT NOT NULL,
+                    user    INTEGER NOT NULL,
+                    PRIMARY KEY (grp, user)
+            )", &[]));
+        try!(db.execute("CREATE TABLE IF NOT EXISTS settings (
+                    name    TEXT NOT NULL PRIMARY KEY,
+                    value   INTEGER NOT NULL
+            )", &[]));
+
+        let mut grants = HashMap::new();
+        let mut groups = HashMap::new();
+        let mut enforced = None;
+        {
+            let mut stmt = try!(db.prepare("SELECT id, source FROM grants"));
+            let rows = try!(stmt.query(&[]));
+            for result_row in rows {
+                let row = try!(result_row);
+                let id: String = try!(row.get_checked(0));
+                let source: String = try!(row.get_checked(1));
+                let grant = try!(Path::new().push_str(&id, |path| Grant::from_str_at(path, &source)));
+                grants.insert(Id::new(&id), (grant, source));
+            }
+
+            let mut stmt = try!(db.prepare("SELECT grp, user FROM group_members"));
+            let rows = try!(stmt.query(&[]));
+            for result_row in rows {
+                let row = try!(result_row);
+                let group: String = try!(row.get_checked(0));
+                let user: i32 = try!(row.get_checked(1));
+                groups.entry(Id::new(&group)).or_insert_with(HashSet::new).insert(user);
+            }
+
+            let mut stmt = try!(db.prepare("SELECT value FROM settings WHERE name = 'enforced'"));
+            let rows = try!(stmt.query(&[]));
+            for result_row in rows {
+                let row = try!(result_row);
+                let value: bool = try!(row.get_checked(0));
+                enforced = Some(value);
+            }
+        }
+
+        // Databases created before the setting existed are enforced as soon as they
+        // hold grants.
+        let enforced = enforced.unwrap_or_else(|| !grants.is_empty());
+
+        Ok(Acl {
+            state: Mutex::new(AclState {
+                db: db,
+                grants: grants,
+                groups: groups,
+                enforced: enforced,
+            }),
+            authenticated: true,
+        })
+    }
+
+    /// Never restrict `User::None`, for builds in which requests are not authenticated.
+    /// As every request is then made on behalf of `User::None`, enforcing access control
+    /// would otherwise deny everything to everybody.
+    pub fn without_authentication(self) -> Self {
+        Acl {
+            authenticated: false,
+            .. self
+        }
+    }
+
+    /// Add a grant, replacing any grant with the same id. This starts enforcing access
+    /// control, if it was not enforced yet.
+    pub fn put_grant(&self, id: &Id<GrantId>, source: &str) -> Result<(), AclError> {
+        let grant = try!(Grant::from_str(source));
+        let mut state = self.state.lock().unwrap();
+        try!(state.db.execute("INSERT OR REPLACE INTO grants (id, source) VALUES ($1, $2)",
+            &[&id.to_string(), &source.to_owned()]));
+        state.grants.insert(id.clone(), (grant, source.to_owned()));
+        if !state.enforced {
+            try!(state.set_enforced(true));
+        }
+        Ok(())
+    }
+
+    /// Remove a grant.
+    pub fn remove_grant(&self, id: &Id<GrantId>) -> Result<(), AclError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.grants.contains_key(id) {
+            return Err(AclError::NoSuchGrant(id.clone()));
+        }
+        try!(state.db.execute("DELETE FROM grants WHERE id = $1", &[&id.to_string()]));
+        state.grants.remove(id);
+        Ok(())
+    }
+
+    /// Get all the grants, as JSON.
+    pub fn get_grants(&self) -> HashMap<Id<GrantId>, JSON> {
+        let state = self.state.lock().unwrap();
+        state.grants.iter()
+            .map(|(id, &(_, ref source))| {
+                // The source has been parsed successfully before being stored.
+                (id.clone(), serde_json::from_str(source).unwrap_or(JSON::Null))
+            })
+            .collect()
+    }
+
+    /// Determine whether access control is enforced.
+    pub fn is_enforced(&self) -> bool {
+        self.state.lock().unwrap().enforced
+    }
+
+    /// Start or stop enforcing access control. While it is not enforced, all users may
+    /// access all channels.
+    pub fn set_enforced(&self, enforced: bool) -> Result<(), AclError> {
+        self.state.lock().unwrap().set_enforced(enforced)
+    }
+
+    /// Add users to a group, creating the group if necessary.
+    pub fn add_group_members(&self, group: &Id<GroupId>, users: &[i32]) -> Result<(), AclError> {
+        let mut state = self.state.lock().unwrap();
+        for user in users {
+            try!(state.db.execute("INSERT OR REPLACE INTO group_members (grp, user) VALUES ($1, $2)",
+                &[&group.to_string(), user]));
+        }
+        state.groups.entry(group.clone()).or_insert_with(HashSet::new).extend(users.iter().cloned());
+        Ok(())
+    }
+
+    /// Remove users from a group. Groups that become empty are removed.
+    pub fn remove_group_members(&self, group: &Id<GroupId>, users: &[i32]) -> Result<(), AclError> {
+        let mut state = self.state.lock().unwrap();
+        for user in users {
+            try!(state.db.execute("DELETE FROM group_members WHERE grp = $1 AND user = $2",
+                &[&group.to_string(), user]));
+        }
+        let is_empty = match state.groups.get_mut(group) {
+            None => return Ok(()),
+            Some(members) => {
+                for user in users {
+                    members.remove(user);
+                }
+                members.is_empty()
+            }
+        };
+        if is_empty {
+            state.groups.remove(group);
+        }
+        Ok(())
+    }
+
+    /// Get the members of all groups.
+    pub fn get_groups(&self) -> HashMap<Id<GroupId>, Vec<i32>> {
+        let state = self.state.lock().unwrap();
+        state.groups.iter()
+            .map(|(group, members)| {
+                let mut members : Vec<_> = members.iter().cloned().collect();
+                members.sort();
+                (group.clone(), members)
+            })
+            .collect()
+    }
+
+    /// Determine whether `user` may fetch values from `channel`.
+    pub fn may_read(&self, user: &User, service_tags: &HashSet<Id<TagId>>, channel: &Channel<Getter>) -> bool {
+        if !self.authenticated && *user == User::None {
+            return true;
+        }
+        self.state.lock().unwrap().is_allowed(user, |grant| {
+            grant.read.iter().any(|selector| selector.matches(service_tags, channel))
+        })
+    }
+
+    /// Determine whether `user` may send values to `channel`.
+    pub fn may_write(&self, user: &User, service_tags: &HashSet<Id<TagId>>, channel: &Channel<Setter>) -> bool {
+        if !self.authenticated && *user == User::None {
+            return true;
+        }
+        self.state.lock().unwrap().is_allowed(user, |grant| {
+            grant.write.iter().any(|selector| selector.matches(service_tags, channel))
+        })
+    }
+}
+
+#[cfg(test)]
+fn get_db_environment() -> PathBuf {
+    use libc::getpid;
+    use std::thread;
+    let tid = format!("{:?}", thread::current()).replace("(", "+").replace(")", "+");
+    let s = format!("./acl_db_test-{}-{}.sqlite", unsafe { getpid() }, tid.replace("/", "42"));
+    PathBuf::from(s)
+}
+
+#[test]
+#[allow(unused_variables)]
+fn acl_test() {
+    use services::{ AdapterId, ChannelKind, ServiceId };
+
+    // Simple RAII style struct to delete the test db.
+    struct AutoDeleteDb { };
+    impl Drop for AutoDeleteDb {
+        fn drop(&mut self) {
+            use std::fs;
+            let dbfile = get_db_environment();
+            if let Err(e) = fs::remove_file(dbfile.clone()) {
+                panic!("Error {} cleaning up {}", e, dbfile.display());
+            }
+        }
+    }
+    let auto_db = AutoDeleteDb { };
+
+    let door_tag = Id::<TagId>::new("door");
+    let mut tags = HashSet::new();
+    tags.insert(door_tag.clone());
+    let no_tags = HashSet::new();
+    let lock = Channel {
+        id: Id::<Setter>::new("lock"),
+        service: Id::<ServiceId>::new("front door"),
+        adapter: Id::<AdapterId>::new("adapter"),
+        last_seen: None,
+        capabilities: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::DoorLocked,
+        },
+    };
+    let light = Channel {
+        id: Id::<Getter>::new("light"),
+        service: Id::<ServiceId>::new("light"),
+        adapter: Id::<AdapterId>::new("adapter"),
+        last_seen: None,
+        capabilities: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+        },
+    };
+
+    let acl = Acl::new(&get_db_environment()).unwrap();
+
+    // Until access control is enforced, everything is allowed.
+    assert!(!acl.is_enforced());
+    assert!(acl.may_write(&User::Id(1), &tags, &lock));
+    assert!(acl.may_read(&User::Id(1), &no_tags, &light));
+    assert!(acl.may_read(&User::None, &no_tags, &light));
+
+    acl.put_grant(&Id::new("family doors"), r#"{
+        "grantee": {"group": "family"},
+        "write": [{"service_tags": ["door"]}]
+    }"#).unwrap();
+    acl.put_grant(&Id::new("guest lights"), r#"{
+        "grantee": {"user": 2},
+        "read": [{"kind": "LightOn"}]
+    }"#).unwrap();
+    acl.add_group_members(&Id::new("family"), &[1]).unwrap();
+
+    // Once there are grants, they are enforced.
+    assert!(acl.may_write(&User::Id(1), &tags, &lock));
+    assert!(!acl.may_write(&User::Id(1), &no_tags, &lock));
+    assert!(!acl.may_write(&User::Id(2), &tags, &lock));
+    assert!(acl.may_read(&User::Id(2), &no_tags, &light));
+    assert!(!acl.may_read(&User::Id(1), &no_tags, &light));
+    assert!(!acl.may_write(&User::None, &tags, &lock));
+    assert!(acl.may_write(&User::System, &no_tags, &lock));
+    assert!(acl.is_enforced());
+
+    // Grants, groups and enforcement are persisted.
+    let acl = Acl::new(&get_db_environment()).unwrap();
+    assert!(acl.is_enforced());
+    assert_eq!(acl.get_grants().len(), 2);
+    assert_eq!(acl.get_groups().get(&Id::new("family")), Some(&vec![1]));
+    assert!(acl.may_write(&User::Id(1), &tags, &lock));
+
+    // Removing a user from a group revokes their permissions.
+    acl.remove_group_members(&Id::new("family"), &[1]).unwrap();
+    assert!(!acl.may_write(&User::Id(1), &tags, &lock));
+    assert_eq!(acl.get_groups().len(), 0);
+
+    // Removing grants.
+    acl.remove_grant(&Id::new("guest lights")).unwrap();
+    assert!(!acl.may_read(&User::Id(2), &no_tags, &light));
+    match acl.remove_grant(&Id::new("guest lights")) {
+        Err(AclError::NoSuchGrant(_)) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    match acl.put_grant(&Id::new("broken"), r#"{"read": []}"#) {
+        Err(AclError::ParseError(_)) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    // Revoking the last grant denies everything, until enforcement is turned off.
+    acl.remove_grant(&Id::new("family doors")).unwrap();
+    assert_eq!(acl.get_grants().len(), 0);
+    assert!(!acl.may_write(&User::Id(1), &tags, &lock));
+    assert!(!acl.may_read(&User::Id(2), &no_tags, &light));
+    assert!(acl.may_read(&User::System, &no_tags, &light));
+    acl.set_enforced(false).unwrap();
+    assert!(acl.may_write(&User::Id(1), &tags, &lock));
+    let acl = Acl::new(&get_db_environment()).unwrap();
+    assert!(!acl.is_enforced());
+
+    // Without authentication, anonymous users are not locked out by enforcement, but
+    // other users still are.
+    let acl = Acl::new(&get_db_environment()).unwrap().without_authentication();
+    acl.put_grant(&Id::new("guest lights"), r#"{
+        "grantee": {"user": 2},
+        "read": [{"kind": "LightOn"}]
+    }"#).unwrap();
+    assert!(acl.is_enforced());
+    assert!(acl.may_write(&User::None, &tags, &lock));
+    assert!(acl.may_read(&User::None, &no_tags, &light));
+    assert!(!acl.may_write(&User::Id(1), &tags, &lock));
+    assert!(acl.may_read(&User::Id(2), &no_tags, &light));
+    acl.remove_grant(&Id::new("guest lights")).unwrap();
+
+    // User ids are integers.
+    for source in &[r#"{"grantee": {"user": 1.5}}"#, r#"{"grantee": {"user": 4294967297}}"#,
+                    r#"{"grantee": {"user": "1"}}"#] {
+        match acl.put_grant(&Id::new("broken"), source) {
+            Err(AclError::ParseError(_)) => {},
+            other => panic!("Unexpected result {:?}", other)
+        }
+    }
+}
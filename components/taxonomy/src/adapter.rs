@@ -1,4 +1,4 @@
-use api::{ Error, User };
+use api::{ AdapterStatus, Error, User };
 use services::*;
 use values::*;
 
@@ -25,7 +25,8 @@ pub trait AdapterManagerHandle: Send {
     /// Returns an error if an adapter with the same id is already present.
     fn add_adapter(& self, adapter: Arc<Adapter>) -> Result<(), Error>;
 
-    /// Remove an adapter from the system, including all its services and channels.
+    /// Remove an adapter from the system, including all its services and channels,
+    /// then call its `stop` method.
     ///
     /// # Errors
     ///
@@ -109,6 +110,15 @@ pub trait AdapterManagerHandle: Send {
     /// is not registered. In either case, it attemps to clean as much as possible, even
     /// if the state is inconsistent.
     fn remove_setter(& self, id: &Id<Setter>) -> Result<(), Error>;
+
+    /// Report the status of an adapter, e.g. when its hardware becomes unreachable or
+    /// reachable again. Adapters are `Running` as soon as they have been added, so
+    /// adapters that take time to initialize should report `Starting` immediately.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no adapter with this identifier exists.
+    fn set_adapter_status(& self, id: &Id<AdapterId>, status: AdapterStatus) -> Result<(), Error>;
 }
 
 pub enum WatchEvent {
@@ -127,6 +137,21 @@ pub enum WatchEvent {
     }
 }
 
+/// How an adapter takes part in `API::send_values_atomic`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionSupport {
+    /// The adapter cannot take part in atomic sends. This is the default.
+    Unsupported,
+
+    /// The adapter can report the current value of its setters, with `read_back_values`.
+    /// If the atomic send fails, the `AdapterManager` restores these values with
+    /// `send_values`.
+    ReadBack,
+
+    /// The adapter implements `prepare_values`, `commit_values` and `rollback_values`.
+    TwoPhase,
+}
+
 /// API that adapters must implement.
 ///
 /// # Requirements
@@ -161,6 +186,48 @@ pub trait Adapter: Send + Sync {
     /// expects the adapter to attempt to minimize the connections with the actual devices.
     fn send_values(&self, values: HashMap<Id<Setter>, Value>, user: User) -> ResultMap<Id<Setter>, (), Error>;
 
+    /// How this adapter takes part in `API::send_values_atomic`.
+    fn transaction_support(&self) -> TransactionSupport {
+        TransactionSupport::Unsupported
+    }
+
+    /// Read the value currently held by a group of setters, so that they can be restored
+    /// if an atomic send fails. Called only if `transaction_support()` is `ReadBack`.
+    fn read_back_values(&self, setters: Vec<Id<Setter>>, _: User) -> ResultMap<Id<Setter>, Value, Error> {
+        let id = self.id();
+        setters.into_iter()
+            .map(|setter| (setter, Err(Error::NotTransactional(id.clone()))))
+            .collect()
+    }
+
+    /// First phase of an atomic send: check that each value can be sent and reserve
+    /// whatever is needed to send it, without actually sending it. Called only if
+    /// `transaction_support()` is `TwoPhase`.
+    ///
+    /// Once all adapters have prepared their values, the `AdapterManager` calls either
+    /// `commit_values` or `rollback_values` with the same setters.
+    fn prepare_values(&self, values: HashMap<Id<Setter>, Value>, _: User) -> ResultMap<Id<Setter>, (), Error> {
+        let id = self.id();
+        values.into_iter()
+            .map(|(setter, _)| (setter, Err(Error::NotTransactional(id.clone()))))
+            .collect()
+    }
+
+    /// Send the values previously prepared. As the other adapters may already have sent
+    /// their values, this should only fail if the device itself fails.
+    fn commit_values(&self, setters: Vec<Id<Setter>>, _: User) -> ResultMap<Id<Setter>, (), Error> {
+        let id = self.id();
+        setters.into_iter()
+            .map(|setter| (setter, Err(Error::NotTransactional(id.clone()))))
+            .collect()
+    }
+
+    /// Forget the values previously prepared. `setters` may contain setters for which
+    /// `prepare_values` failed.
+    fn rollback_values(&self, _: Vec<Id<Setter>>, _: User) {
+        // By default, do nothing.
+    }
+
     /// Watch a bunch of getters as they change.
     ///
     /// The `AdapterManager` always attempts to group calls to `fetch_values` by `Adapter`, and
@@ -187,6 +254,15 @@ pub trait Adapter: Send + Sync {
     fn register_watch(&self, Vec<WatchTarget>) ->
             WatchResult;
 
+    /// Watch a bunch of getters on behalf of a user.
+    ///
+    /// The `AdapterManager` has already checked that the user may read these getters. Adapters
+    /// whose getters are computed from other channels should also check that the user may
+    /// read these channels. By default, call `register_watch`.
+    fn register_watch_for(&self, watch: Vec<WatchTarget>, _: User) -> WatchResult {
+        self.register_watch(watch)
+    }
+
     /// Signal the adapter that it is time to stop.
     ///
     /// Ideally, the adapter should not return until all its threads have been stopped.
@@ -56,7 +56,31 @@ impl<T> Adapter for MakeSyncAdapter<T> where T: Adapter {
         self.lock.lock().unwrap().send_values(values, user)
     }
 
+    fn transaction_support(&self) -> TransactionSupport {
+        self.lock.lock().unwrap().transaction_support()
+    }
+
+    fn read_back_values(&self, setters: Vec<Id<Setter>>, user: User) -> ResultMap<Id<Setter>, Value, Error> {
+        self.lock.lock().unwrap().read_back_values(setters, user)
+    }
+
+    fn prepare_values(&self, values: HashMap<Id<Setter>, Value>, user: User) -> ResultMap<Id<Setter>, (), Error> {
+        self.lock.lock().unwrap().prepare_values(values, user)
+    }
+
+    fn commit_values(&self, setters: Vec<Id<Setter>>, user: User) -> ResultMap<Id<Setter>, (), Error> {
+        self.lock.lock().unwrap().commit_values(setters, user)
+    }
+
+    fn rollback_values(&self, setters: Vec<Id<Setter>>, user: User) {
+        self.lock.lock().unwrap().rollback_values(setters, user)
+    }
+
     fn register_watch(&self, watch: Vec<WatchTarget>) -> WatchResult {
         self.lock.lock().unwrap().register_watch(watch)
     }
+
+    fn register_watch_for(&self, watch: Vec<WatchTarget>, user: User) -> WatchResult {
+        self.lock.lock().unwrap().register_watch_for(watch, user)
+    }
 }
@@ -0,0 +1,473 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+///! The core of the adapters exposing getters whose values are computed from the values of
+///! other getters, i.e. virtual channels and groups.
+///!
+///! Each definition selects the getters it aggregates. The `Aggregator` watches these
+///! getters on behalf of the system and recomputes the value of the aggregate getter
+///! whenever one of them changes, notifying the watchers of the aggregate getter. Since
+///! the system may read any getter, fetching or watching an aggregate getter requires
+///! permission to read all the getters it aggregates. Definitions are stored as their
+///! JSON source, in a database.
+
+use adapter::*;
+use api;
+use api::{ API, Error, InternalError, TargetMap, Targetted, User, WatchOptions };
+use manager::{ AdapterManager, WatchGuard };
+use parse::*;
+use selector::GetterSelector;
+use services::*;
+use util::Exactly;
+use values::*;
+
+use rusqlite::{ Connection, Error as SqliteError };
+use serde_json;
+use transformable_channels::mpsc::*;
+
+use std::collections::{ HashMap, HashSet };
+use std::path::PathBuf;
+use std::sync::{ Arc, Mutex, Weak };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+
+/// The definition of an aggregate getter.
+pub trait Aggregate: Parser<Self> + Clone + Send + 'static {
+    /// The key of the definition in the database.
+    fn key(&self) -> String;
+
+    /// The id of the aggregate getter.
+    fn getter_id(&self) -> Id<Getter>;
+
+    /// The getters whose values are aggregated.
+    fn source(&self) -> GetterSelector;
+
+    /// Combine the values of the aggregated getters. Return `None` if there is no value
+    /// to combine.
+    fn apply(&self, values: Vec<&Value>) -> Option<Value>;
+
+    /// Determine whether a value may be combined with the values of the other getters.
+    fn accepts(&self, _: &Value) -> bool {
+        true
+    }
+
+    /// Register the channels of the definition, including the aggregate getter.
+    fn add_channels(&self, manager: &AdapterManager) -> Result<(), Error>;
+
+    /// Unregister the channels of the definition.
+    fn remove_channels(&self, manager: &AdapterManager) -> Result<(), Error>;
+}
+
+struct AggregateWatchGuard(Arc<AtomicBool>);
+impl AdapterWatchGuard for AggregateWatchGuard {}
+impl Drop for AggregateWatchGuard {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+}
+
+struct Watcher {
+    filter: Option<Range>,
+    on_event: Box<ExtSender<WatchEvent>>,
+    is_met: bool,
+    is_dropped: Arc<AtomicBool>,
+}
+
+struct Entry<T> {
+    definition: T,
+    source: String,
+
+    /// The aggregated getters, with their latest value, if known.
+    inputs: HashMap<Id<Getter>, Option<Value>>,
+
+    /// The latest value of the aggregate getter.
+    value: Option<Value>,
+
+    /// The watchers registered by the `AdapterManager` on the aggregate getter.
+    watchers: Vec<Watcher>,
+
+    /// Watching the aggregated getters, until dropped.
+    guard: Option<WatchGuard>,
+}
+
+impl<T> Entry<T> where T: Aggregate {
+    /// Recompute the value of the aggregate getter and notify watchers if it has changed.
+    fn update(&mut self) {
+        let value = self.definition.apply(self.inputs.values().filter_map(|value| value.as_ref()).collect());
+        if value == self.value {
+            return;
+        }
+        self.value = value.clone();
+        let value = match value {
+            None => return,
+            Some(value) => value
+        };
+        let id = self.definition.getter_id();
+        self.watchers.retain(|watcher| !watcher.is_dropped.load(Ordering::Relaxed));
+        for watcher in &mut self.watchers {
+            let event = match watcher.filter {
+                None => Some(WatchEvent::Enter { id: id.clone(), value: value.clone() }),
+                Some(ref range) => {
+                    let is_met = range.contains(&value);
+                    let event = match (is_met, watcher.is_met) {
+                        (true, false) => Some(WatchEvent::Enter { id: id.clone(), value: value.clone() }),
+                        (false, true) => Some(WatchEvent::Exit { id: id.clone(), value: value.clone() }),
+                        _ => None
+                    };
+                    watcher.is_met = is_met;
+                    event
+                }
+            };
+            if let Some(event) = event {
+                let _ignored = watcher.on_event.send(event);
+            }
+        }
+    }
+}
+
+struct State<T> {
+    db: Connection,
+
+    /// The definitions, by key.
+    entries: HashMap<String, Entry<T>>,
+}
+
+impl<T> State<T> where T: Aggregate {
+    fn is_aggregate(&self, id: &Id<Getter>) -> bool {
+        self.entries.values().any(|entry| entry.definition.getter_id() == *id)
+    }
+
+    fn find(&self, id: &Id<Getter>) -> Option<&Entry<T>> {
+        self.entries.values().find(|entry| entry.definition.getter_id() == *id)
+    }
+
+    fn find_mut(&mut self, id: &Id<Getter>) -> Option<&mut Entry<T>> {
+        self.entries.values_mut().find(|entry| entry.definition.getter_id() == *id)
+    }
+
+    fn on_event(&mut self, key: &str, event: api::WatchEvent) {
+        let (from, value) = match event {
+            api::WatchEvent::EnterRange { from, value } => (from, Some(Some(value))),
+            api::WatchEvent::GetterAdded(from) => (from, Some(None)),
+            api::WatchEvent::GetterRemoved(from) => (from, None),
+            _ => return
+        };
+        if self.is_aggregate(&from) {
+            // Aggregate getters are never aggregated.
+            return;
+        }
+        let entry = match self.entries.get_mut(key) {
+            None => return,
+            Some(entry) => entry
+        };
+        match value {
+            Some(Some(value)) => {
+                if !entry.definition.accepts(&value) {
+                    return;
+                }
+                entry.inputs.insert(from, Some(value));
+            }
+            Some(None) => { entry.inputs.entry(from).or_insert(None); }
+            None => { entry.inputs.remove(&from); }
+        }
+        entry.update();
+    }
+}
+
+/// The definitions of the aggregate getters of an adapter, with their values.
+pub struct Aggregator<T> {
+    /// The adapter exposing the aggregate getters.
+    adapter: Id<AdapterId>,
+
+    /// The manager is only used to watch the aggregated getters, so we don't need to keep
+    /// it alive.
+    manager: Weak<AdapterManager>,
+
+    /// The table holding the definitions, and the name of its key column.
+    table: &'static str,
+    key: &'static str,
+
+    state: Arc<Mutex<State<T>>>,
+
+    /// Events from the aggregated getters, with the key of the definition.
+    tx_watch: Mutex<RawSender<(String, api::WatchEvent)>>,
+}
+
+impl<T> Aggregator<T> where T: Aggregate {
+    /// Open or create the database at `path`, holding definitions in `table` by `key`.
+    /// Return the aggregator and the stored definitions, which the adapter must register
+    /// once it has been added to `manager`.
+    pub fn open<E>(adapter: Id<AdapterId>, manager: &Arc<AdapterManager>, path: &PathBuf,
+                   table: &'static str, key: &'static str) -> Result<(Self, Vec<(T, String)>), E>
+        where E: From<SqliteError> + From<ParseError>
+    {
+        let db = try!(Connection::open(path.clone()));
+        try!(db.execute(&format!("CREATE TABLE IF NOT EXISTS {} (
+                    {}  TEXT NOT NULL PRIMARY KEY,
+                    source  TEXT NOT NULL
+            )", table, key), &[]));
+
+        let mut definitions = vec![];
+        {
+            let mut stmt = try!(db.prepare(&format!("SELECT {}, source FROM {}", key, table)));
+            let rows = try!(stmt.query(&[]));
+            for result_row in rows {
+                let row = try!(result_row);
+                let name: String = try!(row.get_checked(0));
+                let source: String = try!(row.get_checked(1));
+                let definition = try!(Path::new().push_str(&name, |path| T::from_str_at(path, &source)));
+                definitions.push((definition, source));
+            }
+        }
+
+        let state = Arc::new(Mutex::new(State {
+            db: db,
+            entries: HashMap::new(),
+        }));
+        let (tx, rx) = channel();
+        {
+            let state = state.clone();
+            thread::spawn(move || {
+                for (key, event) in rx {
+                    state.lock().unwrap().on_event(&key, event);
+                }
+            });
+        }
+        let aggregator = Aggregator {
+            adapter: adapter,
+            manager: Arc::downgrade(manager),
+            table: table,
+            key: key,
+            state: state,
+            tx_watch: Mutex::new(tx),
+        };
+        Ok((aggregator, definitions))
+    }
+
+    pub fn manager(&self) -> Result<Arc<AdapterManager>, Error> {
+        self.manager.upgrade().ok_or_else(|| {
+            Error::InternalError(InternalError::GenericError("The adapter manager is gone".to_owned()))
+        })
+    }
+
+    /// Register the channels of a definition with `manager` and start watching the getters
+    /// it aggregates.
+    pub fn register(&self, manager: &AdapterManager, definition: T, source: String) -> Result<(), Error> {
+        let key = definition.key();
+        let watch : TargetMap<GetterSelector, Exactly<Range>> =
+            vec![Targetted::new(vec![definition.source()], Exactly::Always)];
+        self.state.lock().unwrap().entries.insert(key.clone(), Entry {
+            definition: definition.clone(),
+            source: source,
+            inputs: HashMap::new(),
+            value: None,
+            watchers: vec![],
+            guard: None,
+        });
+        if let Err(err) = definition.add_channels(manager) {
+            self.state.lock().unwrap().entries.remove(&key);
+            return Err(err);
+        }
+
+        // Watching may call back into this adapter, so we must not hold the lock.
+        let on_event = {
+            let key = key.clone();
+            self.tx_watch.lock().unwrap().map(move |event| (key.clone(), event))
+        };
+        let guard = manager.watch_values_with_options(watch, WatchOptions {
+            initial: true,
+            .. WatchOptions::default()
+        }, Box::new(on_event), User::System);
+        if let Some(entry) = self.state.lock().unwrap().entries.get_mut(&key) {
+            entry.guard = Some(guard);
+        }
+        Ok(())
+    }
+
+    /// Stop watching the getters aggregated by a definition and unregister its channels.
+    fn unregister(&self, manager: &AdapterManager, key: &str) {
+        // Drop the guard outside of the lock, as this may call back into this adapter.
+        let entry = self.state.lock().unwrap().entries.remove(key);
+        if let Some(entry) = entry {
+            let definition = entry.definition.clone();
+            drop(entry);
+            if let Err(err) = definition.remove_channels(manager) {
+                warn!("Could not unregister {}: {}", key, err);
+            }
+        }
+    }
+
+    /// Register again a definition that could not be replaced.
+    fn restore(&self, manager: &AdapterManager, previous: Option<(T, String)>) {
+        if let Some((definition, source)) = previous {
+            let key = definition.key();
+            if let Err(err) = self.register(manager, definition, source) {
+                warn!("Could not restore {}: {}", key, err);
+            }
+        }
+    }
+
+    /// Add a definition, replacing any definition with the same key, and return it.
+    ///
+    /// If the new definition cannot be registered or stored, the previous definition, if
+    /// any, is restored.
+    pub fn put<E>(&self, source: &str) -> Result<T, E>
+        where E: From<SqliteError> + From<ParseError> + From<Error>
+    {
+        let definition = try!(T::from_str(source));
+        let key = definition.key();
+        let id = definition.getter_id();
+        let manager = try!(self.manager());
+        let is_taken = manager.get_getter_channels(vec![GetterSelector::new().with_id(id.clone())]).iter()
+            .any(|getter| getter.adapter != self.adapter);
+        if is_taken {
+            return Err(E::from(Error::InternalError(InternalError::DuplicateGetter(id))));
+        }
+        let previous = self.state.lock().unwrap().entries.get(&key)
+            .map(|entry| (entry.definition.clone(), entry.source.clone()));
+        if previous.is_some() {
+            self.unregister(&manager, &key);
+        }
+        if let Err(err) = self.register(&manager, definition.clone(), source.to_owned()) {
+            self.restore(&manager, previous);
+            return Err(E::from(err));
+        }
+        let stored = self.state.lock().unwrap().db.execute(
+            &format!("INSERT OR REPLACE INTO {} ({}, source) VALUES ($1, $2)", self.table, self.key),
+            &[&key, &source.to_owned()]);
+        if let Err(err) = stored {
+            self.unregister(&manager, &key);
+            self.restore(&manager, previous);
+            return Err(E::from(err));
+        }
+        Ok(definition)
+    }
+
+    /// Remove a definition. Return `false` if there is no definition with this key.
+    pub fn remove<E>(&self, key: &str) -> Result<bool, E>
+        where E: From<SqliteError> + From<Error>
+    {
+        {
+            let state = self.state.lock().unwrap();
+            if !state.entries.contains_key(key) {
+                return Ok(false);
+            }
+            try!(state.db.execute(&format!("DELETE FROM {} WHERE {} = $1", self.table, self.key),
+                &[&key.to_owned()]));
+        }
+        let manager = try!(self.manager());
+        self.unregister(&manager, key);
+        Ok(true)
+    }
+
+    /// Get the definition with this key.
+    pub fn get(&self, key: &str) -> Option<T> {
+        self.state.lock().unwrap().entries.get(key).map(|entry| entry.definition.clone())
+    }
+
+    /// Get all the definitions.
+    pub fn definitions(&self) -> Vec<T> {
+        self.state.lock().unwrap().entries.values().map(|entry| entry.definition.clone()).collect()
+    }
+
+    /// Get the sources of all the definitions, as JSON, by key.
+    pub fn get_sources(&self) -> HashMap<String, JSON> {
+        let state = self.state.lock().unwrap();
+        state.entries.iter()
+            .map(|(key, entry)| {
+                // The source has been parsed successfully before being stored.
+                (key.clone(), serde_json::from_str(&entry.source).unwrap_or(JSON::Null))
+            })
+            .collect()
+    }
+
+    /// The aggregate getters among `ids` that aggregate getters that `user` may not read.
+    fn unreadable(&self, manager: &AdapterManager, ids: &[Id<Getter>], user: &User) -> HashSet<Id<Getter>> {
+        // The manager may call back into this adapter, so we must not hold the lock.
+        let sources : Vec<_> = {
+            let state = self.state.lock().unwrap();
+            state.entries.values()
+                .filter(|entry| ids.contains(&entry.definition.getter_id()))
+                .map(|entry| (entry.definition.getter_id(), entry.definition.source()))
+                .collect()
+        };
+        let denied : Vec<_> = sources.into_iter()
+            .map(|(id, source)| (id, manager.unreadable_getters(vec![source], user)))
+            .filter(|&(_, ref unreadable)| !unreadable.is_empty())
+            .collect();
+        let state = self.state.lock().unwrap();
+        denied.into_iter()
+            // Aggregate getters are never aggregated.
+            .filter(|&(_, ref unreadable)| unreadable.iter().any(|getter| !state.is_aggregate(getter)))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Fetch the values of aggregate getters on behalf of `user`.
+    pub fn fetch_values(&self, mut set: Vec<Id<Getter>>, user: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        let manager = match self.manager() {
+            Ok(manager) => manager,
+            Err(err) => return set.drain(..).map(|id| (id, Err(err.clone()))).collect()
+        };
+        let denied = self.unreadable(&manager, &set, &user);
+        let state = self.state.lock().unwrap();
+        set.drain(..).map(|id| {
+            let result = match state.find(&id) {
+                Some(_) if denied.contains(&id) => Err(Error::PermissionDenied),
+                Some(entry) => Ok(entry.value.clone()),
+                None => Err(Error::InternalError(InternalError::NoSuchGetter(id.clone())))
+            };
+            (id, result)
+        }).collect()
+    }
+
+    pub fn register_watch(&self, mut watch: Vec<WatchTarget>) -> WatchResult {
+        let mut state = self.state.lock().unwrap();
+        watch.drain(..).map(|(id, filter, on_event)| {
+            let entry = match state.find_mut(&id) {
+                Some(entry) => entry,
+                None => return (id.clone(), Err(Error::InternalError(InternalError::NoSuchGetter(id))))
+            };
+            let is_met = match (&filter, &entry.value) {
+                (&Some(ref range), &Some(ref value)) => range.contains(value),
+                _ => false
+            };
+            let is_dropped = Arc::new(AtomicBool::new(false));
+            entry.watchers.push(Watcher {
+                filter: filter,
+                on_event: on_event,
+                is_met: is_met,
+                is_dropped: is_dropped.clone(),
+            });
+            let guard = Box::new(AggregateWatchGuard(is_dropped)) as Box<AdapterWatchGuard>;
+            (id, Ok(guard))
+        }).collect()
+    }
+
+    /// Watch aggregate getters on behalf of `user`.
+    pub fn register_watch_for(&self, watch: Vec<WatchTarget>, user: User) -> WatchResult {
+        let manager = match self.manager() {
+            Ok(manager) => manager,
+            Err(err) => return watch.into_iter().map(|(id, _, _)| (id, Err(err.clone()))).collect()
+        };
+        let ids : Vec<_> = watch.iter().map(|&(ref id, _, _)| id.clone()).collect();
+        let denied = self.unreadable(&manager, &ids, &user);
+        let (refused, allowed) : (Vec<_>, Vec<_>) = watch.into_iter()
+            .partition(|&(ref id, _, _)| denied.contains(id));
+        let mut results = self.register_watch(allowed);
+        results.extend(refused.into_iter().map(|(id, _, _)| (id, Err(Error::PermissionDenied))));
+        results
+    }
+
+    /// Stop watching the aggregated getters.
+    ///
+    /// The state holds the guards of these watches, which hold senders to the thread that
+    /// updates the state. Dropping the guards ends this cycle, so the thread terminates once
+    /// the aggregator itself is dropped.
+    pub fn stop(&self) {
+        // Drop the guards outside of the lock, as this may call back into this adapter.
+        let entries : Vec<_> = self.state.lock().unwrap().entries.drain().collect();
+        drop(entries);
+    }
+}
@@ -13,10 +13,11 @@
 //!
 //!
 
+use history::HistoryEntry;
 use services::*;
 use selector::*;
 pub use util::{ ResultMap, TargetMap, Targetted };
-use values::{ Value, Range, TypeError };
+use values::{ Duration, TimeStamp, Value, Range, TypeError };
 
 use transformable_channels::mpsc::*;
 
@@ -54,6 +55,15 @@ pub enum Error {
     /// An error internal to the foxbox or an adapter. Normally, these errors should never
     /// arise from the high-level API.
     InternalError(InternalError),
+
+    /// The user is not allowed to access this channel (see module `acl`).
+    PermissionDenied,
+
+    /// The adapter did not respond before the deadline of the `AdapterManager`.
+    Timeout(Id<AdapterId>),
+
+    /// Attempting to send values atomically to an adapter that does not support it.
+    NotTransactional(Id<AdapterId>),
 }
 
 impl ToJSON for Error {
@@ -79,6 +89,9 @@ impl fmt::Display for Error {
             Error::RangeError(ref range) => write!(f, "{}: {:?}", self.description(), range),
             Error::InvalidValue(ref value) => write!(f, "{}: {:?}",self.description(), value),
             Error::InternalError(ref err) => write!(f, "{}: {:?}", self.description(), err), // TODO implement Display for InternalError as well
+            Error::PermissionDenied => write!(f, "{}", self.description()),
+            Error::Timeout(ref adapter) |
+            Error::NotTransactional(ref adapter) => write!(f, "{}: {}", self.description(), adapter),
         }
     }
 }
@@ -92,7 +105,10 @@ impl error::Error for Error {
             Error::TypeError(_) => "Attempting to send a value with a wrong type",
             Error::RangeError(_) => "Attempting to use an inconsistent range",
             Error::InvalidValue(_) => "Attempting to send an invalid value",
-            Error::InternalError(_) => "Internal Error" // TODO implement Error for InternalError as well
+            Error::InternalError(_) => "Internal Error", // TODO implement Error for InternalError as well
+            Error::PermissionDenied => "The user is not allowed to access this channel",
+            Error::Timeout(_) => "The adapter did not respond in time",
+            Error::NotTransactional(_) => "The adapter does not support atomic sends",
         }
     }
 
@@ -179,12 +195,273 @@ pub enum WatchEvent {
     },
 }
 
+/// The status of an adapter, as reported by the adapter itself.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub enum AdapterStatus {
+    /// The adapter is initializing, e.g. looking for devices.
+    Starting,
+
+    /// The adapter works normally.
+    Running,
+
+    /// The adapter works, but some of its devices may be unreachable, e.g. a
+    /// Philips Hue bridge has disappeared from the network.
+    Degraded(String),
+
+    /// The adapter cannot work at all, e.g. the Z-Wave stick has been unplugged.
+    Failed(String),
+}
+
+impl ToJSON for AdapterStatus {
+    fn to_json(&self) -> JSON {
+        let mut serializer = Serializer::new();
+        match self.serialize(&mut serializer) {
+            Ok(()) => serializer.unwrap(),
+            Err(_) =>
+                vec![("Internal error while serializing", "")].to_json()
+        }
+    }
+}
+
+/// The health of an adapter, as returned by `get_adapters_health`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdapterHealth {
+    pub id: Id<AdapterId>,
+
+    /// The latest status reported by the adapter.
+    pub status: AdapterStatus,
+
+    /// The instant at which the adapter reported this status.
+    pub since: TimeStamp,
+
+    /// The services of the adapter, which share its status.
+    pub services: Vec<Id<ServiceId>>,
+}
+
+impl ToJSON for AdapterHealth {
+    fn to_json(&self) -> JSON {
+        vec![
+            ("id", self.id.to_json()),
+            ("status", self.status.to_json()),
+            ("since", self.since.to_json()),
+            ("services", self.services.to_json()),
+        ].to_json()
+    }
+}
+
+/// The health of a service, as returned by `get_services_health`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceHealth {
+    pub id: Id<ServiceId>,
+
+    pub adapter: Id<AdapterId>,
+
+    /// The latest status reported by the adapter of the service.
+    pub status: AdapterStatus,
+
+    /// The instant at which a value was last received from any getter of the service,
+    /// if any.
+    pub last_seen: Option<TimeStamp>,
+}
+
+impl ToJSON for ServiceHealth {
+    fn to_json(&self) -> JSON {
+        vec![
+            ("id", self.id.to_json()),
+            ("adapter", self.adapter.to_json()),
+            ("status", self.status.to_json()),
+            ("last_seen", self.last_seen.to_json()),
+        ].to_json()
+    }
+}
+
+/// A value returned by `fetch_values_with_options`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchedValue {
+    /// The value itself.
+    pub value: Value,
+
+    /// The instant at which the value was received from the adapter, either
+    /// by fetching it or by watching the getter.
+    pub timestamp: TimeStamp,
+
+    /// The adapter that provided the value.
+    pub adapter: Id<AdapterId>,
+}
+
+impl ToJSON for FetchedValue {
+    fn to_json(&self) -> JSON {
+        vec![
+            ("value", self.value.to_json()),
+            ("timestamp", self.timestamp.to_json()),
+            ("adapter", self.adapter.to_json()),
+        ].to_json()
+    }
+}
+
+/// What happened to a value sent with `send_values_atomic`.
+#[derive(Serialize, Debug, Clone)]
+pub enum SendOutcome {
+    /// The value has been sent.
+    Sent,
+
+    /// The value has not been sent, because another value could not be sent.
+    NotSent,
+
+    /// The value had been sent, then the previous value has been restored, because
+    /// another value could not be sent.
+    Reverted,
+
+    /// The value had been sent, but the previous value could not be restored.
+    NotReverted(Error),
+}
+
+impl ToJSON for SendOutcome {
+    fn to_json(&self) -> JSON {
+        let mut serializer = Serializer::new();
+        match self.serialize(&mut serializer) {
+            Ok(()) => serializer.unwrap(),
+            Err(_) =>
+                vec![("Internal error while serializing", "")].to_json()
+        }
+    }
+}
+
+/// Options for `fetch_values_with_options`.
+///
+/// # JSON
+///
+/// An object with an optional field `max_age` (a number of seconds).
+///
+/// ```
+/// use foxbox_taxonomy::api::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let options = FetchOptions::from_str("{\"max_age\": 60}").unwrap();
+/// assert!(options.max_age.is_some());
+///
+/// let options = FetchOptions::from_str("{}").unwrap();
+/// assert!(options.max_age.is_none());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FetchOptions {
+    /// If specified, a value received from the getter at most `max_age` ago, either by
+    /// a previous fetch or by a watch, is returned instead of contacting the adapter.
+    /// This is useful e.g. to avoid waking up devices that run on batteries.
+    ///
+    /// If unspecified, the adapter is always contacted.
+    pub max_age: Option<Duration>,
+}
+
+impl Parser<FetchOptions> for FetchOptions {
+    fn description() -> String {
+        "FetchOptions".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        let max_age = match path.push("max_age", |path| Duration::take_opt(path, source, "max_age")) {
+            Some(result) => Some(try!(result)),
+            None => None
+        };
+        Ok(FetchOptions {
+            max_age: max_age,
+        })
+    }
+}
+
+/// Options applied to all the channels of a watch, to limit the number of events
+/// received from noisy channels or to receive the current values.
+///
+/// Except for `initial`, these options only affect `WatchEvent::EnterRange` and `WatchEvent::ExitRange`,
+/// and they are applied separately to each channel. A value that is held back is
+/// replaced by any newer value from the same channel, so the last value is always
+/// delivered eventually.
+///
+/// # JSON
+///
+/// An object with optional fields `min_interval` (a number of seconds),
+/// `debounce` (a number of seconds), `min_delta` (a number) and `initial`
+/// (a boolean).
+///
+/// ```
+/// use foxbox_taxonomy::api::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let options = WatchOptions::from_str("{\"min_interval\": 5, \"min_delta\": 0.5}").unwrap();
+/// assert!(options.min_interval.is_some());
+/// assert!(options.debounce.is_none());
+/// assert_eq!(options.min_delta, Some(0.5));
+/// assert!(!options.initial);
+///
+/// let options = WatchOptions::from_str("{\"initial\": true}").unwrap();
+/// assert!(options.initial);
+/// ```
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct WatchOptions {
+    /// If specified, the minimal delay between two events from the same channel.
+    /// Values arriving sooner are held back until the delay has elapsed.
+    pub min_interval: Option<Duration>,
+
+    /// If specified, values are held back until the channel has not produced any
+    /// new value for this duration.
+    pub debounce: Option<Duration>,
+
+    /// If specified, values of quantities (temperatures, durations, ...) are dropped
+    /// unless they differ by at least `min_delta` from the latest value delivered,
+    /// expressed in the unit of the latter. Entering or exiting the range is always
+    /// delivered.
+    pub min_delta: Option<f64>,
+
+    /// If `true`, the current value of each channel is fetched as soon as the channel
+    /// is attached to the watch, including channels added later. If the value is in
+    /// the range, it is delivered as a `WatchEvent::EnterRange`, before any other value
+    /// of this channel.
+    pub initial: bool,
+}
+
+impl Parser<WatchOptions> for WatchOptions {
+    fn description() -> String {
+        "WatchOptions".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        let min_interval = match path.push("min_interval", |path| Duration::take_opt(path, source, "min_interval")) {
+            Some(result) => Some(try!(result)),
+            None => None
+        };
+        let debounce = match path.push("debounce", |path| Duration::take_opt(path, source, "debounce")) {
+            Some(result) => Some(try!(result)),
+            None => None
+        };
+        let min_delta = match path.push("min_delta", |path| f64::take_opt(path, source, "min_delta")) {
+            Some(result) => Some(try!(result)),
+            None => None
+        };
+        let initial = match path.push("initial", |path| bool::take_opt(path, source, "initial")) {
+            Some(result) => try!(result),
+            None => false
+        };
+        Ok(WatchOptions {
+            min_interval: min_interval,
+            debounce: debounce,
+            min_delta: min_delta,
+            initial: initial,
+        })
+    }
+}
+
 /// User identifier that will be passed from the REST API handlers to the
 /// adapters.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum User {
+    /// A request that could not be attributed to any user, e.g. when authentication
+    /// is disabled.
     None,
-    Id(i32)
+
+    /// An authenticated user.
+    Id(i32),
+
+    /// The box itself, e.g. an adapter combining the values of other channels. Requests
+    /// made on behalf of the system are not subject to access control.
+    System,
 }
 
 #[test]
@@ -316,6 +593,33 @@ pub trait API: Send {
     /// ```
     fn get_services(& self, Vec<ServiceSelector>) -> Vec<Service>;
 
+    /// Get the health of services matching some conditions, i.e. the status of their
+    /// adapter and the instant at which a value was last received from them.
+    ///
+    /// # REST API
+    ///
+    /// `GET /api/v1/services/health`
+    ///
+    /// ### JSON
+    ///
+    /// As `get_services`, this call accepts a vector of `ServiceSelector`, with `POST`.
+    ///
+    /// ## Success
+    ///
+    /// An array of `{id, adapter, status, last_seen}`, where `status` is an `AdapterStatus`.
+    fn get_services_health(& self, Vec<ServiceSelector>) -> Vec<ServiceHealth>;
+
+    /// Get the health of all adapters.
+    ///
+    /// # REST API
+    ///
+    /// `GET /api/v1/adapters/health`
+    ///
+    /// ## Success
+    ///
+    /// An array of `{id, status, since, services}`, where `status` is an `AdapterStatus`.
+    fn get_adapters_health(& self) -> Vec<AdapterHealth>;
+
     /// Label a set of services with a set of tags.
     ///
     /// A call to `API::put_service_tag(vec![req1, req2, ...], vec![tag1,
@@ -330,6 +634,10 @@ pub trait API: Send {
     /// Note that this call is _not live_. In other words, if services
     /// are added after the call, they will not be affected.
     ///
+    /// Services with a channel that `user` is not allowed to access are left
+    /// untouched and are not counted, as tags may extend the permissions that
+    /// are granted on a channel (see module `acl`).
+    ///
     /// # REST API
     ///
     /// `POST /api/v1/services/tag`
@@ -369,7 +677,7 @@ pub trait API: Send {
     /// ## Success
     ///
     /// A JSON string representing a number.
-    fn add_service_tags(& self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>) -> usize;
+    fn add_service_tags(& self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>, user: User) -> usize;
 
     /// Remove a set of tags from a set of services.
     ///
@@ -385,6 +693,9 @@ pub trait API: Send {
     /// Note that this call is _not live_. In other words, if services
     /// are added after the call, they will not be affected.
     ///
+    /// Services with a channel that `user` is not allowed to access are left
+    /// untouched and are not counted.
+    ///
     /// # REST API
     ///
     /// `DELETE /api/v1/services/tag`
@@ -424,7 +735,7 @@ pub trait API: Send {
     /// ## Success
     ///
     /// A JSON string representing a number.
-    fn remove_service_tags(& self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>) -> usize;
+    fn remove_service_tags(& self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>, user: User) -> usize;
 
     /// Get a list of getters matching some conditions
     ///
@@ -498,6 +809,11 @@ pub trait API: Send {
     /// Note that this call is _not live_. In other words, if channels
     /// are added after the call, they will not be affected.
     ///
+    /// Channels that `user` is not allowed to access, i.e. getters that
+    /// `user` may not read and setters that `user` may not write, are left
+    /// untouched and are not counted, as tags may extend the permissions
+    /// that are granted on a channel (see module `acl`).
+    ///
     /// # REST API
     ///
     /// `POST /api/v1/channels/tag`
@@ -528,8 +844,8 @@ pub trait API: Send {
     /// ## Success
     ///
     /// A JSON representing a number.
-    fn add_getter_tags(& self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>) -> usize;
-    fn add_setter_tags(& self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>) -> usize;
+    fn add_getter_tags(& self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>, user: User) -> usize;
+    fn add_setter_tags(& self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>, user: User) -> usize;
 
     /// Remove a set of tags from a set of channels.
     ///
@@ -545,6 +861,10 @@ pub trait API: Send {
     /// Note that this call is _not live_. In other words, if channels
     /// are added after the call, they will not be affected.
     ///
+    /// Channels that `user` is not allowed to access, i.e. getters that
+    /// `user` may not read and setters that `user` may not write, are left
+    /// untouched and are not counted.
+    ///
     /// # REST API
     ///
     /// `DELETE /api/v1/channels/tag`
@@ -575,8 +895,8 @@ pub trait API: Send {
     /// ## Success
     ///
     /// A JSON representing a number.
-    fn remove_getter_tags(& self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>) -> usize;
-    fn remove_setter_tags(& self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>) -> usize;
+    fn remove_getter_tags(& self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>, user: User) -> usize;
+    fn remove_setter_tags(& self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>, user: User) -> usize;
 
     /// Read the latest value from a set of channels
     ///
@@ -613,7 +933,67 @@ pub trait API: Send {
     /// ## Success
     ///
     /// The results, per getter.
-    fn fetch_values(&self, Vec<GetterSelector>, user: User) -> ResultMap<Id<Getter>, Option<Value>, Error>;
+    fn fetch_values(&self, selectors: Vec<GetterSelector>, user: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        self.fetch_values_with_options(selectors, FetchOptions::default(), user)
+            .into_iter()
+            .map(|(id, result)| (id, result.map(|fetched| fetched.map(|fetched| fetched.value))))
+            .collect()
+    }
+
+    /// Read the latest value from a set of channels, as `fetch_values`, along with the instant
+    /// at which each value was received and the adapter that provided it.
+    ///
+    /// With `options.max_age`, values received recently, either by a previous fetch or by a
+    /// watch, are returned without contacting the adapter.
+    fn fetch_values_with_options(&self, Vec<GetterSelector>, options: FetchOptions, user: User)
+        -> ResultMap<Id<Getter>, Option<FetchedValue>, Error>;
+
+    /// Read the values previously seen on a set of channels.
+    ///
+    /// Values are recorded whenever they are returned by `fetch_values` or
+    /// delivered by `watch_values`. Old values are removed or thinned out
+    /// depending on the retention policy of the implementation.
+    ///
+    /// # REST API
+    ///
+    /// `PUT /api/v1/channels/history`
+    ///
+    /// ## JSON
+    ///
+    /// A JSON object with the following fields:
+    /// - getters: array - an array of `GetterSelector`;
+    /// - period: `Period` - the acceptable age of values, e.g. `{"max": 86400}`
+    ///   for the last 24h.
+    ///
+    /// ```
+    /// # extern crate serde;
+    /// # extern crate serde_json;
+    /// # extern crate foxbox_taxonomy;
+    /// # use foxbox_taxonomy::selector::*;
+    ///
+    /// # fn main() {
+    ///  # let source =
+    /// r#"{
+    ///   "getters": [{"tags": ["living room"], "kind": "OvenTemperature"}],
+    ///   "period": {"max": 86400}
+    /// }"#;
+    ///
+    /// # let mut json: JSON = serde_json::from_str(&source).unwrap();
+    /// # Vec::<GetterSelector>::take(Path::new(), &mut json, "getters").unwrap();
+    /// # Period::take(Path::new(), &mut json, "period").unwrap();
+    /// # }
+    /// ```
+    ///
+    /// ## Errors
+    ///
+    /// In case of syntax error, Error 400, accompanied with a
+    /// somewhat human-readable JSON string detailing the error.
+    ///
+    /// ## Success
+    ///
+    /// The results, per getter, as an array of `{timestamp, value}`, oldest first. Getters
+    /// that `user` is not allowed to read produce `Error::PermissionDenied`.
+    fn fetch_history(&self, Vec<GetterSelector>, period: Period, user: User) -> ResultMap<Id<Getter>, Vec<HistoryEntry>, Error>;
 
     /// Send a bunch of values to a set of channels.
     ///
@@ -675,6 +1055,34 @@ pub trait API: Send {
     /// The results, per setter.
     fn send_values(&self, TargetMap<SetterSelector, Value>, user: User) -> ResultMap<Id<Setter>, (), Error>;
 
+    /// Send a bunch of values to a set of channels, atomically: either all the values are
+    /// sent, or none of them is kept. For instance, "lock all doors and arm the alarm"
+    /// should not leave the house half-secured.
+    ///
+    /// All the adapters involved must support transactions (see `TransactionSupport`),
+    /// otherwise nothing is sent. Adapters implementing a two-phase commit are committed
+    /// only once all the other values have been sent. Values sent to adapters that merely
+    /// read back previous values are reverted by sending the previous values again.
+    ///
+    /// # REST API
+    ///
+    /// `PUT /api/v1/channels/set/atomic`
+    ///
+    /// ## JSON
+    ///
+    /// Same as `send_values`.
+    ///
+    /// ## Errors
+    ///
+    /// In case of syntax error, Error 400, accompanied with a
+    /// somewhat human-readable JSON string detailing the error.
+    ///
+    /// ## Success
+    ///
+    /// What happened, per setter. Setters whose value could not be sent, and thus caused
+    /// the other values to be reverted, hold an error.
+    fn send_values_atomic(&self, TargetMap<SetterSelector, Value>, user: User) -> ResultMap<Id<Setter>, SendOutcome, Error>;
+
     /// Watch for changes from channels.
     ///
     /// This method registers a closure to watch over events on a set of channels. Argument `watch`
@@ -694,13 +1102,26 @@ pub trait API: Send {
     /// receiving *every single value coming from the channels*. This is very rarely a good idea.
     /// Many devices may reject such requests.
     ///
+    /// Getters that `user` is not allowed to read are not watched.
+    ///
     /// The watcher is disconnected once the `WatchGuard` returned by this method is dropped.
     ///
     /// # `WebSocket` API
     ///
     /// `/api/v1/channels/watch`
     fn watch_values(& self, watch: TargetMap<GetterSelector, Exactly<Range>>,
-            on_event: Box<ExtSender<WatchEvent>>) -> Self::WatchGuard;
+            on_event: Box<ExtSender<WatchEvent>>, user: User) -> Self::WatchGuard {
+        self.watch_values_with_options(watch, WatchOptions::default(), on_event, user)
+    }
+
+    /// Watch for changes from channels, as `watch_values`, limiting the number of
+    /// events as specified by `options`.
+    ///
+    /// # `WebSocket` API
+    ///
+    /// `/api/v1/channels/watch`, with an optional field `options`.
+    fn watch_values_with_options(& self, watch: TargetMap<GetterSelector, Exactly<Range>>,
+            options: WatchOptions, on_event: Box<ExtSender<WatchEvent>>, user: User) -> Self::WatchGuard;
 
     /// A value that causes a disconnection once it is dropped.
     type WatchGuard;
@@ -3,7 +3,8 @@
 use adapter::{ Adapter, AdapterWatchGuard, ResultMap, WatchEvent as AdapterWatchEvent };
 use transact::InsertInMap;
 
-use api::{ Error, InternalError, TargetMap, Targetted, WatchEvent };
+use api::{ AdapterHealth, AdapterStatus, Error, InternalError, TargetMap, Targetted, User, WatchEvent, WatchOptions };
+use clock::{ Clock, SystemClock, Timer };
 use selector::*;
 use services::*;
 use tag_storage::TagStorage;
@@ -12,6 +13,9 @@ use values::*;
 use sublock::atomlock::*;
 use transformable_channels::mpsc::*;
 
+use chrono::{ DateTime, Duration as ChronoDuration, UTC };
+
+use std::cmp;
 use std::collections::{ HashMap, HashSet };
 use std::collections::hash_map::Entry;
 use std::hash::{ Hash, Hasher };
@@ -19,6 +23,7 @@ use std::path::PathBuf;
 use std::ops::{ Deref };
 use std::sync::{ Arc, Mutex, Weak };
 use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::{ Duration as StdDuration, Instant };
 
 // In release build, log an error and continue.
 // In debug build, log an error and panic.
@@ -49,6 +54,13 @@ pub type WatchRequest = AdapterRequest<Vec<(Id<Getter>, Option<Range>, Weak<Watc
 
 pub type WatchGuardCommit = Vec<(Weak<WatcherData>, Vec<(Id<Getter>, Box<AdapterWatchGuard>)>)>;
 
+/// Determines whether the user on behalf of whom a watch is registered may read a getter,
+/// given the tags of its service.
+pub type ReadCheck = Box<Fn(&HashSet<Id<TagId>>, &Channel<Getter>) -> bool + Send + Sync>;
+
+/// Records a value reported by an adapter on a watched getter.
+pub type Recorder = Arc<Fn(&Id<Getter>, &Value) + Send + Sync>;
+
 /// Information on a service.
 ///
 /// Used to build `Service` values.
@@ -148,6 +160,12 @@ struct AdapterData {
 
     /// The services for this adapter.
     services: HashMap<Id<ServiceId>, Arc<SubCell<ServiceData>>>,
+
+    /// The latest status reported by the adapter.
+    status: AdapterStatus,
+
+    /// The instant at which `status` was reported.
+    since: TimeStamp,
 }
 
 impl AdapterData {
@@ -155,6 +173,17 @@ impl AdapterData {
         AdapterData {
             adapter: adapter,
             services: HashMap::new(),
+            status: AdapterStatus::Running,
+            since: TimeStamp::from_datetime(UTC::now()),
+        }
+    }
+
+    fn health(&self) -> AdapterHealth {
+        AdapterHealth {
+            id: self.adapter.id(),
+            status: self.status.clone(),
+            since: self.since.clone(),
+            services: self.services.keys().cloned().collect(),
         }
     }
 }
@@ -205,6 +234,9 @@ struct GetterData {
 
     /// Watchers that currently watch this channel.
     watchers: HashMap<WatchKey, Weak<WatcherData>>,
+
+    /// Which of the watchers of this channel records the values reported by the adapter.
+    recording: RecordingSlot,
 }
 impl SelectedBy<GetterSelector> for GetterData {
     fn matches(&self, selector: &GetterSelector) -> bool {
@@ -218,6 +250,52 @@ impl GetterData {
             channel: channel,
             service_tags: service_tags.clone(),
             watchers: HashMap::new(),
+            recording: RecordingSlot::default(),
+        }
+    }
+}
+
+/// The watcher of a getter whose events are recorded, if any.
+///
+/// The adapter reports each value once per watcher registered on the getter. Only the
+/// events of one of these watchers are recorded, so that each value is recorded once.
+/// Unfiltered watchers are preferred, as they see every value.
+#[derive(Clone, Default)]
+struct RecordingSlot(Arc<Mutex<Option<(WatchKey, bool)>>>);
+
+impl RecordingSlot {
+    /// Record the events of `key` from now on, unless another watcher already records
+    /// at least as many events.
+    fn offer(&self, key: WatchKey, is_unfiltered: bool) {
+        let mut holder = self.0.lock().unwrap();
+        match *holder {
+            Some((_, true)) => {},
+            Some(_) if !is_unfiltered => {},
+            _ => *holder = Some((key, is_unfiltered))
+        }
+    }
+
+    /// Stop recording the events of `key`.
+    fn release(&self, key: WatchKey) {
+        let mut holder = self.0.lock().unwrap();
+        if let Some((holder_key, _)) = *holder {
+            if holder_key == key {
+                *holder = None;
+            }
+        }
+    }
+
+    /// Determine whether the events of `key` should be recorded. If no watcher records
+    /// events and `may_claim` is `true`, `key` records them from now on.
+    fn records(&self, key: WatchKey, is_unfiltered: bool, may_claim: bool) -> bool {
+        let mut holder = self.0.lock().unwrap();
+        match *holder {
+            Some((holder_key, _)) => holder_key == key,
+            None if may_claim => {
+                *holder = Some((key, is_unfiltered));
+                true
+            }
+            None => false
         }
     }
 }
@@ -283,6 +361,19 @@ pub struct WatcherData {
     /// The listener for this watch.
     on_event: Mutex<Box<ExtSender<WatchEvent>>>,
 
+    /// Throttling, debouncing and filtering, applied to each getter.
+    options: WatchOptions,
+
+    /// Getters for which this returns `false` are not watched.
+    may_read: ReadCheck,
+
+    /// The user on whose behalf initial values are fetched and getters are watched.
+    user: User,
+
+    /// The initial value delivered for each getter, until the getter is registered
+    /// with the adapter. Used to seed the `EventFilter` of the getter.
+    initial: Mutex<HashMap<Id<Getter>, WatchEvent>>,
+
     /// A unique key used to locate the `WatcherData` in the
     /// WatchMap.
     key: WatchKey,
@@ -290,6 +381,12 @@ pub struct WatcherData {
     /// The individual guard for each getter currently watched.
     guards: SubCell<HashMap<Id<Getter>, Vec<Box<AdapterWatchGuard>>>>,
 
+    /// Records the values reported by adapters, if we have a recorder.
+    recorder: Option<Recorder>,
+
+    /// The `RecordingSlot` of each getter watched.
+    recording: Mutex<HashMap<Id<Getter>, RecordingSlot>>,
+
     /// `true` once the WatchGuard has dropped. In this
     /// case, the `WatcherData` will shortly be removed
     /// from the WatchMap.
@@ -309,13 +406,37 @@ impl PartialEq for WatcherData {
 }
 
 impl WatcherData {
-    fn new(liveness: &Arc<Liveness>, key: WatchKey, watch:TargetMap<GetterSelector, Exactly<Range>>, on_event: Box<ExtSender<WatchEvent>>) -> Self {
+    fn new(liveness: &Arc<Liveness>, key: WatchKey, watch:TargetMap<GetterSelector, Exactly<Range>>, options: WatchOptions, on_event: Box<ExtSender<WatchEvent>>, may_read: ReadCheck, user: User) -> Self {
         WatcherData {
             key: key,
             on_event: Mutex::new(on_event),
+            options: options,
+            may_read: may_read,
+            user: user,
+            initial: Mutex::new(HashMap::new()),
             watch: watch,
             is_dropped: Arc::new(AtomicBool::new(false)),
             guards: SubCell::new(liveness, HashMap::new()),
+            recorder: None,
+            recording: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a value reported by the adapter to this watcher, if this watcher is the
+    /// one recording the values of the getter.
+    fn record(&self, id: &Id<Getter>, value: &Value, is_unfiltered: bool) {
+        let recorder = match self.recorder {
+            None => return,
+            Some(ref recorder) => recorder
+        };
+        let slot = match self.recording.lock().unwrap().get(id) {
+            None => return,
+            Some(slot) => slot.clone()
+        };
+        // A dropped watcher keeps recording until it is unregistered, but never
+        // starts recording.
+        if slot.records(self.key, is_unfiltered, !self.is_dropped.load(Ordering::Relaxed)) {
+            recorder(id, value);
         }
     }
 
@@ -337,19 +458,26 @@ pub struct WatchMap {
     counter: usize,
     watchers: HashMap<WatchKey, Arc<WatcherData>>,
     liveness: Arc<Liveness>,
+
+    /// Records the values reported on watched getters, if any.
+    recorder: Option<Recorder>,
 }
 impl WatchMap {
-    fn new(liveness: &Arc<Liveness>) -> Self {
+    fn new(liveness: &Arc<Liveness>, recorder: Option<Recorder>) -> Self {
         WatchMap {
             counter: 0,
             watchers: HashMap::new(),
-            liveness: liveness.clone()
+            liveness: liveness.clone(),
+            recorder: recorder,
         }
     }
-    fn create(&mut self, watch:TargetMap<GetterSelector, Exactly<Range>>, on_event: Box<ExtSender<WatchEvent>>) -> Arc<WatcherData> {
+    fn create(&mut self, watch:TargetMap<GetterSelector, Exactly<Range>>, options: WatchOptions, on_event: Box<ExtSender<WatchEvent>>, may_read: ReadCheck, user: User) -> Arc<WatcherData> {
         let id = WatchKey(self.counter);
         self.counter += 1;
-        let watcher = Arc::new(WatcherData::new(&self.liveness, id, watch, on_event));
+        let watcher = Arc::new(WatcherData {
+            recorder: self.recorder.clone(),
+            .. WatcherData::new(&self.liveness, id, watch, options, on_event, may_read, user)
+        });
         self.watchers.insert(id, watcher.clone());
         watcher
     }
@@ -358,6 +486,264 @@ impl WatchMap {
     }
 }
 
+/// The latest value received from each getter, either by fetching it or by watching it.
+///
+/// Values are recorded outside of the `MainLock`, as this happens for every single value.
+#[derive(Default)]
+pub struct ValueCache {
+    values: Mutex<HashMap<Id<Getter>, (TimeStamp, Value)>>,
+}
+
+impl ValueCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a value received from a getter.
+    pub fn record(&self, id: &Id<Getter>, value: &Value, timestamp: &TimeStamp) {
+        self.values.lock().unwrap().insert(id.clone(), (timestamp.clone(), value.clone()));
+    }
+
+    /// The latest value received from a getter, if it was received at most `max_age`
+    /// before `now`.
+    pub fn get(&self, id: &Id<Getter>, max_age: &Duration, now: &DateTime<UTC>) -> Option<(TimeStamp, Value)> {
+        let max_age : ChronoDuration = max_age.clone().into();
+        match self.values.lock().unwrap().get(id) {
+            Some(&(ref timestamp, ref value)) if *now - *timestamp.as_datetime() <= max_age =>
+                Some((timestamp.clone(), value.clone())),
+            _ => None
+        }
+    }
+
+    /// The instant at which a value was last received from a getter, if any.
+    pub fn last_seen(&self, id: &Id<Getter>) -> Option<TimeStamp> {
+        self.values.lock().unwrap().get(id).map(|&(ref timestamp, _)| timestamp.clone())
+    }
+
+    /// Forget the value of a getter, e.g. once it has been removed.
+    pub fn remove(&self, id: &Id<Getter>) {
+        self.values.lock().unwrap().remove(id);
+    }
+}
+
+/// Convert a `Duration` from the taxonomy into a `Duration` from the standard library.
+fn to_std_duration(duration: &Duration) -> StdDuration {
+    let duration : ChronoDuration = duration.clone().into();
+    StdDuration::from_millis(cmp::max(duration.num_milliseconds(), 0) as u64)
+}
+
+/// The state of an `EventFilterCore`.
+#[derive(Default)]
+struct EventFilterState {
+    /// The latest event delivered, and when it was delivered.
+    latest: Option<(Instant, WatchEvent)>,
+
+    /// An event held back, and the instant at which it should be delivered.
+    pending: Option<(Instant, WatchEvent)>,
+}
+
+/// What an `EventFilterCore` does with an event from the adapter.
+enum Filtered {
+    /// Deliver the event immediately.
+    Deliver(WatchEvent),
+
+    /// The event is held back until the given instant.
+    HoldUntil(Instant),
+
+    /// The event does not differ sufficiently from the latest event delivered.
+    Ignore,
+}
+
+/// Applies the `WatchOptions` of a watcher to the events of a single getter.
+///
+/// Events held back are delivered by the `FilterTimer`.
+struct EventFilterCore {
+    min_interval: Option<StdDuration>,
+    debounce: Option<StdDuration>,
+    min_delta: Option<f64>,
+    state: Mutex<EventFilterState>,
+    on_event: Mutex<Box<ExtSender<WatchEvent>>>,
+    is_dropped: Arc<AtomicBool>,
+}
+
+impl EventFilterCore {
+    /// Determine whether `event` differs sufficiently from the latest event delivered.
+    ///
+    /// Entering or exiting the range always counts as a change.
+    fn is_significant(&self, latest: &Option<(Instant, WatchEvent)>, event: &WatchEvent) -> bool {
+        let min_delta = match self.min_delta {
+            None => return true,
+            Some(min_delta) => min_delta
+        };
+        let (previous, value) = match (latest, event) {
+            (&Some((_, WatchEvent::EnterRange { value: ref previous, .. })), &WatchEvent::EnterRange { ref value, .. }) |
+            (&Some((_, WatchEvent::ExitRange { value: ref previous, .. })), &WatchEvent::ExitRange { ref value, .. }) =>
+                (previous, value),
+            _ => return true
+        };
+        match (previous.magnitude_in(previous), value.magnitude_in(previous)) {
+            (Some(previous), Some(value)) => (value - previous).abs() >= min_delta,
+            // Not a number, or not comparable.
+            _ => true
+        }
+    }
+
+    fn push(&self, now: Instant, event: WatchEvent) -> Filtered {
+        let mut state = self.state.lock().unwrap();
+        if !self.is_significant(&state.latest, &event) {
+            // The latest event delivered is still accurate, anything held back is obsolete.
+            state.pending = None;
+            return Filtered::Ignore;
+        }
+        let mut deadline = now;
+        if let Some(debounce) = self.debounce {
+            deadline = deadline + debounce;
+        }
+        if let (Some(min_interval), &Some((latest, _))) = (self.min_interval, &state.latest) {
+            deadline = cmp::max(deadline, latest + min_interval);
+        }
+        if deadline <= now && state.pending.is_none() {
+            state.latest = Some((now, event.clone()));
+            return Filtered::Deliver(event);
+        }
+        // Hold back the event, replacing any older event.
+        state.pending = Some((deadline, event));
+        Filtered::HoldUntil(deadline)
+    }
+
+    /// Deliver the event held back, if it is due at `now`.
+    fn flush(&self, now: Instant) {
+        let event = {
+            let mut state = self.state.lock().unwrap();
+            match state.pending {
+                Some((deadline, _)) if deadline <= now => {},
+                // Nothing held back, or replaced by a newer event, which has its own deadline.
+                _ => return
+            }
+            let (_, event) = state.pending.take().unwrap();
+            state.latest = Some((now, event.clone()));
+            event
+        };
+        if self.is_dropped.load(Ordering::Relaxed) {
+            return;
+        }
+        let _ = self.on_event.lock().unwrap().send(event);
+    }
+}
+
+/// Delivers the events held back by all the `EventFilter`s of the manager, from the
+/// single thread of its `Clock`.
+pub struct FilterTimer {
+    clock: Arc<Clock>,
+
+    /// The instants at which filters have something to deliver. Filters that have been
+    /// dropped in the meantime are skipped.
+    queue: Mutex<Vec<(Instant, Weak<EventFilterCore>)>>,
+
+    /// Notifies the clock of a new instant in `queue`.
+    on_schedule: Mutex<Option<Box<Fn() + Send + Sync>>>,
+}
+
+impl FilterTimer {
+    pub fn new(clock: Arc<Clock>) -> Arc<Self> {
+        let timer = Arc::new(FilterTimer {
+            clock: clock.clone(),
+            queue: Mutex::new(vec![]),
+            on_schedule: Mutex::new(None),
+        });
+        let as_timer : Arc<Timer> = timer.clone();
+        *timer.on_schedule.lock().unwrap() = Some(clock.drive(Arc::downgrade(&as_timer)));
+        timer
+    }
+
+    fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    fn schedule(&self, deadline: Instant, core: &Arc<EventFilterCore>) {
+        self.queue.lock().unwrap().push((deadline, Arc::downgrade(core)));
+        if let Some(ref on_schedule) = *self.on_schedule.lock().unwrap() {
+            on_schedule();
+        }
+    }
+}
+
+impl Timer for FilterTimer {
+    fn fire(&self, now: Instant) -> Option<Instant> {
+        let due : Vec<_> = {
+            let mut queue = self.queue.lock().unwrap();
+            let (due, later) : (Vec<_>, Vec<_>) = queue.drain(..)
+                .partition(|&(deadline, _)| deadline <= now);
+            *queue = later;
+            due
+        };
+        for (_, core) in due {
+            if let Some(core) = core.upgrade() {
+                core.flush(now);
+            }
+        }
+        self.queue.lock().unwrap().iter()
+            .map(|&(deadline, _)| deadline)
+            .min()
+    }
+}
+
+impl Drop for FilterTimer {
+    fn drop(&mut self) {
+        // Let the clock find out that we are gone.
+        if let Some(ref on_schedule) = *self.on_schedule.lock().unwrap() {
+            on_schedule();
+        }
+    }
+}
+
+/// The `EventFilterCore` of a single getter, owned by the sender passed to the adapter.
+struct EventFilter {
+    core: Arc<EventFilterCore>,
+    timer: Arc<FilterTimer>,
+}
+
+impl EventFilter {
+    /// Start filtering events, or return `None` if `options` do not filter anything.
+    fn start(options: &WatchOptions, on_event: &Mutex<Box<ExtSender<WatchEvent>>>,
+        is_dropped: &Arc<AtomicBool>, timer: &Arc<FilterTimer>) -> Option<Self>
+    {
+        if options.min_interval.is_none() && options.debounce.is_none() && options.min_delta.is_none() {
+            return None;
+        }
+        let core = Arc::new(EventFilterCore {
+            min_interval: options.min_interval.as_ref().map(to_std_duration),
+            debounce: options.debounce.as_ref().map(to_std_duration),
+            min_delta: options.min_delta,
+            state: Mutex::new(EventFilterState::default()),
+            on_event: Mutex::new(on_event.lock().unwrap().clone()),
+            is_dropped: is_dropped.clone(),
+        });
+        Some(EventFilter {
+            core: core,
+            timer: timer.clone(),
+        })
+    }
+
+    /// Handle an event from the adapter. Returns the event if it should be delivered
+    /// immediately.
+    fn push(&self, event: WatchEvent) -> Option<WatchEvent> {
+        match self.core.push(self.timer.now(), event) {
+            Filtered::Deliver(event) => Some(event),
+            Filtered::HoldUntil(deadline) => {
+                self.timer.schedule(deadline, &self.core);
+                None
+            }
+            Filtered::Ignore => None
+        }
+    }
+
+    /// Record an event delivered without going through the filter.
+    fn delivered(&self, event: WatchEvent) {
+        self.core.state.lock().unwrap().latest = Some((self.timer.now(), event));
+    }
+}
+
 pub struct State {
     /// Adapters, indexed by their id.
     adapter_by_id: HashMap<Id<AdapterId>, AdapterData>,
@@ -384,6 +770,9 @@ pub struct State {
     /// - We read all tags once per lifetime of the manager.
     /// - We write occasionaly when adding or removing tags.
     db_path: Option<PathBuf>,
+
+    /// Delivers the events held back by the `WatchOptions` of watches.
+    timer: Arc<FilterTimer>,
 }
 
 impl State {
@@ -524,6 +913,7 @@ impl State {
         }
         for key in keys_to_drop {
             getter_data.watchers.remove(&key);
+            getter_data.recording.release(key);
         }
     }
 
@@ -541,7 +931,9 @@ impl State {
                     let mut getter_data = getter_data.borrow_mut();
 
                     // Determine if the channel matches an ongoing watcher.
-                    for watcher in &mut self.watchers.lock().unwrap().watchers.values() {
+                    let mut watch_map = self.watchers.lock().unwrap();
+                    let watchers : Vec<_> = watch_map.watchers.values().cloned().collect();
+                    for watcher in &watchers {
                         if watcher.guards.borrow().contains_key(&id) {
                             // The watcher already matches this getter.
                             continue;
@@ -550,6 +942,10 @@ impl State {
                             // The guard has been dropped, we don't care anymore.
                             continue;
                         }
+                        if !(watcher.may_read)(&*getter_data.service_tags.borrow(), &getter_data.channel) {
+                            // The watcher is not allowed to see this getter.
+                            continue;
+                        }
                         for targetted in &watcher.watch {
                             let matches = targetted.select.iter().any(|selector| {
                                 getter_data.matches(selector)
@@ -565,7 +961,7 @@ impl State {
 
                             // Register to be informed of future changes.
                             Self::aux_start_channel_watch(&mut watcher.clone(),
-                                &mut *getter_data, &targetted.payload, adapter_by_id, &mut per_adapter)
+                                &mut *getter_data, &targetted.payload, adapter_by_id, &mut *watch_map, &mut per_adapter)
                         }
                     }
                 }
@@ -593,18 +989,31 @@ impl State {
 }
 
 impl State {
-    pub fn new(liveness: &Arc<Liveness>, db_path: Option<PathBuf>) -> Self {
+    /// Create an empty state. If `recorder` is provided, it receives every value reported
+    /// by adapters on watched getters, exactly once.
+    pub fn new(liveness: &Arc<Liveness>, db_path: Option<PathBuf>, recorder: Option<Recorder>) -> Self {
         State {
             liveness: liveness.clone(),
             adapter_by_id: HashMap::new(),
             service_by_id: HashMap::new(),
             getter_by_id: HashMap::new(),
             setter_by_id: HashMap::new(),
-            watchers: Arc::new(Mutex::new(WatchMap::new(liveness))),
+            watchers: Arc::new(Mutex::new(WatchMap::new(liveness, recorder))),
             db_path: db_path,
+            timer: FilterTimer::new(Arc::new(SystemClock)),
        }
     }
 
+    /// Replace the clock used to deliver events held back by the `WatchOptions` of
+    /// watches. Only affects watches started afterwards.
+    pub fn set_clock(&mut self, clock: Arc<Clock>) {
+        self.timer = FilterTimer::new(clock);
+    }
+
+    pub fn timer(&self) -> Arc<FilterTimer> {
+        self.timer.clone()
+    }
+
     /// Add an adapter to the system.
     ///
     /// # Errors
@@ -620,24 +1029,55 @@ impl State {
         Ok(())
     }
 
+    /// Record the status reported by an adapter.
+    ///
+    /// Returns the health of the adapter if its status has changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no adapter with this identifier exists.
+    pub fn set_adapter_status(&mut self, id: &Id<AdapterId>, status: AdapterStatus) -> Result<Option<AdapterHealth>, Error> {
+        let adapter = match self.adapter_by_id.get_mut(id) {
+            None => return Err(Error::InternalError(InternalError::NoSuchAdapter(id.clone()))),
+            Some(adapter) => adapter
+        };
+        if adapter.status == status {
+            return Ok(None);
+        }
+        adapter.status = status;
+        adapter.since = TimeStamp::from_datetime(UTC::now());
+        Ok(Some(adapter.health()))
+    }
+
+    /// The health of an adapter, if it exists.
+    pub fn get_adapter_health(&self, id: &Id<AdapterId>) -> Option<AdapterHealth> {
+        self.adapter_by_id.get(id).map(AdapterData::health)
+    }
+
+    /// The health of all adapters.
+    pub fn get_adapters_health(&self) -> Vec<AdapterHealth> {
+        self.adapter_by_id.values().map(AdapterData::health).collect()
+    }
+
     /// Remove an adapter from the system, including all its services and channels.
+    /// Returns the adapter, which the caller is in charge of stopping.
     ///
     /// # Errors
     ///
     /// Returns an error if no adapter with this identifier exists. Otherwise, attempts
     /// to cleanup as much as possible, even if for some reason the system is in an
     /// inconsistent state.
-    pub fn remove_adapter(&mut self, id: &Id<AdapterId>) -> Result<(), Error> {
-        let mut services = match self.adapter_by_id.remove(id) {
-            Some(AdapterData {services: adapter_services, ..}) => {
-                adapter_services
+    pub fn remove_adapter(&mut self, id: &Id<AdapterId>) -> Result<Arc<Adapter>, Error> {
+        let (adapter, mut services) = match self.adapter_by_id.remove(id) {
+            Some(AdapterData {adapter, services: adapter_services, ..}) => {
+                (adapter, adapter_services)
             }
             None => return Err(Error::InternalError(InternalError::NoSuchAdapter(id.clone()))),
         };
         for (service_id, _) in services.drain() {
             let _ignored = self.aux_remove_service(&service_id);
         }
-        Ok(())
+        Ok(adapter)
     }
 
     /// Add a service to the system. Called by the adapter when a new
@@ -900,10 +1340,21 @@ impl State {
         result
     }
 
-    pub fn add_service_tags(&mut self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>) -> usize {
+    /// Add tags to the services matching `selectors`.
+    ///
+    /// Services with a channel for which `may_read` or `may_write` return `false` are
+    /// left untouched.
+    pub fn add_service_tags<R, W>(&mut self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>,
+                                  may_read: R, may_write: W) -> usize
+        where R: Fn(&HashSet<Id<TagId>>, &Channel<Getter>) -> bool,
+              W: Fn(&HashSet<Id<TagId>>, &Channel<Setter>) -> bool
+    {
         let mut result = 0;
 
         self.with_services(selectors, |service, store| {
+            if !Self::may_access_service(service, &may_read, &may_write) {
+                return;
+            }
             let service = service.borrow_mut();
             let mut tag_set = service.tags.borrow_mut();
 
@@ -920,9 +1371,20 @@ impl State {
         result
     }
 
-    pub fn remove_service_tags(&mut self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>) -> usize {
+    /// Remove tags from the services matching `selectors`.
+    ///
+    /// Services with a channel for which `may_read` or `may_write` return `false` are
+    /// left untouched.
+    pub fn remove_service_tags<R, W>(&mut self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>,
+                                     may_read: R, may_write: W) -> usize
+        where R: Fn(&HashSet<Id<TagId>>, &Channel<Getter>) -> bool,
+              W: Fn(&HashSet<Id<TagId>>, &Channel<Setter>) -> bool
+    {
         let mut result = 0;
         self.with_services(selectors, |service, store| {
+            if !Self::may_access_service(service, &may_read, &may_write) {
+                return;
+            }
             let service = service.borrow_mut();
             let mut tag_set = service.tags.borrow_mut();
 
@@ -939,6 +1401,22 @@ impl State {
         result
     }
 
+    /// Determine whether `may_read` accepts all the getters of `service` and `may_write`
+    /// all its setters.
+    fn may_access_service<R, W>(service: &Arc<SubCell<ServiceData>>, may_read: &R, may_write: &W) -> bool
+        where R: Fn(&HashSet<Id<TagId>>, &Channel<Getter>) -> bool,
+              W: Fn(&HashSet<Id<TagId>>, &Channel<Setter>) -> bool
+    {
+        let service = service.borrow();
+        service.getters.values().all(|getter| {
+            let getter = getter.borrow();
+            may_read(&*getter.service_tags.borrow(), &getter.channel)
+        }) && service.setters.values().all(|setter| {
+            let setter = setter.borrow();
+            may_write(&*setter.service_tags.borrow(), &setter.channel)
+        })
+    }
+
     pub fn get_getter_channels(&self, selectors: Vec<GetterSelector>) -> Vec<Channel<Getter>>
     {
         Self::aux_get_channels(selectors, &self.getter_by_id)
@@ -951,12 +1429,19 @@ impl State {
     /// Add tags to a getter.
     /// As our in-memory representation stores the same getter both in the Service
     /// and in `self.getters`, we need to update both.
-    pub fn add_getter_tags(&mut self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>) -> (WatchRequest, usize) {
+    ///
+    /// Getters for which `is_allowed` returns `false` are left untouched.
+    pub fn add_getter_tags<F>(&mut self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>, is_allowed: F) -> (WatchRequest, usize)
+        where F: Fn(&HashSet<Id<TagId>>, &Channel<Getter>) -> bool
+    {
         let mut size = 0;
         let mut channels = vec![];
         {
             let db_path = self.db_path.clone();
             Self::with_channels_mut(selectors, &mut self.getter_by_id, |mut data| {
+                if !is_allowed(&*data.service_tags.borrow(), &data.channel) {
+                    return;
+                }
                 // This channel has changed, we may need to update watches and the tags database.
                 if data.insert_tags(&tags) {
                     if let Some(ref path) = db_path {
@@ -973,10 +1458,17 @@ impl State {
         (self.aux_getters_may_need_registration(channels), size)
     }
 
-    pub fn add_setter_tags(&mut self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>) -> usize {
+    /// Add tags to the setters matching `selectors`, except those for which `is_allowed`
+    /// returns `false`.
+    pub fn add_setter_tags<F>(&mut self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>, is_allowed: F) -> usize
+        where F: Fn(&HashSet<Id<TagId>>, &Channel<Setter>) -> bool
+    {
         let mut result = 0;
         let db_path = self.db_path.clone();
         Self::with_channels_mut(selectors, &mut self.setter_by_id, |mut data| {
+            if !is_allowed(&*data.service_tags.borrow(), &data.channel) {
+                return;
+            }
             if data.insert_tags(&tags) {
                 if let Some(ref path) = db_path {
                     let mut store = TagStorage::new(&path);
@@ -989,10 +1481,17 @@ impl State {
         result
     }
 
-    pub fn remove_getter_tags(&mut self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>) -> usize {
+    /// Remove tags from the getters matching `selectors`, except those for which
+    /// `is_allowed` returns `false`.
+    pub fn remove_getter_tags<F>(&mut self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>, is_allowed: F) -> usize
+        where F: Fn(&HashSet<Id<TagId>>, &Channel<Getter>) -> bool
+    {
         let mut result = 0;
         let db_path = self.db_path.clone();
         Self::with_channels_mut(selectors, &mut self.getter_by_id, |mut data| {
+            if !is_allowed(&*data.service_tags.borrow(), &data.channel) {
+                return;
+            }
             if data.remove_tags(&tags) {
                 if let Some(ref path) = db_path {
                     let mut store = TagStorage::new(&path);
@@ -1005,10 +1504,17 @@ impl State {
         });
         result
     }
-    pub fn remove_setter_tags(&mut self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>) -> usize {
+    /// Remove tags from the setters matching `selectors`, except those for which
+    /// `is_allowed` returns `false`.
+    pub fn remove_setter_tags<F>(&mut self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>, is_allowed: F) -> usize
+        where F: Fn(&HashSet<Id<TagId>>, &Channel<Setter>) -> bool
+    {
         let mut result = 0;
         let db_path = self.db_path.clone();
         Self::with_channels_mut(selectors, &mut self.setter_by_id, |mut data| {
+            if !is_allowed(&*data.service_tags.borrow(), &data.channel) {
+                return;
+            }
             if data.remove_tags(&tags) {
                 if let Some(ref path) = db_path {
                     let mut store = TagStorage::new(&path);
@@ -1022,14 +1528,24 @@ impl State {
     }
 
     /// Read the latest value from a set of channels
-    pub fn prepare_fetch_values(&self, selectors: Vec<GetterSelector>) -> FetchRequest {
+    ///
+    /// Channels for which `is_allowed` returns `false` are not part of the request.
+    /// Instead, they are returned separately.
+    pub fn prepare_fetch_values<F>(&self, selectors: Vec<GetterSelector>, is_allowed: F) -> (FetchRequest, Vec<Id<Getter>>)
+        where F: Fn(&HashSet<Id<TagId>>, &Channel<Getter>) -> bool
+    {
         // First, prepare the list of actual getters and group it by adapter.
         // Once we have done this, we can release the lock.
         let mut per_adapter : FetchRequest = HashMap::new();
+        let mut denied = vec![];
         let adapter_by_id = &self.adapter_by_id;
         Self::with_channels(selectors, &self.getter_by_id, |data| {
             use std::collections::hash_map::Entry::*;
             let id = data.channel.id.clone();
+            if !is_allowed(&*data.service_tags.borrow(), &data.channel) {
+                denied.push(id);
+                return;
+            }
             let typ = data.channel.mechanism.kind.get_type();
             match per_adapter.entry(data.adapter.clone()) {
                 Vacant(entry) => {
@@ -1050,12 +1566,16 @@ impl State {
                 }
             };
         });
-        per_adapter
+        (per_adapter, denied)
     }
 
 
     /// Send values to a set of channels
-    pub fn prepare_send_values(&self, mut keyvalues: TargetMap<SetterSelector, Value>) -> SendRequest {
+    ///
+    /// Channels for which `is_allowed` returns `false` are reported as failures.
+    pub fn prepare_send_values<F>(&self, mut keyvalues: TargetMap<SetterSelector, Value>, is_allowed: F) -> SendRequest
+        where F: Fn(&HashSet<Id<TagId>>, &Channel<Setter>) -> bool
+    {
         // First determine the channels and group them by adapter.
         let mut per_adapter = HashMap::new();
         for Targetted {select: selectors, payload: value} in keyvalues.drain(..) {
@@ -1063,16 +1583,25 @@ impl State {
                 use std::collections::hash_map::Entry::*;
                 let id = data.channel.id.clone();
 
-                // Check that the values we are about to send have the correct type. If they
-                // don't, no need to even send them to the Adapter.
+                // Check that the values we are about to send have the correct type and
+                // satisfy the capabilities of the channel. If they don't, no need to even
+                // send them to the Adapter.
                 let typ = data.channel.mechanism.kind.get_type();
-                let checked = if value.get_type() == typ {
-                    Ok(value.clone())
-                } else {
+                let accepted = match data.channel.capabilities {
+                    None => true,
+                    Some(ref capabilities) => capabilities.accepts(&value)
+                };
+                let checked = if !is_allowed(&*data.service_tags.borrow(), &data.channel) {
+                    Err(Error::PermissionDenied)
+                } else if value.get_type() != typ {
                     Err(Error::TypeError(TypeError {
                         got: value.get_type(),
                         expected: typ
                     }))
+                } else if !accepted {
+                    Err(Error::InvalidValue(value.clone()))
+                } else {
+                    Ok(value.clone())
                 };
                 match per_adapter.entry(data.channel.adapter.clone()) {
                     Vacant(entry) => {
@@ -1116,6 +1645,7 @@ impl State {
         getter_data: &mut GetterData,
         filter: &Exactly<Range>,
         adapter_by_id: &HashMap<Id<AdapterId>, AdapterData>,
+        watch_map: &mut WatchMap,
         per_adapter: &mut WatchRequest)
     {
         use std::collections::hash_map::Entry::*;
@@ -1141,7 +1671,7 @@ impl State {
             }
         };
 
-        match per_adapter.entry(adapter) {
+        let requests = match per_adapter.entry(adapter) {
             Vacant(entry) => {
                 let adapter = match adapter_by_id.get(&getter_data.channel.adapter) {
                     None => {
@@ -1153,23 +1683,30 @@ impl State {
                         adapter_data.adapter.clone()
                     }
                 };
-                entry.insert((adapter, (vec![(id, range, Arc::downgrade(watcher) )])));
+                &mut entry.insert((adapter, vec![])).1
             },
-            Occupied(mut entry) => {
-                (entry.get_mut().1).push((id, range, Arc::downgrade(watcher)));
+            Occupied(entry) => {
+                &mut entry.into_mut().1
             }
+        };
+
+        if watch_map.recorder.is_some() {
+            getter_data.recording.offer(watcher.key, range.is_none());
+            watcher.recording.lock().unwrap().insert(id.clone(), getter_data.recording.clone());
         }
+        requests.push((id, range, Arc::downgrade(watcher)));
 
         insert_in_getter.commit();
     }
 
     pub fn prepare_channel_watch(&mut self, mut watch: TargetMap<GetterSelector, Exactly<Range>>,
-        on_event: Box<ExtSender<WatchEvent>>) -> (WatchRequest, WatchKey, Arc<AtomicBool>)
+        options: WatchOptions, on_event: Box<ExtSender<WatchEvent>>, may_read: ReadCheck, user: User) -> (WatchRequest, WatchKey, Arc<AtomicBool>)
     {
         // Prepare the watcher and store it. Once we leave the lock, every time a channel is
         // added/removed/updated, this will cause us to reexamine whether the channel should
         // be visible to a watcher.
-        let mut watcher = self.watchers.lock().unwrap().create(watch.clone(), on_event.clone());
+        let mut watch_map = self.watchers.lock().unwrap();
+        let mut watcher = watch_map.create(watch.clone(), options, on_event.clone(), may_read, user);
         let is_dropped = watcher.is_dropped.clone();
 
         // Regroup per adapter.
@@ -1180,8 +1717,12 @@ impl State {
             // the watcher immediately.
             let filter = &filter;
             Self::with_channels_mut(selectors, &mut self.getter_by_id, |mut getter_data| {
+                if !(watcher.may_read)(&*getter_data.service_tags.borrow(), &getter_data.channel) {
+                    // The watcher is not allowed to see this getter.
+                    return;
+                }
                 Self::aux_start_channel_watch(&mut watcher, &mut getter_data, filter,
-                    adapter_by_id, &mut per_adapter)
+                    adapter_by_id, &mut *watch_map, &mut per_adapter)
             });
         }
 
@@ -1217,9 +1758,11 @@ impl State {
                 None => continue, // Race condition between removing the getter and dropping the watcher.
                 Some(getter) => getter
             };
-            if getter.borrow_mut().watchers.remove(&watcher_data.key).is_none() {
+            let mut getter = getter.borrow_mut();
+            if getter.watchers.remove(&watcher_data.key).is_none() {
                 debug_assert!(false, "Attempting to unregister a watcher that has already been removed from its getter {:?}, {:?}", key, getter_id);
             }
+            getter.recording.release(watcher_data.key);
         }
 
         // At this stage, one getter may still have a strong reference to watcher_data, if it has
@@ -1227,8 +1770,92 @@ impl State {
         // the last reference has disappeared, all `guards` will be dropped.
     }
 
+    /// The part of `request` concerning watchers that asked for initial values.
+    pub fn initial_request(request: &WatchRequest) -> WatchRequest {
+        request.iter().filter_map(|(id, &(ref adapter, ref targets))| {
+            let targets : Vec<_> = targets.iter()
+                .filter(|&&(_, _, ref watcher)| {
+                    match watcher.upgrade() {
+                        Some(ref watcher) => watcher.options.initial && !watcher.is_dropped.load(Ordering::Relaxed),
+                        None => false
+                    }
+                })
+                .cloned()
+                .collect();
+            if targets.is_empty() {
+                None
+            } else {
+                Some((id.clone(), (adapter.clone(), targets)))
+            }
+        }).collect()
+    }
+
+    /// Fetch the current values of the getters of an initial request from a single adapter,
+    /// on behalf of the user of each watcher.
+    ///
+    /// This may block for as long as the adapter wishes, so it should not be called
+    /// from the watch thread.
+    pub fn fetch_initial_values(adapter: &Arc<Adapter>, mut targets: Vec<(Id<Getter>, Option<Range>, Weak<WatcherData>)>)
+        -> Vec<(Value, Option<Range>, Weak<WatcherData>, Id<Getter>)>
+    {
+        // Regroup per watcher, as each watcher may have a different user.
+        let mut per_watcher : HashMap<WatchKey, (Arc<WatcherData>, Vec<(Id<Getter>, Option<Range>)>)> = HashMap::new();
+        for (id, range, watcher) in targets.drain(..) {
+            let watcher = match watcher.upgrade() {
+                None => continue,
+                Some(watcher) => watcher
+            };
+            per_watcher.entry(watcher.key)
+                .or_insert_with(|| (watcher.clone(), vec![]))
+                .1.push((id, range));
+        }
+        let mut fetched = vec![];
+        for (_, (watcher, getters)) in per_watcher {
+            let ids = getters.iter().map(|&(ref id, _)| id.clone()).collect();
+            let mut values = adapter.fetch_values(ids, watcher.user.clone());
+            for (id, range) in getters {
+                match values.remove(&id) {
+                    Some(Ok(Some(value))) => {
+                        watcher.record(&id, &value, range.is_none());
+                        fetched.push((value, range, Arc::downgrade(&watcher), id))
+                    }
+                    Some(Err(err)) => {
+                        debug!(target: "Taxonomy-backend", "State::fetch_initial_values, could not fetch initial value of {}: {:?}.", id, err);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        fetched
+    }
+
+    /// Deliver the initial values fetched by `fetch_initial_values` to their watchers, if
+    /// they are in range.
+    pub fn send_initial_values(mut fetched: Vec<(Value, Option<Range>, Weak<WatcherData>, Id<Getter>)>) {
+        for (value, range, watcher, id) in fetched.drain(..) {
+            let watcher = match watcher.upgrade() {
+                None => continue,
+                Some(watcher) => watcher
+            };
+            if watcher.is_dropped.load(Ordering::Relaxed) {
+                continue;
+            }
+            if let Some(ref range) = range {
+                if !range.contains(&value) {
+                    continue;
+                }
+            }
+            let event = WatchEvent::EnterRange {
+                from: id.clone(),
+                value: value
+            };
+            watcher.initial.lock().unwrap().insert(id, event.clone());
+            let _ = watcher.on_event.lock().unwrap().send(event);
+        }
+    }
+
     /// Start watching a set of channels.
-    pub fn start_watch(mut per_adapter: WatchRequest) -> WatchGuardCommit {
+    pub fn start_watch(mut per_adapter: WatchRequest, timer: &Arc<FilterTimer>) -> WatchGuardCommit {
         // In most cases, stop_watch will take place long after start_watch. It is, however,
         // possible that the `WatchGuard` is dropped before start_watch is processed for this
         // channel. In this case, three events take place:
@@ -1264,7 +1891,26 @@ impl State {
                     debug!(target: "Taxonomy-backend", "State::start_watch, the guard has been dropped, is_dropped detected, skipping.");
                     return continue;
                 }
+                let filter = EventFilter::start(&watch_data.options, &watch_data.on_event, &is_dropped, timer);
+                if let Some(event) = watch_data.initial.lock().unwrap().remove(&id) {
+                    // The initial value counts as the latest value delivered.
+                    if let Some(ref filter) = filter {
+                        filter.delivered(event);
+                    }
+                }
+                // Values are recorded before any filtering, even if the watcher has
+                // just been dropped. The adapter holds this closure until the watcher
+                // is dropped, so it must not keep the watcher alive.
+                let recording_data = Arc::downgrade(&watch_data);
+                let is_unfiltered = range.is_none();
                 let on_ok = watch_data.on_event.lock().unwrap().filter_map(move |event| {
+                    if let Some(watch_data) = recording_data.upgrade() {
+                        match event {
+                            AdapterWatchEvent::Enter { ref id, ref value } |
+                            AdapterWatchEvent::Exit { ref id, ref value } =>
+                                watch_data.record(id, value, is_unfiltered)
+                        }
+                    }
                     if is_dropped.load(Ordering::Relaxed) {
                         debug!(target: "Taxonomy-backend", "State::start_watch, the guard has been dropped, is_dropped detected, don't propagate messages.");
 
@@ -1274,7 +1920,7 @@ impl State {
                         // the call to `stop_watch`.
                         return None;
                     }
-                    Some(match event {
+                    let event = match event {
                         AdapterWatchEvent::Enter { id, value } =>
                             WatchEvent::EnterRange {
                                 from: id,
@@ -1285,11 +1931,15 @@ impl State {
                                 from: id,
                                 value: value
                             },
-                    })
+                    };
+                    match filter {
+                        None => Some(event),
+                        Some(ref filter) => filter.push(event)
+                    }
                 });
 
                 let mut guards = vec![];
-                for (id, result) in adapter.register_watch(vec![(id, range, Box::new(on_ok))]) {
+                for (id, result) in adapter.register_watch_for(vec![(id, range, Box::new(on_ok))], watch_data.user.clone()) {
                     debug!(target: "Taxonomy-backend", "State::start_watch, registered watch for {} => {}.", id, result.is_ok());
 
                     match result {
@@ -0,0 +1,125 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Sources of time.
+//!
+//! Everything in the taxonomy that needs to act at some instant, e.g. to deliver a value
+//! held back by the `WatchOptions` of a watch, does so through a `Clock`. Tests replace
+//! the `SystemClock` by a `FakeClock`, which only moves when told to, instead of sleeping.
+
+use std::sync::{ Arc, Condvar, Mutex, Weak };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+/// Something that needs to act at some instants.
+pub trait Timer: Send + Sync {
+    /// Act on everything due at or before `now`. Returns the next instant at which to
+    /// act, if any.
+    fn fire(&self, now: Instant) -> Option<Instant>;
+}
+
+/// A source of time.
+pub trait Clock: Send + Sync {
+    /// The current instant.
+    fn now(&self) -> Instant;
+
+    /// Call `timer.fire()` whenever it has something to do, until `timer` is dropped.
+    ///
+    /// Returns a function that the timer must call whenever it has a new instant at
+    /// which to act, and once it is dropped.
+    fn drive(&self, timer: Weak<Timer>) -> Box<Fn() + Send + Sync>;
+}
+
+/// The clock of the system. Each timer is driven by a single thread.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn drive(&self, timer: Weak<Timer>) -> Box<Fn() + Send + Sync> {
+        // `true` whenever the timer may have a new instant at which to act.
+        let changed = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_changed = changed.clone();
+        thread::spawn(move || {
+            let (ref changed, ref wakeup) = *thread_changed;
+            loop {
+                let next = match timer.upgrade() {
+                    None => return, // The timer has been dropped.
+                    Some(timer) => timer.fire(Instant::now())
+                };
+                let mut changed = changed.lock().unwrap();
+                while !*changed {
+                    match next {
+                        None => {
+                            changed = wakeup.wait(changed).unwrap();
+                        }
+                        Some(next) => {
+                            let now = Instant::now();
+                            if next <= now {
+                                break;
+                            }
+                            changed = wakeup.wait_timeout(changed, next - now).unwrap().0;
+                        }
+                    }
+                }
+                *changed = false;
+            }
+        });
+        Box::new(move || {
+            let (ref changed, ref wakeup) = *changed;
+            *changed.lock().unwrap() = true;
+            wakeup.notify_one();
+        })
+    }
+}
+
+/// A clock that only moves when `advance` is called, firing synchronously everything
+/// that becomes due. Designed to be used as a component of tests.
+pub struct FakeClock {
+    now: Mutex<Instant>,
+    timers: Mutex<Vec<Weak<Timer>>>,
+}
+
+impl FakeClock {
+    pub fn new() -> Self {
+        FakeClock {
+            now: Mutex::new(Instant::now()),
+            timers: Mutex::new(vec![]),
+        }
+    }
+
+    /// Move time forward by `duration`, then fire all the timers.
+    pub fn advance(&self, duration: Duration) {
+        let now = {
+            let mut now = self.now.lock().unwrap();
+            *now = *now + duration;
+            *now
+        };
+        let timers : Vec<_> = self.timers.lock().unwrap().iter()
+            .filter_map(|timer| timer.upgrade())
+            .collect();
+        for timer in timers {
+            timer.fire(now);
+        }
+    }
+}
+
+impl Default for FakeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn drive(&self, timer: Weak<Timer>) -> Box<Fn() + Send + Sync> {
+        self.timers.lock().unwrap().push(timer);
+        Box::new(|| {})
+    }
+}
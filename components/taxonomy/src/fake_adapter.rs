@@ -2,7 +2,7 @@
 //! Used for testing.
 use adapter::*;
 
-use api::{ Error, User };
+use api::{ Error, InternalError, User };
 use selector::*;
 use services::*;
 use values::*;
@@ -15,6 +15,7 @@ use std::collections::hash_map::Entry::*;
 use std::sync::{ Arc, Mutex };
 use std::sync::atomic::{ AtomicBool, Ordering} ;
 use std::thread;
+use std::time::Duration;
 
 /// A tweak sent to the virtual device, to set a value, inject an error, ...
 #[allow(enum_variant_names)]
@@ -24,7 +25,19 @@ pub enum Tweak {
 
     /// Inject an error in a virtual setter. All operations on this setter will
     /// raise the error until `None` is injected instead.
-    InjectSetterError(Id<Setter>, Option<Error>)
+    InjectSetterError(Id<Setter>, Option<Error>),
+
+    /// Make all calls to `fetch_values` and `send_values` wait before responding,
+    /// until `None` is injected instead.
+    InjectDelay(Option<Duration>),
+
+    /// Make the next call to `send_values` wait before responding.
+    InjectSendDelay(Duration),
+
+    /// Change the way the adapter takes part in atomic sends. Setters read back the
+    /// latest value sent to them. Errors injected in setters are raised by
+    /// `prepare_values`, not by `read_back_values`.
+    InjectTransactionSupport(TransactionSupport),
 }
 
 /// Something that happened to the virtual device, e.g. a value was sent.
@@ -61,7 +74,16 @@ pub struct FakeAdapter {
     rx_effect: Mutex<Option<Receiver<Effect>>>,
     values: SyncMap<Id<Getter>, Result<Value, Error>>,
     senders: SyncMap<Id<Setter>, Error>,
-    watchers: SyncMap<Id<Getter>, Vec<WatcherState>>
+    watchers: SyncMap<Id<Getter>, Vec<WatcherState>>,
+    delay: Arc<Mutex<Option<Duration>>>,
+    send_delay: Arc<Mutex<Option<Duration>>>,
+    transactions: Arc<Mutex<TransactionSupport>>,
+
+    /// The latest value sent to each setter.
+    sent: SyncMap<Id<Setter>, Value>,
+
+    /// The values prepared by `prepare_values`, until they are committed or rolled back.
+    prepared: SyncMap<Id<Setter>, Value>,
 }
 
 impl FakeAdapter {
@@ -72,6 +94,9 @@ impl FakeAdapter {
         let (values_main, values_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
         let (senders_main, senders_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
         let (watchers_main, watchers_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
+        let (delay_main, delay_thread) = dup(Arc::new(Mutex::new(None)));
+        let (send_delay_main, send_delay_thread) = dup(Arc::new(Mutex::new(None)));
+        let (transactions_main, transactions_thread) = dup(Arc::new(Mutex::new(TransactionSupport::Unsupported)));
 
         let mutex = Arc::new(Mutex::new(tx));
         let tweak = move |msg| {
@@ -88,6 +113,11 @@ impl FakeAdapter {
             tx_effect: Mutex::new(Box::new(tx_effect)),
             rx_effect: Mutex::new(Some(rx_effect)),
             watchers: watchers_main,
+            delay: delay_main,
+            send_delay: send_delay_main,
+            transactions: transactions_main,
+            sent: Arc::new(Mutex::new(HashMap::new())),
+            prepared: Arc::new(Mutex::new(HashMap::new())),
         };
 
         thread::spawn(move || {
@@ -142,6 +172,15 @@ impl FakeAdapter {
                     InjectSetterError(id, Some(err)) => {
                         senders_thread.lock().unwrap().insert(id, err);
                     }
+                    InjectDelay(delay) => {
+                        *delay_thread.lock().unwrap() = delay;
+                    }
+                    InjectSendDelay(delay) => {
+                        *send_delay_thread.lock().unwrap() = Some(delay);
+                    }
+                    InjectTransactionSupport(support) => {
+                        *transactions_thread.lock().unwrap() = support;
+                    }
                 }
                 tx.send(()).unwrap();
             }
@@ -156,6 +195,13 @@ impl FakeAdapter {
     pub fn get_tweak(&self) -> Arc<Fn(Tweak) + Sync + Send> {
         self.tweak.clone()
     }
+
+    fn wait_for_delay(&self) {
+        let delay = *self.delay.lock().unwrap();
+        if let Some(delay) = delay {
+            thread::sleep(delay);
+        }
+    }
 }
 
 static VERSION : [u32;4] = [0, 0, 0, 0];
@@ -183,6 +229,7 @@ impl Adapter for FakeAdapter {
     /// Request a value from a channel. The `FoxBox` (not the adapter)
     /// is in charge of keeping track of the age of values.
     fn fetch_values(&self, mut channels: Vec<Id<Getter>>, _: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        self.wait_for_delay();
         let map = self.values.lock().unwrap();
         channels.drain(..).map(|id| {
             let result = match map.get(&id) {
@@ -196,10 +243,16 @@ impl Adapter for FakeAdapter {
 
     /// Request that a value be sent to a channel.
     fn send_values(&self, mut values: HashMap<Id<Setter>, Value>, _: User) -> ResultMap<Id<Setter>, (), Error> {
+        self.wait_for_delay();
+        let send_delay = self.send_delay.lock().unwrap().take();
+        if let Some(delay) = send_delay {
+            thread::sleep(delay);
+        }
         let map = self.senders.lock().unwrap();
         values.drain().map(|(id, value)| {
             let result = match map.get(&id) {
                 None => {
+                    self.sent.lock().unwrap().insert(id.clone(), value.clone());
                     self.tx_effect.lock().unwrap().send(Effect::ValueSent(id.clone(), value)).unwrap();
                     Ok(())
                 }
@@ -209,6 +262,55 @@ impl Adapter for FakeAdapter {
         }).collect()
     }
 
+    fn transaction_support(&self) -> TransactionSupport {
+        *self.transactions.lock().unwrap()
+    }
+
+    fn read_back_values(&self, mut setters: Vec<Id<Setter>>, _: User) -> ResultMap<Id<Setter>, Value, Error> {
+        self.wait_for_delay();
+        let sent = self.sent.lock().unwrap();
+        setters.drain(..).map(|id| {
+            let result = match sent.get(&id) {
+                None => Err(Error::InternalError(InternalError::GenericError("No value sent yet".to_owned()))),
+                Some(value) => Ok(value.clone())
+            };
+            (id, result)
+        }).collect()
+    }
+
+    fn prepare_values(&self, mut values: HashMap<Id<Setter>, Value>, _: User) -> ResultMap<Id<Setter>, (), Error> {
+        self.wait_for_delay();
+        let map = self.senders.lock().unwrap();
+        let mut prepared = self.prepared.lock().unwrap();
+        values.drain().map(|(id, value)| {
+            let result = match map.get(&id) {
+                None => {
+                    prepared.insert(id.clone(), value);
+                    Ok(())
+                }
+                Some(error) => Err(error.clone())
+            };
+            (id, result)
+        }).collect()
+    }
+
+    fn commit_values(&self, mut setters: Vec<Id<Setter>>, user: User) -> ResultMap<Id<Setter>, (), Error> {
+        let values = {
+            let mut prepared = self.prepared.lock().unwrap();
+            setters.drain(..)
+                .filter_map(|id| prepared.remove(&id).map(|value| (id, value)))
+                .collect()
+        };
+        self.send_values(values, user)
+    }
+
+    fn rollback_values(&self, mut setters: Vec<Id<Setter>>, _: User) {
+        let mut prepared = self.prepared.lock().unwrap();
+        for id in setters.drain(..) {
+            prepared.remove(&id);
+        }
+    }
+
     fn register_watch(&self, mut watch: Vec<WatchTarget>) -> WatchResult {
         let mut watchers = self.watchers.lock().unwrap();
         watch.drain(..).map(|(id, filter, on_event)| {
@@ -0,0 +1,349 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+///! An adapter exposing groups of channels as services, e.g. "Kitchen lights".
+///!
+///! Each group is a service with one getter and one setter of the same kind as its
+///! members. Members are the channels matched by a selector, so channels joining or
+///! leaving the selection (e.g. when a tag is edited or a device is added) join or
+///! leave the group immediately. Values sent to the setter of the group are sent to the
+///! setters of all the members. The value of the getter of the group is computed from
+///! the values of the getters of the members, using the `Aggregation` of the group,
+///! e.g. `Any` to report `On` if any light is on. Definitions are stored as their JSON
+///! source, in a database.
+///!
+///! Fetching or watching the getter of a group requires permission to read the getters of
+///! all its members. Sending to the setter of a group sends on behalf of the same user to
+///! the setters of the members, which checks that this user may write to them.
+
+use adapter::*;
+use aggregate::{ Aggregate, Aggregator };
+use api::{ API, Error, InternalError, Targetted, User };
+use manager::AdapterManager;
+use parse::*;
+use selector::{ GetterSelector, SetterSelector };
+use services::*;
+use values::*;
+use virtual_channels::Aggregation;
+
+use rusqlite::Error as SqliteError;
+
+use std::collections::{ HashMap, HashSet };
+use std::path::PathBuf;
+use std::sync::Arc;
+
+static ADAPTER_NAME: &'static str = "Groups adapter (built-in)";
+static ADAPTER_VENDOR: &'static str = "team@link.mozilla.org";
+static ADAPTER_VERSION: [u32;4] = [0, 0, 0, 0];
+
+/// An error while managing groups.
+#[derive(Debug, Serialize)]
+pub enum GroupError {
+    /// The source of a definition could not be parsed.
+    ParseError(ParseError),
+
+    /// There is no group with this name.
+    NoSuchGroup(String),
+
+    /// The group could not be registered with the `AdapterManager`.
+    TaxonomyError(Error),
+
+    /// There was an error executing some SQL.
+    SQLError(String),
+}
+
+impl From<SqliteError> for GroupError {
+    fn from(err: SqliteError) -> Self {
+        GroupError::SQLError(format!("{}", err))
+    }
+}
+
+impl From<ParseError> for GroupError {
+    fn from(err: ParseError) -> Self {
+        GroupError::ParseError(err)
+    }
+}
+
+impl From<Error> for GroupError {
+    fn from(err: Error) -> Self {
+        GroupError::TaxonomyError(err)
+    }
+}
+
+/// The definition of a group.
+///
+/// # JSON
+///
+/// An object with the following fields:
+///
+/// - `name`: the name of the group, used to build the ids of its service and channels;
+/// - `kind`: the `ChannelKind` of the channels of the group;
+/// - `members`: a selector, with the same fields as `GetterSelector` and `SetterSelector`,
+///   for the channels of the members, restricted to channels of kind `kind`. Groups are
+///   never members of other groups;
+/// - (optional) `policy`: the `Aggregation` used to compute the value of the getter of
+///   the group, `Any` by default. `Count` is not supported, and the policy must accept
+///   values of kind `kind`, e.g. `Avg` is only supported for quantities.
+///
+/// ```
+/// use foxbox_taxonomy::groups::*;
+/// use foxbox_taxonomy::parse::*;
+/// use foxbox_taxonomy::services::*;
+/// use foxbox_taxonomy::virtual_channels::Aggregation;
+///
+/// let source = r#"{
+///   "name": "kitchen-lights",
+///   "kind": "LightOn",
+///   "members": {"tags": ["kitchen"]}
+/// }"#;
+///
+/// let group = Group::from_str(source).unwrap();
+/// assert_eq!(group.kind, ChannelKind::LightOn);
+/// assert_eq!(group.policy, Aggregation::Any);
+///
+/// // Lights cannot be averaged.
+/// let source = r#"{
+///   "name": "kitchen-lights",
+///   "kind": "LightOn",
+///   "members": {"tags": ["kitchen"]},
+///   "policy": "Avg"
+/// }"#;
+/// assert!(Group::from_str(source).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub kind: ChannelKind,
+    pub getters: GetterSelector,
+    pub setters: SetterSelector,
+    pub policy: Aggregation,
+}
+
+impl Parser<Group> for Group {
+    fn description() -> String {
+        "Group".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        let name = try!(path.push("name", |path| String::take(path, source, "name")));
+        let kind = try!(path.push("kind", |path| ChannelKind::take(path, source, "kind")));
+        let members = match source.find("members") {
+            None => return Err(ParseError::missing_field("members", &path)),
+            Some(members) => members.clone()
+        };
+        let getters = try!(path.push("members", |path| GetterSelector::parse(path, &mut members.clone())));
+        let setters = try!(path.push("members", |path| SetterSelector::parse(path, &mut members.clone())));
+        let policy = match path.push("policy", |path| Aggregation::take_opt(path, source, "policy")) {
+            None => Aggregation::Any,
+            Some(Ok(Aggregation::Count)) => return Err(ParseError::unknown_constant("Count", &path)),
+            Some(result) => try!(result)
+        };
+        if !policy.accepts(&kind.get_type()) {
+            return Err(path.push("policy", |path| {
+                ParseError::type_error("policy", &path, &format!("a policy compatible with {:?}", kind))
+            }));
+        }
+        Ok(Group {
+            name: name,
+            getters: getters.with_kind(kind.clone()),
+            setters: setters.with_kind(kind.clone()),
+            kind: kind,
+            policy: policy,
+        })
+    }
+}
+
+impl Aggregate for Group {
+    fn key(&self) -> String {
+        self.name.clone()
+    }
+
+    fn getter_id(&self) -> Id<Getter> {
+        Groups::getter_id(&self.name)
+    }
+
+    fn source(&self) -> GetterSelector {
+        self.getters.clone()
+    }
+
+    fn apply(&self, values: Vec<&Value>) -> Option<Value> {
+        self.policy.apply(values)
+    }
+
+    fn add_channels(&self, manager: &AdapterManager) -> Result<(), Error> {
+        let service_id = Groups::service_id(&self.name);
+        let mut service = Service::empty(service_id.clone(), Groups::id());
+        service.properties.insert("name".to_owned(), self.name.clone());
+        try!(manager.add_service(service));
+        let getter = Channel {
+            id: Groups::getter_id(&self.name),
+            service: service_id.clone(),
+            adapter: Groups::id(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Getter {
+                kind: self.kind.clone(),
+                updated: None
+            },
+        };
+        let setter = Channel {
+            id: Groups::setter_id(&self.name),
+            service: service_id.clone(),
+            adapter: Groups::id(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Setter {
+                kind: self.kind.clone(),
+                updated: None
+            },
+        };
+        let result = manager.add_getter(getter).and_then(|_| manager.add_setter(setter));
+        if result.is_err() {
+            let _ignored = manager.remove_service(&service_id);
+        }
+        result
+    }
+
+    /// Unregister the service of the group. The members are not affected.
+    fn remove_channels(&self, manager: &AdapterManager) -> Result<(), Error> {
+        manager.remove_service(&Groups::service_id(&self.name))
+    }
+}
+
+/// The adapter exposing groups.
+pub struct Groups {
+    aggregator: Aggregator<Group>,
+}
+
+impl Groups {
+    pub fn id() -> Id<AdapterId> {
+        Id::new("groups@link.mozilla.org")
+    }
+
+    pub fn service_id(name: &str) -> Id<ServiceId> {
+        Id::new(&format!("service:{}@groups.link.mozilla.org", name))
+    }
+
+    pub fn getter_id(name: &str) -> Id<Getter> {
+        Id::new(&format!("getter:{}@groups.link.mozilla.org", name))
+    }
+
+    pub fn setter_id(name: &str) -> Id<Setter> {
+        Id::new(&format!("setter:{}@groups.link.mozilla.org", name))
+    }
+
+    /// Open or create the database at `path`, then register the adapter and the groups
+    /// with `manager`.
+    pub fn init(manager: &Arc<AdapterManager>, path: &PathBuf) -> Result<Arc<Self>, GroupError> {
+        debug!("Opening groups database at {}", path.display());
+        let (aggregator, definitions) =
+            try!(Aggregator::open::<GroupError>(Self::id(), manager, path, "groups", "name"));
+        let adapter = Arc::new(Groups {
+            aggregator: aggregator,
+        });
+        try!(manager.add_adapter(adapter.clone()));
+        for (group, source) in definitions {
+            try!(adapter.aggregator.register(manager, group, source));
+        }
+        Ok(adapter)
+    }
+
+    /// Add a group, replacing any group with the same name. Return the name of the
+    /// group.
+    ///
+    /// If the new definition cannot be registered or stored, the previous definition, if
+    /// any, is restored.
+    pub fn put_group(&self, source: &str) -> Result<String, GroupError> {
+        self.aggregator.put(source).map(|group: Group| group.name)
+    }
+
+    /// Remove a group. The members are not affected.
+    pub fn remove_group(&self, name: &str) -> Result<(), GroupError> {
+        match self.aggregator.remove(name) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(GroupError::NoSuchGroup(name.to_owned())),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Get the definitions of all the groups, as JSON.
+    pub fn get_groups(&self) -> HashMap<String, JSON> {
+        self.aggregator.get_sources()
+    }
+
+    /// Send a value to the setters of all the members of a group.
+    fn send_to_members(&self, manager: &AdapterManager, name: &str, value: Value, user: User) -> Result<(), Error> {
+        let selector = match self.aggregator.get(name) {
+            None => return Err(Error::InternalError(InternalError::NoSuchSetter(Self::setter_id(name)))),
+            Some(group) => group.setters
+        };
+        // Groups are never members of other groups.
+        let members : Vec<_> = manager.get_setter_channels(vec![selector]).into_iter()
+            .filter(|setter| setter.adapter != Self::id())
+            .map(|setter| SetterSelector::new().with_id(setter.id))
+            .collect();
+        if members.is_empty() {
+            return Ok(());
+        }
+        let results = manager.send_values(vec![Targetted::new(members, value)], user);
+        let failures = results.values().filter(|result| result.is_err()).count();
+        if failures == 0 {
+            Ok(())
+        } else {
+            Err(Error::InternalError(InternalError::GenericError(
+                format!("Group {}: could not send to {} of {} members", name, failures, results.len()))))
+        }
+    }
+}
+
+impl Adapter for Groups {
+    fn id(&self) -> Id<AdapterId> {
+        Self::id()
+    }
+
+    fn name(&self) -> &str {
+        ADAPTER_NAME
+    }
+
+    fn vendor(&self) -> &str {
+        ADAPTER_VENDOR
+    }
+
+    fn version(&self) -> &[u32;4] {
+        &ADAPTER_VERSION
+    }
+
+    fn fetch_values(&self, set: Vec<Id<Getter>>, user: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        self.aggregator.fetch_values(set, user)
+    }
+
+    fn send_values(&self, mut values: HashMap<Id<Setter>, Value>, user: User) -> ResultMap<Id<Setter>, (), Error> {
+        let manager = match self.aggregator.manager() {
+            Ok(manager) => manager,
+            Err(err) => return values.drain().map(|(id, _)| (id, Err(err.clone()))).collect()
+        };
+        let names : Vec<_> = self.aggregator.definitions().into_iter().map(|group| group.name).collect();
+        values.drain().map(|(id, value)| {
+            let result = match names.iter().find(|name| Self::setter_id(name) == id) {
+                Some(name) => self.send_to_members(&manager, name, value, user.clone()),
+                None => Err(Error::InternalError(InternalError::NoSuchSetter(id.clone())))
+            };
+            (id, result)
+        }).collect()
+    }
+
+    fn register_watch(&self, watch: Vec<WatchTarget>) -> WatchResult {
+        self.aggregator.register_watch(watch)
+    }
+
+    fn register_watch_for(&self, watch: Vec<WatchTarget>, user: User) -> WatchResult {
+        self.aggregator.register_watch_for(watch, user)
+    }
+
+    /// Stop watching the getters of the members.
+    fn stop(&self) {
+        self.aggregator.stop()
+    }
+}
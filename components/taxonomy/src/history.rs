@@ -0,0 +1,338 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+///! This is the database that holds the history of values seen on getters.
+///! Values are recorded whenever they are fetched or reported by an adapter on a
+///! watched getter, and are pruned according to a `RetentionPolicy`.
+
+use api::{ Error as APIError, InternalError };
+use parse::*;
+use selector::Period;
+use services::Getter;
+use util::Id;
+use values::{ Duration, TimeStamp, Value };
+
+use chrono::{ DateTime, Duration as ChronoDuration, Timelike, TimeZone, UTC };
+use rusqlite::{ Connection, Error as SqliteError, Result };
+use serde_json;
+
+use std::path::PathBuf;
+
+/// A single value recorded in the history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// The instant at which the value was seen.
+    pub timestamp: TimeStamp,
+
+    /// The value itself.
+    pub value: Value,
+}
+
+impl ToJSON for HistoryEntry {
+    fn to_json(&self) -> JSON {
+        vec![
+            ("timestamp", self.timestamp.to_json()),
+            ("value", self.value.to_json()),
+        ].to_json()
+    }
+}
+
+/// Downsampling of old values: once values are older than `after`, we only keep
+/// the first value of each getter for every `interval`.
+#[derive(Debug, Clone)]
+pub struct Downsampling {
+    pub after: Duration,
+    pub interval: Duration,
+}
+
+/// Determines how long values are kept in the history.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    /// Values older than this are removed. If `None`, values are kept forever.
+    pub max_age: Option<Duration>,
+
+    /// If specified, old values are thinned out.
+    pub downsampling: Option<Downsampling>,
+
+    /// How often to enforce the policy.
+    pub check_every: Duration,
+}
+
+impl Default for RetentionPolicy {
+    /// Keep a month of history, with full resolution for the last day and one value
+    /// every five minutes beyond that.
+    fn default() -> Self {
+        RetentionPolicy {
+            max_age: Some(Duration::from(ChronoDuration::days(30))),
+            downsampling: Some(Downsampling {
+                after: Duration::from(ChronoDuration::days(1)),
+                interval: Duration::from(ChronoDuration::minutes(5)),
+            }),
+            check_every: Duration::from(ChronoDuration::hours(1)),
+        }
+    }
+}
+
+fn to_millis(date: &DateTime<UTC>) -> i64 {
+    date.timestamp() * 1000 + (date.nanosecond() / 1_000_000) as i64
+}
+
+fn from_millis(millis: i64) -> DateTime<UTC> {
+    UTC.timestamp(millis / 1000, ((millis % 1000) * 1_000_000) as u32)
+}
+
+fn as_millis(duration: &Duration) -> i64 {
+    let duration : ChronoDuration = duration.clone().into();
+    duration.num_milliseconds()
+}
+
+/// A lighweight struct to manage the history database. As for `TagStorage`, the
+/// underlying database is created lazily when we need it.
+pub struct HistoryStorage {
+    db: Option<Connection>,
+    path: PathBuf,
+    policy: RetentionPolicy,
+
+    /// The last time we enforced the retention policy.
+    last_check: Option<DateTime<UTC>>,
+}
+
+impl HistoryStorage {
+    pub fn new(path: &PathBuf, policy: RetentionPolicy) -> Self {
+        HistoryStorage {
+            db: None,
+            path: path.clone(),
+            policy: policy,
+            last_check: None,
+        }
+    }
+
+    // Ensures that we have a database ready. If we fail to open or create the database,
+    // this will panic.
+    fn ensure_db(&mut self) {
+        if self.db.is_some() {
+            return;
+        }
+
+        debug!("Opening taxonomy history database at {}", self.path.display());
+        let db = Connection::open(self.path.clone()).unwrap_or_else(|err| {
+            panic!("Unable to open taxonomy history database: {}", err);
+        });
+
+        db.execute("CREATE TABLE IF NOT EXISTS history (
+                    getter     TEXT NOT NULL,
+                    timestamp  INTEGER NOT NULL,
+                    value      TEXT NOT NULL
+            )", &[]).unwrap_or_else(|err| {
+                panic!("Unable to create taxonomy history database: {}", err);
+            });
+        db.execute("CREATE INDEX IF NOT EXISTS history_getter_timestamp
+                    ON history (getter, timestamp)", &[]).unwrap_or_else(|err| {
+                panic!("Unable to create taxonomy history index: {}", err);
+            });
+
+        self.db = Some(db);
+    }
+
+    /// Record a value seen on a getter at a given instant.
+    ///
+    /// Binary values (e.g. camera snapshots) are not recorded.
+    pub fn record(&mut self, id: &Id<Getter>, value: &Value, timestamp: DateTime<UTC>) -> Result<()> {
+        if let Value::Binary(_) = *value {
+            return Ok(());
+        }
+        let serialized = match serde_json::to_string(value) {
+            Ok(serialized) => serialized,
+            Err(err) => {
+                error!("Could not serialize value {:?} for history: {}", value, err);
+                return Ok(());
+            }
+        };
+        self.ensure_db();
+        try!(self.db.as_ref().unwrap().execute("INSERT INTO history VALUES ($1, $2, $3)",
+                        &[&id.to_string(), &to_millis(&timestamp), &serialized]));
+
+        let should_check = match self.last_check {
+            None => true,
+            Some(ref last_check) => {
+                let check_every : ChronoDuration = self.policy.check_every.clone().into();
+                timestamp - *last_check >= check_every
+            }
+        };
+        if should_check {
+            try!(self.apply_policy(timestamp));
+        }
+        Ok(())
+    }
+
+    /// Remove or thin out old values, as specified by the retention policy.
+    pub fn apply_policy(&mut self, now: DateTime<UTC>) -> Result<()> {
+        self.ensure_db();
+        self.last_check = Some(now);
+        let now = to_millis(&now);
+        let db = self.db.as_ref().unwrap();
+        if let Some(ref max_age) = self.policy.max_age {
+            try!(db.execute("DELETE FROM history WHERE timestamp < $1",
+                            &[&(now - as_millis(max_age))]));
+        }
+        if let Some(ref downsampling) = self.policy.downsampling {
+            let interval = as_millis(&downsampling.interval);
+            if interval > 0 {
+                try!(db.execute("DELETE FROM history WHERE timestamp < $1 AND rowid NOT IN (
+                                    SELECT MIN(rowid) FROM history WHERE timestamp < $1
+                                    GROUP BY getter, timestamp / $2
+                                )",
+                                &[&(now - as_millis(&downsampling.after)), &interval]));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the values recorded for a getter, oldest first.
+    ///
+    /// A value is returned if its age (i.e. the time elapsed between the moment it was seen
+    /// and `now`) matches `period`.
+    pub fn get_history_for(&mut self, id: &Id<Getter>, period: &Period, now: DateTime<UTC>) -> Result<Vec<HistoryEntry>> {
+        self.ensure_db();
+        let now = to_millis(&now);
+        let since = match period.max {
+            None => i64::min_value(),
+            Some(ref max) => now - as_millis(max)
+        };
+        let until = match period.min {
+            None => i64::max_value(),
+            Some(ref min) => now - as_millis(min)
+        };
+
+        let mut entries = Vec::new();
+        let mut stmt = try!(self.db.as_ref().unwrap().prepare(
+            "SELECT timestamp, value FROM history
+             WHERE getter=$1 AND timestamp >= $2 AND timestamp <= $3
+             ORDER BY timestamp, rowid"));
+        let rows = try!(stmt.query(&[&id.to_string(), &since, &until]));
+        for result_row in rows {
+            let row = try!(result_row);
+            let timestamp: i64 = row.get(0);
+            let serialized: String = row.get(1);
+            let value = match serde_json::from_str(&serialized) {
+                Ok(value) => value,
+                Err(err) => {
+                    // Probably a value recorded by an older version, skip it.
+                    warn!("Could not deserialize history value {}: {}", serialized, err);
+                    continue;
+                }
+            };
+            entries.push(HistoryEntry {
+                timestamp: TimeStamp::from_datetime(from_millis(timestamp)),
+                value: value
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Forget all the values recorded for a getter.
+    pub fn remove_history_for(&mut self, id: &Id<Getter>) -> Result<()> {
+        self.ensure_db();
+        try!(self.db.as_ref().unwrap().execute("DELETE FROM history WHERE getter=$1", &[&id.to_string()]));
+        Ok(())
+    }
+}
+
+/// Convert a database error into something that can be returned by the API.
+pub fn as_api_error(err: SqliteError) -> APIError {
+    APIError::InternalError(InternalError::GenericError(format!("{}", err)))
+}
+
+#[cfg(test)]
+fn get_db_environment() -> PathBuf {
+    use libc::getpid;
+    use std::thread;
+    let tid = format!("{:?}", thread::current()).replace("(", "+").replace(")", "+");
+    let s = format!("./history_db_test-{}-{}.sqlite", unsafe { getpid() }, tid.replace("/", "42"));
+    PathBuf::from(s)
+}
+
+#[test]
+#[allow(unused_variables)]
+fn history_test() {
+    use values::OnOff;
+
+    // Simple RAII style struct to delete the test db.
+    struct AutoDeleteDb { };
+    impl Drop for AutoDeleteDb {
+        fn drop(&mut self) {
+            use std::fs;
+            let dbfile = get_db_environment();
+            if let Err(e) = fs::remove_file(dbfile.clone()) {
+                panic!("Error {} cleaning up {}", e, dbfile.display());
+            }
+        }
+    }
+    let auto_db = AutoDeleteDb { };
+
+    let policy = RetentionPolicy {
+        max_age: Some(Duration::from(ChronoDuration::hours(10))),
+        downsampling: Some(Downsampling {
+            after: Duration::from(ChronoDuration::hours(1)),
+            interval: Duration::from(ChronoDuration::minutes(30)),
+        }),
+        // Only enforce the policy manually.
+        check_every: Duration::from(ChronoDuration::days(365)),
+    };
+    let mut store = HistoryStorage::new(&get_db_environment(), policy);
+
+    let id1 = Id::<Getter>::new("getter 1");
+    let id2 = Id::<Getter>::new("getter 2");
+    // Align on 30 minutes, to simplify downsampling arithmetics.
+    let start = UTC.timestamp(1_800_000, 0);
+    let minutes = |n| start + ChronoDuration::minutes(n);
+
+    // Start with an empty db.
+    assert_eq!(store.get_history_for(&id1, &Period::default(), start).unwrap().len(), 0);
+
+    // Record one value per minute for 12 hours on getter 1, and a single value on getter 2.
+    for i in 0..12 * 60 {
+        let value = if i % 2 == 0 { OnOff::On } else { OnOff::Off };
+        store.record(&id1, &Value::OnOff(value), minutes(i)).unwrap();
+    }
+    store.record(&id2, &Value::OnOff(OnOff::On), minutes(0)).unwrap();
+
+    // Binary values are not recorded.
+    store.record(&id2, &Value::Binary(::values::Binary {
+        data: ::std::sync::Arc::new(vec![1, 2, 3]),
+        mimetype: Id::new("image/png")
+    }), minutes(1)).unwrap();
+
+    let now = minutes(12 * 60);
+    let all = store.get_history_for(&id1, &Period::default(), now).unwrap();
+    assert_eq!(all.len(), 12 * 60);
+    assert_eq!(all[0], HistoryEntry {
+        timestamp: TimeStamp::from_datetime(minutes(0)),
+        value: Value::OnOff(OnOff::On)
+    });
+    assert_eq!(store.get_history_for(&id2, &Period::default(), now).unwrap().len(), 1);
+
+    // Fetch the last 10 minutes.
+    let last_minutes = Period {
+        min: None,
+        max: Some(Duration::from(ChronoDuration::minutes(10)))
+    };
+    let recent = store.get_history_for(&id1, &last_minutes, now).unwrap();
+    assert_eq!(recent.len(), 10);
+    assert_eq!(recent[9].timestamp, TimeStamp::from_datetime(minutes(12 * 60 - 1)));
+
+    // Enforcing the policy removes values older than 10h and keeps one value per 30 minutes
+    // beyond the first hour.
+    store.apply_policy(now).unwrap();
+    let all = store.get_history_for(&id1, &Period::default(), now).unwrap();
+    assert_eq!(all.len(), 60 + 9 * 2);
+    assert_eq!(all[0].timestamp, TimeStamp::from_datetime(minutes(2 * 60)));
+    assert_eq!(store.get_history_for(&id2, &Period::default(), now).unwrap().len(), 0);
+
+    // Removing the history of a getter doesn't affect other getters.
+    store.record(&id2, &Value::OnOff(OnOff::On), now).unwrap();
+    store.remove_history_for(&id1).unwrap();
+    assert_eq!(store.get_history_for(&id1, &Period::default(), now).unwrap().len(), 0);
+    assert_eq!(store.get_history_for(&id2, &Period::default(), now).unwrap().len(), 1);
+}
@@ -37,6 +37,9 @@ pub mod values;
 /// Various utilities
 pub mod util;
 
+/// Sources of time, replaced by a fake clock in tests.
+pub mod clock;
+
 /// The back-end thread, in charge of the heavy lifting of managing adapters.
 mod backend;
 
@@ -57,6 +60,21 @@ pub mod transact;
 /// Implementation of the database storing tags.
 pub mod tag_storage;
 
+/// Implementation of the database storing the history of values.
+pub mod history;
+
+/// Implementation of the database storing access control lists.
+pub mod acl;
+
+/// The core shared by the adapters exposing getters computed from the values of other getters.
+mod aggregate;
+
+/// Implementation of an adapter exposing getters computed from the values of other getters.
+pub mod virtual_channels;
+
+/// Implementation of an adapter exposing groups of channels as services.
+pub mod groups;
+
 /// Implementation of a fake adapter, controlled entirely programmatically. Designed to be used
 /// as a component of tests.
 pub mod fake_adapter;
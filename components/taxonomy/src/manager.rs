@@ -5,20 +5,26 @@
 //! - it exposes an implementation of the taxonomy API.
 
 pub use adapter::*;
+use acl::Acl;
 use api;
-use api::{ API, Error, TargetMap, User };
+use api::{ API, AdapterHealth, AdapterStatus, Error, FetchedValue, FetchOptions, InternalError, SendOutcome, ServiceHealth,
+           TargetMap, User };
 use backend::*;
+use clock::Clock;
+use history::{ as_api_error, HistoryEntry, HistoryStorage };
 use selector::*;
 use services::*;
 use util::is_sync;
-use values::{ Range, TypeError, Value };
+use values::{ Range, TimeStamp, TypeError, Value };
 
-use std::collections::HashMap;
+use std::collections::{ HashMap, HashSet };
 use std::path::PathBuf;
-use std::sync::{ Arc, Mutex, Weak };
+use std::sync::{ Arc, Condvar, Mutex, Weak };
 use std::sync::atomic::{ AtomicBool, Ordering };
 use std::thread;
+use std::time::{ Duration, Instant };
 
+use chrono::UTC;
 use sublock::atomlock::*;
 use transformable_channels::mpsc::*;
 
@@ -36,22 +42,297 @@ pub struct AdapterManager {
     back_end: Arc<MainLock<State>>,
 
     tx_watch: Arc<Mutex<RawSender<WatchOp>>>,
+
+    /// The database of values seen on getters, if any.
+    ///
+    /// Recording takes place outside of the `MainLock`, as it happens for every
+    /// single value. Values reported on watched getters are recorded by the
+    /// backend, through `recorder()`, as are the values of `cache`.
+    history: Option<Arc<Mutex<HistoryStorage>>>,
+
+    /// The access control lists, if any. Without access control lists, all users
+    /// may access all channels.
+    acl: Option<Arc<Acl>>,
+
+    /// The delay after which `fetch_values` and `send_values` stop waiting for an
+    /// adapter.
+    timeout: Duration,
+
+    /// The number of calls currently running on each adapter, including calls that
+    /// have timed out but not returned yet.
+    in_flight: Arc<Mutex<HashMap<Id<AdapterId>, usize>>>,
+
+    /// The latest value seen on each getter, for `fetch_values_with_options`
+    /// and `last_seen`.
+    cache: Arc<ValueCache>,
+
+    /// The listener notified whenever an adapter is added or reports a new status, if any.
+    on_health: Option<Mutex<Box<ExtSender<AdapterHealth>>>>,
 }
 
+/// The default delay after which `fetch_values` and `send_values` stop waiting for
+/// an adapter.
+pub const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+
+/// The maximal number of calls running at once on a single adapter. Once an adapter
+/// reaches it, typically because it hangs, further calls fail immediately with
+/// `Error::Timeout` instead of piling up threads.
+pub const MAX_CALLS_PER_ADAPTER: usize = 8;
+
 impl AdapterManager {
-    /// Create an empty `AdapterManager`.
+    /// Create an empty `AdapterManager`, without history.
     /// This function does not attempt to load any state from the disk.
     pub fn new(db_path: Option<PathBuf>) -> Self {
+        Self::with_history(db_path, None)
+    }
+
+    /// Create an empty `AdapterManager`, recording the values seen on getters
+    /// to `history`.
+    /// This function does not attempt to load any state from the disk.
+    pub fn with_history(db_path: Option<PathBuf>, history: Option<HistoryStorage>) -> Self {
         // The code should build only if AdapterManager implements Sync.
         is_sync::<AdapterManager>();
 
-        let state = Arc::new(MainLock::new(|liveness| State::new(liveness, db_path)));
+        let history = history.map(|history| Arc::new(Mutex::new(history)));
+        let cache = Arc::new(ValueCache::new());
+        let recorder = Self::recorder(history.clone(), cache.clone());
+        let state = Arc::new(MainLock::new(|liveness| State::new(liveness, db_path, Some(recorder))));
         let tx_watch = Arc::new(Mutex::new(Self::handle_watches(Arc::downgrade(&state))));
         AdapterManager {
             back_end: state,
             tx_watch: tx_watch,
+            history: history,
+            acl: None,
+            timeout: Duration::from_millis(DEFAULT_TIMEOUT_MS),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            cache: cache,
+            on_health: None,
+        }
+    }
+
+    /// Enforce access control lists on `fetch_values` and `send_values`.
+    pub fn with_acl(self, acl: Acl) -> Self {
+        AdapterManager {
+            acl: Some(Arc::new(acl)),
+            .. self
+        }
+    }
+
+    /// Stop waiting for adapters after `timeout` in `fetch_values` and `send_values`.
+    /// Channels of adapters that did not respond in time produce `Error::Timeout`.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        AdapterManager {
+            timeout: timeout,
+            .. self
+        }
+    }
+
+    /// Deliver the values held back by the `WatchOptions` of watches according to `clock`,
+    /// e.g. a `FakeClock` in tests.
+    pub fn with_clock(self, clock: Arc<Clock>) -> Self {
+        self.back_end.write().unwrap().set_clock(clock);
+        self
+    }
+
+    /// Notify `on_health` whenever an adapter is added or reports a new status.
+    pub fn with_health_listener(self, on_health: Box<ExtSender<AdapterHealth>>) -> Self {
+        AdapterManager {
+            on_health: Some(Mutex::new(on_health)),
+            .. self
         }
     }
+
+    /// The delay after which `fetch_values` and `send_values` stop waiting for adapters,
+    /// e.g. for adapters that forward requests and need not wait any longer.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The access control lists, if any, e.g. to let administrators manage grants.
+    pub fn acl(&self) -> Option<&Arc<Acl>> {
+        self.acl.as_ref()
+    }
+
+    /// Determine whether `user` may fetch values from `channel`.
+    fn may_read(&self, user: &User, service_tags: &HashSet<Id<TagId>>, channel: &Channel<Getter>) -> bool {
+        match self.acl {
+            None => true,
+            Some(ref acl) => acl.may_read(user, service_tags, channel)
+        }
+    }
+
+    /// Determine whether `user` may send values to `channel`.
+    fn may_write(&self, user: &User, service_tags: &HashSet<Id<TagId>>, channel: &Channel<Setter>) -> bool {
+        match self.acl {
+            None => true,
+            Some(ref acl) => acl.may_write(user, service_tags, channel)
+        }
+    }
+
+    /// The getters matching `selectors` that `user` is not allowed to read.
+    pub fn unreadable_getters(&self, selectors: Vec<GetterSelector>, user: &User) -> Vec<Id<Getter>> {
+        let acl = match self.acl {
+            None => return vec![],
+            Some(ref acl) => acl
+        };
+        let (_, denied) = self.back_end.read().unwrap().prepare_fetch_values(selectors, |service_tags, channel| {
+            acl.may_read(user, service_tags, channel)
+        });
+        denied
+    }
+
+    /// Call each adapter of `request` on its own thread, collecting the results received
+    /// before the timeout.
+    ///
+    /// Adapters that do not respond in time are absent from the result. Their calls keep
+    /// running in the background and their results are discarded. Adapters that already
+    /// run `MAX_CALLS_PER_ADAPTER` calls are not called at all, and are also absent from
+    /// the result.
+    fn dispatch<T, R, F>(&self, request: AdapterRequest<T>, call: F) -> HashMap<Id<AdapterId>, R>
+        where T: Send + 'static, R: Send + 'static, F: Fn(&Arc<Adapter>, T) -> R + Send + Sync + 'static
+    {
+        self.start_dispatch(request, call).wait_until(Instant::now() + self.timeout)
+    }
+
+    /// Call each adapter of `request` on its own thread, without waiting for the results.
+    ///
+    /// Adapters that already run `MAX_CALLS_PER_ADAPTER` calls are not called at all.
+    fn start_dispatch<T, R, F>(&self, request: AdapterRequest<T>, call: F) -> Dispatched<R>
+        where T: Send + 'static, R: Send + 'static, F: Fn(&Arc<Adapter>, T) -> R + Send + Sync + 'static
+    {
+        let mut pending = HashSet::new();
+        let received = Arc::new((Mutex::new(HashMap::new()), Condvar::new()));
+        let call = Arc::new(call);
+        for (id, (adapter, payload)) in request {
+            {
+                let mut in_flight = self.in_flight.lock().unwrap();
+                let count = in_flight.entry(id.clone()).or_insert(0);
+                if *count >= MAX_CALLS_PER_ADAPTER {
+                    warn!(target: "Taxonomy-manager", "Adapter {} is not responding, not calling it.", id);
+                    continue;
+                }
+                *count += 1;
+            }
+            pending.insert(id.clone());
+            let received = received.clone();
+            let call = call.clone();
+            let in_flight = self.in_flight.clone();
+            thread::spawn(move || {
+                let result = call(&adapter, payload);
+                {
+                    let mut in_flight = in_flight.lock().unwrap();
+                    let is_idle = match in_flight.get_mut(&id) {
+                        Some(count) => {
+                            *count -= 1;
+                            *count == 0
+                        }
+                        None => false
+                    };
+                    if is_idle {
+                        in_flight.remove(&id);
+                    }
+                }
+                let (ref results, ref wakeup) = *received;
+                results.lock().unwrap().insert(id, result);
+                wakeup.notify_one();
+            });
+        }
+        Dispatched {
+            pending: pending,
+            received: received,
+        }
+    }
+
+    /// Notify the health listener, if we have one.
+    fn notify_health(&self, health: Option<AdapterHealth>) {
+        if let (Some(on_health), Some(health)) = (self.on_health.as_ref(), health) {
+            let _ = on_health.lock().unwrap().send(health);
+        }
+    }
+
+    /// Fill the `last_seen` of a getter from the latest value received.
+    fn with_last_seen(&self, mut channel: Channel<Getter>) -> Channel<Getter> {
+        if let Some(timestamp) = self.cache.last_seen(&channel.id) {
+            channel.last_seen = Some(timestamp);
+        }
+        channel
+    }
+
+    /// Fill the `last_seen` of the getters of a service.
+    fn service_with_last_seen(&self, mut service: Service) -> Service {
+        service.getters = service.getters.drain()
+            .map(|(id, channel)| (id, self.with_last_seen(channel)))
+            .collect();
+        service
+    }
+
+    /// Record a value in the history, if we have one.
+    fn record_history(history: &Option<Arc<Mutex<HistoryStorage>>>, id: &Id<Getter>, value: &Value) {
+        if let Some(ref history) = *history {
+            if let Err(err) = history.lock().unwrap().record(id, value, UTC::now()) {
+                error!("History record error: {}", err);
+            }
+        }
+    }
+
+    /// Record the values reported by adapters on watched getters, in the cache and in
+    /// the history.
+    ///
+    /// The backend calls it once per value, before any per-watcher filtering.
+    fn recorder(history: Option<Arc<Mutex<HistoryStorage>>>, cache: Arc<ValueCache>) -> Recorder {
+        Arc::new(move |id: &Id<Getter>, value: &Value| {
+            cache.record(id, value, &TimeStamp::from_datetime(UTC::now()));
+            Self::record_history(&history, id, value)
+        })
+    }
+}
+
+/// The setters of a request, without their values.
+fn setters_of(request: &AdapterRequest<HashMap<Id<Setter>, Value>>) -> AdapterRequest<Vec<Id<Setter>>> {
+    request.iter()
+        .map(|(adapter_id, &(ref adapter, ref values))| {
+            (adapter_id.clone(), (adapter.clone(), values.keys().cloned().collect()))
+        })
+        .collect()
+}
+
+/// The result for setter `id` in the response `got` of an adapter to `dispatch`. `got`
+/// is `None` if the adapter did not respond in time.
+fn take_result<T>(got: &mut Option<ResultMap<Id<Setter>, T, Error>>, adapter_id: &Id<AdapterId>, id: &Id<Setter>) -> Result<T, Error> {
+    match *got {
+        None => Err(Error::Timeout(adapter_id.clone())),
+        Some(ref mut got) => got.remove(id)
+            .unwrap_or_else(|| Err(Error::InternalError(InternalError::NoSuchSetter(id.clone()))))
+    }
+}
+
+/// Calls started by `start_dispatch`, whose results may not all have arrived yet.
+struct Dispatched<R> {
+    /// The adapters called, whose results have not been taken yet.
+    pending: HashSet<Id<AdapterId>>,
+
+    /// The results that have arrived and have not been taken yet.
+    received: Arc<(Mutex<HashMap<Id<AdapterId>, R>>, Condvar)>,
+}
+
+impl<R> Dispatched<R> {
+    /// Wait until all the pending results have arrived or `deadline` has passed, then
+    /// take the results that have arrived.
+    fn wait_until(&mut self, deadline: Instant) -> HashMap<Id<AdapterId>, R> {
+        let (ref results, ref wakeup) = *self.received;
+        let mut results = results.lock().unwrap();
+        while results.len() < self.pending.len() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            results = wakeup.wait_timeout(results, deadline - now).unwrap().0;
+        }
+        for id in results.keys() {
+            self.pending.remove(id);
+        }
+        results.drain().collect()
+    }
 }
 
 impl Default for AdapterManager {
@@ -67,10 +348,19 @@ impl AdapterManagerHandle for AdapterManager {
     ///
     /// Returns an error if an adapter with the same id is already present.
     fn add_adapter(&self, adapter: Arc<Adapter>) -> Result<(), Error> {
-        self.back_end.write().unwrap().add_adapter(adapter)
+        let id = adapter.id();
+        let health = {
+            // Acquire and release write lock.
+            let mut back_end = self.back_end.write().unwrap();
+            try!(back_end.add_adapter(adapter));
+            back_end.get_adapter_health(&id)
+        };
+        self.notify_health(health);
+        Ok(())
     }
 
-    /// Remove an adapter from the system, including all its services and channels.
+    /// Remove an adapter from the system, including all its services and channels,
+    /// then stop it.
     ///
     /// # Errors
     ///
@@ -78,7 +368,10 @@ impl AdapterManagerHandle for AdapterManager {
     /// to cleanup as much as possible, even if for some reason the system is in an
     /// inconsistent state.
     fn remove_adapter(&self, id: &Id<AdapterId>) -> Result<(), Error> {
-        self.back_end.write().unwrap().remove_adapter(id)
+        let adapter = try!(self.back_end.write().unwrap().remove_adapter(id));
+        // Stop the adapter once the lock is released, as it may still be calling the manager.
+        adapter.stop();
+        Ok(())
     }
 
     /// Add a service to the system. Called by the adapter when a new
@@ -148,6 +441,7 @@ impl AdapterManagerHandle for AdapterManager {
     /// is not registered. In either case, it attemps to clean as much as possible, even
     /// if the state is inconsistent.
     fn remove_getter(&self, id: &Id<Getter>) -> Result<(), Error> {
+        self.cache.remove(id);
         self.back_end.write().unwrap().remove_getter(id)
     }
 
@@ -179,6 +473,17 @@ impl AdapterManagerHandle for AdapterManager {
     fn remove_setter(&self, id: &Id<Setter>) -> Result<(), Error> {
         self.back_end.write().unwrap().remove_setter(id)
     }
+
+    /// Report the status of an adapter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no adapter with this identifier exists.
+    fn set_adapter_status(&self, id: &Id<AdapterId>, status: AdapterStatus) -> Result<(), Error> {
+        let health = try!(self.back_end.write().unwrap().set_adapter_status(id, status));
+        self.notify_health(health);
+        Ok(())
+    }
 }
 
 /// A handle to the public API.
@@ -189,7 +494,44 @@ impl API for AdapterManager {
     /// the metadata on all services matching _either_ `req1` or `req2`
     /// or ...
     fn get_services(&self, selectors: Vec<ServiceSelector>) -> Vec<Service> {
-        self.back_end.read().unwrap().get_services(selectors)
+        let services = self.back_end.read().unwrap().get_services(selectors);
+        services.into_iter()
+            .map(|service| self.service_with_last_seen(service))
+            .collect()
+    }
+
+    /// Get the health of services matching some conditions.
+    fn get_services_health(&self, selectors: Vec<ServiceSelector>) -> Vec<ServiceHealth> {
+        let (services, adapters) = {
+            // Make sure that the lock is released asap.
+            let back_end = self.back_end.read().unwrap();
+            (back_end.get_services(selectors), back_end.get_adapters_health())
+        };
+        let statuses : HashMap<_, _> = adapters.into_iter()
+            .map(|health| (health.id, health.status))
+            .collect();
+        services.into_iter()
+            .filter_map(|service| {
+                let status = match statuses.get(&service.adapter) {
+                    None => return None, // Race condition between removing the adapter and reading its services.
+                    Some(status) => status.clone()
+                };
+                let last_seen = service.getters.keys()
+                    .filter_map(|id| self.cache.last_seen(id))
+                    .max();
+                Some(ServiceHealth {
+                    id: service.id,
+                    adapter: service.adapter,
+                    status: status,
+                    last_seen: last_seen
+                })
+            })
+            .collect()
+    }
+
+    /// Get the health of all adapters.
+    fn get_adapters_health(&self) -> Vec<AdapterHealth> {
+        self.back_end.read().unwrap().get_adapters_health()
     }
 
     /// Label a set of services with a set of tags.
@@ -205,8 +547,13 @@ impl API for AdapterManager {
     ///
     /// Note that this call is _not live_. In other words, if services
     /// are added after the call, they will not be affected.
-    fn add_service_tags(&self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>) -> usize {
-        self.back_end.write().unwrap().add_service_tags(selectors, tags)
+    ///
+    /// Services with a channel that `user` is not allowed to access are left untouched
+    /// and are not counted.
+    fn add_service_tags(&self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>, user: User) -> usize {
+        self.back_end.write().unwrap().add_service_tags(selectors, tags,
+            |service_tags, channel| self.may_read(&user, service_tags, channel),
+            |service_tags, channel| self.may_write(&user, service_tags, channel))
         // FIXME: This can cause watcher registrations
     }
 
@@ -223,13 +570,21 @@ impl API for AdapterManager {
     ///
     /// Note that this call is _not live_. In okther words, if services
     /// are added after the call, they will not be affected.
-    fn remove_service_tags(&self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>) -> usize {
-        self.back_end.write().unwrap().remove_service_tags(selectors, tags)
+    ///
+    /// Services with a channel that `user` is not allowed to access are left untouched
+    /// and are not counted.
+    fn remove_service_tags(&self, selectors: Vec<ServiceSelector>, tags: Vec<Id<TagId>>, user: User) -> usize {
+        self.back_end.write().unwrap().remove_service_tags(selectors, tags,
+            |service_tags, channel| self.may_read(&user, service_tags, channel),
+            |service_tags, channel| self.may_write(&user, service_tags, channel))
     }
 
     /// Get a list of channels matching some conditions
     fn get_getter_channels(&self, selectors: Vec<GetterSelector>) -> Vec<Channel<Getter>> {
-        self.back_end.read().unwrap().get_getter_channels(selectors)
+        let channels = self.back_end.read().unwrap().get_getter_channels(selectors);
+        channels.into_iter()
+            .map(|channel| self.with_last_seen(channel))
+            .collect()
     }
     fn get_setter_channels(&self, selectors: Vec<SetterSelector>) -> Vec<Channel<Setter>> {
         self.back_end.read().unwrap().get_setter_channels(selectors)
@@ -248,10 +603,14 @@ impl API for AdapterManager {
     ///
     /// Note that this call is _not live_. In other words, if channels
     /// are added after the call, they will not be affected.
-    fn add_getter_tags(&self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>) -> usize {
+    ///
+    /// Channels that `user` is not allowed to access are left untouched and are
+    /// not counted.
+    fn add_getter_tags(&self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>, user: User) -> usize {
         let (request, result) = {
             // Acquire and release the write lock.
-            self.back_end.write().unwrap().add_getter_tags(selectors, tags)
+            self.back_end.write().unwrap().add_getter_tags(selectors, tags,
+                |service_tags, channel| self.may_read(&user, service_tags, channel))
         };
         if !request.is_empty() {
             debug!(target: "Taxonomy-manager", "manager.add_getter_tags => need to register watches");
@@ -259,8 +618,9 @@ impl API for AdapterManager {
         self.register_watches(request);
         result
     }
-    fn add_setter_tags(&self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>) -> usize {
-        self.back_end.write().unwrap().add_setter_tags(selectors, tags)
+    fn add_setter_tags(&self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>, user: User) -> usize {
+        self.back_end.write().unwrap().add_setter_tags(selectors, tags,
+            |service_tags, channel| self.may_write(&user, service_tags, channel))
     }
 
     /// Remove a set of tags from a set of channels.
@@ -276,53 +636,153 @@ impl API for AdapterManager {
     ///
     /// Note that this call is _not live_. In other words, if channels
     /// are added after the call, they will not be affected.
-    fn remove_getter_tags(&self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>) -> usize {
-        self.back_end.write().unwrap().remove_getter_tags(selectors, tags)
+    ///
+    /// Channels that `user` is not allowed to access are left untouched and are
+    /// not counted.
+    fn remove_getter_tags(&self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>, user: User) -> usize {
+        self.back_end.write().unwrap().remove_getter_tags(selectors, tags,
+            |service_tags, channel| self.may_read(&user, service_tags, channel))
     }
-    fn remove_setter_tags(&self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>) -> usize {
-        self.back_end.write().unwrap().remove_setter_tags(selectors, tags)
+    fn remove_setter_tags(&self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>, user: User) -> usize {
+        self.back_end.write().unwrap().remove_setter_tags(selectors, tags,
+            |service_tags, channel| self.may_write(&user, service_tags, channel))
     }
 
-    /// Read the latest value from a set of channels
-    fn fetch_values(&self, selectors: Vec<GetterSelector>, user: User) ->
-        ResultMap<Id<Getter>, Option<Value>, Error>
+    /// Read the latest value from a set of channels, along with the instant at which
+    /// each value was received.
+    ///
+    /// Channels that `user` is not allowed to read produce `Error::PermissionDenied`.
+    fn fetch_values_with_options(&self, selectors: Vec<GetterSelector>, options: FetchOptions, user: User) ->
+        ResultMap<Id<Getter>, Option<FetchedValue>, Error>
     {
         // First, prepare the request.
-        let mut request;
-        {
+        let (mut request, denied) = {
             // Make sure that the lock is released asap.
-            request = self.back_end.read().unwrap().prepare_fetch_values(selectors);
-        }
-        // Now fetch the values
-        let mut results = HashMap::new();
-        for (_, (adapter, mut getters)) in request.drain() {
-            let (getters, mut types) : (Vec<_>, Vec<_>) = getters.drain().unzip();
-            let mut got = adapter
-                .fetch_values(getters, user.clone());
-
-            let checked = got.drain()
-                .zip(types.drain(..))
-                .map(|(result, typ)| {
-                    if let (id, Ok(Some(value))) = result {
-                        if value.get_type() == typ {
-                            (id, Ok(Some(value)))
-                        } else {
-                            (id, Err(Error::TypeError(TypeError {
-                                expected: typ,
+            let acl = &self.acl;
+            self.back_end.read().unwrap().prepare_fetch_values(selectors, |service_tags, channel| {
+                match *acl {
+                    None => true,
+                    Some(ref acl) => acl.may_read(&user, service_tags, channel)
+                }
+            })
+        };
+        let mut results : HashMap<_, _> = denied.into_iter()
+            .map(|id| (id, Err(Error::PermissionDenied)))
+            .collect();
+
+        // Use the values that are recent enough, if the caller accepts cached values.
+        let now = UTC::now();
+        let mut types = HashMap::new();
+        let request : AdapterRequest<_> = request.drain()
+            .filter_map(|(adapter_id, (adapter, mut getters))| {
+                if let Some(ref max_age) = options.max_age {
+                    let cached : Vec<_> = getters.iter()
+                        .filter_map(|(id, typ)| {
+                            match self.cache.get(id, max_age, &now) {
+                                Some((ref timestamp, ref value)) if value.get_type() == *typ =>
+                                    Some((id.clone(), FetchedValue {
+                                        value: value.clone(),
+                                        timestamp: timestamp.clone(),
+                                        adapter: adapter_id.clone()
+                                    })),
+                                _ => None
+                            }
+                        })
+                        .collect();
+                    for (id, fetched) in cached {
+                        getters.remove(&id);
+                        results.insert(id, Ok(Some(fetched)));
+                    }
+                }
+                if getters.is_empty() {
+                    return None;
+                }
+                let ids : Vec<_> = getters.keys().cloned().collect();
+                types.insert(adapter_id.clone(), getters);
+                Some((adapter_id, (adapter, ids)))
+            })
+            .collect();
+
+        // Now fetch the other values, from all adapters at once.
+        let mut got = self.dispatch(request, move |adapter, getters| {
+            adapter.fetch_values(getters, user.clone())
+        });
+        for (adapter_id, mut getters) in types.drain() {
+            let mut got = match got.remove(&adapter_id) {
+                None => {
+                    warn!("Adapter {} did not respond to fetch_values in time", adapter_id);
+                    results.extend(getters.drain().map(|(id, _)| (id, Err(Error::Timeout(adapter_id.clone())))));
+                    continue;
+                }
+                Some(got) => got
+            };
+            let timestamp = TimeStamp::from_datetime(UTC::now());
+            for (id, result) in got.drain() {
+                let result = match result {
+                    Ok(Some(value)) => match getters.remove(&id) {
+                        Some(ref typ) if *typ != value.get_type() =>
+                            Err(Error::TypeError(TypeError {
+                                expected: typ.clone(),
                                 got: value.get_type()
-                            })))
+                            })),
+                        _ => {
+                            self.cache.record(&id, &value, &timestamp);
+                            Self::record_history(&self.history, &id, &value);
+                            Ok(Some(FetchedValue {
+                                value: value,
+                                timestamp: timestamp.clone(),
+                                adapter: adapter_id.clone()
+                            }))
                         }
-                    } else {
-                        result
-                    }
-                });
+                    },
+                    Ok(None) => Ok(None),
+                    Err(err) => Err(err)
+                };
+                results.insert(id, result);
+            }
+        }
+        results
+    }
 
-            results.extend(checked);
+    /// Read the values previously seen on a set of channels
+    ///
+    /// Channels that `user` is not allowed to read produce `Error::PermissionDenied`.
+    fn fetch_history(&self, selectors: Vec<GetterSelector>, period: Period, user: User) ->
+        ResultMap<Id<Getter>, Vec<HistoryEntry>, Error>
+    {
+        let (allowed, denied) = {
+            // Make sure that the lock is released asap.
+            let acl = &self.acl;
+            self.back_end.read().unwrap().prepare_fetch_values(selectors, |service_tags, channel| {
+                match *acl {
+                    None => true,
+                    Some(ref acl) => acl.may_read(&user, service_tags, channel)
+                }
+            })
+        };
+        let mut results : HashMap<_, _> = denied.into_iter()
+            .map(|id| (id, Err(Error::PermissionDenied)))
+            .collect();
+        let now = UTC::now();
+        for (_, (_, getters)) in allowed {
+            for (id, _) in getters {
+                let result = match self.history {
+                    // Without history, nothing has been recorded.
+                    None => Ok(vec![]),
+                    Some(ref history) => history.lock()
+                        .unwrap()
+                        .get_history_for(&id, &period, now)
+                        .map_err(as_api_error)
+                };
+                results.insert(id, result);
+            }
         }
         results
     }
 
     /// Send a bunch of values to a set of channels
+    ///
+    /// Channels that `user` is not allowed to write to produce `Error::PermissionDenied`.
     fn send_values(&self, keyvalues: TargetMap<SetterSelector, Value>, user: User) ->
         ResultMap<Id<Setter>, (), Error>
     {
@@ -330,30 +790,273 @@ impl API for AdapterManager {
         let mut prepared;
         {
             // Make sure that the lock is released asap.
-            prepared = self.back_end.read().unwrap().prepare_send_values(keyvalues);
+            let acl = &self.acl;
+            prepared = self.back_end.read().unwrap().prepare_send_values(keyvalues, |service_tags, channel| {
+                match *acl {
+                    None => true,
+                    Some(ref acl) => acl.may_write(&user, service_tags, channel)
+                }
+            });
         }
 
-        // Dispatch to adapter
+        // Dispatch to adapters, all at once.
         let mut results = HashMap::new();
-        for (_, (adapter, (request, failures))) in prepared.drain() {
-            let got = adapter.send_values(request, user.clone());
-            results.extend(got);
-            results.extend(failures);
+        let mut ids = HashMap::new();
+        let request : AdapterRequest<_> = prepared.drain()
+            .map(|(adapter_id, (adapter, (request, failures)))| {
+                results.extend(failures);
+                ids.insert(adapter_id.clone(), request.keys().cloned().collect::<Vec<_>>());
+                (adapter_id, (adapter, request))
+            })
+            .collect();
+        let mut got = self.dispatch(request, move |adapter, request| {
+            adapter.send_values(request, user.clone())
+        });
+        for (adapter_id, mut ids) in ids.drain() {
+            match got.remove(&adapter_id) {
+                None => {
+                    warn!("Adapter {} did not respond to send_values in time", adapter_id);
+                    results.extend(ids.drain(..).map(|id| (id, Err(Error::Timeout(adapter_id.clone())))));
+                }
+                Some(got) => results.extend(got)
+            }
         }
 
         results
     }
 
+    /// Send a bunch of values to a set of channels, atomically.
+    ///
+    /// Channels that `user` is not allowed to write to produce `Error::PermissionDenied`,
+    /// and nothing is sent.
+    ///
+    /// Setters whose adapter does not respond in time produce `Error::Timeout`. As the
+    /// adapter may still send their value, it is waited for, once more for the timeout,
+    /// before the previous values are restored.
+    fn send_values_atomic(&self, keyvalues: TargetMap<SetterSelector, Value>, user: User) ->
+        ResultMap<Id<Setter>, SendOutcome, Error>
+    {
+        // First, prepare the request.
+        let mut request;
+        {
+            // Make sure that the lock is released asap.
+            let acl = &self.acl;
+            request = self.back_end.read().unwrap().prepare_send_values(keyvalues, |service_tags, channel| {
+                match *acl {
+                    None => true,
+                    Some(ref acl) => acl.may_write(&user, service_tags, channel)
+                }
+            });
+        }
+
+        // Sort adapters by the way they support transactions. Values that cannot be
+        // sent at all abort the whole send before anything is sent.
+        let mut results = HashMap::new();
+        let mut all = vec![];
+        let mut read_back : AdapterRequest<HashMap<Id<Setter>, Value>> = HashMap::new();
+        let mut two_phase : AdapterRequest<HashMap<Id<Setter>, Value>> = HashMap::new();
+        for (adapter_id, (adapter, (values, failures))) in request.drain() {
+            results.extend(failures.into_iter().map(|(id, result)| (id, result.map(|_| SendOutcome::NotSent))));
+            all.extend(values.keys().cloned());
+            match adapter.transaction_support() {
+                TransactionSupport::Unsupported => {
+                    results.extend(values.into_iter().map(|(id, _)| (id, Err(Error::NotTransactional(adapter_id.clone())))));
+                }
+                TransactionSupport::ReadBack => {
+                    read_back.insert(adapter_id, (adapter, values));
+                }
+                TransactionSupport::TwoPhase => {
+                    two_phase.insert(adapter_id, (adapter, values));
+                }
+            }
+        }
+        let mut failed = !results.is_empty();
+
+        // Read back the previous values, then prepare the two-phase adapters.
+        let mut previous : AdapterRequest<HashMap<Id<Setter>, Value>> = HashMap::new();
+        let mut preparing = None;
+        if !failed {
+            let user_read = user.clone();
+            let mut got = self.dispatch(setters_of(&read_back), move |adapter, setters| {
+                adapter.read_back_values(setters, user_read.clone())
+            });
+            for (adapter_id, &(ref adapter, ref values)) in &read_back {
+                let mut got = got.remove(adapter_id);
+                let mut values_before = HashMap::new();
+                for id in values.keys() {
+                    match take_result(&mut got, adapter_id, id) {
+                        Ok(value) => {
+                            values_before.insert(id.clone(), value);
+                        }
+                        Err(err) => {
+                            results.insert(id.clone(), Err(err));
+                        }
+                    }
+                }
+                previous.insert(adapter_id.clone(), (adapter.clone(), values_before));
+            }
+
+            let user_prepare = user.clone();
+            let mut calls = self.start_dispatch(two_phase.clone(), move |adapter, values| {
+                adapter.prepare_values(values, user_prepare.clone())
+            });
+            let mut got = calls.wait_until(Instant::now() + self.timeout);
+            preparing = Some(calls);
+            for (adapter_id, &(_, ref values)) in &two_phase {
+                let mut got = got.remove(adapter_id);
+                for id in values.keys() {
+                    if let Err(err) = take_result(&mut got, adapter_id, id) {
+                        results.insert(id.clone(), Err(err));
+                    }
+                }
+            }
+            failed = !results.is_empty();
+        }
+
+        // Send the values to the read-back adapters, keeping the previous value of the
+        // setters that have received their value, in case we need to restore them.
+        let mut sent : AdapterRequest<HashMap<Id<Setter>, Value>> = HashMap::new();
+        let mut previous_late : AdapterRequest<HashMap<Id<Setter>, Value>> = HashMap::new();
+        let mut sending = None;
+        if !failed {
+            let user_send = user.clone();
+            let mut calls = self.start_dispatch(read_back, move |adapter, values| {
+                adapter.send_values(values, user_send.clone())
+            });
+            let mut got = calls.wait_until(Instant::now() + self.timeout);
+            for (adapter_id, (adapter, mut values_before)) in previous.drain() {
+                if calls.pending.contains(&adapter_id) {
+                    // The adapter may still send these values, so they must be restored
+                    // once it is done.
+                    results.extend(values_before.keys().map(|id| (id.clone(), Err(Error::Timeout(adapter_id.clone())))));
+                    previous_late.insert(adapter_id, (adapter, values_before));
+                    continue;
+                }
+                let mut got = got.remove(&adapter_id);
+                let mut to_restore = HashMap::new();
+                for (id, value) in values_before.drain() {
+                    match take_result(&mut got, &adapter_id, &id) {
+                        Ok(()) => {
+                            to_restore.insert(id, value);
+                        }
+                        Err(err) => {
+                            results.insert(id, Err(err));
+                        }
+                    }
+                }
+                sent.insert(adapter_id, (adapter, to_restore));
+            }
+            sending = Some(calls);
+            failed = !results.is_empty();
+        }
+
+        // Commit the two-phase adapters if everything else has succeeded, otherwise
+        // roll them back. Once committed, values cannot be reverted, so the two-phase
+        // adapters are committed last.
+        if failed {
+            if let Some(mut calls) = preparing {
+                // Don't let a late `prepare_values` overtake its rollback.
+                calls.wait_until(Instant::now() + self.timeout);
+                for adapter_id in &calls.pending {
+                    warn!(target: "Taxonomy-manager", "Adapter {} is still preparing values, rolling back anyway.", adapter_id);
+                }
+                let user_rollback = user.clone();
+                self.dispatch(setters_of(&two_phase), move |adapter, setters| {
+                    adapter.rollback_values(setters, user_rollback.clone())
+                });
+            }
+        } else {
+            let user_commit = user.clone();
+            let mut got = self.dispatch(setters_of(&two_phase), move |adapter, setters| {
+                adapter.commit_values(setters, user_commit.clone())
+            });
+            for (adapter_id, &(_, ref values)) in &two_phase {
+                let mut got = got.remove(adapter_id);
+                for id in values.keys() {
+                    let result = take_result(&mut got, adapter_id, id).map(|()| SendOutcome::Sent);
+                    failed = failed || result.is_err();
+                    results.insert(id.clone(), result);
+                }
+            }
+        }
+
+        // If anything has failed, restore the previous values of the read-back adapters.
+        // Sends that did not complete in time are waited for, so that they cannot
+        // overwrite the values that we restore.
+        if failed {
+            if let Some(mut calls) = sending {
+                let mut got = calls.wait_until(Instant::now() + self.timeout);
+                for (adapter_id, (adapter, mut values_before)) in previous_late.drain() {
+                    let mut got = match got.remove(&adapter_id) {
+                        Some(got) => got,
+                        None => {
+                            warn!(target: "Taxonomy-manager", "Adapter {} is still sending values, they cannot be restored.", adapter_id);
+                            continue;
+                        }
+                    };
+                    let to_restore = values_before.drain()
+                        .filter(|&(ref id, _)| match got.remove(id) {
+                            Some(Ok(())) => true,
+                            _ => false
+                        })
+                        .collect();
+                    sent.insert(adapter_id, (adapter, to_restore));
+                }
+            }
+            let mut got = self.dispatch(sent.clone(), move |adapter, values| {
+                adapter.send_values(values, user.clone())
+            });
+            for (adapter_id, &(_, ref values)) in &sent {
+                let mut got = got.remove(adapter_id);
+                for id in values.keys() {
+                    let outcome = match take_result(&mut got, adapter_id, id) {
+                        Ok(()) => SendOutcome::Reverted,
+                        Err(err) => SendOutcome::NotReverted(err)
+                    };
+                    if results.contains_key(id) {
+                        // A late send, which keeps its `Error::Timeout`.
+                        if let SendOutcome::NotReverted(err) = outcome {
+                            warn!(target: "Taxonomy-manager", "Could not restore the value of setter {}: {}", id, err);
+                        }
+                    } else {
+                        results.insert(id.clone(), Ok(outcome));
+                    }
+                }
+            }
+        } else {
+            for (_, (_, values)) in sent {
+                results.extend(values.into_iter().map(|(id, _)| (id, Ok(SendOutcome::Sent))));
+            }
+        }
+
+        // Everything else has not been sent.
+        for id in all {
+            results.entry(id).or_insert(Ok(SendOutcome::NotSent));
+        }
+        results
+    }
+
     /// Watch for any change
-    fn watch_values(&self, watch: TargetMap<GetterSelector, Exactly<Range>>,
-        on_event: Box<ExtSender<api::WatchEvent>>) -> Self::WatchGuard
+    ///
+    /// Getters that `user` is not allowed to read are not watched.
+    fn watch_values_with_options(&self, watch: TargetMap<GetterSelector, Exactly<Range>>,
+        options: api::WatchOptions, on_event: Box<ExtSender<api::WatchEvent>>, user: User) -> Self::WatchGuard
     {
+        let acl = self.acl.clone();
+        let reader = user.clone();
+        let may_read : ReadCheck = Box::new(move |service_tags: &HashSet<Id<TagId>>, channel: &Channel<Getter>| {
+            match acl {
+                None => true,
+                Some(ref acl) => acl.may_read(&reader, service_tags, channel)
+            }
+        });
+
         let (request, watch_key, is_dropped) =
         {
             // Acquire and release write lock.
             self.back_end.write()
                 .unwrap()
-                .prepare_channel_watch(watch, on_event)
+                .prepare_channel_watch(watch, options, on_event, may_read, user)
         };
 
         if !request.is_empty() {
@@ -383,8 +1086,18 @@ enum WatchOp {
 impl AdapterManager {
     /// Register watches on the dedicated background thread. This must be done outside of any
     /// lock!
+    ///
+    /// Watchers that asked for initial values receive them first, fetched with the same
+    /// timeout as `fetch_values`.
     fn register_watches(&self, request: WatchRequest) {
         if !request.is_empty() {
+            let initial = State::initial_request(&request);
+            if !initial.is_empty() {
+                let fetched = self.dispatch(initial, State::fetch_initial_values);
+                for (_, fetched) in fetched {
+                    State::send_initial_values(fetched);
+                }
+            }
             let (tx, rx) = channel();
             let _ = self.tx_watch.lock().unwrap().send(WatchOp::Start(request, tx));
             let _ = rx.recv();
@@ -402,7 +1115,8 @@ impl AdapterManager {
                     Some(backend) =>
                         match msg {
                             WatchOp::Start(request, tx) => {
-                                let add = State::start_watch(request);
+                                let timer = backend.read().unwrap().timer();
+                                let add = State::start_watch(request, &timer);
                                 backend.write().unwrap().register_ongoing_watch(add);
                                 let _ = tx.send(());
                             }
@@ -94,7 +94,7 @@ impl Default for Path {
 }
 
 /// An error during parsing.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub enum ParseError {
     JSON(JSONError),
     MissingField {
@@ -294,6 +294,19 @@ impl Parser<u8> for u8 {
     }
 }
 
+impl Parser<i32> for i32 {
+    fn description() -> String {
+        "integer".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match source.as_i64() {
+            Some(val) if val >= i32::min_value() as i64 && val <= i32::max_value() as i64 =>
+                Ok(val as i32),
+            _ => Err(ParseError::type_error("as integer", &path, "32-bit integer"))
+        }
+    }
+}
+
 impl<T> Parser<Vec<T>> for Vec<T> where T: Parser<T> {
     fn description() -> String {
         format!("Array<{}>", T::description())
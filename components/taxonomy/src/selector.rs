@@ -734,6 +734,40 @@ impl Period {
     }
 }
 
+/// A `Period` is represented as an object with two optional fields `min` and `max`,
+/// each a `Duration` (in seconds).
+///
+/// ```
+/// use foxbox_taxonomy::selector::*;
+///
+/// // Anything within the last 24h.
+/// let period = Period::from_str(r#"{"max": 86400}"#).unwrap();
+/// assert!(period.min.is_none());
+/// assert!(period.max.is_some());
+///
+/// // Anything at all.
+/// Period::from_str("{}").unwrap();
+/// ```
+impl Parser<Period> for Period {
+    fn description() -> String {
+        "Period".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        let min = match path.push("min", |path| Duration::take_opt(path, source, "min")) {
+            None => None,
+            Some(result) => Some(try!(result))
+        };
+        let max = match path.push("max", |path| Duration::take_opt(path, source, "max")) {
+            None => None,
+            Some(result) => Some(try!(result))
+        };
+        Ok(Period {
+            min: min,
+            max: max
+        })
+    }
+}
+
 
 fn has_selected_tags(actual: &HashSet<Id<TagId>>, requested: &HashSet<Id<TagId>>) -> bool {
     for tag in &*actual {
@@ -209,6 +209,38 @@ pub enum ChannelKind {
     /// ```
     DoorLocked,
 
+    /// The service is used to detect whether some motion is taking place.
+    /// Values are `On` while motion is detected, `Off` otherwise.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "MotionDetected".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"MotionDetected\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::MotionDetected);
+    /// ```
+    MotionDetected,
+
+    /// The service is used to detect whether a device is available, e.g.
+    /// whether a light bulb is plugged in. Values are `On` or `Off`.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "Available".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"Available\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::Available);
+    /// ```
+    Available,
+
     //
     // # String
     //
@@ -348,6 +380,143 @@ pub enum ChannelKind {
     /// ```
     OvenTemperature,
 
+    //
+    // # Light
+    //
+
+    /// The service is used to detect or decide the color of some light.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "LightColor".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"LightColor\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::LightColor);
+    /// ```
+    LightColor,
+
+    /// The service is used to detect or decide the brightness of some light,
+    /// as a percentage.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "LightBrightness".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"LightBrightness\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::LightBrightness);
+    /// ```
+    LightBrightness,
+
+    //
+    // # Environment
+    //
+
+    /// The service is used to measure the relative humidity, as a percentage.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "RelativeHumidity".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"RelativeHumidity\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::RelativeHumidity);
+    /// ```
+    RelativeHumidity,
+
+    /// The service is used to measure the illuminance, e.g. to determine whether
+    /// the lights should be turned on.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "AmbientLight".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"AmbientLight\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::AmbientLight);
+    /// ```
+    AmbientLight,
+
+    /// The service is used to detect or decide the temperature that a thermostat
+    /// maintains.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "ThermostatSetpoint".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"ThermostatSetpoint\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::ThermostatSetpoint);
+    /// ```
+    ThermostatSetpoint,
+
+    //
+    // # Energy
+    //
+
+    /// The service is used to detect the remaining charge of a battery, as a
+    /// percentage.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "BatteryLevel".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"BatteryLevel\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::BatteryLevel);
+    /// ```
+    BatteryLevel,
+
+    /// The service is used to measure the power currently consumed by a device.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "PowerConsumption".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"PowerConsumption\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::PowerConsumption);
+    /// ```
+    PowerConsumption,
+
+    /// The service is used to measure the energy consumed by a device since
+    /// some device-specific origin, e.g. since it was installed.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "EnergyConsumption".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"EnergyConsumption\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::EnergyConsumption);
+    /// ```
+    EnergyConsumption,
+
     //
     // # Thinkerbell
     //
@@ -377,6 +546,36 @@ pub enum ChannelKind {
     /// ```
     TakeSnapshot,
 
+    /// The service is used to list the images captured by a camera, as JSON.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "ImageList".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"ImageList\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::ImageList);
+    /// ```
+    ImageList,
+
+    /// The service is used to read the latest image captured by a camera.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "LatestImage".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"LatestImage\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::LatestImage);
+    /// ```
+    LatestImage,
+
     /// Write to a log file
     ///
     /// # JSON
@@ -397,12 +596,43 @@ pub enum ChannelKind {
     /// ```
     Log,
 
+    /// The service is used to say a sentence out loud, e.g. through a
+    /// speech synthesizer.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "Sentence".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"Sentence\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::Sentence);
+    /// ```
+    Sentence,
+
     //
     // # WebPush
     //
 
     WebPushNotify,
 
+    /// Activate a scene, i.e. send all the values of the scene to their setters.
+    ///
+    /// # JSON
+    ///
+    /// This kind is represented by string "ActivateScene".
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = ChannelKind::from_str("\"ActivateScene\"").unwrap();
+    /// assert_eq!(parsed, ChannelKind::ActivateScene);
+    /// ```
+    ActivateScene,
+
     // TODO: Add more
 
     /// An operation of a kind that has not been standardized yet.
@@ -488,6 +718,20 @@ impl Parser<ChannelKind> for ChannelKind {
                 "TakeSnapshot" => Ok(ChannelKind::TakeSnapshot),
                 "Log" => Ok(ChannelKind::Log),
                 "WebPushNotify" => Ok(ChannelKind::WebPushNotify),
+                "ActivateScene" => Ok(ChannelKind::ActivateScene),
+                "MotionDetected" => Ok(ChannelKind::MotionDetected),
+                "Available" => Ok(ChannelKind::Available),
+                "LightColor" => Ok(ChannelKind::LightColor),
+                "LightBrightness" => Ok(ChannelKind::LightBrightness),
+                "RelativeHumidity" => Ok(ChannelKind::RelativeHumidity),
+                "AmbientLight" => Ok(ChannelKind::AmbientLight),
+                "ThermostatSetpoint" => Ok(ChannelKind::ThermostatSetpoint),
+                "BatteryLevel" => Ok(ChannelKind::BatteryLevel),
+                "PowerConsumption" => Ok(ChannelKind::PowerConsumption),
+                "EnergyConsumption" => Ok(ChannelKind::EnergyConsumption),
+                "ImageList" => Ok(ChannelKind::ImageList),
+                "LatestImage" => Ok(ChannelKind::LatestImage),
+                "Sentence" => Ok(ChannelKind::Sentence),
                 _ => Err(ParseError::unknown_constant(str, &path))
             }
         }
@@ -536,6 +780,20 @@ impl ToJSON for ChannelKind {
             TakeSnapshot => JSON::String("TakeSnapshot".to_owned()),
             Log => JSON::String("Log".to_owned()),
             WebPushNotify => JSON::String("WebPushNotify".to_owned()),
+            ActivateScene => JSON::String("ActivateScene".to_owned()),
+            MotionDetected => JSON::String("MotionDetected".to_owned()),
+            Available => JSON::String("Available".to_owned()),
+            LightColor => JSON::String("LightColor".to_owned()),
+            LightBrightness => JSON::String("LightBrightness".to_owned()),
+            RelativeHumidity => JSON::String("RelativeHumidity".to_owned()),
+            AmbientLight => JSON::String("AmbientLight".to_owned()),
+            ThermostatSetpoint => JSON::String("ThermostatSetpoint".to_owned()),
+            BatteryLevel => JSON::String("BatteryLevel".to_owned()),
+            PowerConsumption => JSON::String("PowerConsumption".to_owned()),
+            EnergyConsumption => JSON::String("EnergyConsumption".to_owned()),
+            ImageList => JSON::String("ImageList".to_owned()),
+            LatestImage => JSON::String("LatestImage".to_owned()),
+            Sentence => JSON::String("Sentence".to_owned()),
             Extension { ref vendor, ref adapter, ref kind, ref typ } => {
                 vec![
                     ("vendor", vendor.to_json()),
@@ -548,6 +806,26 @@ impl ToJSON for ChannelKind {
     }
 }
 
+#[test]
+fn test_channel_kind_json() {
+    use self::ChannelKind::*;
+
+    // Each kind survives a round-trip through JSON.
+    for kind in vec![MotionDetected, Available, LightColor, LightBrightness, RelativeHumidity, AmbientLight,
+                     BatteryLevel, PowerConsumption, EnergyConsumption, ImageList, LatestImage, Sentence] {
+        let json = kind.to_json();
+        let parsed = ChannelKind::parse(Path::new(), &mut json.clone()).unwrap();
+        assert_eq!(parsed, kind);
+        assert_eq!(ChannelKind::from_str(&::serde_json::to_string(&json).unwrap()).unwrap(), kind);
+    }
+
+    // Names are case-sensitive, and extensions need all their fields.
+    for source in vec!["\"motiondetected\"", "\"MotionDetect\"", "\"\"", "42", "null",
+                       "{\"vendor\": \"mozilla.org\", \"kind\": \"GroundHumidity\"}"] {
+        assert!(ChannelKind::from_str(source).is_err(), "{} should not parse", source);
+    }
+}
+
 impl ChannelKind {
     /// Get the type of values used to communicate with this service.
     pub fn get_type(&self) -> Type {
@@ -560,7 +838,7 @@ impl ChannelKind {
             DoorLocked => Type::DoorLocked,
             CurrentTime => Type::TimeStamp,
             CurrentTimeOfDay | RemainingTime | Countdown | CountEveryInterval => Type::Duration,
-            OvenTemperature => Type::Temperature,
+            OvenTemperature | ThermostatSetpoint => Type::Temperature,
             AddThinkerbellRule => Type::ThinkerbellRule,
             RemoveThinkerbellRule => Type::Unit,
 			ThinkerbellRuleSource => Type::String,
@@ -569,6 +847,16 @@ impl ChannelKind {
             TakeSnapshot => Type::Unit,
 			Username | Password => Type::String,
             WebPushNotify => Type::WebPushNotify,
+            ActivateScene => Type::Unit,
+            MotionDetected | Available => Type::OnOff,
+            LightColor => Type::Color,
+            LightBrightness | RelativeHumidity | BatteryLevel => Type::Percentage,
+            AmbientLight => Type::Illuminance,
+            PowerConsumption => Type::Power,
+            EnergyConsumption => Type::Energy,
+            ImageList => Type::Json,
+            LatestImage => Type::Binary,
+            Sentence => Type::String,
             Extension { ref typ, ..} => typ.clone(),
         }
     }
@@ -634,6 +922,10 @@ pub struct Channel<IO> where IO: IOMechanism {
     /// The last time the device was seen.
     #[serde(default)]
     pub last_seen: Option<TimeStamp>,
+
+    /// Constraints on the values accepted or produced by this channel, if known.
+    #[serde(default)]
+    pub capabilities: Option<Capabilities>,
 }
 
 impl ToJSON for Channel<Getter> {
@@ -649,6 +941,9 @@ impl ToJSON for Channel<Getter> {
         if let Some(ref ts) = self.last_seen {
             source.push(("last_seen", ts.to_json()))
         }
+        if let Some(ref capabilities) = self.capabilities {
+            source.push(("capabilities", capabilities.to_json()))
+        }
         if let Some(ref ts) = self.mechanism.updated {
             source.push(("updated", ts.to_json()));
         }
@@ -674,6 +969,9 @@ impl ToJSON for Channel<Setter> {
         if let Some(ref ts) = self.last_seen {
             source.push(("last_seen", ts.to_json()))
         }
+        if let Some(ref capabilities) = self.capabilities {
+            source.push(("capabilities", capabilities.to_json()))
+        }
         if let Some(ref ts) = self.mechanism.updated {
             source.push(("updated", ts.to_json()));
         }
@@ -700,6 +998,123 @@ impl<IO> Hash for Channel<IO> where IO: IOMechanism {
     }
 }
 
+/// Constraints on the values accepted or produced by a channel, e.g. to let
+/// applications present the appropriate controls.
+///
+/// All constraints are optional.
+///
+/// # JSON
+///
+/// Represented as an object with optional fields `min` (a Value), `max` (a Value),
+/// `step` (a number), `choices` (an array of Values) and `unit` (a string).
+///
+/// ```
+/// use foxbox_taxonomy::services::*;
+/// use foxbox_taxonomy::values::*;
+///
+/// let thermostat = Capabilities {
+///     min: Some(Value::Temperature(Temperature::C(5.))),
+///     max: Some(Value::Temperature(Temperature::C(30.))),
+///     step: Some(0.5),
+///     .. Capabilities::default()
+/// };
+/// assert!(thermostat.accepts(&Value::Temperature(Temperature::C(20.5))));
+/// assert!(!thermostat.accepts(&Value::Temperature(Temperature::C(20.2))));
+/// assert!(!thermostat.accepts(&Value::Temperature(Temperature::C(35.))));
+///
+/// let presets = Capabilities {
+///     choices: Some(vec![Value::Temperature(Temperature::C(19.)), Value::Temperature(Temperature::C(21.))]),
+///     .. Capabilities::default()
+/// };
+/// assert!(presets.accepts(&Value::Temperature(Temperature::F(69.8))));
+/// assert!(!presets.accepts(&Value::Temperature(Temperature::F(68.))));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// The smallest acceptable value.
+    #[serde(default)]
+    pub min: Option<Value>,
+
+    /// The largest acceptable value.
+    #[serde(default)]
+    pub max: Option<Value>,
+
+    /// The granularity of values, in the unit of `min`. Acceptable values are
+    /// `min + k * step`, for any integer `k`. Ignored if there is no `min`.
+    #[serde(default)]
+    pub step: Option<f64>,
+
+    /// If specified, the only acceptable values.
+    #[serde(default)]
+    pub choices: Option<Vec<Value>>,
+
+    /// The unit in which the device natively operates, e.g. "C" or "lux",
+    /// as a hint for applications.
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+impl Capabilities {
+    /// Determine whether two values are the same, regardless of the unit in which
+    /// quantities are expressed.
+    fn is_same(choice: &Value, value: &Value) -> bool {
+        match (value.magnitude_in(choice), choice.magnitude_in(choice)) {
+            (Some(value), Some(choice)) => (value - choice).abs() <= 1e-6,
+            _ => choice == value
+        }
+    }
+
+    /// Determine whether a value satisfies all the constraints.
+    pub fn accepts(&self, value: &Value) -> bool {
+        if let Some(ref choices) = self.choices {
+            if !choices.iter().any(|choice| Self::is_same(choice, value)) {
+                return false;
+            }
+        }
+        if let Some(ref max) = self.max {
+            if !(value <= max) {
+                return false;
+            }
+        }
+        if let Some(ref min) = self.min {
+            if !(value >= min) {
+                return false;
+            }
+            if let Some(step) = self.step {
+                if let (Some(value), Some(min)) = (value.magnitude_in(min), min.magnitude_in(min)) {
+                    let steps = (value - min) / step;
+                    if (steps - steps.round()).abs() > 1e-6 {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+impl ToJSON for Capabilities {
+    fn to_json(&self) -> JSON {
+        let mut source = vec![];
+        if let Some(ref min) = self.min {
+            source.push(("min", min.to_json()));
+        }
+        if let Some(ref max) = self.max {
+            source.push(("max", max.to_json()));
+        }
+        if let Some(step) = self.step {
+            source.push(("step", step.to_json()));
+        }
+        if let Some(ref choices) = self.choices {
+            source.push(("choices", choices.to_json()));
+        }
+        if let Some(ref unit) = self.unit {
+            source.push(("unit", unit.to_json()));
+        }
+        source.to_json()
+    }
+}
+
 /// The communication mechanism used by the channel.
 pub trait IOMechanism: Deserialize + Serialize {
 }
@@ -119,7 +119,7 @@ pub type ResultMap<K, T, E> = HashMap<K, Result<T, E>>;
 /// A bunch of instructions, going to different targets.
 pub type TargetMap<K, T> = Vec<Targetted<K, T>>;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Targetted<K, T> where K: Clone, T: Clone {
     pub select: Vec<K>,
     pub payload: T
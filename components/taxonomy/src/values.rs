@@ -86,6 +86,30 @@ pub enum Type {
     WebPushNotify,
 
     Temperature,
+
+    ///
+    /// # Physical quantities
+    ///
+
+    /// A percentage. Used for instance for brightness, battery levels
+    /// or relative humidity.
+    Percentage,
+
+    /// An instantaneous power, e.g. the consumption of a plug.
+    Power,
+
+    /// An amount of energy, e.g. the cumulated consumption of a plug.
+    Energy,
+
+    /// An illuminance, as measured by light sensors.
+    Illuminance,
+
+    /// A pressure, e.g. an atmospheric pressure.
+    Pressure,
+
+    /// A distance.
+    Distance,
+
     String,
     ///
     /// ...
@@ -112,6 +136,12 @@ impl Parser<Type> for Type {
                 "Duration" => Ok(Duration),
                 "TimeStamp" => Ok(TimeStamp),
                 "Temperature" => Ok(Temperature),
+                "Percentage" => Ok(Percentage),
+                "Power" => Ok(Power),
+                "Energy" => Ok(Energy),
+                "Illuminance" => Ok(Illuminance),
+                "Pressure" => Ok(Pressure),
+                "Distance" => Ok(Distance),
                 "ThinkerbellRule" => Ok(ThinkerbellRule),
                 "WebPushNotify" => Ok(WebPushNotify),
                 "String" => Ok(String),
@@ -137,6 +167,12 @@ impl ToJSON for Type {
             Duration => "Duration",
             TimeStamp => "TimeStamp",
             Temperature => "Temperature",
+            Percentage => "Percentage",
+            Power => "Power",
+            Energy => "Energy",
+            Illuminance => "Illuminance",
+            Pressure => "Pressure",
+            Distance => "Distance",
             ThinkerbellRule => "ThinkerbellRule",
             WebPushNotify => "WebPushNotify",
             String => "String",
@@ -157,12 +193,33 @@ impl Type {
     pub fn supports_eq(&self) -> bool {
         use self::Type::*;
         match *self {
-            Duration | TimeStamp | Temperature | ExtNumeric | Color | ThinkerbellRule => false,
+            Duration | TimeStamp | Temperature | Percentage | Power | Energy | Illuminance |
+            Pressure | Distance | ExtNumeric | Color | ThinkerbellRule => false,
             WebPushNotify | Unit | String | Json | Binary | OnOff | OpenClosed |
             DoorLocked | ExtBool => true,
         }
     }
 
+    /// Determine whether values of this type are quantities, i.e. have a magnitude
+    /// (see `Value::magnitude_in`).
+    pub fn is_quantity(&self) -> bool {
+        use self::Type::*;
+        match *self {
+            Duration | Temperature | Percentage | Power | Energy | Illuminance |
+            Pressure | Distance | ExtNumeric => true,
+            _ => false,
+        }
+    }
+
+    /// Determine whether values of this type are boolean-like (see `Value::as_bool`).
+    pub fn is_bool(&self) -> bool {
+        use self::Type::*;
+        match *self {
+            OnOff | OpenClosed | DoorLocked | ExtBool => true,
+            _ => false,
+        }
+    }
+
     pub fn ensure_eq(&self, other: &Self) -> Result<(), TypeError> {
         if self == other {
             Ok(())
@@ -629,12 +686,18 @@ pub enum Temperature {
 impl Temperature {
     /// Get a temperature in Fahrenheit.
     pub fn as_f(&self) -> f64 {
-        unimplemented!();
+        match *self {
+            Temperature::F(val) => val,
+            Temperature::C(val) => val * 9. / 5. + 32.,
+        }
     }
 
     /// Get a temperature in Celcius.
     pub fn as_c(&self) -> f64 {
-        unimplemented!();
+        match *self {
+            Temperature::F(val) => (val - 32.) * 5. / 9.,
+            Temperature::C(val) => val,
+        }
     }
 }
 
@@ -673,6 +736,617 @@ impl PartialOrd for Temperature {
     }
 }
 
+/// A physical quantity, which may be expressed in several units.
+///
+/// Values of such types are represented by objects `{unit: float}`. Comparing
+/// two values converts them to a common unit first, so `{kW: 1}` is greater
+/// than `{W: 900}` and equal to `{W: 1000}`.
+pub trait Quantity: Sized {
+    /// The magnitude of this quantity, in its own unit.
+    fn magnitude(&self) -> f64;
+
+    /// The size of the unit of this quantity, in the reference unit of its type
+    /// (e.g. 1000 for kilowatts, as the reference unit for power is the Watt).
+    fn unit_size(&self) -> f64;
+
+    /// A quantity expressed in the same unit as `self`.
+    fn with_magnitude(&self, magnitude: f64) -> Self;
+
+    /// The magnitude of this quantity, in the reference unit of its type.
+    fn reference_magnitude(&self) -> f64 {
+        self.magnitude() * self.unit_size()
+    }
+
+    /// Convert this quantity to the unit of `other`.
+    fn in_unit_of(&self, other: &Self) -> Self {
+        other.with_magnitude(self.reference_magnitude() / other.unit_size())
+    }
+}
+
+/// Parse an object `{unit: float}`, where `unit` is one of `units`.
+fn parse_quantity<'a>(description: &str, path: &Path, source: &mut JSON, units: &[&'a str]) ->
+    Result<(&'a str, f64), ParseError>
+{
+    if !source.is_object() {
+        return Err(ParseError::type_error(description, path, "object"));
+    }
+    for unit in units {
+        if let Some(result) = path.push(unit, |path| f64::take_opt(path, source, unit)) {
+            return result.map(|val| (*unit, val));
+        }
+    }
+    Err(ParseError::missing_field(&units.join("|"), path))
+}
+
+/// Serialize a quantity as an object `{unit: float}`.
+fn quantity_to_json(unit: &str, val: f64) -> JSON {
+    JSON::Object(vec![(unit.to_owned(), JSON::F64(val))].iter().cloned().collect())
+}
+
+/// A percentage, e.g. a brightness, a battery level or a relative humidity.
+///
+/// # JSON
+///
+/// Values of this type are represented by objects `{Percent: float}`, with
+/// a value in [0, 100], or `{Ratio: float}`, with a value in [0, 1].
+///
+/// ```
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let parsed = Percentage::from_str("{\"Ratio\": 0.5}").unwrap();
+/// assert_eq!(parsed, Percentage::Ratio(0.5));
+/// assert_eq!(parsed.as_percent(), 50.);
+/// assert!(parsed < Percentage::Percent(60.));
+/// assert_eq!(parsed, Percentage::Percent(50.));
+///
+/// let serialized : JSON = parsed.to_json();
+/// assert_eq!(serialized.find("Ratio").unwrap().as_f64().unwrap(), 0.5);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Percentage {
+    /// A value in [0, 100].
+    Percent(f64),
+
+    /// A value in [0, 1].
+    Ratio(f64),
+}
+
+impl Percentage {
+    /// Get a percentage in [0, 100].
+    pub fn as_percent(&self) -> f64 {
+        self.reference_magnitude()
+    }
+
+    /// Get a percentage in [0, 1].
+    pub fn as_ratio(&self) -> f64 {
+        self.reference_magnitude() / 100.
+    }
+}
+impl Quantity for Percentage {
+    fn magnitude(&self) -> f64 {
+        match *self {
+            Percentage::Percent(val) | Percentage::Ratio(val) => val,
+        }
+    }
+    fn unit_size(&self) -> f64 {
+        match *self {
+            Percentage::Percent(_) => 1.,
+            Percentage::Ratio(_) => 100.,
+        }
+    }
+    fn with_magnitude(&self, magnitude: f64) -> Self {
+        match *self {
+            Percentage::Percent(_) => Percentage::Percent(magnitude),
+            Percentage::Ratio(_) => Percentage::Ratio(magnitude),
+        }
+    }
+}
+impl Parser<Percentage> for Percentage {
+    fn description() -> String {
+        "Percentage".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match try!(parse_quantity("Percentage", &path, source, &["Percent", "Ratio"])) {
+            ("Ratio", val) => Ok(Percentage::Ratio(val)),
+            (_, val) => Ok(Percentage::Percent(val)),
+        }
+    }
+}
+impl ToJSON for Percentage {
+    fn to_json(&self) -> JSON {
+        match *self {
+            Percentage::Percent(val) => quantity_to_json("Percent", val),
+            Percentage::Ratio(val) => quantity_to_json("Ratio", val),
+        }
+    }
+}
+impl PartialEq for Percentage {
+    fn eq(&self, other: &Self) -> bool {
+        self.reference_magnitude() == other.reference_magnitude()
+    }
+}
+impl PartialOrd for Percentage {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.reference_magnitude().partial_cmp(&other.reference_magnitude())
+    }
+}
+
+/// An instantaneous power.
+///
+/// # JSON
+///
+/// Values of this type are represented by objects `{W: float}` or `{kW: float}`.
+///
+/// ```
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let parsed = Power::from_str("{\"kW\": 1.5}").unwrap();
+/// assert_eq!(parsed, Power::KW(1.5));
+/// assert_eq!(parsed.as_w(), 1500.);
+/// assert!(parsed > Power::W(900.));
+/// assert_eq!(Power::KW(1.), Power::W(1000.));
+///
+/// let serialized : JSON = parsed.to_json();
+/// assert_eq!(serialized.find("kW").unwrap().as_f64().unwrap(), 1.5);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Power {
+    /// Watts
+    W(f64),
+
+    /// Kilowatts
+    #[serde(rename="kW")]
+    KW(f64),
+}
+
+impl Power {
+    /// Get a power in Watts.
+    pub fn as_w(&self) -> f64 {
+        self.reference_magnitude()
+    }
+}
+impl Quantity for Power {
+    fn magnitude(&self) -> f64 {
+        match *self {
+            Power::W(val) | Power::KW(val) => val,
+        }
+    }
+    fn unit_size(&self) -> f64 {
+        match *self {
+            Power::W(_) => 1.,
+            Power::KW(_) => 1000.,
+        }
+    }
+    fn with_magnitude(&self, magnitude: f64) -> Self {
+        match *self {
+            Power::W(_) => Power::W(magnitude),
+            Power::KW(_) => Power::KW(magnitude),
+        }
+    }
+}
+impl Parser<Power> for Power {
+    fn description() -> String {
+        "Power".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match try!(parse_quantity("Power", &path, source, &["W", "kW"])) {
+            ("kW", val) => Ok(Power::KW(val)),
+            (_, val) => Ok(Power::W(val)),
+        }
+    }
+}
+impl ToJSON for Power {
+    fn to_json(&self) -> JSON {
+        match *self {
+            Power::W(val) => quantity_to_json("W", val),
+            Power::KW(val) => quantity_to_json("kW", val),
+        }
+    }
+}
+impl PartialEq for Power {
+    fn eq(&self, other: &Self) -> bool {
+        self.reference_magnitude() == other.reference_magnitude()
+    }
+}
+impl PartialOrd for Power {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.reference_magnitude().partial_cmp(&other.reference_magnitude())
+    }
+}
+
+/// An amount of energy.
+///
+/// # JSON
+///
+/// Values of this type are represented by objects `{Wh: float}`, `{kWh: float}`
+/// or `{J: float}`.
+///
+/// ```
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let parsed = Energy::from_str("{\"kWh\": 1.5}").unwrap();
+/// assert_eq!(parsed, Energy::KWh(1.5));
+/// assert_eq!(parsed.as_wh(), 1500.);
+/// assert!(parsed < Energy::J(6000000.));
+///
+/// let serialized : JSON = parsed.to_json();
+/// assert_eq!(serialized.find("kWh").unwrap().as_f64().unwrap(), 1.5);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Energy {
+    /// Watt-hours
+    Wh(f64),
+
+    /// Kilowatt-hours
+    #[serde(rename="kWh")]
+    KWh(f64),
+
+    /// Joules
+    J(f64),
+}
+
+impl Energy {
+    /// Get an amount of energy in Watt-hours.
+    pub fn as_wh(&self) -> f64 {
+        self.reference_magnitude()
+    }
+
+    /// Get an amount of energy in kilowatt-hours.
+    pub fn as_kwh(&self) -> f64 {
+        self.reference_magnitude() / 1000.
+    }
+}
+impl Quantity for Energy {
+    fn magnitude(&self) -> f64 {
+        match *self {
+            Energy::Wh(val) | Energy::KWh(val) | Energy::J(val) => val,
+        }
+    }
+    fn unit_size(&self) -> f64 {
+        match *self {
+            Energy::Wh(_) => 1.,
+            Energy::KWh(_) => 1000.,
+            Energy::J(_) => 1. / 3600.,
+        }
+    }
+    fn with_magnitude(&self, magnitude: f64) -> Self {
+        match *self {
+            Energy::Wh(_) => Energy::Wh(magnitude),
+            Energy::KWh(_) => Energy::KWh(magnitude),
+            Energy::J(_) => Energy::J(magnitude),
+        }
+    }
+}
+impl Parser<Energy> for Energy {
+    fn description() -> String {
+        "Energy".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match try!(parse_quantity("Energy", &path, source, &["Wh", "kWh", "J"])) {
+            ("kWh", val) => Ok(Energy::KWh(val)),
+            ("J", val) => Ok(Energy::J(val)),
+            (_, val) => Ok(Energy::Wh(val)),
+        }
+    }
+}
+impl ToJSON for Energy {
+    fn to_json(&self) -> JSON {
+        match *self {
+            Energy::Wh(val) => quantity_to_json("Wh", val),
+            Energy::KWh(val) => quantity_to_json("kWh", val),
+            Energy::J(val) => quantity_to_json("J", val),
+        }
+    }
+}
+impl PartialEq for Energy {
+    fn eq(&self, other: &Self) -> bool {
+        self.reference_magnitude() == other.reference_magnitude()
+    }
+}
+impl PartialOrd for Energy {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.reference_magnitude().partial_cmp(&other.reference_magnitude())
+    }
+}
+
+/// An illuminance.
+///
+/// # JSON
+///
+/// Values of this type are represented by objects `{lux: float}` or `{fc: float}`
+/// (foot-candles).
+///
+/// ```
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let parsed = Illuminance::from_str("{\"lux\": 300}").unwrap();
+/// assert_eq!(parsed, Illuminance::Lux(300.));
+/// assert!(parsed > Illuminance::FootCandle(20.));
+///
+/// let serialized : JSON = parsed.to_json();
+/// assert_eq!(serialized.find("lux").unwrap().as_f64().unwrap(), 300.);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Illuminance {
+    /// Lux
+    #[serde(rename="lux")]
+    Lux(f64),
+
+    /// Foot-candles
+    #[serde(rename="fc")]
+    FootCandle(f64),
+}
+
+impl Illuminance {
+    /// Get an illuminance in lux.
+    pub fn as_lux(&self) -> f64 {
+        self.reference_magnitude()
+    }
+}
+impl Quantity for Illuminance {
+    fn magnitude(&self) -> f64 {
+        match *self {
+            Illuminance::Lux(val) | Illuminance::FootCandle(val) => val,
+        }
+    }
+    fn unit_size(&self) -> f64 {
+        match *self {
+            Illuminance::Lux(_) => 1.,
+            Illuminance::FootCandle(_) => 10.763_910_4,
+        }
+    }
+    fn with_magnitude(&self, magnitude: f64) -> Self {
+        match *self {
+            Illuminance::Lux(_) => Illuminance::Lux(magnitude),
+            Illuminance::FootCandle(_) => Illuminance::FootCandle(magnitude),
+        }
+    }
+}
+impl Parser<Illuminance> for Illuminance {
+    fn description() -> String {
+        "Illuminance".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match try!(parse_quantity("Illuminance", &path, source, &["lux", "fc"])) {
+            ("fc", val) => Ok(Illuminance::FootCandle(val)),
+            (_, val) => Ok(Illuminance::Lux(val)),
+        }
+    }
+}
+impl ToJSON for Illuminance {
+    fn to_json(&self) -> JSON {
+        match *self {
+            Illuminance::Lux(val) => quantity_to_json("lux", val),
+            Illuminance::FootCandle(val) => quantity_to_json("fc", val),
+        }
+    }
+}
+impl PartialEq for Illuminance {
+    fn eq(&self, other: &Self) -> bool {
+        self.reference_magnitude() == other.reference_magnitude()
+    }
+}
+impl PartialOrd for Illuminance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.reference_magnitude().partial_cmp(&other.reference_magnitude())
+    }
+}
+
+/// A pressure, e.g. an atmospheric pressure.
+///
+/// # JSON
+///
+/// Values of this type are represented by objects `{Pa: float}`, `{hPa: float}`,
+/// `{bar: float}` or `{psi: float}`.
+///
+/// ```
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let parsed = Pressure::from_str("{\"hPa\": 1013.25}").unwrap();
+/// assert_eq!(parsed, Pressure::HPa(1013.25));
+/// assert_eq!(parsed.as_pa(), 101325.);
+/// assert!(parsed < Pressure::Bar(1.1));
+///
+/// let serialized : JSON = parsed.to_json();
+/// assert_eq!(serialized.find("hPa").unwrap().as_f64().unwrap(), 1013.25);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Pressure {
+    /// Pascals
+    Pa(f64),
+
+    /// Hectopascals
+    #[serde(rename="hPa")]
+    HPa(f64),
+
+    /// Bars
+    #[serde(rename="bar")]
+    Bar(f64),
+
+    /// Pounds per square inch
+    #[serde(rename="psi")]
+    Psi(f64),
+}
+
+impl Pressure {
+    /// Get a pressure in Pascals.
+    pub fn as_pa(&self) -> f64 {
+        self.reference_magnitude()
+    }
+}
+impl Quantity for Pressure {
+    fn magnitude(&self) -> f64 {
+        match *self {
+            Pressure::Pa(val) | Pressure::HPa(val) | Pressure::Bar(val) | Pressure::Psi(val) => val,
+        }
+    }
+    fn unit_size(&self) -> f64 {
+        match *self {
+            Pressure::Pa(_) => 1.,
+            Pressure::HPa(_) => 100.,
+            Pressure::Bar(_) => 100_000.,
+            Pressure::Psi(_) => 6_894.757_293,
+        }
+    }
+    fn with_magnitude(&self, magnitude: f64) -> Self {
+        match *self {
+            Pressure::Pa(_) => Pressure::Pa(magnitude),
+            Pressure::HPa(_) => Pressure::HPa(magnitude),
+            Pressure::Bar(_) => Pressure::Bar(magnitude),
+            Pressure::Psi(_) => Pressure::Psi(magnitude),
+        }
+    }
+}
+impl Parser<Pressure> for Pressure {
+    fn description() -> String {
+        "Pressure".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match try!(parse_quantity("Pressure", &path, source, &["Pa", "hPa", "bar", "psi"])) {
+            ("hPa", val) => Ok(Pressure::HPa(val)),
+            ("bar", val) => Ok(Pressure::Bar(val)),
+            ("psi", val) => Ok(Pressure::Psi(val)),
+            (_, val) => Ok(Pressure::Pa(val)),
+        }
+    }
+}
+impl ToJSON for Pressure {
+    fn to_json(&self) -> JSON {
+        match *self {
+            Pressure::Pa(val) => quantity_to_json("Pa", val),
+            Pressure::HPa(val) => quantity_to_json("hPa", val),
+            Pressure::Bar(val) => quantity_to_json("bar", val),
+            Pressure::Psi(val) => quantity_to_json("psi", val),
+        }
+    }
+}
+impl PartialEq for Pressure {
+    fn eq(&self, other: &Self) -> bool {
+        self.reference_magnitude() == other.reference_magnitude()
+    }
+}
+impl PartialOrd for Pressure {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.reference_magnitude().partial_cmp(&other.reference_magnitude())
+    }
+}
+
+/// A distance, e.g. as measured by an ultrasonic sensor.
+///
+/// # JSON
+///
+/// Values of this type are represented by objects `{m: float}`, `{cm: float}`,
+/// `{km: float}`, `{in: float}` or `{ft: float}`.
+///
+/// ```
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let parsed = Distance::from_str("{\"cm\": 50}").unwrap();
+/// assert_eq!(parsed, Distance::Cm(50.));
+/// assert_eq!(parsed.as_m(), 0.5);
+/// assert!(parsed < Distance::Ft(2.));
+/// assert_eq!(parsed, Distance::M(0.5));
+///
+/// let serialized : JSON = parsed.to_json();
+/// assert_eq!(serialized.find("cm").unwrap().as_f64().unwrap(), 50.);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Distance {
+    /// Meters
+    #[serde(rename="m")]
+    M(f64),
+
+    /// Centimeters
+    #[serde(rename="cm")]
+    Cm(f64),
+
+    /// Kilometers
+    #[serde(rename="km")]
+    Km(f64),
+
+    /// Inches
+    #[serde(rename="in")]
+    In(f64),
+
+    /// Feet
+    #[serde(rename="ft")]
+    Ft(f64),
+}
+
+impl Distance {
+    /// Get a distance in meters.
+    pub fn as_m(&self) -> f64 {
+        self.reference_magnitude()
+    }
+}
+impl Quantity for Distance {
+    fn magnitude(&self) -> f64 {
+        match *self {
+            Distance::M(val) | Distance::Cm(val) | Distance::Km(val) |
+            Distance::In(val) | Distance::Ft(val) => val,
+        }
+    }
+    fn unit_size(&self) -> f64 {
+        match *self {
+            Distance::M(_) => 1.,
+            Distance::Cm(_) => 0.01,
+            Distance::Km(_) => 1000.,
+            Distance::In(_) => 0.0254,
+            Distance::Ft(_) => 0.3048,
+        }
+    }
+    fn with_magnitude(&self, magnitude: f64) -> Self {
+        match *self {
+            Distance::M(_) => Distance::M(magnitude),
+            Distance::Cm(_) => Distance::Cm(magnitude),
+            Distance::Km(_) => Distance::Km(magnitude),
+            Distance::In(_) => Distance::In(magnitude),
+            Distance::Ft(_) => Distance::Ft(magnitude),
+        }
+    }
+}
+impl Parser<Distance> for Distance {
+    fn description() -> String {
+        "Distance".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match try!(parse_quantity("Distance", &path, source, &["m", "cm", "km", "in", "ft"])) {
+            ("cm", val) => Ok(Distance::Cm(val)),
+            ("km", val) => Ok(Distance::Km(val)),
+            ("in", val) => Ok(Distance::In(val)),
+            ("ft", val) => Ok(Distance::Ft(val)),
+            (_, val) => Ok(Distance::M(val)),
+        }
+    }
+}
+impl ToJSON for Distance {
+    fn to_json(&self) -> JSON {
+        match *self {
+            Distance::M(val) => quantity_to_json("m", val),
+            Distance::Cm(val) => quantity_to_json("cm", val),
+            Distance::Km(val) => quantity_to_json("km", val),
+            Distance::In(val) => quantity_to_json("in", val),
+            Distance::Ft(val) => quantity_to_json("ft", val),
+        }
+    }
+}
+impl PartialEq for Distance {
+    fn eq(&self, other: &Self) -> bool {
+        self.reference_magnitude() == other.reference_magnitude()
+    }
+}
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.reference_magnitude().partial_cmp(&other.reference_magnitude())
+    }
+}
+
 /// A color. Internal representation may vary. The `FoxBox` adapters are
 /// expected to perform conversions to the format requested by their
 /// device.
@@ -1271,6 +1945,96 @@ pub enum Value {
     /// ```
     Temperature(Temperature),
 
+    /// A percentage.
+    ///
+    /// # JSON
+    ///
+    /// Represented by `{Percentage: {Percent: float}}` or `{Percentage: {Ratio: float}}`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = Value::from_str("{\"Percentage\": {\"Percent\": 75}}").unwrap();
+    /// assert_eq!(parsed, Value::Percentage(Percentage::Percent(75.)));
+    /// ```
+    Percentage(Percentage),
+
+    /// An instantaneous power.
+    ///
+    /// # JSON
+    ///
+    /// Represented by `{Power: {unit: float}}`, see `Power`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = Value::from_str("{\"Power\": {\"W\": 60}}").unwrap();
+    /// assert_eq!(parsed, Value::Power(Power::W(60.)));
+    /// ```
+    Power(Power),
+
+    /// An amount of energy.
+    ///
+    /// # JSON
+    ///
+    /// Represented by `{Energy: {unit: float}}`, see `Energy`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = Value::from_str("{\"Energy\": {\"kWh\": 1.5}}").unwrap();
+    /// assert_eq!(parsed, Value::Energy(Energy::KWh(1.5)));
+    /// ```
+    Energy(Energy),
+
+    /// An illuminance.
+    ///
+    /// # JSON
+    ///
+    /// Represented by `{Illuminance: {unit: float}}`, see `Illuminance`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = Value::from_str("{\"Illuminance\": {\"lux\": 300}}").unwrap();
+    /// assert_eq!(parsed, Value::Illuminance(Illuminance::Lux(300.)));
+    /// ```
+    Illuminance(Illuminance),
+
+    /// A pressure.
+    ///
+    /// # JSON
+    ///
+    /// Represented by `{Pressure: {unit: float}}`, see `Pressure`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = Value::from_str("{\"Pressure\": {\"hPa\": 1013}}").unwrap();
+    /// assert_eq!(parsed, Value::Pressure(Pressure::HPa(1013.)));
+    /// ```
+    Pressure(Pressure),
+
+    /// A distance.
+    ///
+    /// # JSON
+    ///
+    /// Represented by `{Distance: {unit: float}}`, see `Distance`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = Value::from_str("{\"Distance\": {\"m\": 2.5}}").unwrap();
+    /// assert_eq!(parsed, Value::Distance(Distance::M(2.5)));
+    /// ```
+    Distance(Distance),
+
     /// A color.
     ///
     /// # JSON
@@ -1458,6 +2222,30 @@ lazy_static! {
             let value = try!(path.push("Temperature", |path| self::Temperature::parse(path, v)));
             Ok(Temperature(value))
         }));
+        map.insert("Percentage", Box::new(|path, v| {
+            let value = try!(path.push("Percentage", |path| self::Percentage::parse(path, v)));
+            Ok(Percentage(value))
+        }));
+        map.insert("Power", Box::new(|path, v| {
+            let value = try!(path.push("Power", |path| self::Power::parse(path, v)));
+            Ok(Power(value))
+        }));
+        map.insert("Energy", Box::new(|path, v| {
+            let value = try!(path.push("Energy", |path| self::Energy::parse(path, v)));
+            Ok(Energy(value))
+        }));
+        map.insert("Illuminance", Box::new(|path, v| {
+            let value = try!(path.push("Illuminance", |path| self::Illuminance::parse(path, v)));
+            Ok(Illuminance(value))
+        }));
+        map.insert("Pressure", Box::new(|path, v| {
+            let value = try!(path.push("Pressure", |path| self::Pressure::parse(path, v)));
+            Ok(Pressure(value))
+        }));
+        map.insert("Distance", Box::new(|path, v| {
+            let value = try!(path.push("Distance", |path| self::Distance::parse(path, v)));
+            Ok(Distance(value))
+        }));
         map.insert("ThinkerbellRule", Box::new(|path, v| {
             let value = try!(path.push("ThinkerbellRule", |path| self::ThinkerbellRule::parse(path, v)));
             Ok(ThinkerbellRule(value))
@@ -1534,6 +2322,12 @@ impl ToJSON for Value {
             Json(ref val) => ("Json", val.to_json()),
             Binary(ref val) => ("Binary", val.to_json()),
             Temperature(ref val) => ("Temperature", val.to_json()),
+            Percentage(ref val) => ("Percentage", val.to_json()),
+            Power(ref val) => ("Power", val.to_json()),
+            Energy(ref val) => ("Energy", val.to_json()),
+            Illuminance(ref val) => ("Illuminance", val.to_json()),
+            Pressure(ref val) => ("Pressure", val.to_json()),
+            Distance(ref val) => ("Distance", val.to_json()),
             ThinkerbellRule(ref val) => ("ThinkerbellRule", val.to_json()),
             WebPushNotify(ref val) => ("WebPushNotify", val.to_json()),
             ExtBool(ref val) => ("ExtBool", val.to_json()),
@@ -1556,6 +2350,12 @@ impl Value {
             Value::Duration(_) => Type::Duration,
             Value::TimeStamp(_) => Type::TimeStamp,
             Value::Temperature(_) => Type::Temperature,
+            Value::Percentage(_) => Type::Percentage,
+            Value::Power(_) => Type::Power,
+            Value::Energy(_) => Type::Energy,
+            Value::Illuminance(_) => Type::Illuminance,
+            Value::Pressure(_) => Type::Pressure,
+            Value::Distance(_) => Type::Distance,
             Value::Color(_) => Type::Color,
             Value::Json(_) => Type::Json,
             Value::Binary(_) => Type::Binary,
@@ -1579,6 +2379,90 @@ impl Value {
             _ => Err(TypeError {expected: Type::Duration, got: self.get_type()})
         }
     }
+
+    /// The magnitude of a quantity, expressed in the unit of `unit_of`, e.g. the number
+    /// of degrees Fahrenheit of a temperature if `unit_of` is in Fahrenheit. Durations
+    /// are expressed in seconds.
+    ///
+    /// Return `None` if the values are not quantities of the same type.
+    pub fn magnitude_in(&self, unit_of: &Value) -> Option<f64> {
+        use self::Value::*;
+        match (self, unit_of) {
+            (&Temperature(ref t), &Temperature(self::Temperature::C(_))) => Some(t.as_c()),
+            (&Temperature(ref t), &Temperature(self::Temperature::F(_))) => Some(t.as_f()),
+            (&Duration(ref d), &Duration(_)) => {
+                let d : ChronoDuration = d.clone().into();
+                Some(d.num_milliseconds() as f64 / 1000.)
+            }
+            (&ExtNumeric(ref e), &ExtNumeric(_)) => Some(e.value),
+            (&Percentage(ref a), &Percentage(ref b)) => Some(a.in_unit_of(b).magnitude()),
+            (&Power(ref a), &Power(ref b)) => Some(a.in_unit_of(b).magnitude()),
+            (&Energy(ref a), &Energy(ref b)) => Some(a.in_unit_of(b).magnitude()),
+            (&Illuminance(ref a), &Illuminance(ref b)) => Some(a.in_unit_of(b).magnitude()),
+            (&Pressure(ref a), &Pressure(ref b)) => Some(a.in_unit_of(b).magnitude()),
+            (&Distance(ref a), &Distance(ref b)) => Some(a.in_unit_of(b).magnitude()),
+            _ => None
+        }
+    }
+
+    /// A quantity of the same type and unit as `self`, with the given magnitude, as
+    /// returned by `magnitude_in`.
+    ///
+    /// Return `None` if `self` is not a quantity.
+    pub fn with_magnitude(&self, magnitude: f64) -> Option<Value> {
+        use self::Value::*;
+        match *self {
+            Temperature(self::Temperature::C(_)) => Some(Temperature(self::Temperature::C(magnitude))),
+            Temperature(self::Temperature::F(_)) => Some(Temperature(self::Temperature::F(magnitude))),
+            Duration(_) => Some(Duration(self::Duration::from(ChronoDuration::milliseconds((magnitude * 1000.) as i64)))),
+            ExtNumeric(ref e) => Some(ExtNumeric(ExtValue {
+                value: magnitude,
+                .. e.clone()
+            })),
+            Percentage(ref a) => Some(Percentage(a.with_magnitude(magnitude))),
+            Power(ref a) => Some(Power(a.with_magnitude(magnitude))),
+            Energy(ref a) => Some(Energy(a.with_magnitude(magnitude))),
+            Illuminance(ref a) => Some(Illuminance(a.with_magnitude(magnitude))),
+            Pressure(ref a) => Some(Pressure(a.with_magnitude(magnitude))),
+            Distance(ref a) => Some(Distance(a.with_magnitude(magnitude))),
+            _ => None
+        }
+    }
+
+    /// A quantity expressed in the reference unit of its type, e.g. degrees Celsius for
+    /// temperatures or Watts for power. Durations and extension numbers have a single
+    /// unit and are returned unchanged.
+    ///
+    /// Return `None` if `self` is not a quantity.
+    pub fn in_reference_unit(&self) -> Option<Value> {
+        use self::Value::*;
+        let reference = match *self {
+            Temperature(_) => Temperature(self::Temperature::C(1.)),
+            Percentage(_) => Percentage(self::Percentage::Percent(1.)),
+            Power(_) => Power(self::Power::W(1.)),
+            Energy(_) => Energy(self::Energy::Wh(1.)),
+            Illuminance(_) => Illuminance(self::Illuminance::Lux(1.)),
+            Pressure(_) => Pressure(self::Pressure::Pa(1.)),
+            Distance(_) => Distance(self::Distance::M(1.)),
+            Duration(_) | ExtNumeric(_) => return Some(self.clone()),
+            _ => return None
+        };
+        self.magnitude_in(&reference)
+            .and_then(|magnitude| reference.with_magnitude(magnitude))
+    }
+
+    /// Whether a boolean-like value is set, i.e. `On`, `Open`, `Locked` or `true`.
+    ///
+    /// Return `None` if the value is not boolean-like.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::OnOff(ref val) => Some(val.as_bool()),
+            Value::OpenClosed(ref val) => Some(val.as_bool()),
+            Value::DoorLocked(ref val) => Some(val.as_bool()),
+            Value::ExtBool(ref val) => Some(val.value),
+            _ => None
+        }
+    }
 }
 
 impl PartialOrd for Value {
@@ -1610,6 +2494,24 @@ impl PartialOrd for Value {
             (&Temperature(ref a), &Temperature(ref b)) => a.partial_cmp(b),
             (&Temperature(_), _) => None,
 
+            (&Percentage(ref a), &Percentage(ref b)) => a.partial_cmp(b),
+            (&Percentage(_), _) => None,
+
+            (&Power(ref a), &Power(ref b)) => a.partial_cmp(b),
+            (&Power(_), _) => None,
+
+            (&Energy(ref a), &Energy(ref b)) => a.partial_cmp(b),
+            (&Energy(_), _) => None,
+
+            (&Illuminance(ref a), &Illuminance(ref b)) => a.partial_cmp(b),
+            (&Illuminance(_), _) => None,
+
+            (&Pressure(ref a), &Pressure(ref b)) => a.partial_cmp(b),
+            (&Pressure(_), _) => None,
+
+            (&Distance(ref a), &Distance(ref b)) => a.partial_cmp(b),
+            (&Distance(_), _) => None,
+
             (&Color(ref a), &Color(ref b)) => a.partial_cmp(b),
             (&Color(_), _) => None,
 
@@ -1788,6 +2690,105 @@ pub enum Range {
 
     /// Eq(x) accespts any value v such that v == x
     Eq(Value),
+
+    /// Not(r) accepts any value v that is not accepted by r.
+    ///
+    /// # JSON
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    /// extern crate serde_json;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let source = "{
+    ///   \"Not\": { \"Eq\": { \"OnOff\": \"On\" } }
+    /// }";
+    ///
+    /// let parsed = Range::from_str(source).unwrap();
+    /// assert!(parsed.contains(&Value::OnOff(OnOff::Off)));
+    /// assert!(!parsed.contains(&Value::OnOff(OnOff::On)));
+    ///
+    /// let as_json = parsed.to_json();
+    /// let as_string = serde_json::to_string(&as_json).unwrap();
+    /// assert_eq!(as_string, "{\"Not\":{\"Eq\":{\"OnOff\":\"On\"}}}");
+    ///
+    /// # }
+    /// ```
+    Not(Box<Range>),
+
+    /// AnyOf(rs) accepts any value v that is accepted by at least one
+    /// of the ranges of rs. All the ranges must have the same type.
+    ///
+    /// # JSON
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    /// extern crate chrono;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let source = "{
+    ///   \"AnyOf\": [
+    ///     { \"Leq\": { \"Duration\": 5 } },
+    ///     { \"Geq\": { \"Duration\": 25 } }
+    ///   ]
+    /// }";
+    ///
+    /// let parsed = Range::from_str(source).unwrap();
+    /// assert!(parsed.contains(&Value::Duration(Duration::from(chrono::Duration::seconds(0)))));
+    /// assert!(parsed.contains(&Value::Duration(Duration::from(chrono::Duration::seconds(30)))));
+    /// assert!(!parsed.contains(&Value::Duration(Duration::from(chrono::Duration::seconds(20)))));
+    ///
+    /// # }
+    /// ```
+    AnyOf(Vec<Range>),
+
+    /// AllOf(rs) accepts any value v that is accepted by each of the
+    /// ranges of rs. All the ranges must have the same type.
+    ///
+    /// # JSON
+    ///
+    /// An object `{"AllOf": [range, range, ...]}`, with at least one range.
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    /// extern crate chrono;
+    /// extern crate serde_json;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let source = "{
+    ///   \"AllOf\": [
+    ///     { \"Geq\": { \"Duration\": 5 } },
+    ///     { \"Not\": { \"Eq\": { \"Duration\": 10 } } },
+    ///     { \"Leq\": { \"Duration\": 25 } }
+    ///   ]
+    /// }";
+    ///
+    /// let parsed = Range::from_str(source).unwrap();
+    /// assert!(parsed.contains(&Value::Duration(Duration::from(chrono::Duration::seconds(5)))));
+    /// assert!(parsed.contains(&Value::Duration(Duration::from(chrono::Duration::seconds(20)))));
+    /// assert!(!parsed.contains(&Value::Duration(Duration::from(chrono::Duration::seconds(0)))));
+    /// assert!(!parsed.contains(&Value::Duration(Duration::from(chrono::Duration::seconds(10)))));
+    /// assert!(!parsed.contains(&Value::Duration(Duration::from(chrono::Duration::seconds(30)))));
+    ///
+    /// let as_json = parsed.to_json();
+    /// let as_string = serde_json::to_string(&as_json).unwrap();
+    /// assert!(as_string.starts_with("{\"AllOf\":[{\"Geq\":"));
+    ///
+    /// # }
+    /// ```
+    AllOf(Vec<Range>),
 }
 
 impl Parser<Range> for Range {
@@ -1833,7 +2834,25 @@ impl Parser<Range> for Range {
                         return Err(ParseError::type_error("OutOfStrict", &path, "an array of two values"))
                     }
                 }
-                Err(ParseError::type_error("Range", &path, "a field Eq, Leq, Geq, BetweenEq or OutOfStrict"))
+                if let Some(not) = obj.get_mut("Not") {
+                    let range = try!(path.push("Not", |path| Range::parse(path, not)));
+                    return Ok(Not(Box::new(range)))
+                }
+                if let Some(any) = obj.get_mut("AnyOf") {
+                    let ranges = try!(path.push("AnyOf", |path| Vec::<Range>::parse(path, any)));
+                    if ranges.is_empty() {
+                        return Err(ParseError::type_error("AnyOf", &path, "a non-empty array of ranges"))
+                    }
+                    return Ok(AnyOf(ranges))
+                }
+                if let Some(all) = obj.get_mut("AllOf") {
+                    let ranges = try!(path.push("AllOf", |path| Vec::<Range>::parse(path, all)));
+                    if ranges.is_empty() {
+                        return Err(ParseError::type_error("AllOf", &path, "a non-empty array of ranges"))
+                    }
+                    return Ok(AllOf(ranges))
+                }
+                Err(ParseError::type_error("Range", &path, "a field Eq, Leq, Geq, BetweenEq, OutOfStrict, Not, AnyOf or AllOf"))
             }
             _ => Err(ParseError::type_error("Range", &path, "object"))
         }
@@ -1848,6 +2867,9 @@ impl ToJSON for Range {
             Range::Leq(ref val) => ("Leq", val.to_json()),
             Range::BetweenEq { ref min, ref max } => ("BetweenEq", JSON::Array(vec![min.to_json(), max.to_json()])),
             Range::OutOfStrict { ref min, ref max } => ("OutOfStrict", JSON::Array(vec![min.to_json(), max.to_json()])),
+            Range::Not(ref range) => ("Not", range.to_json()),
+            Range::AnyOf(ref ranges) => ("AnyOf", JSON::Array(ranges.iter().map(Range::to_json).collect())),
+            Range::AllOf(ref ranges) => ("AllOf", JSON::Array(ranges.iter().map(Range::to_json).collect())),
         };
         vec![(key, value)].to_json()
     }
@@ -1855,6 +2877,21 @@ impl ToJSON for Range {
 
 impl Range {
     /// Determine if a value is accepted by this range.
+    ///
+    /// Quantities are compared regardless of their unit.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let range = Range::from_str("{\"BetweenEq\": [
+    ///   {\"Power\": {\"W\": 500}},
+    ///   {\"Power\": {\"kW\": 2}}
+    /// ]}").unwrap();
+    /// assert!(range.contains(&Value::Power(Power::KW(1.))));
+    /// assert!(range.contains(&Value::Power(Power::W(1500.))));
+    /// assert!(!range.contains(&Value::Power(Power::KW(2.5))));
+    /// ```
     pub fn contains(&self, value: &Value) -> bool {
         use self::Range::*;
         match *self {
@@ -1863,13 +2900,18 @@ impl Range {
             BetweenEq { ref min, ref max } => min <= value && value <= max,
             OutOfStrict { ref min, ref max } => value < min || max < value,
             Eq(ref val) => value == val,
+            Not(ref range) => !range.contains(value),
+            AnyOf(ref ranges) => ranges.iter().any(|range| range.contains(value)),
+            AllOf(ref ranges) => ranges.iter().all(|range| range.contains(value)),
         }
     }
 
     /// Get the type associated to this range.
     ///
     /// If this range has a `min` and a `max` with conflicting types,
-    /// produce an error.
+    /// or if it combines subranges with conflicting types, produce an error.
+    /// An empty `AnyOf` or `AllOf` (which cannot be obtained by parsing)
+    /// has type `Unit`.
     pub fn get_type(&self) -> Result<Type, TypeError> {
         use self::Range::*;
         match *self {
@@ -1886,6 +2928,24 @@ impl Range {
                     })
                 }
             }
+            Not(ref range) => range.get_type(),
+            AnyOf(ref ranges) | AllOf(ref ranges) => {
+                let mut result = None;
+                for range in ranges {
+                    let typ = try!(range.get_type());
+                    match result {
+                        None => result = Some(typ),
+                        Some(ref expected) if *expected != typ => {
+                            return Err(TypeError {
+                                expected: expected.clone(),
+                                got: typ
+                            })
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(result.unwrap_or(Type::Unit))
+            }
         }
     }
 }
@@ -0,0 +1,472 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+///! An adapter exposing virtual getters, whose values are computed from the values of
+///! other getters, e.g. the average temperature of the house or whether any window
+///! is open.
+///!
+///! Each virtual getter aggregates the getters matched by a selector. The adapter
+///! watches these getters and recomputes the value of the virtual getter whenever
+///! one of them changes, so virtual getters may be fetched and watched like any other
+///! getter. Definitions are stored as their JSON source, in a database.
+///!
+///! Fetching or watching a virtual getter requires permission to read all the getters it
+///! aggregates.
+
+use adapter::*;
+use aggregate::{ Aggregate, Aggregator };
+use api::{ Error, InternalError, User };
+use manager::AdapterManager;
+use parse::*;
+use selector::GetterSelector;
+use services::*;
+use util::{ Exactly, KindId };
+use values::*;
+
+use rusqlite::Error as SqliteError;
+
+use std::cmp::Ordering;
+use std::collections::{ HashMap, HashSet };
+use std::path::PathBuf;
+use std::sync::Arc;
+
+static ADAPTER_NAME: &'static str = "Virtual channels adapter (built-in)";
+static ADAPTER_VENDOR: &'static str = "team@link.mozilla.org";
+static ADAPTER_VERSION: [u32;4] = [0, 0, 0, 0];
+
+/// An error while managing virtual getters.
+#[derive(Debug, Serialize)]
+pub enum VirtualError {
+    /// The source of a definition could not be parsed.
+    ParseError(ParseError),
+
+    /// There is no virtual getter with this id.
+    NoSuchGetter(Id<Getter>),
+
+    /// The virtual getter could not be registered with the `AdapterManager`.
+    TaxonomyError(Error),
+
+    /// There was an error executing some SQL.
+    SQLError(String),
+}
+
+impl From<SqliteError> for VirtualError {
+    fn from(err: SqliteError) -> Self {
+        VirtualError::SQLError(format!("{}", err))
+    }
+}
+
+impl From<ParseError> for VirtualError {
+    fn from(err: ParseError) -> Self {
+        VirtualError::ParseError(err)
+    }
+}
+
+impl From<Error> for VirtualError {
+    fn from(err: Error) -> Self {
+        VirtualError::TaxonomyError(err)
+    }
+}
+
+/// The way the values of the underlying getters are combined.
+///
+/// # JSON
+///
+/// One of `"Min"`, `"Max"`, `"Avg"`, `"Any"`, `"All"`, `"Count"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregation {
+    /// The smallest value. Quantities are expressed in the reference unit of their
+    /// type, e.g. degrees Celsius for temperatures.
+    Min,
+
+    /// The largest value. Quantities are expressed in the reference unit of their
+    /// type.
+    Max,
+
+    /// The average of quantities (temperatures, durations, ...), in the reference unit
+    /// of their type.
+    Avg,
+
+    /// One of the boolean-like values that is set (`On`, `Open`, `Locked`, ...), if
+    /// any, otherwise one of the values that is not set.
+    Any,
+
+    /// One of the boolean-like values that is not set, if any, otherwise one of the
+    /// values that is set.
+    All,
+
+    /// The number of values, not counting boolean-like values that are not set, as an
+    /// `ExtNumeric`.
+    Count,
+}
+
+impl Aggregation {
+    /// Combine values. Return `None` if there is no value to combine.
+    pub fn apply(&self, values: Vec<&Value>) -> Option<Value> {
+        use self::Aggregation::*;
+        if values.is_empty() {
+            return None;
+        }
+        match *self {
+            Min | Max | Avg => {
+                // Values arrive in no particular order, so express all quantities in
+                // the same unit, lest the unit of the result depend on that order.
+                let values : Vec<_> = values.iter()
+                    .map(|value| value.in_reference_unit().unwrap_or_else(|| (*value).clone()))
+                    .collect();
+                let first = values[0].clone();
+                if *self == Avg {
+                    return Self::average(&first, &values);
+                }
+                let wanted = if *self == Min { Ordering::Less } else { Ordering::Greater };
+                Some(values.into_iter().fold(first, |best, value| {
+                    if value.partial_cmp(&best) == Some(wanted) {
+                        value
+                    } else {
+                        best
+                    }
+                }))
+            }
+            Any | All => {
+                let wanted = *self == Any;
+                values.iter()
+                    .find(|value| value.as_bool() == Some(wanted))
+                    .or_else(|| values.iter().find(|value| value.as_bool() == Some(!wanted)))
+                    .map(|value| (*value).clone())
+            }
+            Count => {
+                let count = values.iter().filter(|value| value.as_bool() != Some(false)).count();
+                Some(Value::ExtNumeric(ExtValue {
+                    value: count as f64,
+                    vendor: Id::new(ADAPTER_VENDOR),
+                    adapter: VirtualChannels::id(),
+                    kind: Id::new("Count"),
+                }))
+            }
+        }
+    }
+
+    /// The average of quantities, in the unit of `first`.
+    fn average(first: &Value, values: &[Value]) -> Option<Value> {
+        let magnitudes : Vec<_> = values.iter()
+            .filter_map(|value| value.magnitude_in(first))
+            .collect();
+        if magnitudes.is_empty() {
+            return None;
+        }
+        let sum = magnitudes.iter().fold(0., |sum, magnitude| sum + magnitude);
+        first.with_magnitude(sum / magnitudes.len() as f64)
+    }
+
+    /// Check that the values of this aggregation may have type `typ`.
+    pub fn accepts(&self, typ: &Type) -> bool {
+        use self::Aggregation::*;
+        match *self {
+            Min | Max => true,
+            Avg => typ.is_quantity(),
+            Any | All => typ.is_bool(),
+            Count => *typ == Type::ExtNumeric,
+        }
+    }
+
+    /// The kind of the getters produced by `Count`.
+    pub fn count_kind() -> ChannelKind {
+        ChannelKind::Extension {
+            vendor: Id::new(ADAPTER_VENDOR),
+            adapter: VirtualChannels::id(),
+            kind: Id::<KindId>::new("Count"),
+            typ: Type::ExtNumeric
+        }
+    }
+}
+
+impl Parser<Aggregation> for Aggregation {
+    fn description() -> String {
+        "Aggregation".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match source.as_string() {
+            Some("Min") => Ok(Aggregation::Min),
+            Some("Max") => Ok(Aggregation::Max),
+            Some("Avg") => Ok(Aggregation::Avg),
+            Some("Any") => Ok(Aggregation::Any),
+            Some("All") => Ok(Aggregation::All),
+            Some("Count") => Ok(Aggregation::Count),
+            Some(str) => Err(ParseError::unknown_constant(str, &path)),
+            None => Err(ParseError::type_error("Aggregation", &path, "string"))
+        }
+    }
+}
+
+/// The definition of a virtual getter.
+///
+/// # JSON
+///
+/// An object with the following fields:
+///
+/// - `id`: the id of the virtual getter;
+/// - `aggregation` (see `Aggregation`);
+/// - `source`: a `GetterSelector` for the getters whose values are aggregated. Virtual
+///   getters are never aggregated, even if they match this selector;
+/// - `kind`: the `ChannelKind` of the virtual getter, optional with aggregation `Count`.
+///
+/// ```
+/// use foxbox_taxonomy::parse::*;
+/// use foxbox_taxonomy::services::*;
+/// use foxbox_taxonomy::virtual_channels::*;
+///
+/// let source = r#"{
+///   "id": "getter:any-window-open@virtual",
+///   "kind": "OpenClosed",
+///   "aggregation": "Any",
+///   "source": {"kind": "OpenClosed", "tags": ["window"]}
+/// }"#;
+///
+/// let definition = VirtualGetter::from_str(source).unwrap();
+/// assert_eq!(definition.aggregation, Aggregation::Any);
+/// assert_eq!(definition.kind, ChannelKind::OpenClosed);
+///
+/// // The kind must match the aggregation and the getters it aggregates.
+/// let source = r#"{
+///   "id": "getter:average-window@virtual",
+///   "kind": "OpenClosed",
+///   "aggregation": "Avg",
+///   "source": {"kind": "OpenClosed", "tags": ["window"]}
+/// }"#;
+/// assert!(VirtualGetter::from_str(source).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct VirtualGetter {
+    pub id: Id<Getter>,
+    pub kind: ChannelKind,
+    pub aggregation: Aggregation,
+    pub source: GetterSelector,
+}
+
+impl Parser<VirtualGetter> for VirtualGetter {
+    fn description() -> String {
+        "VirtualGetter".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        let id = try!(path.push("id", |path| Id::take(path, source, "id")));
+        let aggregation = try!(path.push("aggregation", |path| Aggregation::take(path, source, "aggregation")));
+        let selector = try!(path.push("source", |path| GetterSelector::take(path, source, "source")));
+        let kind = match path.push("kind", |path| ChannelKind::take_opt(path, source, "kind")) {
+            Some(result) => try!(result),
+            None if aggregation == Aggregation::Count => Aggregation::count_kind(),
+            None => return Err(ParseError::missing_field("kind", &path))
+        };
+        let typ = kind.get_type();
+        if !aggregation.accepts(&typ) {
+            return Err(path.push("kind", |path| {
+                ParseError::type_error("kind", &path, &format!("a kind compatible with {:?}", aggregation))
+            }));
+        }
+        if aggregation != Aggregation::Count {
+            // Except for `Count`, the virtual getter has the type of the getters it aggregates.
+            if let Exactly::Exactly(ref source_kind) = selector.kind {
+                if source_kind.get_type() != typ {
+                    return Err(path.push("source", |path| path.push("kind", |path| {
+                        ParseError::type_error("kind", &path, &format!("a kind of type {:?}", typ))
+                    })));
+                }
+            }
+        }
+        Ok(VirtualGetter {
+            id: id,
+            kind: kind,
+            aggregation: aggregation,
+            source: selector,
+        })
+    }
+}
+
+impl Aggregate for VirtualGetter {
+    fn key(&self) -> String {
+        self.id.to_string()
+    }
+
+    fn getter_id(&self) -> Id<Getter> {
+        self.id.clone()
+    }
+
+    fn source(&self) -> GetterSelector {
+        self.source.clone()
+    }
+
+    fn apply(&self, values: Vec<&Value>) -> Option<Value> {
+        self.aggregation.apply(values)
+    }
+
+    fn accepts(&self, value: &Value) -> bool {
+        // A selector without a kind may match getters of any type, which we cannot
+        // combine with the others.
+        self.aggregation == Aggregation::Count || value.get_type() == self.kind.get_type()
+    }
+
+    fn add_channels(&self, manager: &AdapterManager) -> Result<(), Error> {
+        manager.add_getter(Channel {
+            id: self.id.clone(),
+            service: VirtualChannels::service_id(),
+            adapter: VirtualChannels::id(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Getter {
+                kind: self.kind.clone(),
+                updated: None
+            },
+        })
+    }
+
+    fn remove_channels(&self, manager: &AdapterManager) -> Result<(), Error> {
+        manager.remove_getter(&self.id)
+    }
+}
+
+/// The adapter exposing virtual getters, backed by a database.
+pub struct VirtualChannels {
+    aggregator: Aggregator<VirtualGetter>,
+}
+
+impl VirtualChannels {
+    pub fn id() -> Id<AdapterId> {
+        Id::new("virtual-channels@link.mozilla.org")
+    }
+
+    /// The service holding all the virtual getters.
+    pub fn service_id() -> Id<ServiceId> {
+        Id::new("service:virtual-channels@link.mozilla.org")
+    }
+
+    /// Open or create the database at `path`, then register the adapter and the
+    /// virtual getters with `manager`.
+    pub fn init(manager: &Arc<AdapterManager>, path: &PathBuf) -> Result<Arc<Self>, VirtualError> {
+        debug!("Opening virtual channels database at {}", path.display());
+        let (aggregator, definitions) =
+            try!(Aggregator::open::<VirtualError>(Self::id(), manager, path, "virtual_getters", "id"));
+        let adapter = Arc::new(VirtualChannels {
+            aggregator: aggregator,
+        });
+        try!(manager.add_adapter(adapter.clone()));
+        try!(manager.add_service(Service::empty(Self::service_id(), Self::id())));
+        for (definition, source) in definitions {
+            try!(adapter.aggregator.register(manager, definition, source));
+        }
+        Ok(adapter)
+    }
+
+    /// Add a virtual getter, replacing any virtual getter with the same id. Return the
+    /// id of the virtual getter.
+    ///
+    /// If the new definition cannot be registered or stored, the previous definition, if
+    /// any, is restored.
+    pub fn put_getter(&self, source: &str) -> Result<Id<Getter>, VirtualError> {
+        self.aggregator.put(source).map(|definition: VirtualGetter| definition.id)
+    }
+
+    /// Remove a virtual getter.
+    pub fn remove_getter(&self, id: &Id<Getter>) -> Result<(), VirtualError> {
+        match self.aggregator.remove(&id.to_string()) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(VirtualError::NoSuchGetter(id.clone())),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Get the definitions of all the virtual getters, as JSON.
+    pub fn get_getters(&self) -> HashMap<Id<Getter>, JSON> {
+        self.aggregator.get_sources().into_iter()
+            .map(|(id, source)| (Id::new(&id), source))
+            .collect()
+    }
+}
+
+impl Adapter for VirtualChannels {
+    fn id(&self) -> Id<AdapterId> {
+        Self::id()
+    }
+
+    fn name(&self) -> &str {
+        ADAPTER_NAME
+    }
+
+    fn vendor(&self) -> &str {
+        ADAPTER_VENDOR
+    }
+
+    fn version(&self) -> &[u32;4] {
+        &ADAPTER_VERSION
+    }
+
+    fn fetch_values(&self, set: Vec<Id<Getter>>, user: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        self.aggregator.fetch_values(set, user)
+    }
+
+    fn send_values(&self, mut values: HashMap<Id<Setter>, Value>, _: User) -> ResultMap<Id<Setter>, (), Error> {
+        values.drain().map(|(id, _)| {
+            (id.clone(), Err(Error::InternalError(InternalError::NoSuchSetter(id))))
+        }).collect()
+    }
+
+    fn register_watch(&self, watch: Vec<WatchTarget>) -> WatchResult {
+        self.aggregator.register_watch(watch)
+    }
+
+    fn register_watch_for(&self, watch: Vec<WatchTarget>, user: User) -> WatchResult {
+        self.aggregator.register_watch_for(watch, user)
+    }
+
+    /// Stop watching the underlying getters.
+    fn stop(&self) {
+        self.aggregator.stop()
+    }
+}
+
+#[test]
+fn test_aggregations() {
+    let temperatures = vec![
+        Value::Temperature(Temperature::C(20.)),
+        Value::Temperature(Temperature::F(68.)),
+        Value::Temperature(Temperature::C(23.)),
+    ];
+    let temperatures : Vec<_> = temperatures.iter().collect();
+    assert_eq!(Aggregation::Min.apply(temperatures.clone()), Some(Value::Temperature(Temperature::C(20.))));
+    assert_eq!(Aggregation::Max.apply(temperatures.clone()), Some(Value::Temperature(Temperature::C(23.))));
+    assert_eq!(Aggregation::Avg.apply(temperatures.clone()), Some(Value::Temperature(Temperature::C(21.))));
+
+    let windows = vec![
+        Value::OpenClosed(OpenClosed::Closed),
+        Value::OpenClosed(OpenClosed::Open),
+        Value::OpenClosed(OpenClosed::Closed),
+    ];
+    let windows : Vec<_> = windows.iter().collect();
+    assert_eq!(Aggregation::Any.apply(windows.clone()), Some(Value::OpenClosed(OpenClosed::Open)));
+    assert_eq!(Aggregation::All.apply(windows.clone()), Some(Value::OpenClosed(OpenClosed::Closed)));
+    match Aggregation::Count.apply(windows) {
+        Some(Value::ExtNumeric(ref count)) => assert_eq!(count.value, 1.),
+        other => panic!("Unexpected count {:?}", other)
+    }
+
+    // Quantities are expressed in the reference unit of their type, whatever the order.
+    let powers = vec![
+        Value::Power(Power::KW(1.)),
+        Value::Power(Power::W(500.)),
+    ];
+    let powers : Vec<_> = powers.iter().collect();
+    let mut reversed = powers.clone();
+    reversed.reverse();
+    for values in vec![powers, reversed] {
+        match Aggregation::Max.apply(values.clone()) {
+            Some(Value::Power(Power::W(watts))) => assert_eq!(watts, 1000.),
+            other => panic!("Unexpected max {:?}", other)
+        }
+        match Aggregation::Avg.apply(values) {
+            Some(Value::Power(Power::W(watts))) => assert_eq!(watts, 750.),
+            other => panic!("Unexpected average {:?}", other)
+        }
+    }
+
+    assert_eq!(Aggregation::Avg.apply(vec![]), None);
+}
@@ -1,15 +1,23 @@
+extern crate chrono;
 extern crate foxbox_taxonomy;
 extern crate libc;
 extern crate transformable_channels;
 #[macro_use]
 extern crate assert_matches;
 
+use foxbox_taxonomy::acl::Acl;
 use foxbox_taxonomy::manager::*;
+use foxbox_taxonomy::clock::FakeClock;
 use foxbox_taxonomy::fake_adapter::*;
-use foxbox_taxonomy::api::{ API, Error, InternalError, TargetMap, Targetted, User, WatchEvent as Event };
+use foxbox_taxonomy::groups::*;
+use foxbox_taxonomy::history::{ HistoryStorage, RetentionPolicy };
+use foxbox_taxonomy::parse::*;
+use foxbox_taxonomy::api::{ API, AdapterStatus, Error, FetchOptions, InternalError, SendOutcome, TargetMap, Targetted, User, WatchEvent as Event,
+                            WatchOptions };
 use foxbox_taxonomy::selector::*;
 use foxbox_taxonomy::services::*;
 use foxbox_taxonomy::values::*;
+use foxbox_taxonomy::virtual_channels::*;
 
 use transformable_channels::mpsc::*;
 
@@ -78,6 +86,7 @@ fn test_tags_in_db() {
         service: service_id_1.clone(),
         adapter: id_1.clone(),
         last_seen: None,
+        capabilities: None,
         tags: HashSet::new(),
         mechanism: Getter {
             updated: None,
@@ -90,6 +99,7 @@ fn test_tags_in_db() {
         service: service_id_1.clone(),
         adapter: id_1.clone(),
         last_seen: None,
+        capabilities: None,
         tags: HashSet::new(),
         mechanism: Setter {
             updated: None,
@@ -106,13 +116,13 @@ fn test_tags_in_db() {
         manager.add_setter(setter_1.clone()).unwrap();
 
         manager.add_service_tags(vec![ServiceSelector::new().with_id(service_id_1.clone())],
-                                 vec![tag_id_1.clone(), tag_id_2.clone()]);
+                                 vec![tag_id_1.clone(), tag_id_2.clone()], User::None);
 
         manager.add_getter_tags(vec![GetterSelector::new().with_id(getter_id_1.clone())],
-                                vec![tag_id_2.clone(), tag_id_3.clone()]);
+                                vec![tag_id_2.clone(), tag_id_3.clone()], User::None);
 
         manager.add_setter_tags(vec![SetterSelector::new().with_id(setter_id_1.clone())],
-                                vec![tag_id_1.clone(), tag_id_4.clone(), tag_id_3.clone()]);
+                                vec![tag_id_1.clone(), tag_id_4.clone(), tag_id_3.clone()], User::None);
 
         manager.remove_getter(&getter_id_1).unwrap();
         manager.remove_setter(&setter_id_1).unwrap();
@@ -184,19 +194,19 @@ fn test_tags_in_db() {
 
         // Remove all the tags, to check in session 3 if we start empty again.
         manager.remove_service_tags(vec![ServiceSelector::new().with_id(service_id_1.clone())],
-                                    vec![tag_id_1.clone(), tag_id_2.clone()]);
+                                    vec![tag_id_1.clone(), tag_id_2.clone()], User::None);
         let services = manager.get_services(vec![]);
         assert_eq!(services.len(), 1);
         assert_eq!(services[0].tags.len(), 0);
 
         manager.remove_getter_tags(vec![GetterSelector::new().with_id(getter_id_1.clone())],
-                                vec![tag_id_2.clone(), tag_id_3.clone()]);
+                                vec![tag_id_2.clone(), tag_id_3.clone()], User::None);
         let getters = manager.get_getter_channels(vec![GetterSelector::new()]);
         assert_eq!(getters.len(), 1);
         assert_eq!(getters[0].tags.len(), 0);
 
         manager.remove_setter_tags(vec![SetterSelector::new().with_id(setter_id_1.clone())],
-                                vec![tag_id_1.clone(), tag_id_4.clone(), tag_id_3.clone()]);
+                                vec![tag_id_1.clone(), tag_id_4.clone(), tag_id_3.clone()], User::None);
         let setters = manager.get_setter_channels(vec![SetterSelector::new()]);
         assert_eq!(setters.len(), 1);
         assert_eq!(setters[0].tags.len(), 0);
@@ -307,6 +317,7 @@ fn test_add_remove_services() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -319,6 +330,7 @@ fn test_add_remove_services() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 updated: None,
@@ -331,6 +343,7 @@ fn test_add_remove_services() {
             service: service_id_3.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -343,6 +356,7 @@ fn test_add_remove_services() {
             service: service_id_3.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 updated: None,
@@ -374,6 +388,7 @@ fn test_add_remove_services() {
             service: service_id_2.clone(),
             adapter: id_2.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -386,6 +401,7 @@ fn test_add_remove_services() {
             service: service_id_2.clone(),
             adapter: id_2.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 updated: None,
@@ -619,6 +635,7 @@ fn test_add_remove_tags() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -631,6 +648,7 @@ fn test_add_remove_tags() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 updated: None,
@@ -652,6 +670,7 @@ fn test_add_remove_tags() {
             service: service_id_2.clone(),
             adapter: id_2.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -664,6 +683,7 @@ fn test_add_remove_tags() {
             service: service_id_2.clone(),
             adapter: id_2.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 updated: None,
@@ -701,34 +721,34 @@ fn test_add_remove_tags() {
         println!("* Removing tags from non-existent services and channels doesn't hurt and returns 0.");
         assert_eq!(manager
             .remove_service_tags(
-                vec![ServiceSelector::new().with_id(service_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![ServiceSelector::new().with_id(service_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             0);
         assert_eq!(manager
             .remove_getter_tags(
-                vec![GetterSelector::new().with_id(getter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![GetterSelector::new().with_id(getter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             0);
         assert_eq!(manager
             .remove_setter_tags(
-                vec![SetterSelector::new().with_id(setter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![SetterSelector::new().with_id(setter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             0);
 
         println!("* Adding tags to non-existent services and channels doesn't hurt and returns 0.");
         assert_eq!(manager
             .add_service_tags(
-                vec![ServiceSelector::new().with_id(service_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![ServiceSelector::new().with_id(service_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             0);
         assert_eq!(manager
             .add_getter_tags(
-                vec![GetterSelector::new().with_id(getter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![GetterSelector::new().with_id(getter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             0);
         assert_eq!(manager
             .add_setter_tags(
-                vec![SetterSelector::new().with_id(setter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![SetterSelector::new().with_id(setter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             0);
 
@@ -744,34 +764,34 @@ fn test_add_remove_tags() {
         manager.add_setter(setter_2.clone()).unwrap();
         assert_eq!(manager
             .remove_service_tags(
-                vec![ServiceSelector::new().with_id(service_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![ServiceSelector::new().with_id(service_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             1);
         assert_eq!(manager
             .remove_getter_tags(
-                vec![GetterSelector::new().with_id(getter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![GetterSelector::new().with_id(getter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             1);
         assert_eq!(manager
             .remove_setter_tags(
-                vec![SetterSelector::new().with_id(setter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![SetterSelector::new().with_id(setter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             1);
 
         println!("* We can add tags tags to services and channels, this returns 1.");
         assert_eq!(manager
             .add_service_tags(
-                vec![ServiceSelector::new().with_id(service_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![ServiceSelector::new().with_id(service_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             1);
         assert_eq!(manager
             .add_getter_tags(
-                vec![GetterSelector::new().with_id(getter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![GetterSelector::new().with_id(getter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             1);
         assert_eq!(manager
             .add_setter_tags(
-                vec![SetterSelector::new().with_id(setter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()]
+                vec![SetterSelector::new().with_id(setter_id_2.clone())], vec![tag_2.clone(), tag_3.clone()], User::None
             ),
             1);
 
@@ -876,17 +896,17 @@ fn test_add_remove_tags() {
         println!("* We can remove tags, both existent and non-existent.");
         assert_eq!(manager
             .remove_service_tags(
-                vec![ServiceSelector::new().with_id(service_id_2.clone())], vec![tag_1.clone(), tag_3.clone()]
+                vec![ServiceSelector::new().with_id(service_id_2.clone())], vec![tag_1.clone(), tag_3.clone()], User::None
             ),
             1);
         assert_eq!(manager
             .remove_getter_tags(
-                vec![GetterSelector::new().with_id(getter_id_2.clone())], vec![tag_1.clone(), tag_3.clone()]
+                vec![GetterSelector::new().with_id(getter_id_2.clone())], vec![tag_1.clone(), tag_3.clone()], User::None
             ),
             1);
         assert_eq!(manager
             .remove_setter_tags(
-                vec![SetterSelector::new().with_id(setter_id_2.clone())], vec![tag_1.clone(), tag_3.clone()]
+                vec![SetterSelector::new().with_id(setter_id_2.clone())], vec![tag_1.clone(), tag_3.clone()], User::None
             ),
             1);
 
@@ -955,6 +975,89 @@ fn test_add_remove_tags() {
     println!("");
 }
 
+#[test]
+fn test_tags_acl() {
+    println!("");
+
+    let mut db_path = get_db_environment();
+    db_path.set_extension("acl.sqlite");
+
+    let acl = Acl::new(&db_path).unwrap();
+    acl.put_grant(&Id::new("shared"), r#"{
+        "grantee": {"user": 1},
+        "read": [{"id": "getter id 1"}],
+        "write": [{"tags": ["shared"]}]
+    }"#).unwrap();
+
+    let manager = AdapterManager::new(None).with_acl(acl);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let shared = Id::<TagId>::new("shared");
+
+    manager.add_adapter(Arc::new(FakeAdapter::new(&id_1))).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        capabilities: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::DoorLocked,
+        },
+    }).unwrap();
+    manager.add_setter(Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        capabilities: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::DoorLocked,
+        },
+    }).unwrap();
+    let select_setter = || vec![SetterSelector::new().with_id(setter_id_1.clone())];
+    let is_shared = || !manager.get_setter_channels(vec![SetterSelector::new().with_tags(vec![shared.clone()])]).is_empty();
+
+    println!("* Users cannot tag channels that they may not access, to gain access to them.");
+    assert_eq!(manager.add_setter_tags(select_setter(), vec![shared.clone()], User::Id(1)), 0);
+    assert_eq!(manager.add_setter_tags(select_setter(), vec![shared.clone()], User::Id(2)), 0);
+    assert_eq!(manager.add_setter_tags(select_setter(), vec![shared.clone()], User::None), 0);
+    assert!(!is_shared());
+
+    println!("* Users cannot tag services with channels that they may not access.");
+    let select_service = || vec![ServiceSelector::new().with_id(service_id_1.clone())];
+    assert_eq!(manager.add_service_tags(select_service(), vec![shared.clone()], User::Id(1)), 0);
+    assert!(manager.get_services(vec![ServiceSelector::new().with_tags(vec![shared.clone()])]).is_empty());
+
+    println!("* Users may tag the channels that they may access.");
+    let select_getter = vec![GetterSelector::new().with_id(getter_id_1.clone())];
+    assert_eq!(manager.add_getter_tags(select_getter.clone(), vec![shared.clone()], User::Id(2)), 0);
+    assert_eq!(manager.add_getter_tags(select_getter.clone(), vec![shared.clone()], User::Id(1)), 1);
+    assert_eq!(manager.remove_getter_tags(select_getter.clone(), vec![shared.clone()], User::Id(1)), 1);
+
+    println!("* The box itself may tag any channel.");
+    assert_eq!(manager.add_setter_tags(select_setter(), vec![shared.clone()], User::System), 1);
+    assert!(is_shared());
+    assert_eq!(manager.add_service_tags(select_service(), vec![shared.clone()], User::Id(1)), 1);
+
+    println!("* Users cannot untag channels that they may not access.");
+    assert_eq!(manager.remove_setter_tags(select_setter(), vec![shared.clone()], User::Id(2)), 0);
+    assert!(is_shared());
+    assert_eq!(manager.remove_setter_tags(select_setter(), vec![shared.clone()], User::Id(1)), 1);
+    assert!(!is_shared());
+
+    std::fs::remove_file(&db_path).unwrap();
+
+    println!("");
+}
+
 #[test]
 fn test_fetch() {
     println!("");
@@ -981,6 +1084,7 @@ fn test_fetch() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -993,6 +1097,7 @@ fn test_fetch() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -1005,6 +1110,7 @@ fn test_fetch() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -1017,6 +1123,7 @@ fn test_fetch() {
             service: service_id_2.clone(),
             adapter: id_2.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -1178,6 +1285,7 @@ fn test_send() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 kind: ChannelKind::LightOn,
@@ -1190,6 +1298,7 @@ fn test_send() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 kind: ChannelKind::LightOn,
@@ -1202,6 +1311,7 @@ fn test_send() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 kind: ChannelKind::LightOn,
@@ -1214,6 +1324,7 @@ fn test_send() {
             service: service_id_2.clone(),
             adapter: id_2.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 kind: ChannelKind::LightOn,
@@ -1378,6 +1489,408 @@ fn test_send() {
     println!("");
 }
 
+#[test]
+fn test_send_capabilities() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+
+    let setter_1 = Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        capabilities: Some(Capabilities {
+            min: Some(Value::Percentage(Percentage::Ratio(0.))),
+            max: Some(Value::Percentage(Percentage::Ratio(1.))),
+            step: Some(0.1),
+            .. Capabilities::default()
+        }),
+        tags: HashSet::new(),
+        mechanism: Setter {
+            kind: ChannelKind::LightBrightness,
+            updated: None,
+        },
+    };
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let rx_adapter_1 = adapter_1.take_rx();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_setter(setter_1.clone()).unwrap();
+
+    println!("* Sending values that satisfy the capabilities succeeds, regardless of the unit.");
+    for value in vec![Value::Percentage(Percentage::Ratio(0.5)), Value::Percentage(Percentage::Percent(70.))] {
+        let data = manager.send_values(target_map(vec![(vec![SetterSelector::new()], value.clone())]), User::None);
+        match data.get(&setter_id_1) {
+            Some(&Ok(())) => {},
+            other => panic!("Unexpected result for {:?}: {:?}", value, other)
+        }
+        match rx_adapter_1.try_recv().unwrap() {
+            Effect::ValueSent(ref id, ref sent) if *id == setter_id_1 && *sent == value => {},
+            effect => panic!("Unexpected effect {:?}", effect)
+        }
+    }
+
+    println!("* Sending values that do not satisfy the capabilities fails without reaching the adapter.");
+    for value in vec![Value::Percentage(Percentage::Percent(150.)), Value::Percentage(Percentage::Ratio(0.55))] {
+        let data = manager.send_values(target_map(vec![(vec![SetterSelector::new()], value.clone())]), User::None);
+        match data.get(&setter_id_1) {
+            Some(&Err(Error::InvalidValue(ref rejected))) if *rejected == value => {},
+            other => panic!("Unexpected result for {:?}: {:?}", value, other)
+        }
+    }
+    assert_matches!(rx_adapter_1.try_recv(), Err(_));
+
+    println!("* The capabilities are part of the channel JSON.");
+    let setters = manager.get_setter_channels(vec![SetterSelector::new()]);
+    assert_eq!(setters.len(), 1);
+    let json = setters[0].to_json();
+    let max = json.find_path(&["capabilities", "max", "Percentage", "Ratio"]).unwrap().as_f64().unwrap();
+    assert_eq!(max, 1.);
+
+    println!("");
+}
+
+
+#[test]
+fn test_fetch_max_age() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let tweak_1 = adapter_1.get_tweak();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        capabilities: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+        },
+    }).unwrap();
+
+    let fetch = |max_age: Option<i64>| {
+        let options = FetchOptions {
+            max_age: max_age.map(|s| Duration::from(chrono::Duration::seconds(s)))
+        };
+        let mut data = manager.fetch_values_with_options(vec![GetterSelector::new()], options, User::None);
+        match data.remove(&getter_id_1) {
+            Some(Ok(Some(fetched))) => fetched,
+            other => panic!("Unexpected result {:?}", other)
+        }
+    };
+
+    println!("* Fetched values are timestamped and tagged with their adapter.");
+    tweak_1(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    let before = chrono::UTC::now();
+    let fetched = fetch(None);
+    assert_eq!(fetched.value, Value::OnOff(OnOff::On));
+    assert_eq!(fetched.adapter, id_1);
+    assert!(*fetched.timestamp.as_datetime() >= before);
+
+    println!("* With max_age, a recent value is returned without contacting the adapter.");
+    tweak_1(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::Off)))));
+    assert_eq!(fetch(Some(60)).value, Value::OnOff(OnOff::On));
+
+    println!("* Without max_age, the adapter is contacted.");
+    assert_eq!(fetch(None).value, Value::OnOff(OnOff::Off));
+
+    println!("* Values delivered by watches are also used.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), Box::new(tx_watch), User::None);
+    tweak_1(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    match rx_watch.recv().unwrap() {
+        Event::EnterRange { .. } => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+    tweak_1(Tweak::InjectDelay(Some(std::time::Duration::new(60, 0))));
+    assert_eq!(fetch(Some(60)).value, Value::OnOff(OnOff::On));
+
+    println!("");
+}
+
+#[test]
+fn test_history_of_watched_values() {
+    println!("");
+
+    let mut db_path = get_db_environment();
+    db_path.set_extension("history.sqlite");
+
+    let manager = AdapterManager::with_history(None, Some(HistoryStorage::new(&db_path, RetentionPolicy::default())));
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let tweak_1 = adapter_1.get_tweak();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        capabilities: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+        },
+    }).unwrap();
+
+    let inject = |value: OnOff| {
+        tweak_1(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(value)))));
+    };
+    let history = || -> Vec<Value> {
+        match manager.fetch_history(vec![GetterSelector::new()], Period::default(), User::None).remove(&getter_id_1) {
+            Some(Ok(entries)) => entries.into_iter().map(|entry| entry.value).collect(),
+            other => panic!("Unexpected history {:?}", other)
+        }
+    };
+
+    println!("* Values reported to watchers are recorded, including to filtered watchers.");
+    let (tx_watch_1, rx_watch_1) = channel();
+    let guard_1 = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(Range::Eq(Value::OnOff(OnOff::On)))
+    )]), Box::new(tx_watch_1), User::None);
+    inject(OnOff::Off);
+    inject(OnOff::On);
+    assert_matches!(rx_watch_1.recv().unwrap(), Event::EnterRange { .. });
+    assert_eq!(history(), vec![Value::OnOff(OnOff::On)]);
+
+    println!("* Values are recorded once, regardless of the number of watchers.");
+    let (tx_watch_2, rx_watch_2) = channel();
+    let guard_2 = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), Box::new(tx_watch_2), User::None);
+    inject(OnOff::Off);
+    assert_matches!(rx_watch_1.recv().unwrap(), Event::ExitRange { .. });
+    assert_matches!(rx_watch_2.recv().unwrap(), Event::EnterRange { .. });
+    assert_eq!(history(), vec![Value::OnOff(OnOff::On), Value::OnOff(OnOff::Off)]);
+
+    println!("* Values are not recorded anymore once nobody watches.");
+    drop(guard_1);
+    drop(guard_2);
+    thread::sleep(std::time::Duration::new(1, 0));
+    inject(OnOff::On);
+    assert_eq!(history().len(), 2);
+
+    println!("* Initial values are recorded.");
+    let (tx_watch_3, rx_watch_3) = channel();
+    let _guard_3 = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), WatchOptions {
+        initial: true,
+        .. WatchOptions::default()
+    }, Box::new(tx_watch_3), User::None);
+    assert_matches!(rx_watch_3.recv().unwrap(), Event::EnterRange { .. });
+    assert_eq!(history(), vec![Value::OnOff(OnOff::On), Value::OnOff(OnOff::Off), Value::OnOff(OnOff::On)]);
+
+    std::fs::remove_file(&db_path).unwrap();
+
+    println!("");
+}
+
+#[test]
+fn test_health() {
+    println!("");
+
+    let (tx_health, rx_health) = channel();
+    let manager = AdapterManager::new(None)
+        .with_health_listener(Box::new(tx_health));
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    println!("* Adapters are running as soon as they are added.");
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let tweak_1 = adapter_1.get_tweak();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    let health = rx_health.recv().unwrap();
+    assert_eq!(health.id, id_1);
+    assert_eq!(health.status, AdapterStatus::Running);
+
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        capabilities: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+        },
+    }).unwrap();
+
+    println!("* Changes of status are notified and reported.");
+    let degraded = AdapterStatus::Degraded("Bridge unreachable".to_owned());
+    manager.set_adapter_status(&id_1, degraded.clone()).unwrap();
+    let health = rx_health.recv().unwrap();
+    assert_eq!(health.status, degraded);
+    assert_eq!(health.services, vec![service_id_1.clone()]);
+
+    let adapters = manager.get_adapters_health();
+    assert_eq!(adapters.len(), 1);
+    assert_eq!(adapters[0].status, degraded);
+
+    println!("* Reporting the same status again is not notified.");
+    manager.set_adapter_status(&id_1, degraded.clone()).unwrap();
+    assert_matches!(rx_health.try_recv(), Err(_));
+
+    println!("* Reporting the status of an unknown adapter fails.");
+    assert_matches!(manager.set_adapter_status(&Id::new("no such adapter"), AdapterStatus::Running),
+        Err(Error::InternalError(InternalError::NoSuchAdapter(_))));
+
+    println!("* Services share the status of their adapter, and have not been seen yet.");
+    let services = manager.get_services_health(vec![ServiceSelector::new()]);
+    assert_eq!(services.len(), 1);
+    assert_eq!(services[0].id, service_id_1);
+    assert_eq!(services[0].status, degraded);
+    assert_eq!(services[0].last_seen, None);
+    assert_eq!(manager.get_getter_channels(vec![GetterSelector::new()])[0].last_seen, None);
+
+    println!("* Successful fetches update last_seen.");
+    tweak_1(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    manager.fetch_values(vec![GetterSelector::new()], User::None);
+    let services = manager.get_services_health(vec![ServiceSelector::new()]);
+    assert!(services[0].last_seen.is_some());
+    let getters = manager.get_getter_channels(vec![GetterSelector::new()]);
+    assert_eq!(getters[0].last_seen, services[0].last_seen);
+
+    println!("");
+}
+
+#[test]
+fn test_timeout() {
+    println!("");
+
+    let manager = AdapterManager::new(None)
+        .with_timeout(std::time::Duration::from_millis(200));
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let id_2 = Id::<AdapterId>::new("adapter id 2");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let service_id_2 = Id::<ServiceId>::new("service id 2");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let setter_id_2 = Id::<Setter>::new("setter id 2");
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let adapter_2 = FakeAdapter::new(&id_2);
+    let tweak_1 = adapter_1.get_tweak();
+    let tweak_2 = adapter_2.get_tweak();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_adapter(Arc::new(adapter_2)).unwrap();
+
+    for &(ref adapter, ref service, ref getter, ref setter) in &[(&id_1, &service_id_1, &getter_id_1, &setter_id_1),
+                                                                 (&id_2, &service_id_2, &getter_id_2, &setter_id_2)] {
+        manager.add_service(Service::empty((*service).clone(), (*adapter).clone())).unwrap();
+        manager.add_getter(Channel {
+            id: (*getter).clone(),
+            service: (*service).clone(),
+            adapter: (*adapter).clone(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Getter {
+                updated: None,
+                kind: ChannelKind::LightOn,
+            },
+        }).unwrap();
+        manager.add_setter(Channel {
+            id: (*setter).clone(),
+            service: (*service).clone(),
+            adapter: (*adapter).clone(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Setter {
+                updated: None,
+                kind: ChannelKind::LightOn,
+            },
+        }).unwrap();
+    }
+
+    tweak_1(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    tweak_2(Tweak::InjectGetterValue(getter_id_2.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    tweak_2(Tweak::InjectDelay(Some(std::time::Duration::new(2, 0))));
+
+    println!("* Fetching from an adapter that does not respond in time produces a timeout, without delaying other adapters.");
+    let start = std::time::Instant::now();
+    let data = manager.fetch_values(vec![GetterSelector::new()], User::None);
+    assert!(start.elapsed() < std::time::Duration::new(1, 0));
+    assert_eq!(data.len(), 2);
+    match data.get(&getter_id_1) {
+        Some(&Ok(Some(Value::OnOff(OnOff::On)))) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    match data.get(&getter_id_2) {
+        Some(&Err(Error::Timeout(ref id))) if *id == id_2 => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    println!("* Sending to an adapter that does not respond in time produces a timeout, without delaying other adapters.");
+    let start = std::time::Instant::now();
+    let data = manager.send_values(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::Off))]), User::None);
+    assert!(start.elapsed() < std::time::Duration::new(1, 0));
+    assert_eq!(data.len(), 2);
+    match data.get(&setter_id_1) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    match data.get(&setter_id_2) {
+        Some(&Err(Error::Timeout(ref id))) if *id == id_2 => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    println!("* Once the adapter responds again, its results are available.");
+    tweak_2(Tweak::InjectDelay(None));
+    let data = manager.fetch_values(vec![GetterSelector::new()], User::None);
+    match data.get(&getter_id_2) {
+        Some(&Ok(Some(Value::OnOff(OnOff::On)))) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    println!("* Once too many calls are stuck on an adapter, further calls fail immediately.");
+    tweak_2(Tweak::InjectDelay(Some(std::time::Duration::new(30, 0))));
+    for _ in 0..MAX_CALLS_PER_ADAPTER {
+        manager.fetch_values(vec![GetterSelector::new().with_id(getter_id_2.clone())], User::None);
+    }
+    let start = std::time::Instant::now();
+    let data = manager.fetch_values(vec![GetterSelector::new()], User::None);
+    assert!(start.elapsed() < std::time::Duration::from_millis(100));
+    match data.get(&getter_id_1) {
+        Some(&Ok(Some(Value::OnOff(OnOff::On)))) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    match data.get(&getter_id_2) {
+        Some(&Err(Error::Timeout(ref id))) if *id == id_2 => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    println!("");
+}
 
 #[test]
 fn test_watch() {
@@ -1406,6 +1919,7 @@ fn test_watch() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -1418,6 +1932,7 @@ fn test_watch() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -1430,6 +1945,7 @@ fn test_watch() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -1442,6 +1958,7 @@ fn test_watch() {
             service: service_id_1.clone(),
             adapter: id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -1454,6 +1971,7 @@ fn test_watch() {
             service: service_id_2.clone(),
             adapter: id_2.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 updated: None,
@@ -1498,7 +2016,7 @@ fn test_watch() {
         guards.push(manager.watch_values(target_map(vec![(
             vec![GetterSelector::new().with_id(Id::new("No such getter"))],
             Exactly::Always
-        )]), Box::new(tx_watch_1)));
+        )]), Box::new(tx_watch_1), User::None));
 
         println!("* With adapters, watching values from a selector that has no channels does nothing.");
         manager.add_adapter(Arc::new(adapter_1)).unwrap();
@@ -1514,14 +2032,14 @@ fn test_watch() {
         guards.push(manager.watch_values(target_map(vec![(
             vec![GetterSelector::new().with_id(Id::new("No such getter"))],
             Exactly::Always
-        )]), Box::new(tx_watch)));
+        )]), Box::new(tx_watch), User::None));
 
         println!("* We can observe channels being added.");
         let (tx_watch, rx_watch) = channel();
         let guard = manager.watch_values(target_map(vec![(
             vec![GetterSelector::new()],
             Exactly::Always
-        )]), Box::new(tx_watch)); // We keep `guard` out of `guards` to drop it manually later.
+        )]), Box::new(tx_watch), User::None); // We keep `guard` out of `guards` to drop it manually later.
 
         manager.add_getter(getter_1_1.clone()).unwrap();
         manager.add_getter(getter_1_2.clone()).unwrap();
@@ -1571,7 +2089,7 @@ fn test_watch() {
         assert_eq!(manager.add_getter_tags(vec![
             GetterSelector::new().with_id(getter_id_1_3.clone()),
             GetterSelector::new().with_id(getter_id_2.clone()),
-        ], vec![tag_1.clone()]), 2);
+        ], vec![tag_1.clone()], User::None), 2);
 
         let (tx_watch_2, rx_watch_2) = channel();
         guards.push(manager.watch_values(target_map(vec![(
@@ -1580,7 +2098,7 @@ fn test_watch() {
                     .with_tags(vec![tag_1.clone()])
             ],
             Exactly::Exactly(Range::Eq(Value::OnOff(OnOff::On)))
-        )]), Box::new(tx_watch_2)));
+        )]), Box::new(tx_watch_2), User::None));
 
         println!("* Value changes are observed on both watchers");
         tweak_1(Tweak::InjectGetterValue(getter_id_1_1.clone(), Ok(Some(Value::OnOff(OnOff::Off)))));
@@ -1655,7 +2173,7 @@ fn test_watch() {
         assert_eq!(manager.add_getter_tags(vec![
             GetterSelector::new().with_id(getter_id_1_1.clone()),
             GetterSelector::new().with_id(getter_id_2.clone()),
-        ], vec![tag_1.clone()]), 2);
+        ], vec![tag_1.clone()], User::None), 2);
         match rx_watch_2.recv().unwrap() {
             Event::GetterAdded(ref id) if *id == getter_id_1_1 => { }
             other => panic!("Unexpected event {:?}", other)
@@ -1667,7 +2185,7 @@ fn test_watch() {
 
         assert_eq!(manager.remove_getter_tags(vec![
             GetterSelector::new().with_id(getter_id_1_1.clone()),
-        ], vec![tag_1.clone()]), 1);
+        ], vec![tag_1.clone()], User::None), 1);
         match rx_watch_2.recv().unwrap() {
             Event::GetterRemoved(ref id) if *id == getter_id_1_1 => { }
             other => panic!("Unexpected event {:?}", other)
@@ -1691,3 +2209,738 @@ fn test_watch() {
 
     println!("");
 }
+
+#[test]
+fn test_watch_options() {
+    println!("");
+
+    let clock = Arc::new(FakeClock::new());
+    let manager = AdapterManager::new(None).with_clock(clock.clone());
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let getter_1 = Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        capabilities: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::RelativeHumidity,
+        },
+    };
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let tweak_1 = adapter_1.get_tweak();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(getter_1.clone()).unwrap();
+
+    let inject = |percent: f64| {
+        tweak_1(Tweak::InjectGetterValue(getter_id_1.clone(),
+            Ok(Some(Value::Percentage(Percentage::Percent(percent))))));
+    };
+    let expect = |rx: &Receiver<Event>, percent: f64| {
+        match rx.recv().unwrap() {
+            Event::EnterRange { ref from, value: Value::Percentage(ref value) }
+                if *from == getter_id_1 && value.as_percent() == percent => {},
+            other => panic!("Unexpected event {:?}, expected {}", other, percent)
+        }
+    };
+
+    println!("* Changes smaller than min_delta are not delivered, regardless of the unit.");
+    let (tx_watch, rx_watch) = channel();
+    let guard = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), WatchOptions {
+        min_delta: Some(5.),
+        .. WatchOptions::default()
+    }, Box::new(tx_watch), User::None);
+
+    inject(50.);
+    expect(&rx_watch, 50.);
+    inject(52.);
+    inject(46.);
+    inject(56.);
+    expect(&rx_watch, 56.);
+    tweak_1(Tweak::InjectGetterValue(getter_id_1.clone(),
+        Ok(Some(Value::Percentage(Percentage::Ratio(0.58))))));
+    inject(62.);
+    expect(&rx_watch, 62.);
+    assert_matches!(rx_watch.try_recv(), Err(_));
+    drop(guard);
+
+    println!("* Values arriving sooner than min_interval are held back and replaced by newer values.");
+    let (tx_watch, rx_watch) = channel();
+    let guard = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), WatchOptions {
+        min_interval: Some(Duration::from(chrono::Duration::milliseconds(500))),
+        .. WatchOptions::default()
+    }, Box::new(tx_watch), User::None);
+
+    inject(10.);
+    expect(&rx_watch, 10.);
+    inject(20.);
+    inject(30.);
+    assert_matches!(rx_watch.try_recv(), Err(_));
+    clock.advance(std::time::Duration::from_millis(400));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+    clock.advance(std::time::Duration::from_millis(100));
+    expect(&rx_watch, 30.);
+    clock.advance(std::time::Duration::new(1, 0));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+    drop(guard);
+
+    println!("* Values are only delivered once the channel has been quiet for the debounce duration.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), WatchOptions {
+        debounce: Some(Duration::from(chrono::Duration::milliseconds(200))),
+        .. WatchOptions::default()
+    }, Box::new(tx_watch), User::None);
+
+    inject(70.);
+    clock.advance(std::time::Duration::from_millis(100));
+    inject(80.);
+    clock.advance(std::time::Duration::from_millis(100));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+    clock.advance(std::time::Duration::from_millis(100));
+    expect(&rx_watch, 80.);
+    clock.advance(std::time::Duration::new(1, 0));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+
+    println!("* With `initial`, the current value is delivered as soon as the watch starts, if it is in range.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard_in_range = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), WatchOptions {
+        initial: true,
+        .. WatchOptions::default()
+    }, Box::new(tx_watch), User::None);
+    expect(&rx_watch, 80.);
+    assert_matches!(rx_watch.try_recv(), Err(_));
+
+    let (tx_watch, rx_watch) = channel();
+    let _guard_out_of_range = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(Range::Leq(Value::Percentage(Percentage::Percent(50.))))
+    )]), WatchOptions {
+        initial: true,
+        .. WatchOptions::default()
+    }, Box::new(tx_watch), User::None);
+    assert_matches!(rx_watch.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_send_atomic() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let id_2 = Id::<AdapterId>::new("adapter id 2");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let service_id_2 = Id::<ServiceId>::new("service id 2");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let setter_id_2 = Id::<Setter>::new("setter id 2");
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let adapter_2 = FakeAdapter::new(&id_2);
+    let tweak_1 = adapter_1.get_tweak();
+    let tweak_2 = adapter_2.get_tweak();
+    let rx_adapter_1 = adapter_1.take_rx();
+    let rx_adapter_2 = adapter_2.take_rx();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_adapter(Arc::new(adapter_2)).unwrap();
+
+    for &(ref adapter, ref service, ref setter) in &[(&id_1, &service_id_1, &setter_id_1),
+                                                     (&id_2, &service_id_2, &setter_id_2)] {
+        manager.add_service(Service::empty((*service).clone(), (*adapter).clone())).unwrap();
+        manager.add_setter(Channel {
+            id: (*setter).clone(),
+            service: (*service).clone(),
+            adapter: (*adapter).clone(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Setter {
+                updated: None,
+                kind: ChannelKind::LightOn,
+            },
+        }).unwrap();
+    }
+    let send_on = || manager.send_values_atomic(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::On))]), User::None);
+
+    println!("* Sending atomically to adapters that do not support it sends nothing.");
+    tweak_1(Tweak::InjectTransactionSupport(TransactionSupport::ReadBack));
+    let data = send_on();
+    assert_eq!(data.len(), 2);
+    assert_matches!(data.get(&setter_id_1), Some(&Ok(SendOutcome::NotSent)));
+    match data.get(&setter_id_2) {
+        Some(&Err(Error::NotTransactional(ref id))) if *id == id_2 => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    assert_matches!(rx_adapter_1.try_recv(), Err(_));
+    assert_matches!(rx_adapter_2.try_recv(), Err(_));
+
+    println!("* Sending atomically to adapters that read back previous values sends everything.");
+    tweak_2(Tweak::InjectTransactionSupport(TransactionSupport::ReadBack));
+    manager.send_values(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::Off))]), User::None);
+    for rx in &[&rx_adapter_1, &rx_adapter_2] {
+        assert_matches!(rx.try_recv(), Ok(Effect::ValueSent(_, Value::OnOff(OnOff::Off))));
+    }
+    let data = send_on();
+    assert_eq!(data.len(), 2);
+    for id in vec![&setter_id_1, &setter_id_2] {
+        assert_matches!(data.get(id), Some(&Ok(SendOutcome::Sent)));
+    }
+    for rx in &[&rx_adapter_1, &rx_adapter_2] {
+        assert_matches!(rx.try_recv(), Ok(Effect::ValueSent(_, Value::OnOff(OnOff::On))));
+        assert_matches!(rx.try_recv(), Err(_));
+    }
+
+    println!("* If a value cannot be sent, the values already sent are reverted.");
+    tweak_2(Tweak::InjectSetterError(setter_id_2.clone(), Some(Error::InternalError(InternalError::InvalidInitialService))));
+    let data = manager.send_values_atomic(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::Off))]), User::None);
+    assert_eq!(data.len(), 2);
+    assert_matches!(data.get(&setter_id_1), Some(&Ok(SendOutcome::Reverted)));
+    assert_matches!(data.get(&setter_id_2), Some(&Err(Error::InternalError(InternalError::InvalidInitialService))));
+    assert_matches!(rx_adapter_1.try_recv(), Ok(Effect::ValueSent(_, Value::OnOff(OnOff::Off))));
+    assert_matches!(rx_adapter_1.try_recv(), Ok(Effect::ValueSent(_, Value::OnOff(OnOff::On))));
+    assert_matches!(rx_adapter_1.try_recv(), Err(_));
+    assert_matches!(rx_adapter_2.try_recv(), Err(_));
+
+    println!("* If a two-phase adapter cannot prepare a value, nothing is sent.");
+    tweak_2(Tweak::InjectTransactionSupport(TransactionSupport::TwoPhase));
+    let data = manager.send_values_atomic(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::Off))]), User::None);
+    assert_eq!(data.len(), 2);
+    assert_matches!(data.get(&setter_id_1), Some(&Ok(SendOutcome::NotSent)));
+    assert_matches!(data.get(&setter_id_2), Some(&Err(Error::InternalError(InternalError::InvalidInitialService))));
+    assert_matches!(rx_adapter_1.try_recv(), Err(_));
+    assert_matches!(rx_adapter_2.try_recv(), Err(_));
+
+    println!("* Two-phase adapters are committed once everything else has been sent.");
+    tweak_2(Tweak::InjectSetterError(setter_id_2.clone(), None));
+    let data = manager.send_values_atomic(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::Off))]), User::None);
+    assert_eq!(data.len(), 2);
+    for id in vec![&setter_id_1, &setter_id_2] {
+        assert_matches!(data.get(id), Some(&Ok(SendOutcome::Sent)));
+    }
+    for rx in &[&rx_adapter_1, &rx_adapter_2] {
+        assert_matches!(rx.try_recv(), Ok(Effect::ValueSent(_, Value::OnOff(OnOff::Off))));
+        assert_matches!(rx.try_recv(), Err(_));
+    }
+
+    println!("* If a value cannot be sent, two-phase adapters are rolled back.");
+    tweak_1(Tweak::InjectSetterError(setter_id_1.clone(), Some(Error::InternalError(InternalError::InvalidInitialService))));
+    let data = send_on();
+    assert_eq!(data.len(), 2);
+    assert_matches!(data.get(&setter_id_1), Some(&Err(Error::InternalError(InternalError::InvalidInitialService))));
+    assert_matches!(data.get(&setter_id_2), Some(&Ok(SendOutcome::NotSent)));
+    assert_matches!(rx_adapter_1.try_recv(), Err(_));
+    assert_matches!(rx_adapter_2.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_send_atomic_timeout() {
+    println!("");
+
+    let manager = AdapterManager::new(None).with_timeout(std::time::Duration::from_millis(200));
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let id_2 = Id::<AdapterId>::new("adapter id 2");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let service_id_2 = Id::<ServiceId>::new("service id 2");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let setter_id_2 = Id::<Setter>::new("setter id 2");
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let adapter_2 = FakeAdapter::new(&id_2);
+    let tweak_1 = adapter_1.get_tweak();
+    let tweak_2 = adapter_2.get_tweak();
+    let rx_adapter_1 = adapter_1.take_rx();
+    let rx_adapter_2 = adapter_2.take_rx();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_adapter(Arc::new(adapter_2)).unwrap();
+
+    for &(ref adapter, ref service, ref setter) in &[(&id_1, &service_id_1, &setter_id_1),
+                                                     (&id_2, &service_id_2, &setter_id_2)] {
+        manager.add_service(Service::empty((*service).clone(), (*adapter).clone())).unwrap();
+        manager.add_setter(Channel {
+            id: (*setter).clone(),
+            service: (*service).clone(),
+            adapter: (*adapter).clone(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Setter {
+                updated: None,
+                kind: ChannelKind::LightOn,
+            },
+        }).unwrap();
+    }
+    tweak_1(Tweak::InjectTransactionSupport(TransactionSupport::ReadBack));
+    tweak_2(Tweak::InjectTransactionSupport(TransactionSupport::ReadBack));
+    manager.send_values(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::Off))]), User::None);
+    for rx in &[&rx_adapter_1, &rx_adapter_2] {
+        assert_matches!(rx.try_recv(), Ok(Effect::ValueSent(_, Value::OnOff(OnOff::Off))));
+    }
+
+    println!("* A value sent too late is restored once it has been sent.");
+    tweak_1(Tweak::InjectSendDelay(std::time::Duration::from_millis(300)));
+    let data = manager.send_values_atomic(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::On))]), User::None);
+    assert_eq!(data.len(), 2);
+    match data.get(&setter_id_1) {
+        Some(&Err(Error::Timeout(ref id))) if *id == id_1 => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    assert_matches!(data.get(&setter_id_2), Some(&Ok(SendOutcome::Reverted)));
+    for rx in &[&rx_adapter_1, &rx_adapter_2] {
+        assert_matches!(rx.try_recv(), Ok(Effect::ValueSent(_, Value::OnOff(OnOff::On))));
+        assert_matches!(rx.try_recv(), Ok(Effect::ValueSent(_, Value::OnOff(OnOff::Off))));
+        assert_matches!(rx.try_recv(), Err(_));
+    }
+
+    println!("");
+}
+
+#[test]
+fn test_virtual_channels() {
+    println!("");
+
+    let mut db_path = get_db_environment();
+    db_path.set_extension("virtual.sqlite");
+
+    let manager = Arc::new(AdapterManager::new(None));
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+    let virtual_id = Id::<Getter>::new("getter:average@virtual");
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let tweak_1 = adapter_1.get_tweak();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    for id in vec![&getter_id_1, &getter_id_2] {
+        manager.add_getter(Channel {
+            id: id.clone(),
+            service: service_id_1.clone(),
+            adapter: id_1.clone(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Getter {
+                updated: None,
+                kind: ChannelKind::OvenTemperature,
+            },
+        }).unwrap();
+    }
+
+    let inject = |id: &Id<Getter>, celsius: f64| {
+        tweak_1(Tweak::InjectGetterValue(id.clone(), Ok(Some(Value::Temperature(Temperature::C(celsius))))));
+    };
+    let expect = |rx: &Receiver<Event>, celsius: f64| {
+        match rx.recv().unwrap() {
+            Event::EnterRange { ref from, value: Value::Temperature(ref value) }
+                if *from == virtual_id && value.as_c() == celsius => {},
+            other => panic!("Unexpected event {:?}, expected {}", other, celsius)
+        }
+    };
+
+    println!("* Definitions are checked.");
+    let virtual_channels = VirtualChannels::init(&manager, &db_path).unwrap();
+    assert_matches!(virtual_channels.put_getter(r#"{
+        "id": "getter:average@virtual",
+        "aggregation": "Avg",
+        "source": {"kind": "OvenTemperature"}
+    }"#), Err(VirtualError::ParseError(_)));
+    assert_matches!(virtual_channels.put_getter(r#"{
+        "id": "getter:average@virtual",
+        "kind": "OpenClosed",
+        "aggregation": "Avg",
+        "source": {"kind": "OvenTemperature"}
+    }"#), Err(VirtualError::ParseError(_)));
+    assert_matches!(virtual_channels.put_getter(r#"{
+        "id": "getter:average@virtual",
+        "kind": "LightOn",
+        "aggregation": "Any",
+        "source": {"kind": "OvenTemperature"}
+    }"#), Err(VirtualError::ParseError(_)));
+
+    println!("* Virtual getters are registered as getters.");
+    virtual_channels.put_getter(r#"{
+        "id": "getter:average@virtual",
+        "kind": "OvenTemperature",
+        "aggregation": "Avg",
+        "source": {"kind": "OvenTemperature"}
+    }"#).unwrap();
+    let channels = manager.get_getter_channels(vec![GetterSelector::new().with_kind(ChannelKind::OvenTemperature)]);
+    assert_eq!(channels.len(), 3);
+
+    println!("* Virtual getters cannot take the id of another getter.");
+    assert_matches!(virtual_channels.put_getter(r#"{
+        "id": "getter id 1",
+        "kind": "OvenTemperature",
+        "aggregation": "Max",
+        "source": {"kind": "OvenTemperature"}
+    }"#), Err(VirtualError::TaxonomyError(Error::InternalError(InternalError::DuplicateGetter(_)))));
+    let channels = manager.get_getter_channels(vec![GetterSelector::new().with_id(getter_id_1.clone())]);
+    assert_eq!(channels[0].adapter, id_1);
+    assert_eq!(virtual_channels.get_getters().len(), 1);
+
+    println!("* Virtual getters are recomputed whenever an underlying getter changes.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new().with_id(virtual_id.clone())],
+        Exactly::Always
+    )]), Box::new(tx_watch), User::None);
+
+    inject(&getter_id_1, 20.);
+    expect(&rx_watch, 20.);
+    inject(&getter_id_2, 24.);
+    expect(&rx_watch, 22.);
+
+    let values = manager.fetch_values(vec![GetterSelector::new().with_id(virtual_id.clone())], User::None);
+    match values.get(&virtual_id) {
+        Some(&Ok(Some(Value::Temperature(ref value)))) if value.as_c() == 22. => {},
+        other => panic!("Unexpected value {:?}", other)
+    }
+
+    println!("* Getters removed from the selection are not aggregated anymore.");
+    manager.remove_getter(&getter_id_2).unwrap();
+    expect(&rx_watch, 20.);
+
+    println!("* Stopping the adapter stops watching the underlying getters.");
+    manager.remove_adapter(&VirtualChannels::id()).unwrap();
+    assert!(virtual_channels.get_getters().is_empty());
+
+    println!("* Definitions persist.");
+    assert!(manager.get_getter_channels(vec![GetterSelector::new().with_id(virtual_id.clone())]).is_empty());
+    let virtual_channels = VirtualChannels::init(&manager, &db_path).unwrap();
+    assert_eq!(virtual_channels.get_getters().len(), 1);
+    assert_eq!(manager.get_getter_channels(vec![GetterSelector::new().with_id(virtual_id.clone())]).len(), 1);
+
+    virtual_channels.remove_getter(&virtual_id).unwrap();
+    assert!(manager.get_getter_channels(vec![GetterSelector::new().with_id(virtual_id.clone())]).is_empty());
+    assert_matches!(virtual_channels.remove_getter(&virtual_id), Err(VirtualError::NoSuchGetter(_)));
+
+    std::fs::remove_file(&db_path).unwrap();
+
+    println!("");
+}
+
+#[test]
+fn test_virtual_channels_acl() {
+    println!("");
+
+    let mut db_path = get_db_environment();
+    db_path.set_extension("virtual-acl.sqlite");
+    let mut acl_path = get_db_environment();
+    acl_path.set_extension("virtual-acl-grants.sqlite");
+
+    let acl = Acl::new(&acl_path).unwrap();
+    acl.put_grant(&Id::new("everything"), r#"{
+        "grantee": {"user": 1},
+        "read": [{"id": "getter:max@virtual"}, {"id": "getter id 1"}]
+    }"#).unwrap();
+    acl.put_grant(&Id::new("virtual only"), r#"{
+        "grantee": {"user": 2},
+        "read": [{"id": "getter:max@virtual"}]
+    }"#).unwrap();
+
+    let manager = Arc::new(AdapterManager::new(None).with_acl(acl));
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let virtual_id = Id::<Getter>::new("getter:max@virtual");
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let tweak_1 = adapter_1.get_tweak();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        capabilities: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OvenTemperature,
+        },
+    }).unwrap();
+
+    let virtual_channels = VirtualChannels::init(&manager, &db_path).unwrap();
+    virtual_channels.put_getter(r#"{
+        "id": "getter:max@virtual",
+        "kind": "OvenTemperature",
+        "aggregation": "Max",
+        "source": {"kind": "OvenTemperature"}
+    }"#).unwrap();
+    let select_virtual = || target_map(vec![(
+        vec![GetterSelector::new().with_id(virtual_id.clone())],
+        Exactly::Always
+    )]);
+
+    println!("* Users may not watch virtual getters that aggregate getters that they may not read.");
+    let (tx_denied, rx_denied) = channel();
+    let _guard_denied = manager.watch_values(select_virtual(), Box::new(tx_denied), User::Id(2));
+    match rx_denied.recv().unwrap() {
+        Event::InitializationError { ref channel, error: Error::PermissionDenied } if *channel == virtual_id => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* Users may watch virtual getters that aggregate getters that they may read.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(select_virtual(), Box::new(tx_watch), User::Id(1));
+    tweak_1(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::Temperature(Temperature::C(20.))))));
+    match rx_watch.recv().unwrap() {
+        Event::EnterRange { ref from, .. } if *from == virtual_id => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* The same rules apply to fetching.");
+    let select_virtual = || vec![GetterSelector::new().with_id(virtual_id.clone())];
+    assert_matches!(manager.fetch_values(select_virtual(), User::Id(1)).get(&virtual_id), Some(&Ok(Some(_))));
+    assert_matches!(manager.fetch_values(select_virtual(), User::Id(2)).get(&virtual_id), Some(&Err(Error::PermissionDenied)));
+    assert_matches!(manager.fetch_values(select_virtual(), User::System).get(&virtual_id), Some(&Ok(Some(_))));
+    assert_matches!(rx_denied.try_recv(), Err(_));
+
+    manager.remove_adapter(&VirtualChannels::id()).unwrap();
+    std::fs::remove_file(&db_path).unwrap();
+    std::fs::remove_file(&acl_path).unwrap();
+
+    println!("");
+}
+
+#[test]
+fn test_groups() {
+    println!("");
+
+    let mut db_path = get_db_environment();
+    db_path.set_extension("groups.sqlite");
+
+    let manager = Arc::new(AdapterManager::new(None));
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_ids = vec![Id::<Getter>::new("getter id 1"), Id::<Getter>::new("getter id 2"),
+                          Id::<Getter>::new("getter id 3")];
+    let setter_ids = vec![Id::<Setter>::new("setter id 1"), Id::<Setter>::new("setter id 2")];
+    let tag_kitchen = Id::<TagId>::new("kitchen");
+    let group_getter = Groups::getter_id("kitchen-lights");
+    let group_setter = Groups::setter_id("kitchen-lights");
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let tweak_1 = adapter_1.get_tweak();
+    let rx_adapter_1 = adapter_1.take_rx();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+
+    let getter = |id: &Id<Getter>| Channel {
+        id: id.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        capabilities: None,
+        tags: vec![tag_kitchen.clone()].into_iter().collect(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+        },
+    };
+    for id in &getter_ids[0..2] {
+        manager.add_getter(getter(id)).unwrap();
+    }
+    for id in &setter_ids {
+        manager.add_setter(Channel {
+            id: id.clone(),
+            service: service_id_1.clone(),
+            adapter: id_1.clone(),
+            last_seen: None,
+            capabilities: None,
+            tags: vec![tag_kitchen.clone()].into_iter().collect(),
+            mechanism: Setter {
+                updated: None,
+                kind: ChannelKind::LightOn,
+            },
+        }).unwrap();
+    }
+
+    let inject = |id: &Id<Getter>, value: OnOff| {
+        tweak_1(Tweak::InjectGetterValue(id.clone(), Ok(Some(Value::OnOff(value)))));
+    };
+    let expect = |rx: &Receiver<Event>, expected: OnOff| {
+        match rx.recv().unwrap() {
+            Event::EnterRange { ref from, value: Value::OnOff(ref value) }
+                if *from == group_getter && *value == expected => {},
+            other => panic!("Unexpected event {:?}, expected {:?}", other, expected)
+        }
+    };
+
+    println!("* Definitions are checked.");
+    let groups = Groups::init(&manager, &db_path).unwrap();
+    assert_matches!(groups.put_group(r#"{
+        "name": "kitchen-lights",
+        "kind": "LightOn",
+        "members": {"tags": ["kitchen"]},
+        "policy": "Avg"
+    }"#), Err(GroupError::ParseError(_)));
+
+    println!("* Groups are services with a getter and a setter.");
+    assert_eq!(groups.put_group(r#"{
+        "name": "kitchen-lights",
+        "kind": "LightOn",
+        "members": {"tags": ["kitchen"]},
+        "policy": "Any"
+    }"#).unwrap(), "kitchen-lights");
+    assert_eq!(manager.get_getter_channels(vec![GetterSelector::new().with_parent(Groups::service_id("kitchen-lights"))]).len(), 1);
+    assert_eq!(manager.get_setter_channels(vec![SetterSelector::new().with_parent(Groups::service_id("kitchen-lights"))]).len(), 1);
+
+    println!("* The getter of the group aggregates the values of the members.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new().with_id(group_getter.clone())],
+        Exactly::Always
+    )]), Box::new(tx_watch), User::None);
+
+    inject(&getter_ids[0], OnOff::Off);
+    expect(&rx_watch, OnOff::Off);
+    inject(&getter_ids[1], OnOff::On);
+    expect(&rx_watch, OnOff::On);
+    inject(&getter_ids[1], OnOff::Off);
+    expect(&rx_watch, OnOff::Off);
+
+    println!("* Getters joining or leaving the selection join or leave the group.");
+    manager.add_getter(getter(&getter_ids[2])).unwrap();
+    inject(&getter_ids[2], OnOff::On);
+    expect(&rx_watch, OnOff::On);
+    manager.remove_getter(&getter_ids[2]).unwrap();
+    expect(&rx_watch, OnOff::Off);
+
+    let values = manager.fetch_values(vec![GetterSelector::new().with_id(group_getter.clone())], User::None);
+    assert_matches!(values.get(&group_getter), Some(&Ok(Some(Value::OnOff(OnOff::Off)))));
+
+    println!("* Values sent to the setter of the group are sent to all the members.");
+    let results = manager.send_values(target_map(vec![(
+        vec![SetterSelector::new().with_id(group_setter.clone())],
+        Value::OnOff(OnOff::On)
+    )]), User::None);
+    assert_matches!(results.get(&group_setter), Some(&Ok(())));
+    let mut sent = HashSet::new();
+    for _ in 0..2 {
+        match rx_adapter_1.recv().unwrap() {
+            Effect::ValueSent(id, Value::OnOff(OnOff::On)) => { sent.insert(id); }
+            other => panic!("Unexpected effect {:?}", other)
+        }
+    }
+    let expected : HashSet<_> = setter_ids.iter().cloned().collect();
+    assert_eq!(sent, expected);
+    assert_matches!(rx_adapter_1.try_recv(), Err(_));
+
+    println!("* Stopping the adapter stops watching the members.");
+    manager.remove_adapter(&Groups::id()).unwrap();
+    assert!(groups.get_groups().is_empty());
+
+    println!("* Definitions persist.");
+    assert!(manager.get_getter_channels(vec![GetterSelector::new().with_id(group_getter.clone())]).is_empty());
+    let groups = Groups::init(&manager, &db_path).unwrap();
+    assert_eq!(groups.get_groups().len(), 1);
+    assert_eq!(manager.get_getter_channels(vec![GetterSelector::new().with_id(group_getter.clone())]).len(), 1);
+
+    println!("* Removing a group does not affect its members.");
+    groups.remove_group("kitchen-lights").unwrap();
+    assert!(manager.get_getter_channels(vec![GetterSelector::new().with_id(group_getter.clone())]).is_empty());
+    assert_eq!(manager.get_setter_channels(vec![SetterSelector::new().with_tags(vec![tag_kitchen.clone()])]).len(), 2);
+    assert_matches!(groups.remove_group("kitchen-lights"), Err(GroupError::NoSuchGroup(_)));
+
+    std::fs::remove_file(&db_path).unwrap();
+
+    println!("");
+}
+
+#[test]
+fn test_groups_acl() {
+    println!("");
+
+    let mut db_path = get_db_environment();
+    db_path.set_extension("groups-acl.sqlite");
+    let mut acl_path = get_db_environment();
+    acl_path.set_extension("groups-acl-grants.sqlite");
+
+    let acl = Acl::new(&acl_path).unwrap();
+    acl.put_grant(&Id::new("everything"), r#"{
+        "grantee": {"user": 1},
+        "read": [{"id": "getter:lights@groups.link.mozilla.org"}, {"id": "getter id 1"}]
+    }"#).unwrap();
+    acl.put_grant(&Id::new("group only"), r#"{
+        "grantee": {"user": 2},
+        "read": [{"id": "getter:lights@groups.link.mozilla.org"}]
+    }"#).unwrap();
+
+    let manager = Arc::new(AdapterManager::new(None).with_acl(acl));
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let group_getter = Groups::getter_id("lights");
+
+    let adapter_1 = FakeAdapter::new(&id_1);
+    let tweak_1 = adapter_1.get_tweak();
+    manager.add_adapter(Arc::new(adapter_1)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        capabilities: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+        },
+    }).unwrap();
+
+    let groups = Groups::init(&manager, &db_path).unwrap();
+    groups.put_group(r#"{
+        "name": "lights",
+        "kind": "LightOn",
+        "members": {}
+    }"#).unwrap();
+
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new().with_id(group_getter.clone())],
+        Exactly::Always
+    )]), Box::new(tx_watch), User::Id(1));
+    tweak_1(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    match rx_watch.recv().unwrap() {
+        Event::EnterRange { ref from, .. } if *from == group_getter => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* Users may only read groups whose members they may read.");
+    let select_group = || vec![GetterSelector::new().with_id(group_getter.clone())];
+    assert_matches!(manager.fetch_values(select_group(), User::Id(1)).get(&group_getter),
+        Some(&Ok(Some(Value::OnOff(OnOff::On)))));
+    assert_matches!(manager.fetch_values(select_group(), User::Id(2)).get(&group_getter),
+        Some(&Err(Error::PermissionDenied)));
+
+    manager.remove_adapter(&Groups::id()).unwrap();
+    std::fs::remove_file(&db_path).unwrap();
+    std::fs::remove_file(&acl_path).unwrap();
+
+    println!("");
+}
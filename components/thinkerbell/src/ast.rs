@@ -8,6 +8,8 @@ use foxbox_taxonomy::selector::*;
 use foxbox_taxonomy::services::*;
 use foxbox_taxonomy::values::*;
 
+use chrono::Duration as ChronoDuration;
+
 use std::marker::PhantomData;
 
 /// A thinkerbell scrip"t.
@@ -43,10 +45,15 @@ impl Parser<Script<UncheckedCtx>> for Script<UncheckedCtx> {
 ///
 /// A single rule is represented as an object with the following fields:
 ///
-/// - conditions (array of Match): the conditions in which to execute
-///   the code – *all* conditions must be met;
-/// - execute (array of Statement): the code to execute once all conditions
-///   are met.
+/// - conditions (array of Condition): the conditions in which to execute
+///   the code;
+/// - mode ("all" | "any", optional): whether *all* conditions must be
+///   met or whether *any* of them is sufficient. Defaults to "all";
+/// - execute (array of Action): the code to execute once the conditions
+///   are met;
+/// - cancel_when_unmet (bool, optional): if `true`, delayed statements
+///   that have not been executed yet are cancelled once the conditions
+///   stop being met. Defaults to `false`.
 ///
 /// ```
 /// extern crate foxbox_thinkerbell;
@@ -76,12 +83,21 @@ impl Parser<Script<UncheckedCtx>> for Script<UncheckedCtx> {
 #[derive(Debug)]
 pub struct Rule<Ctx> where Ctx: Context {
     /// The condition in which to execute the trigger. The condition
-    /// is matched once *all* the `Match` branches are true. Whenever
-    /// `conditions` was false and becomes true, we execute `execute`.
-    pub conditions: Vec<Match<Ctx>>,
+    /// is matched once the `Condition` branches are true, as specified
+    /// by `mode`. Whenever `conditions` was false and becomes true, we
+    /// execute `execute`.
+    pub conditions: Vec<Condition<Ctx>>,
+
+    /// How `conditions` are combined.
+    pub mode: Mode,
 
     /// Stuff to do once `condition` is met.
-    pub execute: Vec<Statement<Ctx>>,
+    pub execute: Vec<Action<Ctx>>,
+
+    /// If `true`, statements of `execute` that are still waiting for their
+    /// `delay` are cancelled once `conditions` stop being met. In any case,
+    /// they are cancelled once the script is stopped.
+    pub cancel_when_unmet: bool,
 
     pub phantom: PhantomData<Ctx>,
 }
@@ -92,19 +108,213 @@ impl Parser<Rule<UncheckedCtx>> for Rule<UncheckedCtx> {
 
     fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
         let conditions = try!(path.push("conditions",
-            |path| Match::take_vec(path, source, "conditions"))
+            |path| Condition::take_vec(path, source, "conditions"))
+        );
+        let mode = try!(path.push("mode",
+            |path| Mode::take_default(path, source, "mode"))
         );
         let execute = try!(path.push("execute",
-            |path| Statement::take_vec(path, source, "execute"))
+            |path| Action::take_vec(path, source, "execute"))
         );
+        let cancel_when_unmet = match path.push("cancel_when_unmet",
+            |path| bool::take_opt(path, source, "cancel_when_unmet"))
+        {
+            None => false,
+            Some(result) => try!(result)
+        };
         Ok(Rule {
             conditions: conditions,
+            mode: mode,
             execute: execute,
+            cancel_when_unmet: cancel_when_unmet,
             phantom: PhantomData,
         })
     }
 }
 
+impl<Ctx> Rule<Ctx> where Ctx: Context {
+    /// All the `Match` of this rule, including those of nested groups, in
+    /// depth-first order.
+    pub fn matches(&self) -> Vec<&Match<Ctx>> {
+        let mut result = vec![];
+        for condition in &self.conditions {
+            condition.collect_matches(&mut result);
+        }
+        result
+    }
+
+    /// All the `Statement` of this rule, including those of sequences, in
+    /// depth-first order, each with the delay after which it must be
+    /// executed once the rule is triggered.
+    pub fn statements(&self) -> Vec<(ChronoDuration, &Statement<Ctx>)> {
+        let mut result = vec![];
+        for action in &self.execute {
+            action.collect_statements(ChronoDuration::zero(), &mut result);
+        }
+        result
+    }
+}
+
+/// The manner in which a list of conditions is combined.
+///
+/// # JSON
+///
+/// Either the string `"all"` or the string `"any"`.
+///
+/// ```
+/// extern crate foxbox_thinkerbell;
+/// extern crate foxbox_taxonomy;
+///
+/// use foxbox_thinkerbell::ast::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// # fn main() {
+/// assert_eq!(Mode::from_str(r#""any""#).unwrap(), Mode::Any);
+/// assert_eq!(Mode::from_str(r#""all""#).unwrap(), Mode::All);
+/// assert!(Mode::from_str(r#""some""#).is_err());
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The conditions are met once *all* of them are met.
+    All,
+
+    /// The conditions are met once *any* of them is met.
+    Any,
+}
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::All
+    }
+}
+impl Mode {
+    /// Combine the results of individual conditions.
+    pub fn combine<I>(&self, results: I) -> bool where I: IntoIterator<Item = bool> {
+        let mut results = results.into_iter();
+        match *self {
+            Mode::All => results.all(|is_met| is_met),
+            Mode::Any => results.any(|is_met| is_met),
+        }
+    }
+
+    /// Parse an optional field, defaulting to `Mode::All`.
+    fn take_default(path: Path, source: &mut JSON, field_name: &str) -> Result<Self, ParseError> {
+        match Mode::take_opt(path, source, field_name) {
+            None => Ok(Mode::default()),
+            Some(result) => result
+        }
+    }
+}
+impl Parser<Mode> for Mode {
+    fn description() -> String {
+        "Mode".to_owned()
+    }
+
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match source.as_string() {
+            Some("all") => Ok(Mode::All),
+            Some("any") => Ok(Mode::Any),
+            Some(other) => Err(ParseError::unknown_constant(other, &path)),
+            None => Err(ParseError::type_error("Mode", &path, "string"))
+        }
+    }
+}
+
+/// A condition of a rule: either an individual `Match` or a group of
+/// conditions.
+///
+/// # JSON
+///
+/// An object with a field `conditions` is a group, represented with the
+/// following fields:
+///
+/// - conditions (array of Condition): the nested conditions;
+/// - mode ("all" | "any", optional): how the nested conditions are
+///   combined. Defaults to "all".
+///
+/// Any other object is a `Match`.
+///
+/// ```
+/// extern crate foxbox_thinkerbell;
+/// extern crate foxbox_taxonomy;
+///
+/// use foxbox_thinkerbell::ast::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// # fn main() {
+/// let source = r#"{
+///   "mode": "any",
+///   "conditions": [{
+///     "source": [{"id": "my getter"}],
+///     "kind": "LightOn",
+///     "range": {"Eq": {"OnOff": "On"}}
+///   }, {
+///     "source": [{"id": "my other getter"}],
+///     "kind": "LightOn",
+///     "range": {"Eq": {"OnOff": "On"}}
+///   }]
+/// }"#;
+///
+/// match Condition::<UncheckedCtx>::from_str(&source).unwrap() {
+///   Condition::Group(group) => {
+///     assert_eq!(group.mode, Mode::Any);
+///     assert_eq!(group.conditions.len(), 2);
+///   }
+///   _ => panic!()
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub enum Condition<Ctx> where Ctx: Context {
+    Match(Match<Ctx>),
+    Group(ConditionGroup<Ctx>),
+}
+impl<Ctx> Condition<Ctx> where Ctx: Context {
+    fn collect_matches<'a>(&'a self, result: &mut Vec<&'a Match<Ctx>>) {
+        match *self {
+            Condition::Match(ref match_) => result.push(match_),
+            Condition::Group(ref group) => {
+                for condition in &group.conditions {
+                    condition.collect_matches(result);
+                }
+            }
+        }
+    }
+}
+impl Parser<Condition<UncheckedCtx>> for Condition<UncheckedCtx> {
+    fn description() -> String {
+        "Condition".to_owned()
+    }
+
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        if source.find("conditions").is_some() {
+            let conditions = try!(path.push("conditions",
+                |path| Condition::take_vec(path, source, "conditions"))
+            );
+            let mode = try!(path.push("mode",
+                |path| Mode::take_default(path, source, "mode"))
+            );
+            Ok(Condition::Group(ConditionGroup {
+                conditions: conditions,
+                mode: mode,
+                phantom: PhantomData,
+            }))
+        } else {
+            Match::parse(path, source).map(Condition::Match)
+        }
+    }
+}
+
+/// A group of conditions, combined as specified by `mode`.
+#[derive(Debug)]
+pub struct ConditionGroup<Ctx> where Ctx: Context {
+    pub conditions: Vec<Condition<Ctx>>,
+
+    pub mode: Mode,
+
+    pub phantom: PhantomData<Ctx>,
+}
+
 /// An individual match.
 ///
 /// Matchs always take the form: "data received from getter channel
@@ -210,8 +420,10 @@ impl Parser<Match<UncheckedCtx>> for Match<UncheckedCtx> {
 ///
 /// A statement is represented as an object with the following fields:
 /// - destination (array of SetterSelector);
-/// - value (Value);
+/// - value (Expression);
 /// - kind (ChannelKind);
+/// - delay (Duration, optional) - if provided, wait for `delay` before
+///   sending the value.
 ///
 /// ```
 /// extern crate foxbox_thinkerbell;
@@ -230,7 +442,11 @@ impl Parser<Match<UncheckedCtx>> for Match<UncheckedCtx> {
 /// }"#;
 ///
 /// let statement = Statement::<UncheckedCtx>::from_str(&source).unwrap();
-/// assert_eq!(statement.value, Value::OnOff(OnOff::Off));
+/// if let Expression::Value(ref value) = statement.value {
+///   assert_eq!(*value, Value::OnOff(OnOff::Off));
+/// } else {
+///   panic!();
+/// }
 /// assert_eq!(statement.kind, ChannelKind::LightOn);
 /// # }
 /// ```
@@ -241,10 +457,10 @@ pub struct Statement<Ctx> where Ctx: Context {
     /// added/removed) without rebooting the script.
     pub destination: Vec<SetterSelector>,
 
-    /// Data to send to the resource. During compilation, we check
-    /// that the type of `value` is compatible with that of
-    /// `destination`.
-    pub value: Value,
+    /// Data to send to the resource. It is evaluated whenever the
+    /// statement is executed. During compilation, we check that the
+    /// type of `value` is compatible with that of `destination`.
+    pub value: Expression,
 
     /// The kind of channel expected from `destination`, e.g. "close
     /// the door", "set the temperature", etc. During compilation, we
@@ -252,6 +468,11 @@ pub struct Statement<Ctx> where Ctx: Context {
     /// offer `kind`.
     pub kind: ChannelKind,
 
+    /// If specified, wait for `delay` before sending the value. In a
+    /// `Sequence`, the delay starts once the previous action is complete,
+    /// otherwise once the rule is triggered.
+    pub delay: Option<Duration>,
+
     pub phantom: PhantomData<Ctx>,
 }
 impl Parser<Statement<UncheckedCtx>> for Statement<UncheckedCtx> {
@@ -267,17 +488,298 @@ impl Parser<Statement<UncheckedCtx>> for Statement<UncheckedCtx> {
             |path| ChannelKind::take(path, source, "kind"))
         );
         let value = try!(path.push("value",
-            |path| Value::take(path, source, "value"))
+            |path| Expression::take(path, source, "value"))
         );
+        let delay = match path.push("delay",
+            |path| Duration::take_opt(path, source, "delay"))
+        {
+            None => None,
+            Some(result) => Some(try!(result))
+        };
         Ok(Statement {
             destination: destination,
             value: value,
             kind: kind,
+            delay: delay,
             phantom: PhantomData,
         })
     }
 }
 
+/// The value sent by a statement, computed whenever the statement is
+/// executed.
+///
+/// # JSON
+///
+/// An expression is one of:
+///
+/// - a Value, e.g. `{"OnOff": "On"}`;
+/// - a number, e.g. `2`, which takes the unit of the value it is combined
+///   with (degrees of the same scale for temperatures, seconds for durations);
+/// - `{"Trigger": {}}`, the value that caused the rule to be triggered;
+/// - `{"Fetch": {"source": [GetterSelector], "kind": ChannelKind}}`, the
+///   current value of a getter of `source` that provides a value;
+/// - `{"Add": [Expression, Expression]}`, and similarly `Sub`, `Mul`, `Div`;
+/// - `{"Template": {"format": string, "args": [Expression]}}`, a `String`
+///   obtained by replacing each `{}` of `format` with the next argument.
+///
+/// ```
+/// extern crate foxbox_thinkerbell;
+/// extern crate foxbox_taxonomy;
+///
+/// use foxbox_thinkerbell::ast::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// # fn main() {
+/// let source = r#"{
+///   "Add": [{
+///     "Fetch": {
+///       "source": [{"id": "outdoor thermometer"}],
+///       "kind": "Thermostat"
+///     }
+///   }, 2]
+/// }"#;
+///
+/// match Expression::from_str(&source).unwrap() {
+///   Expression::Arithmetic(Operator::Add, left, right) => {
+///     if let Expression::Fetch { .. } = *left {} else { panic!() }
+///     if let Expression::Number(2.) = *right {} else { panic!() }
+///   }
+///   _ => panic!()
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub enum Expression {
+    /// A constant value.
+    Value(Value),
+
+    /// A number, without unit.
+    Number(f64),
+
+    /// The value that caused the rule to be triggered. During compilation,
+    /// we check that all the matches of the rule have the same type.
+    Trigger,
+
+    /// The current value of a getter. During compilation, we restrict
+    /// `source` to the getters that offer `kind`.
+    Fetch {
+        source: Vec<GetterSelector>,
+        kind: ChannelKind,
+    },
+
+    /// Arithmetics. During compilation, we check that the operator
+    /// supports the types of both operands.
+    Arithmetic(Operator, Box<Expression>, Box<Expression>),
+
+    /// String templating. Each `{}` in `format` is replaced with the
+    /// next value of `args`.
+    Template {
+        format: String,
+        args: Vec<Expression>,
+    },
+}
+impl Parser<Expression> for Expression {
+    fn description() -> String {
+        "Expression".to_owned()
+    }
+
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match *source {
+            JSON::I64(_) | JSON::U64(_) | JSON::F64(_) =>
+                return f64::parse(path, source).map(Expression::Number),
+            _ => {}
+        }
+        if let JSON::Object(ref mut obj) = *source {
+            if obj.len() == 1 {
+                if obj.contains_key("Trigger") {
+                    return Ok(Expression::Trigger)
+                }
+                if let Some(fetch) = obj.get_mut("Fetch") {
+                    return path.push("Fetch", |path| {
+                        let sources = try!(path.push("source",
+                            |path| GetterSelector::take_vec(path, fetch, "source"))
+                        );
+                        let kind = try!(path.push("kind",
+                            |path| ChannelKind::take(path, fetch, "kind"))
+                        );
+                        Ok(Expression::Fetch {
+                            source: sources,
+                            kind: kind,
+                        })
+                    })
+                }
+                if let Some(template) = obj.get_mut("Template") {
+                    return path.push("Template", |path| {
+                        let format = try!(path.push("format",
+                            |path| String::take(path, template, "format"))
+                        );
+                        let args = match path.push("args",
+                            |path| Expression::take_vec_opt(path, template, "args"))
+                        {
+                            None => vec![],
+                            Some(result) => try!(result)
+                        };
+                        Ok(Expression::Template {
+                            format: format,
+                            args: args,
+                        })
+                    })
+                }
+                let ops = [Operator::Add, Operator::Sub, Operator::Mul, Operator::Div];
+                for &op in &ops {
+                    let key = op.as_str();
+                    if let Some(operands) = obj.get_mut(key) {
+                        let mut operands = try!(path.push(key, |path| Vec::<Expression>::parse(path, operands)));
+                        if operands.len() != 2 {
+                            return Err(ParseError::type_error(key, &path, "an array of two expressions"))
+                        }
+                        let right = operands.pop().unwrap();
+                        let left = operands.pop().unwrap();
+                        return Ok(Expression::Arithmetic(op, Box::new(left), Box::new(right)))
+                    }
+                }
+            }
+        }
+        Value::parse(path, source).map(Expression::Value)
+    }
+}
+
+/// An arithmetic operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+impl Operator {
+    /// The name of the operator in JSON, e.g. `"Add"`.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Operator::Add => "Add",
+            Operator::Sub => "Sub",
+            Operator::Mul => "Mul",
+            Operator::Div => "Div",
+        }
+    }
+}
+
+/// Something to do once the conditions of a rule are met: either an
+/// individual `Statement` or a `Sequence` of actions.
+///
+/// # JSON
+///
+/// An object with a field `sequence` is a sequence, represented with the
+/// following fields:
+///
+/// - sequence (array of Action): the actions to execute, in order;
+/// - delay (Duration, optional): if provided, wait for `delay` before
+///   starting the sequence.
+///
+/// Any other object is a `Statement`.
+///
+/// ```
+/// extern crate foxbox_thinkerbell;
+/// extern crate foxbox_taxonomy;
+///
+/// use foxbox_thinkerbell::ast::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// # fn main() {
+/// let source = r#"{
+///   "sequence": [{
+///     "destination": [{"id": "my setter"}],
+///     "value": {"OnOff": "On"},
+///     "kind": "LightOn"
+///   }, {
+///     "destination": [{"id": "my setter"}],
+///     "value": {"OnOff": "Off"},
+///     "kind": "LightOn",
+///     "delay": 300
+///   }]
+/// }"#;
+///
+/// match Action::<UncheckedCtx>::from_str(&source).unwrap() {
+///   Action::Sequence(sequence) => assert_eq!(sequence.actions.len(), 2),
+///   _ => panic!()
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub enum Action<Ctx> where Ctx: Context {
+    Statement(Statement<Ctx>),
+    Sequence(Sequence<Ctx>),
+}
+impl<Ctx> Action<Ctx> where Ctx: Context {
+    /// Collect the statements of this action, assuming that it starts after
+    /// `start`. Return the delay after which the action is complete.
+    fn collect_statements<'a>(&'a self, start: ChronoDuration,
+        result: &mut Vec<(ChronoDuration, &'a Statement<Ctx>)>) -> ChronoDuration
+    {
+        match *self {
+            Action::Statement(ref statement) => {
+                let date = start + delay_of(&statement.delay);
+                result.push((date, statement));
+                date
+            }
+            Action::Sequence(ref sequence) => {
+                let mut date = start + delay_of(&sequence.delay);
+                for action in &sequence.actions {
+                    date = action.collect_statements(date, result);
+                }
+                date
+            }
+        }
+    }
+}
+impl Parser<Action<UncheckedCtx>> for Action<UncheckedCtx> {
+    fn description() -> String {
+        "Action".to_owned()
+    }
+
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        if source.find("sequence").is_some() {
+            let actions = try!(path.push("sequence",
+                |path| Action::take_vec(path, source, "sequence"))
+            );
+            let delay = match path.push("delay",
+                |path| Duration::take_opt(path, source, "delay"))
+            {
+                None => None,
+                Some(result) => Some(try!(result))
+            };
+            Ok(Action::Sequence(Sequence {
+                actions: actions,
+                delay: delay,
+                phantom: PhantomData,
+            }))
+        } else {
+            Statement::parse(path, source).map(Action::Statement)
+        }
+    }
+}
+
+fn delay_of(delay: &Option<Duration>) -> ChronoDuration {
+    match *delay {
+        None => ChronoDuration::zero(),
+        Some(ref delay) => delay.clone().into()
+    }
+}
+
+/// A sequence of actions, executed in order.
+#[derive(Debug)]
+pub struct Sequence<Ctx> where Ctx: Context {
+    /// The actions to execute. The `delay` of each action is counted from
+    /// the moment the previous action is complete.
+    pub actions: Vec<Action<Ctx>>,
+
+    /// If specified, wait for `delay` before starting the sequence.
+    pub delay: Option<Duration>,
+
+    pub phantom: PhantomData<Ctx>,
+}
+
 
 /// A manner of representing internal nodes.
 ///
@@ -4,30 +4,46 @@
 //! performs the following transformations and checks:
 //!
 //! - Ensure that the `Script` has at least one `Rule`.
-//! - Ensure that each `Rule` has at least one `Match`.
-//! - Ensure that each `Rule` has at least one `Statement`.
+//! - Ensure that each `Rule` and each group of conditions has at least
+//!   one `Condition`.
+//! - Ensure that each `Rule` and each `Sequence` has at least one `Action`.
 //! - Ensure that each `Match` has at least one `source`.
 //! - Ensure that each `Statement` has at least one `destination`.
 //! - Ensure that in each `Match`, the type of `range` matches
 //!   the `kind`.
 //! - Ensure that in each `Statement`, the type of `value` matches
-//!   the `kind`.
+//!   the `kind`, including through arithmetics, fetches and references
+//!   to the value that triggered the rule.
 //! - Transform each `Match` to make sure that the kind of the
 //!   `source` matches the `kind`, even if devices change.
 //! - Transform each `Statement` to make sure that the kind of the
 //!   `destination` matches the `kind`, even if devices change.
+//! - Transform each `Fetch` expression to make sure that the kind of
+//!   the `source` matches the `kind`, even if devices change.
+//!
+//! Compilation does not stop at the first problem: all the problems of
+//! a script are reported as `Diagnostic`s, each with the path of the
+//! offending field in the JSON source.
 
-use ast::{ Script, Rule, Statement, Match, Context, UncheckedCtx };
-use util::*;
+use ast::{ Script, Rule, Statement, Match, Condition, ConditionGroup, Action, Sequence, Expression, Operator, Context, UncheckedCtx };
 
 use foxbox_taxonomy::api::API;
-use foxbox_taxonomy::values::Duration;
+use foxbox_taxonomy::parse::Path;
+use foxbox_taxonomy::values::{ Duration, Type };
 
 use transformable_channels::mpsc::*;
 
 use std::fmt::{ Debug, Formatter, Error as FmtError };
 use std::marker::PhantomData;
 
+/// A variant of `try!` for `Option`.
+macro_rules! try_opt {
+    ($e:expr) => (match $e {
+        Some(value) => value,
+        None => return None
+    })
+}
+
 /// The environment in which the code is meant to be executed.  This
 /// can typically be instantiated either with actual bindings to
 /// devices, or with a unit-testing framework. // FIXME: Move this to run.rs
@@ -66,10 +82,10 @@ pub enum SourceError {
     /// The source doesn't define any rule.
     NoRule,
 
-    /// A rule doesn't have any statements.
+    /// A rule or a sequence doesn't have any statements.
     NoStatement,
 
-    /// A rule doesn't have any condition.
+    /// A rule or a group of conditions doesn't have any condition.
     NoMatch,
 
     /// A match doesn't have any source.
@@ -77,6 +93,9 @@ pub enum SourceError {
 
     /// A statement doesn't have any destination.
     NoStatementDestination,
+
+    /// A fetch expression doesn't have any source.
+    NoFetchSource,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -91,6 +110,53 @@ pub enum TypeError {
     /// The value has one type but this type is incompatible with the
     /// kind of the `Statement`.
     KindAndValueDoNotAgree,
+
+    /// The value refers to the value that triggered the rule, but the
+    /// matches of the rule do not all have the same type.
+    InvalidTrigger,
+
+    /// The operator of an arithmetic expression does not support the
+    /// types of its operands.
+    InvalidOperands,
+}
+
+/// The type of an expression. Numbers have no unit, hence no `Type`.
+#[derive(Clone, Debug, PartialEq)]
+enum ExpressionType {
+    Value(Type),
+    Number,
+}
+
+/// Types that support arithmetics.
+fn is_quantity(typ: &Type) -> bool {
+    match *typ {
+        Type::Temperature | Type::Duration | Type::ExtNumeric | Type::Percentage |
+        Type::Power | Type::Energy | Type::Illuminance | Type::Pressure | Type::Distance => true,
+        _ => false
+    }
+}
+
+/// Determine the type of an arithmetic expression, if it is valid.
+fn arithmetic_type(op: Operator, left: &ExpressionType, right: &ExpressionType) -> Option<ExpressionType> {
+    use self::ExpressionType::Number;
+    use self::ExpressionType::Value as Val;
+    use ast::Operator::*;
+    match (op, left, right) {
+        (_, &Number, &Number) => Some(Number),
+        (Add, &Val(Type::String), &Val(Type::String)) => Some(Val(Type::String)),
+        (Add, &Val(Type::TimeStamp), &Val(Type::Duration)) |
+        (Add, &Val(Type::Duration), &Val(Type::TimeStamp)) |
+        (Sub, &Val(Type::TimeStamp), &Val(Type::Duration)) => Some(Val(Type::TimeStamp)),
+        (Add, &Val(ref a), &Val(ref b)) |
+        (Sub, &Val(ref a), &Val(ref b)) if a == b && is_quantity(a) => Some(Val(a.clone())),
+        (Add, &Val(ref a), &Number) |
+        (Sub, &Val(ref a), &Number) |
+        (Mul, &Val(ref a), &Number) |
+        (Div, &Val(ref a), &Number) |
+        (Add, &Number, &Val(ref a)) |
+        (Mul, &Number, &Val(ref a)) if is_quantity(a) => Some(Val(a.clone())),
+        _ => None
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -99,6 +165,44 @@ pub enum Error {
     TypeError(TypeError),
 }
 
+/// A problem detected during compilation.
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    /// The path in the JSON source at which the problem was detected,
+    /// e.g. `.rules[0].execute[1].value`.
+    pub path: String,
+
+    /// The problem itself.
+    pub error: Error,
+}
+
+/// Compile each element of `vec`, even if some of them fail to compile, so
+/// as to report the problems of all of them.
+fn map_all<T, U, F>(path: &Path, vec: Vec<T>, mut cb: F) -> Option<Vec<U>>
+    where F: FnMut(&Path, T) -> Option<U>
+{
+    let mut result = Vec::with_capacity(vec.len());
+    let mut is_ok = true;
+    for (value, index) in vec.into_iter().zip(0..) {
+        match path.push_index(index, |path| cb(&path, value)) {
+            Some(compiled) => result.push(compiled),
+            None => is_ok = false
+        }
+    }
+    if is_ok {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+fn report(path: &Path, diagnostics: &mut Vec<Diagnostic>, error: Error) {
+    diagnostics.push(Diagnostic {
+        path: path.to_string(),
+        error: error
+    });
+}
+
 pub struct Compiler<Env> where Env: ExecutableDevEnv {
     phantom: PhantomData<Env>,
 }
@@ -111,65 +215,166 @@ impl<Env> Compiler<Env> where Env: ExecutableDevEnv {
     }
 
     /// Attempt to compile a script.
+    ///
+    /// In case of failure, produce the first problem. Use
+    /// `compile_with_diagnostics` to obtain all the problems.
     pub fn compile(&self, script: Script<UncheckedCtx>)
                    -> Result<Script<CompiledCtx<Env>>, Error> {
-        self.compile_script(script)
+        self.compile_with_diagnostics(script)
+            .map_err(|mut diagnostics| diagnostics.remove(0).error)
     }
 
-    fn compile_script(&self, script: Script<UncheckedCtx>) -> Result<Script<CompiledCtx<Env>>, Error>
-    {
-        if script.rules.len() == 0 {
-            return Err(Error::SourceError(SourceError::NoRule));
+    /// Attempt to compile a script.
+    ///
+    /// In case of failure, produce a non-empty list of all the problems
+    /// detected, each with its path in the JSON source.
+    pub fn compile_with_diagnostics(&self, script: Script<UncheckedCtx>)
+                   -> Result<Script<CompiledCtx<Env>>, Vec<Diagnostic>> {
+        let mut diagnostics = vec![];
+        match self.compile_script(&Path::new(), script, &mut diagnostics) {
+            Some(script) => {
+                debug_assert!(diagnostics.is_empty());
+                Ok(script)
+            }
+            None => {
+                debug_assert!(!diagnostics.is_empty());
+                Err(diagnostics)
+            }
         }
-        let rules = try!(map(script.rules, |rule| {
-            self.compile_rule(rule)
-        }));
-        Ok(Script {
-            name: script.name,
-            rules: rules,
+    }
+
+    fn compile_script(&self, path: &Path, script: Script<UncheckedCtx>, diagnostics: &mut Vec<Diagnostic>) -> Option<Script<CompiledCtx<Env>>>
+    {
+        path.push("rules", |path| {
+            if script.rules.len() == 0 {
+                report(&path, diagnostics, Error::SourceError(SourceError::NoRule));
+                return None;
+            }
+            let rules = try_opt!(map_all(&path, script.rules, |path, rule| {
+                self.compile_rule(path, rule, diagnostics)
+            }));
+            Some(Script {
+                name: script.name,
+                rules: rules,
+                phantom: PhantomData
+            })
+        })
+    }
+
+    fn compile_rule(&self, path: &Path, trigger: Rule<UncheckedCtx>, diagnostics: &mut Vec<Diagnostic>) -> Option<Rule<CompiledCtx<Env>>>
+    {
+        // The type of the value that triggers the rule, if all matches agree.
+        let trigger_type = {
+            let mut types = trigger.matches().into_iter().map(|match_| match_.kind.get_type());
+            let first = types.next();
+            if types.all(|typ| Some(typ) == first) {
+                first
+            } else {
+                None
+            }
+        };
+        let Rule { conditions, mode, execute, cancel_when_unmet, .. } = trigger;
+        let conditions = path.push("conditions", |path| {
+            if conditions.len() == 0 {
+                report(&path, diagnostics, Error::SourceError(SourceError::NoMatch));
+                return None;
+            }
+            map_all(&path, conditions, |path, condition| {
+                self.compile_condition(path, condition, diagnostics)
+            })
+        });
+        let execute = path.push("execute", |path| {
+            if execute.len() == 0 {
+                report(&path, diagnostics, Error::SourceError(SourceError::NoStatement));
+                return None;
+            }
+            map_all(&path, execute, |path, action| {
+                self.compile_action(path, action, &trigger_type, diagnostics)
+            })
+        });
+        Some(Rule {
+            conditions: try_opt!(conditions),
+            mode: mode,
+            execute: try_opt!(execute),
+            cancel_when_unmet: cancel_when_unmet,
             phantom: PhantomData
         })
     }
 
-    fn compile_rule(&self, trigger: Rule<UncheckedCtx>) -> Result<Rule<CompiledCtx<Env>>, Error>
+    fn compile_action(&self, path: &Path, action: Action<UncheckedCtx>, trigger_type: &Option<Type>, diagnostics: &mut Vec<Diagnostic>) -> Option<Action<CompiledCtx<Env>>>
     {
-        if trigger.execute.len() == 0 {
-            return Err(Error::SourceError(SourceError::NoStatement));
+        match action {
+            Action::Statement(statement) => self.compile_statement(path, statement, trigger_type, diagnostics).map(Action::Statement),
+            Action::Sequence(Sequence { actions, delay, .. }) => {
+                let actions = try_opt!(path.push("sequence", |path| {
+                    if actions.len() == 0 {
+                        report(&path, diagnostics, Error::SourceError(SourceError::NoStatement));
+                        return None;
+                    }
+                    map_all(&path, actions, |path, action| {
+                        self.compile_action(path, action, trigger_type, diagnostics)
+                    })
+                }));
+                Some(Action::Sequence(Sequence {
+                    actions: actions,
+                    delay: delay,
+                    phantom: PhantomData
+                }))
+            }
         }
-        if trigger.conditions.len() == 0 {
-            return Err(Error::SourceError(SourceError::NoMatch));
+    }
+
+    fn compile_condition(&self, path: &Path, condition: Condition<UncheckedCtx>, diagnostics: &mut Vec<Diagnostic>) -> Option<Condition<CompiledCtx<Env>>>
+    {
+        match condition {
+            Condition::Match(match_) => self.compile_match(path, match_, diagnostics).map(Condition::Match),
+            Condition::Group(ConditionGroup { conditions, mode, .. }) => {
+                let conditions = try_opt!(path.push("conditions", |path| {
+                    if conditions.len() == 0 {
+                        report(&path, diagnostics, Error::SourceError(SourceError::NoMatch));
+                        return None;
+                    }
+                    map_all(&path, conditions, |path, condition| {
+                        self.compile_condition(path, condition, diagnostics)
+                    })
+                }));
+                Some(Condition::Group(ConditionGroup {
+                    conditions: conditions,
+                    mode: mode,
+                    phantom: PhantomData
+                }))
+            }
         }
-        let conditions = try!(map(trigger.conditions, |match_| {
-            self.compile_match(match_)
-        }));
-        let execute = try!(map(trigger.execute, |statement| {
-            self.compile_statement(statement)
-        }));
-        Ok(Rule {
-            conditions: conditions,
-            execute: execute,
-            phantom: PhantomData
-        })
     }
 
-    fn compile_match(&self, match_: Match<UncheckedCtx>) -> Result<Match<CompiledCtx<Env>>, Error>
+    fn compile_match(&self, path: &Path, match_: Match<UncheckedCtx>, diagnostics: &mut Vec<Diagnostic>) -> Option<Match<CompiledCtx<Env>>>
     {
+        let mut is_ok = true;
         if match_.source.len() == 0 {
-            return Err(Error::SourceError(SourceError::NoMatchSource));
+            path.push("source", |path| report(&path, diagnostics, Error::SourceError(SourceError::NoMatchSource)));
+            is_ok = false;
         }
-        let typ = match match_.range.get_type() {
-            Err(_) => return Err(Error::TypeError(TypeError::InvalidRange)),
-            Ok(typ) => typ
-        };
-        if match_.kind.get_type() != typ {
-            return Err(Error::TypeError(TypeError::KindAndRangeDoNotAgree));
+        path.push("range", |path| {
+            match match_.range.get_type() {
+                Err(_) => {
+                    report(&path, diagnostics, Error::TypeError(TypeError::InvalidRange));
+                    is_ok = false;
+                }
+                Ok(typ) => if match_.kind.get_type() != typ {
+                    report(&path, diagnostics, Error::TypeError(TypeError::KindAndRangeDoNotAgree));
+                    is_ok = false;
+                }
+            }
+        });
+        if !is_ok {
+            return None;
         }
         let source = match_.source
             .iter()
             .map(|input| input.clone()
                  .with_kind(match_.kind.clone()))
             .collect();
-        Ok(Match {
+        Some(Match {
             source: source,
             kind: match_.kind,
             range: match_.range,
@@ -178,24 +383,98 @@ impl<Env> Compiler<Env> where Env: ExecutableDevEnv {
         })
     }
 
-    fn compile_statement(&self, statement: Statement<UncheckedCtx>) -> Result<Statement<CompiledCtx<Env>>, Error>
+    fn compile_statement(&self, path: &Path, statement: Statement<UncheckedCtx>, trigger_type: &Option<Type>, diagnostics: &mut Vec<Diagnostic>) -> Option<Statement<CompiledCtx<Env>>>
     {
-        if statement.destination.len() == 0 {
-            return Err(Error::SourceError(SourceError::NoStatementDestination));
+        let Statement { destination, value, kind, delay, .. } = statement;
+        let mut is_ok = true;
+        if destination.len() == 0 {
+            path.push("destination", |path| report(&path, diagnostics, Error::SourceError(SourceError::NoStatementDestination)));
+            is_ok = false;
         }
-        if statement.kind.get_type() != statement.value.get_type() {
-            return Err(Error::TypeError(TypeError::KindAndValueDoNotAgree));
+        let value = path.push("value", |path| {
+            let (value, typ) = try_opt!(self.compile_expression(&path, value, trigger_type, diagnostics));
+            if typ != ExpressionType::Value(kind.get_type()) {
+                report(&path, diagnostics, Error::TypeError(TypeError::KindAndValueDoNotAgree));
+                return None;
+            }
+            Some(value)
+        });
+        if !is_ok {
+            return None;
         }
-        let destination = statement.destination
+        let value = try_opt!(value);
+        let destination = destination
             .iter()
             .map(|output| output.clone()
-                 .with_kind(statement.kind.clone()))
+                 .with_kind(kind.clone()))
             .collect();
-        Ok(Statement {
+        Some(Statement {
             destination: destination,
-            value: statement.value,
-            kind: statement.kind,
+            value: value,
+            kind: kind,
+            delay: delay,
             phantom: PhantomData
         })
     }
+
+    fn compile_expression(&self, path: &Path, expression: Expression, trigger_type: &Option<Type>, diagnostics: &mut Vec<Diagnostic>) -> Option<(Expression, ExpressionType)>
+    {
+        match expression {
+            Expression::Value(value) => {
+                let typ = value.get_type();
+                Some((Expression::Value(value), ExpressionType::Value(typ)))
+            }
+            Expression::Number(number) => Some((Expression::Number(number), ExpressionType::Number)),
+            Expression::Trigger => match *trigger_type {
+                None => {
+                    report(path, diagnostics, Error::TypeError(TypeError::InvalidTrigger));
+                    None
+                }
+                Some(ref typ) => Some((Expression::Trigger, ExpressionType::Value(typ.clone())))
+            },
+            Expression::Fetch { source, kind } => {
+                if source.len() == 0 {
+                    path.push("Fetch", |path| path.push("source", |path| {
+                        report(&path, diagnostics, Error::SourceError(SourceError::NoFetchSource))
+                    }));
+                    return None;
+                }
+                let source = source
+                    .iter()
+                    .map(|input| input.clone()
+                         .with_kind(kind.clone()))
+                    .collect();
+                let typ = kind.get_type();
+                Some((Expression::Fetch {
+                    source: source,
+                    kind: kind
+                }, ExpressionType::Value(typ)))
+            }
+            Expression::Arithmetic(op, left, right) => {
+                path.push(op.as_str(), |path| {
+                    let left = path.push_index(0, |path| self.compile_expression(&path, *left, trigger_type, diagnostics));
+                    let right = path.push_index(1, |path| self.compile_expression(&path, *right, trigger_type, diagnostics));
+                    let ((left, left_type), (right, right_type)) = (try_opt!(left), try_opt!(right));
+                    match arithmetic_type(op, &left_type, &right_type) {
+                        None => {
+                            report(&path, diagnostics, Error::TypeError(TypeError::InvalidOperands));
+                            None
+                        }
+                        Some(typ) => Some((Expression::Arithmetic(op, Box::new(left), Box::new(right)), typ))
+                    }
+                })
+            }
+            Expression::Template { format, args } => {
+                let args = try_opt!(path.push("Template", |path| path.push("args", |path| {
+                    map_all(&path, args, |path, arg| {
+                        self.compile_expression(path, arg, trigger_type, diagnostics).map(|(arg, _)| arg)
+                    })
+                })));
+                Some((Expression::Template {
+                    format: format,
+                    args: args
+                }, ExpressionType::Value(Type::String)))
+            }
+        }
+    }
 }
@@ -1,13 +1,15 @@
-use ast::Script;
-use compile::ExecutableDevEnv;
+use ast::{ Action, Condition, Expression, Script, UncheckedCtx };
+use compile::{ Compiler, Diagnostic as CompileDiagnostic, ExecutableDevEnv };
 use run::{ Execution, ExecutionEvent, Error as RunError, StartStopError };
 
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::path::{ Path as FilePath, PathBuf as FilePathBuf };
 
-use foxbox_taxonomy::api::{ ResultMap, User };
+use foxbox_taxonomy::api::{ API, ResultMap, User };
 use foxbox_taxonomy::parse::*;
+use foxbox_taxonomy::selector::{ GetterSelector, SetterSelector };
+use foxbox_taxonomy::services::ChannelKind;
 use foxbox_taxonomy::util::{ Id };
 
 use rusqlite;
@@ -29,10 +31,48 @@ pub enum Error {
     ParseError(String),
 }
 
+/// A problem detected while validating a script (see `ScriptManager::validate`).
+#[derive(Serialize, Debug)]
+pub enum Diagnostic {
+    /// The source could not be parsed. Parsing stops at the first error,
+    /// so there is at most one such diagnostic.
+    ParseError(ParseError),
+
+    /// The script could not be compiled.
+    CompileError(CompileDiagnostic),
+
+    /// A selector does not match any channel at the moment. This does not
+    /// prevent the script from being installed, as matching channels may
+    /// appear later, e.g. when a device is plugged in.
+    NoMatchingChannel {
+        /// The path of the selector in the JSON source, e.g.
+        /// `.rules[0].conditions[0].source[0]`.
+        path: String
+    },
+}
+
 /// A type for ensuring type-safety (Id<ScriptId>).
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Eq)]
 pub struct ScriptId;
 
+/// The owner of a script, as stored in the database.
+fn owner_to_db(owner: &User) -> i32 {
+    match *owner {
+        User::Id(id) => id,
+        User::None => -1,
+        User::System => -2
+    }
+}
+
+/// The owner of a script, from its value in the database.
+fn owner_from_db(value: i32) -> User {
+    match value {
+        -1 => User::None,
+        -2 => User::System,
+        _ => User::Id(value)
+    }
+}
+
 /// ScriptManager stores a persistent database of scripts and executes them.
 /// Each script can be individually enabled or disabled.
 /// When a script is enabled, it is always running (unless an error occured during launch).
@@ -65,6 +105,7 @@ impl<Env, T> ScriptManager<Env, T>
     ///   source, // Script source. Defines the behavior of the rule.
     ///   is_enabled, // Boolean flag that indicates if the rule is enabled or disabled.
     ///   owner // User identifier (i32) of the owner of the rule. Defaults to no user (-1).
+    ///         // The box itself is -2.
     /// }
     ///
     /// The database stores the raw script source, but only after the source has been parsed
@@ -101,10 +142,7 @@ impl<Env, T> ScriptManager<Env, T>
             let source: String = try!(row.get_checked(1));
             let is_enabled: bool = try!(row.get_checked(2));
             let owner_value: i32 = try!(row.get_checked(3));
-            let owner: User = match owner_value {
-                -1 => User::None,
-                _ => User::Id(owner_value)
-            };
+            let owner = owner_from_db(owner_value);
 
             if is_enabled {
                 result_map.insert(
@@ -126,10 +164,7 @@ impl<Env, T> ScriptManager<Env, T>
     pub fn put(&mut self, id: &Id<ScriptId>, source: &String, owner: &User) -> Result<(), Error> {
         try!(self.start_script(&id, &source, &owner));
 
-        let owner_value: i32 = match *owner {
-            User::Id(id) => id,
-            User::None => -1
-        };
+        let owner_value = owner_to_db(owner);
 
         let connection = try!(rusqlite::Connection::open(&self.path));
         connection.execute("INSERT OR REPLACE INTO scripts (id, source, is_enabled, owner)
@@ -216,13 +251,39 @@ impl<Env, T> ScriptManager<Env, T>
         let first_row = try!(try!(rows.nth(0).ok_or(Error::NoSuchScriptError)));
         let source = try!(first_row.get_checked(0));
         let owner_value = try!(first_row.get_checked(1));
-        let owner = match owner_value {
-            -1 => User::None,
-            _ => User::Id(owner_value)
-        };
+        let owner = owner_from_db(owner_value);
         Ok((source, owner))
     }
 
+    /// Check a script without installing or running it.
+    ///
+    /// Return all the problems detected, or an empty vector if the script
+    /// could be installed as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the script could not be checked at all, i.e. if the
+    /// compiler could not be created.
+    pub fn validate(&self, source: &str) -> Result<Vec<Diagnostic>, Error> {
+        let script = match Script::from_str(source) {
+            Err(err) => return Ok(vec![Diagnostic::ParseError(err)]),
+            Ok(script) => script
+        };
+        let mut diagnostics = vec![];
+        {
+            let mut checker = SelectorChecker {
+                api: self.env.api(),
+                diagnostics: &mut diagnostics
+            };
+            checker.check_script(&Path::new(), &script);
+        }
+        let compiler = try!(Compiler::<Env>::new().map_err(RunError::CompileError));
+        if let Err(errors) = compiler.compile_with_diagnostics(script) {
+            diagnostics.extend(errors.into_iter().map(Diagnostic::CompileError));
+        }
+        Ok(diagnostics)
+    }
+
     /// Return true if the script is enabled.
     pub fn is_enabled(&self, id: &Id<ScriptId>) -> bool {
         self.runners.contains_key(id)
@@ -252,6 +313,90 @@ impl<Env, T> ScriptManager<Env, T>
     }
 }
 
+/// Walk through a script, looking for selectors that do not match any channel.
+struct SelectorChecker<'a, A> where A: API + 'a {
+    api: &'a A,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, A> SelectorChecker<'a, A> where A: API {
+    fn check_script(&mut self, path: &Path, script: &Script<UncheckedCtx>) {
+        path.push("rules", |path| {
+            for (rule, index) in script.rules.iter().zip(0..) {
+                path.push_index(index, |path| {
+                    path.push("conditions", |path| self.check_conditions(&path, &rule.conditions));
+                    path.push("execute", |path| self.check_actions(&path, &rule.execute));
+                })
+            }
+        })
+    }
+
+    fn check_conditions(&mut self, path: &Path, conditions: &[Condition<UncheckedCtx>]) {
+        for (condition, index) in conditions.iter().zip(0..) {
+            path.push_index(index, |path| match *condition {
+                Condition::Match(ref match_) =>
+                    path.push("source", |path| self.check_getters(&path, &match_.source, &match_.kind)),
+                Condition::Group(ref group) =>
+                    path.push("conditions", |path| self.check_conditions(&path, &group.conditions)),
+            })
+        }
+    }
+
+    fn check_actions(&mut self, path: &Path, actions: &[Action<UncheckedCtx>]) {
+        for (action, index) in actions.iter().zip(0..) {
+            path.push_index(index, |path| match *action {
+                Action::Statement(ref statement) => {
+                    path.push("destination", |path| self.check_setters(&path, &statement.destination, &statement.kind));
+                    path.push("value", |path| self.check_expression(&path, &statement.value));
+                }
+                Action::Sequence(ref sequence) =>
+                    path.push("sequence", |path| self.check_actions(&path, &sequence.actions)),
+            })
+        }
+    }
+
+    fn check_expression(&mut self, path: &Path, expression: &Expression) {
+        match *expression {
+            Expression::Fetch { ref source, ref kind } =>
+                path.push("Fetch", |path| path.push("source", |path| self.check_getters(&path, source, kind))),
+            Expression::Arithmetic(op, ref left, ref right) => path.push(op.as_str(), |path| {
+                path.push_index(0, |path| self.check_expression(&path, left));
+                path.push_index(1, |path| self.check_expression(&path, right));
+            }),
+            Expression::Template { ref args, .. } => path.push("Template", |path| path.push("args", |path| {
+                for (arg, index) in args.iter().zip(0..) {
+                    path.push_index(index, |path| self.check_expression(&path, arg))
+                }
+            })),
+            Expression::Value(_) | Expression::Number(_) | Expression::Trigger => {}
+        }
+    }
+
+    fn check_getters(&mut self, path: &Path, selectors: &[GetterSelector], kind: &ChannelKind) {
+        for (selector, index) in selectors.iter().zip(0..) {
+            let selector = selector.clone().with_kind(kind.clone());
+            if self.api.get_getter_channels(vec![selector]).is_empty() {
+                self.report(path, index);
+            }
+        }
+    }
+
+    fn check_setters(&mut self, path: &Path, selectors: &[SetterSelector], kind: &ChannelKind) {
+        for (selector, index) in selectors.iter().zip(0..) {
+            let selector = selector.clone().with_kind(kind.clone());
+            if self.api.get_setter_channels(vec![selector]).is_empty() {
+                self.report(path, index);
+            }
+        }
+    }
+
+    fn report(&mut self, path: &Path, index: usize) {
+        let path = path.push_index(index, |path| path.to_string());
+        self.diagnostics.push(Diagnostic::NoMatchingChannel {
+            path: path
+        });
+    }
+}
 
 impl From<rusqlite::Error> for Error {
     fn from(err: rusqlite::Error) -> Error {
@@ -1,24 +1,29 @@
 //! Launching and running the script
 
-use ast::{ Script, Statement, UncheckedCtx } ;
+use ast::{ Condition, Expression, Mode, Operator, Script, Statement, UncheckedCtx } ;
 use compile::{ Compiler, CompiledCtx, ExecutableDevEnv } ;
-pub use compile::{ Error as CompileError, SourceError, TypeError };
+pub use compile::{ Error as CompileError, Diagnostic, SourceError, TypeError };
 use compile;
 
 use foxbox_taxonomy::api;
 use foxbox_taxonomy::api::{ API, Error as APIError, Targetted, User, WatchEvent };
+use foxbox_taxonomy::parse::{ JSON, ToJSON };
 use foxbox_taxonomy::services::{ Getter, Setter };
 use foxbox_taxonomy::util::{ Exactly, Id };
-use foxbox_taxonomy::values::Duration;
+use foxbox_taxonomy::values::{ Duration, Quantity, Temperature, TimeStamp, Value };
 
 use transformable_channels::mpsc::*;
 
-use std::collections::HashSet;
+use chrono::Duration as ChronoDuration;
+
+use std::collections::{ HashMap, HashSet };
 use std::fmt;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::thread;
-use std::sync::Mutex;
+use std::sync::{ Arc, Mutex };
+
+use serde_json;
 
 /// Running and controlling a single script.
 pub struct Execution<Env> where Env: ExecutableDevEnv + Debug + 'static {
@@ -154,6 +159,11 @@ pub enum ExecutionEvent {
     ChannelError {
         id: Id<Getter>,
         error: APIError,
+    },
+    StatementError {
+        rule_index: usize,
+        statement_index: usize,
+        error: Error,
     }
 }
 
@@ -179,6 +189,9 @@ enum ExecutionOp {
         /// `true` if the condition is now met, `false` otherwise.
         is_met: bool,
 
+        /// If the condition is now met, the value that entered the range.
+        value: Option<Value>,
+
         /// The rule to which this event applies.
         rule_index: usize,
 
@@ -186,6 +199,21 @@ enum ExecutionOp {
         condition_index: usize,
     },
 
+    /// A delayed statement is ready to be executed.
+    Send {
+        /// The rule to which this statement belongs.
+        rule_index: usize,
+
+        /// The index of the statement, in the order of `Rule::statements()`.
+        statement_index: usize,
+
+        /// The key of this send in `RuleState::pending_sends`.
+        send_id: usize,
+
+        /// The value that caused the rule to be triggered, if any.
+        trigger: Option<Value>,
+    },
+
     /// Time to stop executing the script.
     Stop(Mutex<Box<Fn(Result<(), Error>) + Send>>)
 }
@@ -196,12 +224,13 @@ impl Debug for ExecutionOp {
         match *self {
             Update {..} => formatter.write_str("Update"),
             UpdateCondition { .. } => formatter.write_str("UpdateCondition"),
+            Send { .. } => formatter.write_str("Send"),
             Stop (_) => formatter.write_str("Stop")
         }
     }
 }
 
-struct ConditionState {
+struct ConditionState<Env> where Env: ExecutableDevEnv {
     match_is_met: bool,
 
     /// The set of getters for which the condition is met.
@@ -210,12 +239,22 @@ struct ConditionState {
     /// If `None`, a duration is attached to this condition and we need to make sure that the
     /// condition remains true for at least `duration` before we decide whether to proceed with
     /// statements.
-    duration: Option<Duration>
+    duration: Option<Duration>,
+
+    /// If a timer has been started because of `duration`, the guard for this timer.
+    ongoing_timer: Option<Env::TimerGuard>,
 }
 struct RuleState<Env> where Env: ExecutableDevEnv {
     rule_is_met: bool,
-    per_condition: Vec<ConditionState>,
-    ongoing_timer: Option<Env::TimerGuard>, // FIXME: It's actually a guard.
+
+    /// The state of each `Match` of the rule, in the order of `Rule::matches()`.
+    per_condition: Vec<ConditionState<Env>>,
+
+    /// The statements waiting for their delay. Dropping a guard cancels the send.
+    pending_sends: HashMap<usize, Env::TimerGuard>,
+
+    /// The key of the next entry of `pending_sends`.
+    next_send_id: usize,
 }
 
 impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
@@ -227,7 +266,13 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
         where S: ExtSender<ExecutionOp> + Clone
     {
         let compiler = try!(Compiler::new().map_err(|err| Error::CompileError(err)));
-        let script = try!(compiler.compile(script).map_err(|err| Error::CompileError(err)));
+        let name = script.name.clone();
+        let script = try!(compiler.compile_with_diagnostics(script).map_err(|mut diagnostics| {
+            for diagnostic in &diagnostics {
+                info!("[Recipe '{}'] At {}: {:?}", name, diagnostic.path, diagnostic.error);
+            }
+            Error::CompileError(diagnostics.remove(0).error)
+        }));
 
         Ok(ExecutionTask {
             script: script,
@@ -250,7 +295,7 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
 
         // FIXME: We could optimize requests by detecting if several share a `TargetMap<GetterSelector, Exactly<Range>>`
         let mut per_rule : Vec<_> = self.script.rules.iter().zip(0 as usize..).map(|(rule, rule_index)| {
-            let per_condition = rule.conditions.iter().zip(0 as usize..).map(|(condition, condition_index)| {
+            let per_condition = rule.matches().into_iter().zip(0 as usize..).map(|(condition, condition_index)| {
                 // We will often end up watching several times the
                 // same channel. For the moment, we do not attempt to
                 // optimize either I/O (which we expect will be
@@ -276,18 +321,20 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
                                 rule_index: rule_index,
                                 condition_index: condition_index
                             }
-                        }))));
+                        })), self.owner.clone()));
                 ConditionState {
                     match_is_met: false,
                     per_getter: HashSet::new(),
                     duration: condition.duration.clone(),
+                    ongoing_timer: None,
                 }
             }).collect();
 
             RuleState {
                 rule_is_met: false,
                 per_condition: per_condition,
-                ongoing_timer: None,
+                pending_sends: HashMap::new(),
+                next_send_id: 0,
             }
         }).collect();
 
@@ -297,14 +344,22 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
                     info!("[Recipe '{}'] Shutting down recipe.", self.script.name);
 
                     // Leave the loop. Watching will stop once
-                    // `witnesses` is dropped.
+                    // `witnesses` is dropped and pending sends are
+                    // cancelled once `per_rule` is dropped.
                     cb.lock().unwrap()(Ok(()));
                     return;
                 },
-                ExecutionOp::UpdateCondition { id, is_met, rule_index, condition_index } => {
+                ExecutionOp::UpdateCondition { id, is_met, value, rule_index, condition_index } => {
                     debug!("[Recipe '{}'] Updating the state of rule {}, condition {} => {}", self.script.name, rule_index, condition_index, is_met);
-                    self.update_conditions(&self.script.name, id, is_met, &mut per_rule,
-                        rule_index, condition_index, &api, &on_event);
+                    self.update_conditions(&self.script.name, id, is_met, value, &mut per_rule,
+                        rule_index, condition_index, &env, &on_event);
+                }
+                ExecutionOp::Send { rule_index, statement_index, send_id, trigger } => {
+                    if per_rule[rule_index].pending_sends.remove(&send_id).is_none() {
+                        debug!("[Recipe '{}'] Statement {} of rule {} was cancelled before its delay.", self.script.name, statement_index, rule_index);
+                        continue;
+                    }
+                    self.send(&self.script.name, rule_index, statement_index, trigger.as_ref(), &api, &on_event);
                 }
                 ExecutionOp::Update { event, rule_index, condition_index } => {
                     match event {
@@ -324,6 +379,7 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
                             let msg = ExecutionOp::UpdateCondition {
                                 id: id.clone(),
                                 is_met: false,
+                                value: None,
                                 rule_index: rule_index,
                                 condition_index: condition_index
                             };
@@ -342,6 +398,7 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
                                 ExecutionOp::UpdateCondition {
                                     id: id.clone(),
                                     is_met: true,
+                                    value: Some(value.clone()),
                                     rule_index: rule_index,
                                     condition_index: condition_index
                                 }
@@ -363,7 +420,7 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
                             let tx = self.tx.map(move |()| {
                                 msg()
                             });
-                            per_rule[rule_index].ongoing_timer =
+                            per_rule[rule_index].per_condition[condition_index].ongoing_timer =
                                 Some(env.start_timer(duration.clone(), Box::new(tx)));
                             let _ = on_event.send(ExecutionEvent::TimerStart {
                                 rule_index: rule_index,
@@ -372,10 +429,10 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
                         }
                         WatchEvent::ExitRange { from: id, value } => {
                             debug!("[Recipe '{}'] Getter {} has left the range for rule {}, condition {}: {:?}", self.script.name, id, rule_index, condition_index, value);
-                            if per_rule[rule_index].ongoing_timer.is_some() {
-                                debug!("[Recipe '{}'] I need to cancel the timer for rule {}, condition {}", self.script.name, id, rule_index);
+                            if per_rule[rule_index].per_condition[condition_index].ongoing_timer.is_some() {
+                                debug!("[Recipe '{}'] I need to cancel the timer for rule {}, condition {}", self.script.name, rule_index, condition_index);
                                 // Cancel the timer.
-                                per_rule[rule_index].ongoing_timer.take();
+                                per_rule[rule_index].per_condition[condition_index].ongoing_timer.take();
                                 let _ = on_event.send(ExecutionEvent::TimerCancel {
                                     rule_index: rule_index,
                                     condition_index: condition_index,
@@ -385,6 +442,7 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
                             let msg = ExecutionOp::UpdateCondition {
                                 id: id,
                                 is_met: false,
+                                value: None,
                                 rule_index: rule_index,
                                 condition_index: condition_index
                             };
@@ -398,9 +456,9 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
 
     /// A getter just entered/left a range. Update the conditions to determine whether
     /// we now need to fire the statements.
-    fn update_conditions<S>(&self, name: &str, id: Id<Getter>, getter_is_met: bool,
+    fn update_conditions<S>(&self, name: &str, id: Id<Getter>, getter_is_met: bool, value: Option<Value>,
             per_rule: &mut Vec<RuleState<Env>>, rule_index: usize, condition_index: usize,
-            api: &Env::API, on_event: &S)
+            env: &Env, on_event: &S)
             where S: ExtSender<ExecutionEvent> + Clone
     {
         use std::mem::replace;
@@ -440,14 +498,14 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
 
         // 2. Is the condition met?
         //
-        // The condition is met iff all of the
-        // matches are met.
-        let condition_is_met =
-            per_rule[rule_index]
-            .per_condition
-            .iter()
-            .find(|condition_state| condition_state.match_is_met)
-            .is_some();
+        // The condition is met iff all of the matches are met
+        // (`Mode::All`) or any of them is met (`Mode::Any`),
+        // recursively through groups.
+        let rule = &self.script.rules[rule_index];
+        let condition_is_met = {
+            let mut states = per_rule[rule_index].per_condition.iter();
+            conditions_are_met(&rule.mode, &rule.conditions, &mut states)
+        };
 
         // 3. Are we in a case in which the
         // condition was not met and is now met?
@@ -458,42 +516,260 @@ impl<Env> ExecutionTask<Env> where Env: ExecutableDevEnv + Debug {
 
         if !condition_was_met && condition_is_met {
             // Ahah, we have just triggered the statements!
-            debug!("[Thinkerbell update_condition {}] Triggering {} statements.", name, self.script.rules[rule_index].execute.len());
-            for (statement, statement_index) in self.script.rules[rule_index].execute.iter().zip(0..) {
-                debug!("[Thinkerbell update_condition {}] Triggering statement {}/{}.", name, statement_index, self.script.rules[rule_index].execute.len());
-                let result = statement.eval(&api, &self.owner);
-                debug!("[Thinkerbell update_condition {}] Statement result {}/{}: {:?}.", name, statement_index, self.script.rules[rule_index].execute.len(), result);
-                if result.is_empty() {
-                    warn!("[Recipe '{}'] In rule {}, attempting to trigger statement {}, couldn't find any receiver channel.", name,
-                            rule_index, condition_index);
+            let statements = rule.statements();
+            debug!("[Thinkerbell update_condition {}] Triggering {} statements.", name, statements.len());
+            for ((delay, _), statement_index) in statements.into_iter().zip(0..) {
+                if delay <= ChronoDuration::zero() {
+                    self.send(name, rule_index, statement_index, value.as_ref(), env.api(), on_event);
+                    continue;
                 }
+                debug!("[Thinkerbell update_condition {}] Scheduling statement {} in {}.", name, statement_index, delay);
+                let rule_state = &mut per_rule[rule_index];
+                let send_id = rule_state.next_send_id;
+                rule_state.next_send_id += 1;
+                let trigger = value.clone();
+                let tx = self.tx.map(move |()| {
+                    ExecutionOp::Send {
+                        rule_index: rule_index,
+                        statement_index: statement_index,
+                        send_id: send_id,
+                        trigger: trigger.clone(),
+                    }
+                });
+                rule_state.pending_sends.insert(send_id,
+                    env.start_timer(Duration::from(delay), Box::new(tx)));
+            }
+        } else if condition_was_met && !condition_is_met && rule.cancel_when_unmet {
+            debug!("[Thinkerbell update_condition {}] Cancelling {} pending statements.", name, per_rule[rule_index].pending_sends.len());
+            // Dropping the guards cancels the timers.
+            per_rule[rule_index].pending_sends.clear();
+        }
+        debug!("[Thinkerbell update_condition {}] done.", name);
+    }
 
-                let _ = on_event.send(ExecutionEvent::Sent {
+    /// Execute a statement of a rule, now.
+    fn send<S>(&self, name: &str, rule_index: usize, statement_index: usize, trigger: Option<&Value>,
+            api: &Env::API, on_event: &S)
+        where S: ExtSender<ExecutionEvent> + Clone
+    {
+        let statements = self.script.rules[rule_index].statements();
+        let statement = statements[statement_index].1;
+        debug!("[Thinkerbell send {}] Triggering statement {}/{}.", name, statement_index, statements.len());
+        let result = match statement.eval(api, &self.owner, trigger) {
+            Ok(result) => result,
+            Err(err) => {
+                warn!("[Recipe '{}'] In rule {}, could not evaluate statement {}: {:?}", name,
+                    rule_index, statement_index, err);
+                let _ = on_event.send(ExecutionEvent::StatementError {
                     rule_index: rule_index,
                     statement_index: statement_index,
-                    result: result,
+                    error: err,
                 });
+                return;
             }
+        };
+        debug!("[Thinkerbell send {}] Statement result {}/{}: {:?}.", name, statement_index, statements.len(), result);
+        if result.is_empty() {
+            warn!("[Recipe '{}'] In rule {}, attempting to trigger statement {}, couldn't find any receiver channel.", name,
+                    rule_index, statement_index);
         }
-        debug!("[Thinkerbell update_condition {}] done.", name);
+
+        let _ = on_event.send(ExecutionEvent::Sent {
+            rule_index: rule_index,
+            statement_index: statement_index,
+            result: result,
+        });
     }
 }
 
 
+/// Determine whether a list of conditions is met.
+///
+/// `states` holds the state of each `Match`, in depth-first order, as
+/// returned by `Rule::matches()`. Each `Match` consumes one state.
+fn conditions_are_met<'a, Env, I>(mode: &Mode, conditions: &[Condition<CompiledCtx<Env>>], states: &mut I) -> bool
+    where Env: ExecutableDevEnv + 'a, I: Iterator<Item = &'a ConditionState<Env>>
+{
+    // Evaluate every branch before combining, so that short-circuiting
+    // does not desynchronize `states`.
+    let results : Vec<bool> = conditions.iter().map(|condition| {
+        match *condition {
+            Condition::Match(_) => states.next().map_or(false, |state| state.match_is_met),
+            Condition::Group(ref group) => conditions_are_met(&group.mode, &group.conditions, states),
+        }
+    }).collect();
+    mode.combine(results)
+}
+
 impl<Env> Statement<CompiledCtx<Env>> where Env: ExecutableDevEnv {
-    fn eval(&self, api: &Env::API, owner: &User) ->  Vec<(Id<Setter>, Result<(), Error>)> {
-        api.send_values(vec![Targetted {
+    fn eval(&self, api: &Env::API, owner: &User, trigger: Option<&Value>) -> Result<Vec<(Id<Setter>, Result<(), Error>)>, Error> {
+        let value = match try!(self.value.eval(api, owner, trigger).map_err(Error::EvalError)) {
+            Operand::Value(value) => value,
+            // Rejected during compilation.
+            Operand::Number(_) => return Err(Error::EvalError(EvalError::InvalidOperands))
+        };
+        Ok(api.send_values(vec![Targetted {
             select: self.destination.clone(),
-            payload: self.value.clone()
+            payload: value
         }], owner.clone())
             .into_iter()
             .map(|(id, result)|
                  (id, result.map_err(|err| Error::APIError(err))))
-            .collect()
+            .collect())
+    }
+}
+
+/// The result of evaluating an `Expression`.
+enum Operand {
+    Value(Value),
+    Number(f64),
+}
+
+impl Expression {
+    fn eval<A>(&self, api: &A, owner: &User, trigger: Option<&Value>) -> Result<Operand, EvalError> where A: API {
+        match *self {
+            Expression::Value(ref value) => Ok(Operand::Value(value.clone())),
+            Expression::Number(number) => Ok(Operand::Number(number)),
+            Expression::Trigger => match trigger {
+                None => Err(EvalError::NoTrigger),
+                Some(value) => Ok(Operand::Value(value.clone()))
+            },
+            Expression::Fetch { ref source, .. } => {
+                api.fetch_values(source.clone(), owner.clone())
+                    .into_iter()
+                    .filter_map(|(_, result)| match result {
+                        Ok(Some(value)) => Some(Operand::Value(value)),
+                        _ => None
+                    })
+                    .next()
+                    .ok_or(EvalError::NoValue)
+            },
+            Expression::Arithmetic(op, ref left, ref right) => {
+                let left = try!(left.eval(api, owner, trigger));
+                let right = try!(right.eval(api, owner, trigger));
+                arithmetic(op, left, right)
+            },
+            Expression::Template { ref format, ref args } => {
+                let mut args = args.iter();
+                let mut pieces = format.split("{}");
+                let mut result = pieces.next().unwrap_or("").to_owned();
+                for piece in pieces {
+                    match args.next() {
+                        Some(arg) => result.push_str(&render(&try!(arg.eval(api, owner, trigger)))),
+                        None => result.push_str("{}")
+                    }
+                    result.push_str(piece);
+                }
+                Ok(Operand::Value(Value::String(Arc::new(result))))
+            }
+        }
+    }
+}
+
+/// Apply an arithmetic operator. Numbers are interpreted in the unit of
+/// the other operand.
+fn arithmetic(op: Operator, left: Operand, right: Operand) -> Result<Operand, EvalError> {
+    use self::Operand::Number;
+    use self::Operand::Value as Val;
+    use ast::Operator::*;
+
+    let apply = |a: f64, b: f64| match op {
+        Add => a + b,
+        Sub => a - b,
+        Mul => a * b,
+        Div => a / b,
+    };
+    match (op, left, right) {
+        (Div, _, Number(b)) if b == 0. => Err(EvalError::DivisionByZero),
+        (_, Number(a), Number(b)) => Ok(Number(apply(a, b))),
+        (Add, Val(Value::String(a)), Val(Value::String(b))) =>
+            Ok(Val(Value::String(Arc::new(format!("{}{}", a, b))))),
+        (Add, Val(Value::TimeStamp(ts)), Val(Value::Duration(d))) |
+        (Add, Val(Value::Duration(d)), Val(Value::TimeStamp(ts))) => {
+            let d : ChronoDuration = d.into();
+            Ok(Val(Value::TimeStamp(TimeStamp::from_datetime(*ts.as_datetime() + d))))
+        }
+        (Sub, Val(Value::TimeStamp(ts)), Val(Value::Duration(d))) => {
+            let d : ChronoDuration = d.into();
+            Ok(Val(Value::TimeStamp(TimeStamp::from_datetime(*ts.as_datetime() - d))))
+        }
+        (Add, Val(a), Val(b)) | (Sub, Val(a), Val(b)) => {
+            let b = try!(b.magnitude_in(&a).ok_or(EvalError::InvalidOperands));
+            map_quantity(a, |a| apply(a, b)).map(Val)
+        }
+        (Add, Val(a), Number(b)) | (Sub, Val(a), Number(b)) |
+        (Mul, Val(a), Number(b)) | (Div, Val(a), Number(b)) => {
+            map_quantity(a, |a| apply(a, b)).map(Val)
+        }
+        (Add, Number(a), Val(b)) | (Mul, Number(a), Val(b)) => {
+            map_quantity(b, |b| apply(a, b)).map(Val)
+        }
+        _ => Err(EvalError::InvalidOperands)
     }
 }
 
+/// Apply `f` to the magnitude of a quantity, in its own unit.
+fn map_quantity<F>(value: Value, f: F) -> Result<Value, EvalError> where F: Fn(f64) -> f64 {
+    match value {
+        Value::Temperature(Temperature::C(val)) => Ok(Value::Temperature(Temperature::C(f(val)))),
+        Value::Temperature(Temperature::F(val)) => Ok(Value::Temperature(Temperature::F(f(val)))),
+        Value::Duration(d) => {
+            let d : ChronoDuration = d.into();
+            let s = f(d.num_milliseconds() as f64 / 1000.);
+            Ok(Value::Duration(Duration::from(ChronoDuration::milliseconds((s * 1000.) as i64))))
+        }
+        Value::ExtNumeric(mut e) => {
+            e.value = f(e.value);
+            Ok(Value::ExtNumeric(e))
+        }
+        Value::Percentage(q) => Ok(Value::Percentage(q.with_magnitude(f(q.magnitude())))),
+        Value::Power(q) => Ok(Value::Power(q.with_magnitude(f(q.magnitude())))),
+        Value::Energy(q) => Ok(Value::Energy(q.with_magnitude(f(q.magnitude())))),
+        Value::Illuminance(q) => Ok(Value::Illuminance(q.with_magnitude(f(q.magnitude())))),
+        Value::Pressure(q) => Ok(Value::Pressure(q.with_magnitude(f(q.magnitude())))),
+        Value::Distance(q) => Ok(Value::Distance(q.with_magnitude(f(q.magnitude())))),
+        _ => Err(EvalError::InvalidOperands)
+    }
+}
 
+/// Convert the result of an expression to a string, for templating.
+fn render(operand: &Operand) -> String {
+    match *operand {
+        Operand::Number(number) => format!("{}", number),
+        Operand::Value(Value::String(ref string)) => (**string).clone(),
+        Operand::Value(Value::Temperature(Temperature::C(val))) => format!("{}°C", val),
+        Operand::Value(Value::Temperature(Temperature::F(val))) => format!("{}°F", val),
+        Operand::Value(ref value) => {
+            // Values are serialized as `{key: payload}`, we only display the payload.
+            match value.to_json() {
+                JSON::Object(obj) => match obj.values().next() {
+                    Some(&JSON::String(ref string)) => string.clone(),
+                    Some(payload) => serde_json::to_string(payload).unwrap_or(String::new()),
+                    None => String::new()
+                },
+                json => serde_json::to_string(&json).unwrap_or(String::new())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub enum EvalError {
+    /// The statement refers to the value that triggered the rule, but
+    /// the rule was not triggered by a value.
+    NoTrigger,
+
+    /// None of the getters of a `Fetch` expression provided a value.
+    NoValue,
+
+    /// Attempting to divide by zero.
+    DivisionByZero,
+
+    /// The operands of an arithmetic expression do not have the
+    /// expected types, e.g. because a getter provided a value of an
+    /// unexpected type.
+    InvalidOperands,
+}
 
 #[derive(Clone, Debug, Serialize)]
 pub enum StartStopError {
@@ -507,4 +783,5 @@ pub enum Error {
     CompileError(compile::Error),
     StartStopError(StartStopError),
     APIError(api::Error),
+    EvalError(EvalError),
 }
@@ -5,15 +5,18 @@ extern crate foxbox_thinkerbell;
 extern crate serde;
 extern crate transformable_channels;
 
+use std::fs;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 use transformable_channels::mpsc::*;
 
+use foxbox_thinkerbell::compile::{ Error as CompileError, TypeError };
 use foxbox_thinkerbell::fake_env::FakeEnv;
 use foxbox_thinkerbell::manager::*;
 
 use foxbox_taxonomy::api::User;
+use foxbox_taxonomy::parse::ParseError;
 use foxbox_taxonomy::util::Id;
 
 fn load_json(path: &str) -> String {
@@ -48,6 +51,13 @@ fn test_database_add_remove_script() {
     let (_, owner) = db.get_source_and_owner(&name).unwrap();
     assert_eq!(owner, User::Id(1));
 
+    println!("* A recipe put by the box itself should keep running on behalf of the box.");
+    let system_name = Id::<ScriptId>::new("System Ruleset");
+    db.put(&system_name, &load_json("./examples/ruleset.json"), &User::System).unwrap();
+    let (_, owner) = db.get_source_and_owner(&system_name).unwrap();
+    assert_eq!(owner, User::System);
+    db.remove(&system_name).unwrap();
+
     println!("* Enable the recipe again. It should still be reported as running.");
     db.set_enabled(&name, true).unwrap();
     assert_eq!(db.get_running_count(), 1);
@@ -72,3 +82,75 @@ fn test_database_add_remove_script() {
     db.put(&name, &load_json("./examples/ruleset.json"), &User::Id(1)).unwrap();
     assert_eq!(db.get_running_count(), 1);
 }
+
+#[test]
+fn test_validate_script() {
+    let (tx_env, _) = channel();
+    let env = FakeEnv::new(Box::new(tx_env));
+    let (tx, _) = channel();
+    let path = std::env::temp_dir().join("test_script_validation.sqlite");
+    let _ = fs::remove_file(&path);
+    let db = ScriptManager::new(env, &path, Box::new(tx)).unwrap();
+
+    println!("* Validating a script that cannot be parsed reports the path of the error.");
+    let diagnostics = db.validate(r#"{
+      "name": "foo",
+      "rules": [{
+        "conditions": [{
+          "source": [{"id": "Light"}],
+          "range": {"Eq": {"OnOff": "On"}}
+        }],
+        "execute": []
+      }]
+    }"#).unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    match diagnostics[0] {
+        Diagnostic::ParseError(ParseError::MissingField { ref name, ref at }) => {
+            assert_eq!(name, "kind");
+            assert_eq!(at, ".rules[0].conditions[0].kind");
+        },
+        ref other => panic!("Unexpected diagnostic {:?}", other)
+    }
+
+    println!("* Validating a script reports compile errors and selectors that match no channel.");
+    let diagnostics = db.validate(r#"{
+      "name": "foo",
+      "rules": [{
+        "conditions": [{
+          "source": [{"id": "Light"}],
+          "kind": "LightOn",
+          "range": {"Eq": {"OnOff": "On"}}
+        }],
+        "execute": [{
+          "destination": [{"id": "Oven"}],
+          "kind": "OvenTemperature",
+          "value": {"OnOff": "On"}
+        }]
+      }]
+    }"#).unwrap();
+    assert_eq!(diagnostics.len(), 3);
+    match diagnostics[0] {
+        Diagnostic::NoMatchingChannel { ref path } => assert_eq!(path, ".rules[0].conditions[0].source[0]"),
+        ref other => panic!("Unexpected diagnostic {:?}", other)
+    }
+    match diagnostics[1] {
+        Diagnostic::NoMatchingChannel { ref path } => assert_eq!(path, ".rules[0].execute[0].destination[0]"),
+        ref other => panic!("Unexpected diagnostic {:?}", other)
+    }
+    match diagnostics[2] {
+        Diagnostic::CompileError(ref diagnostic) => {
+            assert_eq!(diagnostic.path, ".rules[0].execute[0].value");
+            match diagnostic.error {
+                CompileError::TypeError(TypeError::KindAndValueDoNotAgree) => {},
+                ref other => panic!("Unexpected error {:?}", other)
+            }
+        },
+        ref other => panic!("Unexpected diagnostic {:?}", other)
+    }
+
+    println!("* Validating a script does not install it.");
+    assert_eq!(db.get_running_count(), 0);
+
+    drop(db);
+    fs::remove_file(&path).unwrap();
+}
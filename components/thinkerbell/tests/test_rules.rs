@@ -5,6 +5,7 @@ extern crate transformable_channels;
 
 extern crate chrono;
 
+use foxbox_thinkerbell::compile::Compiler;
 use foxbox_thinkerbell::fake_env::*;
 use foxbox_thinkerbell::run::*;
 use foxbox_thinkerbell::ast::*;
@@ -12,7 +13,7 @@ use foxbox_thinkerbell::ast::*;
 use foxbox_taxonomy::api::{ Error as APIError, User };
 use foxbox_taxonomy::selector::*;
 use foxbox_taxonomy::services::*;
-use foxbox_taxonomy::values::{ Duration, OnOff, Range, TimeStamp, Type, TypeError as APITypeError , Value };
+use foxbox_taxonomy::values::{ Duration, OnOff, Range, Temperature, TimeStamp, Type, TypeError as APITypeError , Value };
 
 use std::fmt::Debug;
 use std::marker::PhantomData;
@@ -96,7 +97,7 @@ fn test_run() {
         rules: vec![
             Rule {
                 conditions: vec![
-                    Match {
+                    Condition::Match(Match {
                         source: vec![
                             GetterSelector::new()
                         ],
@@ -104,18 +105,21 @@ fn test_run() {
                         range: Range::Eq(Value::OnOff(OnOff::On)),
                         duration: None,
                         phantom: PhantomData
-                    }
+                    })
                 ],
+                mode: Mode::All,
                 execute: vec![
-                    Statement {
+                    Action::Statement(Statement {
                         destination: vec![
                             SetterSelector::new()
                         ],
-                        value: Value::OnOff(OnOff::Off),
+                        value: Expression::Value(Value::OnOff(OnOff::Off)),
                         kind: ChannelKind::LightOn,
+                        delay: None,
                         phantom: PhantomData,
-                    }
+                    })
                 ],
+                cancel_when_unmet: false,
                 phantom: PhantomData
             }
         ],
@@ -156,6 +160,7 @@ fn test_run() {
             service: service_id_1.clone(),
             tags: HashSet::new(),
             last_seen: None,
+            capabilities: None,
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
@@ -170,6 +175,7 @@ fn test_run() {
             adapter: adapter_id_1.clone(),
             service: service_id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 updated: None,
@@ -226,6 +232,7 @@ fn test_run() {
             service: service_id_1.clone(),
             tags: HashSet::new(),
             last_seen: None,
+            capabilities: None,
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
@@ -314,6 +321,7 @@ fn test_run() {
             adapter: adapter_id_1.clone(),
             service: service_id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 updated: None,
@@ -350,6 +358,7 @@ fn test_run() {
             adapter: adapter_id_1.clone(),
             service: service_id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 updated: None,
@@ -435,6 +444,7 @@ fn test_run() {
             adapter: adapter_id_1.clone(),
             service: service_id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 updated: None,
@@ -521,7 +531,7 @@ fn test_run_with_delay() {
         rules: vec![
             Rule {
                 conditions: vec![
-                    Match {
+                    Condition::Match(Match {
                         source: vec![
                             GetterSelector::new()
                         ],
@@ -529,18 +539,21 @@ fn test_run_with_delay() {
                         range: Range::Eq(Value::OnOff(OnOff::On)),
                         duration: Some(Duration::from(chrono::Duration::seconds(10))),
                         phantom: PhantomData
-                    }
+                    })
                 ],
+                mode: Mode::All,
                 execute: vec![
-                    Statement {
+                    Action::Statement(Statement {
                         destination: vec![
                             SetterSelector::new()
                         ],
-                        value: Value::OnOff(OnOff::Off),
+                        value: Expression::Value(Value::OnOff(OnOff::Off)),
                         kind: ChannelKind::LightOn,
+                        delay: None,
                         phantom: PhantomData,
-                    }
+                    })
                 ],
+                cancel_when_unmet: false,
                 phantom: PhantomData
             }
         ],
@@ -581,6 +594,7 @@ fn test_run_with_delay() {
             service: service_id_1.clone(),
             tags: HashSet::new(),
             last_seen: None,
+            capabilities: None,
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
@@ -595,6 +609,7 @@ fn test_run_with_delay() {
             adapter: adapter_id_1.clone(),
             service: service_id_1.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 updated: None,
@@ -717,6 +732,7 @@ fn test_run_with_delay() {
             service: service_id_1.clone(),
             tags: HashSet::new(),
             last_seen: None,
+            capabilities: None,
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
@@ -775,3 +791,619 @@ fn test_run_with_delay() {
 
     println!("* Drop complete.");
 }
+
+/// Add an adapter, a service, getters `Getter 1`, `Getter 2`, `Getter 3` and setter `Setter 1`,
+/// all with kind `LightOn`, getters `Thermometer` and `Outdoor` and setter `Oven`, with kind
+/// `OvenTemperature`, and setter `Log`, with kind `Log`.
+fn populate_env(env: &FakeEnv, rx_done: &Receiver<()>) {
+    let adapter_id_1 = Id::<AdapterId>::new("Adapter 1");
+    let service_id_1 = Id::<ServiceId>::new("Service 1");
+
+    env.execute(Instruction::AddAdapters(vec![adapter_id_1.to_string()]));
+    rx_done.recv().unwrap();
+
+    env.execute(Instruction::AddServices(vec![
+        Service {
+            id: service_id_1.clone(),
+            adapter: adapter_id_1.clone(),
+            getters: HashMap::new(),
+            setters: HashMap::new(),
+            tags: HashSet::new(),
+            properties: HashMap::new(),
+        }
+    ]));
+    rx_done.recv().unwrap();
+
+    env.execute(Instruction::AddGetters(vec![
+        ("Getter 1", ChannelKind::LightOn),
+        ("Getter 2", ChannelKind::LightOn),
+        ("Getter 3", ChannelKind::LightOn),
+        ("Thermometer", ChannelKind::OvenTemperature),
+        ("Outdoor", ChannelKind::OvenTemperature),
+    ].drain(..).map(|(id, kind)| {
+        Channel {
+            id: Id::<Getter>::new(id),
+            adapter: adapter_id_1.clone(),
+            service: service_id_1.clone(),
+            tags: HashSet::new(),
+            last_seen: None,
+            capabilities: None,
+            mechanism: Getter {
+                updated: None,
+                kind: kind,
+            }
+        }
+    }).collect()));
+    rx_done.recv().unwrap();
+
+    env.execute(Instruction::AddSetters(vec![
+        ("Setter 1", ChannelKind::LightOn),
+        ("Oven", ChannelKind::OvenTemperature),
+        ("Log", ChannelKind::Log),
+    ].drain(..).map(|(id, kind)| {
+        Channel {
+            id: Id::<Setter>::new(id),
+            adapter: adapter_id_1.clone(),
+            service: service_id_1.clone(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Setter {
+                updated: None,
+                kind: kind,
+            }
+        }
+    }).collect()));
+    rx_done.recv().unwrap();
+}
+
+/// Start `source` on a fresh `FakeEnv`, populated with `populate_env`.
+fn start_script(source: &str) -> (FakeEnv, Execution<FakeEnv>, Receiver<()>, Receiver<(Id<Setter>, Value)>) {
+    let (tx, rx) : (_, Receiver<Event>) = channel();
+
+    let tx_env = Box::new(tx.map(|event| Event::Env(event)));
+    let tx_run = tx.map(|event| Event::Run(event));
+    let (tx_done, rx_done) = channel();
+    let (tx_send, rx_send) = channel();
+
+    let env = FakeEnv::new(tx_env);
+    let mut exec = Execution::<FakeEnv>::new();
+
+    thread::spawn(move || {
+        for msg in rx {
+            if let Event::Env(FakeEnvEvent::Done) = msg {
+                tx_done.send(()).unwrap();
+            } else if let Event::Env(FakeEnvEvent::Send { id, value }) = msg {
+                tx_send.send((id, value)).unwrap();
+            } else {
+                // Can be useful for debugging, but that's generally noise.
+                // println!("LOG: {:?}", msg)
+            }
+        }
+    });
+
+    let script = Script::from_str(source).unwrap();
+    exec.start(env.clone(), script, User::None, tx_run).unwrap();
+    populate_env(&env, &rx_done);
+
+    (env, exec, rx_done, rx_send)
+}
+
+fn inject(env: &FakeEnv, rx_done: &Receiver<()>, id: &str, value: OnOff) {
+    inject_value(env, rx_done, id, Value::OnOff(value))
+}
+
+fn inject_value(env: &FakeEnv, rx_done: &Receiver<()>, id: &str, value: Value) {
+    env.execute(Instruction::InjectGetterValues(vec![
+        (Id::<Getter>::new(id), Ok(value))
+    ]));
+    rx_done.recv().unwrap();
+}
+
+#[test]
+fn test_run_with_modes() {
+    let source = r#"{
+      "name": "Test script",
+      "rules": [{
+        "mode": "all",
+        "conditions": [{
+          "source": [{"id": "Getter 1"}],
+          "kind": "LightOn",
+          "range": {"Eq": {"OnOff": "On"}}
+        }, {
+          "source": [{"id": "Getter 2"}],
+          "kind": "LightOn",
+          "range": {"Eq": {"OnOff": "On"}}
+        }],
+        "execute": [{
+          "destination": [{"id": "Setter 1"}],
+          "value": {"OnOff": "Off"},
+          "kind": "LightOn"
+        }]
+      }]
+    }"#;
+    let setter_id_1 = Id::<Setter>::new("Setter 1");
+
+    println!("* With mode `all`, meeting a single condition does not trigger the send.");
+    let (env, _exec, rx_done, rx_send) = start_script(source);
+    inject(&env, &rx_done, "Getter 1", OnOff::On);
+    rx_send.try_recv().unwrap_err();
+
+    println!("* With mode `all`, meeting all conditions triggers the send.");
+    inject(&env, &rx_done, "Getter 2", OnOff::On);
+    let (id, value) = rx_send.recv().unwrap();
+    assert_eq!(id, setter_id_1);
+    assert_eq!(value, Value::OnOff(OnOff::Off));
+
+    println!("* With mode `all`, unmeeting then meeting again a condition triggers the send again.");
+    inject(&env, &rx_done, "Getter 1", OnOff::Off);
+    rx_send.try_recv().unwrap_err();
+    inject(&env, &rx_done, "Getter 1", OnOff::On);
+    let (id, _) = rx_send.recv().unwrap();
+    assert_eq!(id, setter_id_1);
+
+    println!("* With mode `any`, meeting a single condition triggers the send.");
+    let (env, _exec, rx_done, rx_send) = start_script(&source.replace(r#""mode": "all""#, r#""mode": "any""#));
+    inject(&env, &rx_done, "Getter 2", OnOff::On);
+    let (id, _) = rx_send.recv().unwrap();
+    assert_eq!(id, setter_id_1);
+
+    println!("* With mode `any`, meeting a second condition does not trigger the send again.");
+    inject(&env, &rx_done, "Getter 1", OnOff::On);
+    rx_send.try_recv().unwrap_err();
+
+    println!("* With mode `any`, the send is triggered again only once no condition was met.");
+    inject(&env, &rx_done, "Getter 2", OnOff::Off);
+    rx_send.try_recv().unwrap_err();
+    inject(&env, &rx_done, "Getter 1", OnOff::Off);
+    rx_send.try_recv().unwrap_err();
+    inject(&env, &rx_done, "Getter 1", OnOff::On);
+    let (id, _) = rx_send.recv().unwrap();
+    assert_eq!(id, setter_id_1);
+
+    println!("* Without a mode, a rule defaults to `all`.");
+    let (env, _exec, rx_done, rx_send) = start_script(&source.replace(r#""mode": "all","#, ""));
+    inject(&env, &rx_done, "Getter 1", OnOff::On);
+    rx_send.try_recv().unwrap_err();
+    inject(&env, &rx_done, "Getter 2", OnOff::On);
+    let (id, _) = rx_send.recv().unwrap();
+    assert_eq!(id, setter_id_1);
+
+    println!("");
+}
+
+#[test]
+fn test_run_with_groups() {
+    let source = r#"{
+      "name": "Test script",
+      "rules": [{
+        "mode": "any",
+        "conditions": [{
+          "source": [{"id": "Getter 3"}],
+          "kind": "LightOn",
+          "range": {"Eq": {"OnOff": "On"}}
+        }, {
+          "mode": "all",
+          "conditions": [{
+            "source": [{"id": "Getter 1"}],
+            "kind": "LightOn",
+            "range": {"Eq": {"OnOff": "On"}}
+          }, {
+            "source": [{"id": "Getter 2"}],
+            "kind": "LightOn",
+            "range": {"Eq": {"OnOff": "On"}}
+          }]
+        }],
+        "execute": [{
+          "destination": [{"id": "Setter 1"}],
+          "value": {"OnOff": "Off"},
+          "kind": "LightOn"
+        }]
+      }]
+    }"#;
+    let setter_id_1 = Id::<Setter>::new("Setter 1");
+
+    let (env, _exec, rx_done, rx_send) = start_script(source);
+
+    println!("* Meeting part of a nested `all` group does not trigger the send.");
+    inject(&env, &rx_done, "Getter 1", OnOff::On);
+    rx_send.try_recv().unwrap_err();
+
+    println!("* Meeting the top-level condition triggers the send.");
+    inject(&env, &rx_done, "Getter 3", OnOff::On);
+    let (id, _) = rx_send.recv().unwrap();
+    assert_eq!(id, setter_id_1);
+
+    inject(&env, &rx_done, "Getter 3", OnOff::Off);
+    rx_send.try_recv().unwrap_err();
+
+    println!("* Meeting the whole nested group triggers the send.");
+    inject(&env, &rx_done, "Getter 2", OnOff::On);
+    let (id, _) = rx_send.recv().unwrap();
+    assert_eq!(id, setter_id_1);
+
+    println!("* Meeting the top-level condition while the group is met does nothing.");
+    inject(&env, &rx_done, "Getter 3", OnOff::On);
+    rx_send.try_recv().unwrap_err();
+
+    println!("");
+}
+
+#[test]
+fn test_compile_groups() {
+    let (tx, _rx) : (_, Receiver<Event>) = channel();
+    let tx_env = Box::new(tx.map(|event| Event::Env(event)));
+    let tx_run = tx.map(|event| Event::Run(event));
+    let env = FakeEnv::new(tx_env);
+    let mut exec = Execution::<FakeEnv>::new();
+
+    println!("* Attempting to run a script with an empty group of conditions will raise an error.");
+    let script = Script::from_str(r#"{
+      "name": "foo",
+      "rules": [{
+        "conditions": [{"mode": "any", "conditions": []}],
+        "execute": [{
+          "destination": [{"id": "Setter 1"}],
+          "value": {"OnOff": "Off"},
+          "kind": "LightOn"
+        }]
+      }]
+    }"#).unwrap();
+    match exec.start(env, script, User::None, tx_run) {
+        Err(Error::CompileError(CompileError::SourceError(SourceError::NoMatch))) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    println!("* Attempting to parse a rule with an unknown mode will raise an error.");
+    assert!(Script::from_str(r#"{
+      "name": "foo",
+      "rules": [{
+        "mode": "most",
+        "conditions": [],
+        "execute": []
+      }]
+    }"#).is_err());
+
+    println!("");
+}
+
+#[test]
+fn test_run_with_delayed_statements() {
+    let source = r#"{
+      "name": "Test script",
+      "rules": [{
+        "conditions": [{
+          "source": [{"id": "Getter 1"}],
+          "kind": "LightOn",
+          "range": {"Eq": {"OnOff": "On"}}
+        }],
+        "execute": [{
+          "sequence": [{
+            "destination": [{"id": "Setter 1"}],
+            "value": {"OnOff": "On"},
+            "kind": "LightOn"
+          }, {
+            "destination": [{"id": "Setter 1"}],
+            "value": {"OnOff": "Off"},
+            "kind": "LightOn",
+            "delay": 300
+          }]
+        }]
+      }]
+    }"#;
+    let setter_id_1 = Id::<Setter>::new("Setter 1");
+
+    println!("* In a sequence, the first statement is sent immediately.");
+    let (env, _exec, rx_done, rx_send) = start_script(source);
+    inject(&env, &rx_done, "Getter 1", OnOff::On);
+    let (id, value) = rx_send.recv().unwrap();
+    assert_eq!(id, setter_id_1);
+    assert_eq!(value, Value::OnOff(OnOff::On));
+
+    println!("* In a sequence, the second statement waits for its delay.");
+    env.execute(Instruction::TriggerTimersUntil(TimeStamp::from(UTC::now() + ChronoDuration::seconds(100))));
+    rx_done.recv().unwrap();
+    thread::sleep(std::time::Duration::from_millis(100));
+    rx_send.try_recv().unwrap_err();
+
+    env.execute(Instruction::TriggerTimersUntil(TimeStamp::from(UTC::now() + ChronoDuration::seconds(400))));
+    rx_done.recv().unwrap();
+    let (id, value) = rx_send.recv().unwrap();
+    assert_eq!(id, setter_id_1);
+    assert_eq!(value, Value::OnOff(OnOff::Off));
+
+    let source = r#"{
+      "name": "Test script",
+      "rules": [{
+        "conditions": [{
+          "source": [{"id": "Getter 1"}],
+          "kind": "LightOn",
+          "range": {"Eq": {"OnOff": "On"}}
+        }],
+        "execute": [{
+          "destination": [{"id": "Setter 1"}],
+          "value": {"OnOff": "Off"},
+          "kind": "LightOn",
+          "delay": 60
+        }],
+        "cancel_when_unmet": true
+      }]
+    }"#;
+
+    println!("* With `cancel_when_unmet`, a delayed statement is cancelled once the condition is unmet.");
+    let (env, _exec, rx_done, rx_send) = start_script(source);
+    inject(&env, &rx_done, "Getter 1", OnOff::On);
+    rx_send.try_recv().unwrap_err();
+    inject(&env, &rx_done, "Getter 1", OnOff::Off);
+    thread::sleep(std::time::Duration::from_millis(100));
+
+    env.execute(Instruction::TriggerTimersUntil(TimeStamp::from(UTC::now() + ChronoDuration::seconds(120))));
+    rx_done.recv().unwrap();
+    thread::sleep(std::time::Duration::from_millis(100));
+    rx_send.try_recv().unwrap_err();
+
+    println!("* Without `cancel_when_unmet`, a delayed statement is sent even if the condition is unmet.");
+    let (env, _exec, rx_done, rx_send) = start_script(&source.replace(r#""cancel_when_unmet": true"#, r#""cancel_when_unmet": false"#));
+    inject(&env, &rx_done, "Getter 1", OnOff::On);
+    rx_send.try_recv().unwrap_err();
+    inject(&env, &rx_done, "Getter 1", OnOff::Off);
+    thread::sleep(std::time::Duration::from_millis(100));
+
+    env.execute(Instruction::TriggerTimersUntil(TimeStamp::from(UTC::now() + ChronoDuration::seconds(120))));
+    rx_done.recv().unwrap();
+    let (id, value) = rx_send.recv().unwrap();
+    assert_eq!(id, setter_id_1);
+    assert_eq!(value, Value::OnOff(OnOff::Off));
+
+    println!("* Stopping the script cancels delayed statements.");
+    let (env, mut exec, rx_done, rx_send) = start_script(source);
+    inject(&env, &rx_done, "Getter 1", OnOff::On);
+    exec.stop(|_| {});
+    thread::sleep(std::time::Duration::from_millis(100));
+
+    env.execute(Instruction::TriggerTimersUntil(TimeStamp::from(UTC::now() + ChronoDuration::seconds(120))));
+    rx_done.recv().unwrap();
+    thread::sleep(std::time::Duration::from_millis(100));
+    rx_send.try_recv().unwrap_err();
+
+    println!("");
+}
+
+#[test]
+fn test_run_with_expressions() {
+    let source = r#"{
+      "name": "Test script",
+      "rules": [{
+        "conditions": [{
+          "source": [{"id": "Thermometer"}],
+          "kind": "OvenTemperature",
+          "range": {"Geq": {"Temperature": {"C": 100}}}
+        }],
+        "execute": [{
+          "destination": [{"id": "Oven"}],
+          "kind": "OvenTemperature",
+          "value": {"Add": [{"Trigger": {}}, 2]}
+        }, {
+          "destination": [{"id": "Log"}],
+          "kind": "Log",
+          "value": {"Template": {
+            "format": "Triggered by {}, outdoor is {}",
+            "args": [{"Trigger": {}}, {"Fetch": {
+              "source": [{"id": "Outdoor"}],
+              "kind": "OvenTemperature"
+            }}]
+          }}
+        }]
+      }]
+    }"#;
+    let setter_oven = Id::<Setter>::new("Oven");
+    let setter_log = Id::<Setter>::new("Log");
+
+    let (env, _exec, rx_done, rx_send) = start_script(source);
+    inject_value(&env, &rx_done, "Outdoor", Value::Temperature(Temperature::C(20.)));
+
+    println!("* Expressions are evaluated with the value that triggered the rule and with fetched values.");
+    inject_value(&env, &rx_done, "Thermometer", Value::Temperature(Temperature::C(150.)));
+
+    let events : HashMap<_, _> = (0..2).map(|_| {
+        rx_send.recv().unwrap()
+    }).collect();
+    assert_eq!(*events.get(&setter_oven).unwrap(), Value::Temperature(Temperature::C(152.)));
+    assert_eq!(*events.get(&setter_log).unwrap(), Value::String(std::sync::Arc::new("Triggered by 150°C, outdoor is 20°C".to_owned())));
+    rx_send.try_recv().unwrap_err();
+
+    println!("");
+}
+
+#[test]
+fn test_compile_expressions() {
+    let compile = |source: &str| {
+        let (tx, _rx) : (_, Receiver<Event>) = channel();
+        let tx_env = Box::new(tx.map(|event| Event::Env(event)));
+        let tx_run = tx.map(|event| Event::Run(event));
+        let env = FakeEnv::new(tx_env);
+        let mut exec = Execution::<FakeEnv>::new();
+        let script = Script::from_str(source).unwrap();
+        exec.start(env, script, User::None, tx_run)
+    };
+
+    println!("* Referring to the trigger is rejected if the matches do not agree on its type.");
+    match compile(r#"{
+      "name": "foo",
+      "rules": [{
+        "mode": "any",
+        "conditions": [{
+          "source": [{"id": "Thermometer"}],
+          "kind": "OvenTemperature",
+          "range": {"Geq": {"Temperature": {"C": 100}}}
+        }, {
+          "source": [{"id": "Light"}],
+          "kind": "LightOn",
+          "range": {"Eq": {"OnOff": "On"}}
+        }],
+        "execute": [{
+          "destination": [{"id": "Oven"}],
+          "kind": "OvenTemperature",
+          "value": {"Trigger": {}}
+        }]
+      }]
+    }"#) {
+        Err(Error::CompileError(CompileError::TypeError(TypeError::InvalidTrigger))) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    println!("* Arithmetics are rejected on values that do not support them.");
+    match compile(r#"{
+      "name": "foo",
+      "rules": [{
+        "conditions": [{
+          "source": [{"id": "Light"}],
+          "kind": "LightOn",
+          "range": {"Eq": {"OnOff": "On"}}
+        }],
+        "execute": [{
+          "destination": [{"id": "Light"}],
+          "kind": "LightOn",
+          "value": {"Add": [{"Trigger": {}}, 1]}
+        }]
+      }]
+    }"#) {
+        Err(Error::CompileError(CompileError::TypeError(TypeError::InvalidOperands))) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    println!("* The type of an expression must match the kind of the statement.");
+    match compile(r#"{
+      "name": "foo",
+      "rules": [{
+        "conditions": [{
+          "source": [{"id": "Thermometer"}],
+          "kind": "OvenTemperature",
+          "range": {"Geq": {"Temperature": {"C": 100}}}
+        }],
+        "execute": [{
+          "destination": [{"id": "Light"}],
+          "kind": "LightOn",
+          "value": {"Template": {"format": "{}", "args": [{"Trigger": {}}]}}
+        }]
+      }]
+    }"#) {
+        Err(Error::CompileError(CompileError::TypeError(TypeError::KindAndValueDoNotAgree))) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    println!("* A well-typed expression is accepted.");
+    compile(r#"{
+      "name": "foo",
+      "rules": [{
+        "conditions": [{
+          "source": [{"id": "Thermometer"}],
+          "kind": "OvenTemperature",
+          "range": {"Geq": {"Temperature": {"C": 100}}}
+        }],
+        "execute": [{
+          "destination": [{"id": "Oven"}],
+          "kind": "OvenTemperature",
+          "value": {"Sub": [{"Mul": [{"Trigger": {}}, 2]}, {"Temperature": {"F": 32}}]}
+        }]
+      }]
+    }"#).unwrap();
+
+    println!("");
+}
+
+#[test]
+fn test_compile_diagnostics() {
+    println!("* All the problems of a script are reported, with their path.");
+    let script = Script::from_str(r#"{
+      "name": "foo",
+      "rules": [{
+        "conditions": [{
+          "source": [{"id": "Light"}],
+          "kind": "LightOn",
+          "range": {"Geq": {"Temperature": {"C": 100}}}
+        }, {
+          "source": [],
+          "kind": "LightOn",
+          "range": {"Eq": {"OnOff": "On"}}
+        }],
+        "execute": [{
+          "destination": [{"id": "Oven"}],
+          "kind": "OvenTemperature",
+          "value": {"OnOff": "On"}
+        }, {
+          "sequence": []
+        }]
+      }, {
+        "conditions": [],
+        "execute": [{
+          "destination": [{"id": "Light"}],
+          "kind": "LightOn",
+          "value": {"Add": [{"OnOff": "On"}, {"Trigger": {}}]}
+        }]
+      }]
+    }"#).unwrap();
+    let diagnostics = match Compiler::<FakeEnv>::new().unwrap().compile_with_diagnostics(script) {
+        Err(diagnostics) => diagnostics,
+        Ok(_) => panic!("The script should not have compiled")
+    };
+
+    let paths : Vec<_> = diagnostics.iter().map(|diagnostic| diagnostic.path.clone()).collect();
+    assert_eq!(paths, vec![
+        ".rules[0].conditions[0].range".to_owned(),
+        ".rules[0].conditions[1].source".to_owned(),
+        ".rules[0].execute[0].value".to_owned(),
+        ".rules[0].execute[1].sequence".to_owned(),
+        ".rules[1].conditions".to_owned(),
+        ".rules[1].execute[0].value.Add[1]".to_owned(),
+    ]);
+
+    match diagnostics[0].error {
+        CompileError::TypeError(TypeError::KindAndRangeDoNotAgree) => {},
+        ref other => panic!("Unexpected error {:?}", other)
+    }
+    match diagnostics[1].error {
+        CompileError::SourceError(SourceError::NoMatchSource) => {},
+        ref other => panic!("Unexpected error {:?}", other)
+    }
+    match diagnostics[2].error {
+        CompileError::TypeError(TypeError::KindAndValueDoNotAgree) => {},
+        ref other => panic!("Unexpected error {:?}", other)
+    }
+    match diagnostics[3].error {
+        CompileError::SourceError(SourceError::NoStatement) => {},
+        ref other => panic!("Unexpected error {:?}", other)
+    }
+    match diagnostics[4].error {
+        CompileError::SourceError(SourceError::NoMatch) => {},
+        ref other => panic!("Unexpected error {:?}", other)
+    }
+    match diagnostics[5].error {
+        CompileError::TypeError(TypeError::InvalidTrigger) => {},
+        ref other => panic!("Unexpected error {:?}", other)
+    }
+
+    println!("* `compile` reports the first problem.");
+    let script = Script::from_str(r#"{
+      "name": "foo",
+      "rules": [{
+        "conditions": [{
+          "source": [{"id": "Oven"}],
+          "kind": "OvenTemperature",
+          "range": {"Eq": {"OnOff": "On"}}
+        }],
+        "execute": [{
+          "destination": [{"id": "Oven"}],
+          "kind": "OvenTemperature",
+          "value": {"OnOff": "On"}
+        }]
+      }]
+    }"#).unwrap();
+    match Compiler::<FakeEnv>::new().unwrap().compile(script) {
+        Err(CompileError::TypeError(TypeError::KindAndRangeDoNotAgree)) => {},
+        Err(other) => panic!("Unexpected error {:?}", other),
+        Ok(_) => panic!("The script should not have compiled")
+    }
+
+    println!("");
+}
@@ -0,0 +1,178 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+extern crate serde_json;
+
+use foxbox_taxonomy::acl::{ Acl, AclError };
+use foxbox_taxonomy::manager::AdapterManager;
+use foxbox_taxonomy::util::Id;
+
+use foxbox_users::{ AuthEndpoint, ReadFilter, SessionToken, UsersManager };
+
+use iron::{ Handler, headers, IronResult, Request, Response };
+use iron::headers::ContentType;
+use iron::method::Method;
+use iron::prelude::Chain;
+use iron::status::Status;
+
+use serde::ser::Serialize;
+
+use std::io::Read;
+use std::sync::Arc;
+use traits::Controller;
+
+/// This is a specialized Router for managing the access control lists of the taxonomy.
+/// It handles all the calls under the api/v1/acl/ url space. Only administrators may
+/// use it.
+pub struct AclRouter {
+    acl: Arc<Acl>,
+    users_manager: Arc<UsersManager>,
+}
+
+impl AclRouter {
+    pub fn new(acl: Arc<Acl>, users_manager: Arc<UsersManager>) -> Self {
+        AclRouter {
+            acl: acl,
+            users_manager: users_manager
+        }
+    }
+
+    fn build_response<S: Serialize>(&self, obj: &S) -> IronResult<Response> {
+        let serialized = itry!(serde_json::to_string(obj));
+        let mut response = Response::with(serialized);
+        response.status = Some(Status::Ok);
+        response.headers.set(ContentType::json());
+        Ok(response)
+    }
+
+    fn build_result(&self, result: Result<(), AclError>) -> IronResult<Response> {
+        let err = match result {
+            Ok(()) => return Ok(Response::with(Status::NoContent)),
+            Err(err) => err
+        };
+        let status = match err {
+            AclError::ParseError(_) => Status::BadRequest,
+            AclError::NoSuchGrant(_) => Status::NotFound,
+            AclError::SQLError(_) => Status::InternalServerError,
+        };
+        let mut response = try!(self.build_response(&err));
+        response.status = Some(status);
+        Ok(response)
+    }
+}
+
+/// Determine whether the author of a request is an administrator.
+pub fn is_admin(users_manager: &UsersManager, req: &Request) -> Result<bool, Response> {
+    let id = match req.headers.get::<headers::Authorization<headers::Bearer>>() {
+        Some(&headers::Authorization(headers::Bearer { ref token })) => {
+            match SessionToken::from_string(token) {
+                Ok(token) => token.claims.id,
+                Err(_) => return Err(Response::with(Status::Unauthorized))
+            }
+        },
+        // Without authentication, as in tests, everybody is an administrator.
+        None => return Ok(!cfg!(feature = "authentication") || cfg!(test))
+    };
+    match users_manager.get_db().read(ReadFilter::IsAdmin(true)) {
+        Ok(admins) => Ok(admins.iter().any(|admin| admin.id == Some(id))),
+        Err(_) => Err(Response::with(Status::InternalServerError))
+    }
+}
+
+impl Handler for AclRouter {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        match is_admin(&self.users_manager, req) {
+            Ok(true) => {},
+            Ok(false) => return Ok(Response::with(Status::Forbidden)),
+            Err(response) => return Ok(response)
+        }
+
+        // We are handling urls relative to the mounter set up in http_server.rs
+        // That means that for a full url like http://localhost/api/v1/acl/grants
+        // the req.url.path will only contain ["grants"]
+        let path = req.url.path.clone();
+        let mut body = String::new();
+        itry!(req.body.read_to_string(&mut body));
+
+        match (&req.method, path.len(), path.get(0).map(|s| &s[..])) {
+            // List grants.
+            (&Method::Get, 1, Some("grants")) =>
+                self.build_response(&self.acl.get_grants()),
+
+            // Add or replace a grant. The body is the JSON source of the grant.
+            (&Method::Put, 2, Some("grants")) =>
+                self.build_result(self.acl.put_grant(&Id::new(&path[1]), &body)),
+
+            // Remove a grant.
+            (&Method::Delete, 2, Some("grants")) =>
+                self.build_result(self.acl.remove_grant(&Id::new(&path[1]))),
+
+            // List groups and their members.
+            (&Method::Get, 1, Some("groups")) =>
+                self.build_response(&self.acl.get_groups()),
+
+            // Add users to a group or remove users from a group. The body is an array
+            // of user ids.
+            (&Method::Put, 2, Some("groups")) | (&Method::Delete, 2, Some("groups")) => {
+                let users : Vec<i32> = match serde_json::from_str(&body) {
+                    Ok(users) => users,
+                    Err(err) => return Ok(Response::with((Status::BadRequest, format!("{}", err))))
+                };
+                let group = Id::new(&path[1]);
+                if req.method == Method::Put {
+                    self.build_result(self.acl.add_group_members(&group, &users))
+                } else {
+                    self.build_result(self.acl.remove_group_members(&group, &users))
+                }
+            },
+
+            // Whether access control lists are enforced.
+            (&Method::Get, 1, Some("enforced")) =>
+                self.build_response(&self.acl.is_enforced()),
+
+            // Turn enforcement on or off. The body is a boolean.
+            (&Method::Put, 1, Some("enforced")) => {
+                let enforced : bool = match serde_json::from_str(&body) {
+                    Ok(enforced) => enforced,
+                    Err(err) => return Ok(Response::with((Status::BadRequest, format!("{}", err))))
+                };
+                self.build_result(self.acl.set_enforced(enforced))
+            },
+
+            // Fallthrough, returning a 404.
+            _ => Ok(Response::with((Status::NotFound,
+                                    format!("Unknown url: {}", req.url))))
+        }
+    }
+}
+
+/// Create the router, if `adapter_api` enforces access control lists.
+pub fn create<T>(controller: T, adapter_api: &Arc<AdapterManager>) -> Option<Chain>
+    where T: Controller {
+    let acl = match adapter_api.acl() {
+        None => return None,
+        Some(acl) => acl.clone()
+    };
+    let users_manager = controller.get_users_manager();
+    let router = AclRouter::new(acl, users_manager.clone());
+
+    let auth_endpoints = if cfg!(feature = "authentication") && !cfg!(test) {
+        // Keep this list in sync with all the (url path, http method) from
+        // the handle() method and with the CORS chain in http_server.rs
+        vec![
+            AuthEndpoint(vec![Method::Get], "grants".to_owned()),
+            AuthEndpoint(vec![Method::Put, Method::Delete], "grants/:id".to_owned()),
+            AuthEndpoint(vec![Method::Get], "groups".to_owned()),
+            AuthEndpoint(vec![Method::Put, Method::Delete], "groups/:id".to_owned()),
+            AuthEndpoint(vec![Method::Get, Method::Put], "enforced".to_owned())
+        ]
+    } else {
+        vec![]
+    };
+
+    let mut chain = Chain::new(router);
+    chain.around(users_manager.get_middleware(auth_endpoints));
+
+    Some(chain)
+}
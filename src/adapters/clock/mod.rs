@@ -214,6 +214,9 @@ impl Clock {
                     .clone().into();
                 vec![(Movement::Enter, ts.clone()), (Movement::Exit, ts)]
             }
+            Not(_) | AnyOf(_) | AllOf(_) => {
+                return self.aux_register_watch_timeofday_composite(id, range.clone(), tx)
+            }
         };
 
         // Determine when the next timers needs to launch.
@@ -297,6 +300,9 @@ impl Clock {
                     .as_datetime();
                 vec![(Movement::Enter, ts_min), (Movement::Exit, ts_max)]
             }
+            Not(_) | AnyOf(_) | AllOf(_) => {
+                return self.aux_register_watch_timestamp_composite(id, range.clone(), tx)
+            }
         };
 
         // Determine when/if the next timers needs to launch.
@@ -327,6 +333,102 @@ impl Clock {
     }
 }
 
+/// Composite ranges are watched by checking them whenever the clock crosses one
+/// of the bounds of their leaves, then right after it, as leaves may or may not
+/// include their bounds.
+impl Clock {
+    /// The bounds of all the leaves of a range.
+    fn leaf_bounds(range: &Range) -> Vec<&Value> {
+        use foxbox_taxonomy::values::Range::*;
+        match *range {
+            Leq(ref val) | Geq(ref val) | Eq(ref val) => vec![val],
+            BetweenEq { ref min, ref max } | OutOfStrict { ref min, ref max } => vec![min, max],
+            Not(ref range) => Self::leaf_bounds(range),
+            AnyOf(ref ranges) | AllOf(ref ranges) =>
+                ranges.iter().flat_map(Self::leaf_bounds).collect()
+        }
+    }
+
+    /// Send `Enter` or `Exit` if `value` moves into or out of `range`.
+    fn check_composite(id: &Id<Getter>, range: &Range, is_met: &Mutex<bool>, tx: &Box<ExtSender<Op>>, value: Value) {
+        let contained = range.contains(&value);
+        let mut is_met = is_met.lock().unwrap();
+        if contained == *is_met {
+            return;
+        }
+        *is_met = contained;
+        let event = if contained {
+            Op::Enter(id.clone(), value)
+        } else {
+            Op::Exit(id.clone(), value)
+        };
+        let _ = tx.send(event);
+    }
+
+    fn aux_register_watch_timeofday_composite(&self, id: &Id<Getter>, range: Range, tx: Box<ExtSender<Op>>)
+        -> Result<Box<AdapterWatchGuard>, Error>
+    {
+        let mut checkpoints = vec![];
+        for bound in Self::leaf_bounds(&range) {
+            let bound : chrono::Duration = try!(bound.as_duration().map_err(Error::TypeError))
+                .clone().into();
+            let after = bound + Duration::seconds(1);
+            checkpoints.push(bound);
+            checkpoints.push(if after >= Duration::days(1) { after - Duration::days(1) } else { after });
+        }
+
+        let now = chrono::Local::now();
+        let time_of_day = Duration::seconds(now.num_seconds_from_midnight() as i64);
+        let is_met = Arc::new(Mutex::new(range.contains(&Value::Duration(ValDuration::from(time_of_day)))));
+        let range = Arc::new(range);
+        let guards : Vec<timer::Guard> = checkpoints.drain(..).filter_map(|checkpoint| {
+            let date = match Self::get_next_date(&now, checkpoint) {
+                Err(_) => return None,
+                Ok(date) => date,
+            };
+            let id = id.clone();
+            let range = range.clone();
+            let is_met = is_met.clone();
+            let tx = tx.clone();
+            let guard = self.timer.lock().unwrap().schedule(date, Some(Duration::days(1)), move || {
+                Self::check_composite(&id, &range, &is_met, &tx, Value::Duration(ValDuration::from(checkpoint)));
+            });
+            Some(guard)
+        }).collect();
+        Ok(Box::new(Guard(guards)))
+    }
+
+    fn aux_register_watch_timestamp_composite(&self, id: &Id<Getter>, range: Range, tx: Box<ExtSender<Op>>)
+        -> Result<Box<AdapterWatchGuard>, Error>
+    {
+        let mut checkpoints = vec![];
+        for bound in Self::leaf_bounds(&range) {
+            let bound = *try!(bound.as_timestamp().map_err(Error::TypeError))
+                .as_datetime();
+            checkpoints.push(bound);
+            checkpoints.push(bound + Duration::seconds(1));
+        }
+
+        let now = chrono::UTC::now();
+        let is_met = Arc::new(Mutex::new(range.contains(&Value::TimeStamp(TimeStamp::from_datetime(now)))));
+        let range = Arc::new(range);
+        let guards : Vec<timer::Guard> = checkpoints.drain(..).filter_map(|checkpoint| {
+            if checkpoint < now {
+                return None
+            }
+            let id = id.clone();
+            let range = range.clone();
+            let is_met = is_met.clone();
+            let tx = tx.clone();
+            let guard = self.timer.lock().unwrap().schedule_with_date(checkpoint, move || {
+                Self::check_composite(&id, &range, &is_met, &tx, Value::TimeStamp(TimeStamp::from_datetime(checkpoint)));
+            });
+            Some(guard)
+        }).collect();
+        Ok(Box::new(Guard(guards)))
+    }
+}
+
 impl Clock {
     pub fn init(adapt: &Arc<AdapterManager>) -> Result<(), Error> {
         let getter_timestamp_id = Clock::getter_timestamp_id();
@@ -348,6 +450,7 @@ impl Clock {
                 adapter: Clock::id(),
                 id: getter_time_of_day_id,
                 last_seen: None,
+                capabilities: None,
                 service: service_clock_id.clone(),
                 mechanism: Getter {
                     kind: ChannelKind::CurrentTimeOfDay,
@@ -359,6 +462,7 @@ impl Clock {
                 adapter: Clock::id(),
                 id: getter_timestamp_id,
                 last_seen: None,
+                capabilities: None,
                 service: service_clock_id.clone(),
                 mechanism: Getter {
                     kind: ChannelKind::CurrentTime,
@@ -370,6 +474,7 @@ impl Clock {
                 adapter: Clock::id(),
                 id: getter_interval_id,
                 last_seen: None,
+                capabilities: None,
                 service: service_clock_id.clone(),
                 mechanism: Getter {
                     kind: ChannelKind::CountEveryInterval,
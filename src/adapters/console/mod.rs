@@ -100,6 +100,7 @@ impl Console {
                 adapter: Console::id(),
                 id: setter_stdout_id.clone(),
                 last_seen: None,
+                capabilities: None,
                 service: service_console_id.clone(),
                 mechanism: Setter {
                     kind: ChannelKind::Log,
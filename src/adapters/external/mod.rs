@@ -0,0 +1,738 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A proxy adapter for adapters implemented as external processes.
+//!
+//! The process is started with `sh -c <command>` and supervised: whenever it dies, all
+//! the services it has registered are removed and it is restarted. The process talks to
+//! foxbox through its stdin/stdout, one JSON object per line. Anything the process writes
+//! to stderr is left untouched.
+//!
+//! # Messages from the process
+//!
+//! The adapter id of services and channels is filled in by the proxy.
+//!
+//! ```ignore
+//! { "type": "ready" }          // Once the process is initialized.
+//! { "type": "add_service", "service": { "id": string, "properties": object?, "tags": [string]? } }
+//! { "type": "remove_service", "id": string }
+//! { "type": "add_getter", "getter": Channel }
+//! { "type": "remove_getter", "id": string }
+//! { "type": "add_setter", "setter": Channel }
+//! { "type": "remove_setter", "id": string }
+//!
+//! // A channel is `{ "id": string, "service": string, "kind": ChannelKind,
+//! //                 "tags": [string]?, "capabilities": Capabilities? }`.
+//!
+//! // The answer to a `fetch` or `send` request, with one result per channel.
+//! // For `fetch`, `Ok` is a `Value` or `null` if no value is available.
+//! { "type": "response", "request": number, "results": { id: { "Ok": Value | null } | { "Err": string } } }
+//!
+//! // A watched getter entered or exited its range.
+//! { "type": "event", "watch": number, "event": "enter" | "exit", "value": Value }
+//! ```
+//!
+//! # Messages to the process
+//!
+//! ```ignore
+//! { "type": "fetch", "request": number, "getters": [string], "user": User }
+//! { "type": "send", "request": number, "values": { id: Value }, "user": User }
+//! { "type": "watch", "watch": number, "getter": string, "range": Range | null }
+//! { "type": "unwatch", "watch": number }
+//!
+//! // `User`, on behalf of whom a request is made, is a user id, "system" for requests
+//! // made by the box itself, or `null` if the request is not authenticated.
+//! ```
+//!
+//! Each `fetch` and `send` must eventually be answered by a `response` with the same
+//! `request` number. Requests that are still pending when the process dies, or that
+//! are not answered within the timeout of the `AdapterManager`, fail.
+
+use foxbox_taxonomy::api::{ AdapterStatus, Error, InternalError, User };
+use foxbox_taxonomy::manager::*;
+use foxbox_taxonomy::parse::*;
+use foxbox_taxonomy::services::*;
+use foxbox_taxonomy::values::{ Range, Value };
+
+use managed_process::ManagedProcess;
+
+use serde_json;
+use transformable_channels::mpsc::*;
+
+use std::collections::{ HashMap, HashSet };
+use std::io::{ self, BufRead, BufReader, Write };
+use std::process::{ ChildStdin, ChildStdout, Command, Stdio };
+use std::sync::{ Arc, Mutex, Weak };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::sync::mpsc::{ channel as std_channel, RecvTimeoutError, Sender as StdSender };
+use std::thread;
+
+static ADAPTER_VENDOR: &'static str = "team@link.mozilla.org";
+static ADAPTER_VERSION: [u32;4] = [0, 0, 0, 0];
+
+type Results = HashMap<String, Result<Option<Value>, Error>>;
+
+enum Movement { Enter, Exit }
+
+/// A message sent by the external process.
+enum ChildMessage {
+    Ready,
+    AddService(Service),
+    RemoveService(Id<ServiceId>),
+    AddGetter(Channel<Getter>),
+    RemoveGetter(Id<Getter>),
+    AddSetter(Channel<Setter>),
+    RemoveSetter(Id<Setter>),
+    Response {
+        request: u64,
+        results: Results
+    },
+    Event {
+        watch: u64,
+        movement: Movement,
+        value: Value
+    }
+}
+
+fn take_u64(path: Path, source: &mut JSON, field: &str) -> Result<u64, ParseError> {
+    path.push(field, |path| {
+        match source.as_object_mut().and_then(|obj| obj.remove(field)) {
+            None => Err(ParseError::missing_field(field, &path)),
+            Some(json) => match json.as_u64() {
+                Some(number) => Ok(number),
+                None => Err(ParseError::type_error(field, &path, "positive integer"))
+            }
+        }
+    })
+}
+
+fn take_tags(path: Path, source: &mut JSON) -> Result<HashSet<Id<TagId>>, ParseError> {
+    match path.push("tags", |path| Vec::<Id<TagId>>::take_opt(path, source, "tags")) {
+        None => Ok(HashSet::new()),
+        Some(result) => Ok(try!(result).into_iter().collect())
+    }
+}
+
+fn parse_service(path: Path, source: &mut JSON, adapter: &Id<AdapterId>) -> Result<Service, ParseError> {
+    let id = try!(path.push("id", |path| Id::take(path, source, "id")));
+    let mut service = Service::empty(id, adapter.clone());
+    service.tags = try!(take_tags(path.clone(), source));
+    if let Some(properties) = source.as_object_mut().and_then(|obj| obj.remove("properties")) {
+        let properties = match properties {
+            JSON::Object(properties) => properties,
+            _ => return Err(ParseError::type_error("properties", &path, "object"))
+        };
+        for (key, value) in properties {
+            match value {
+                JSON::String(value) => { service.properties.insert(key, value); },
+                _ => return path.push("properties", |path| Err(ParseError::type_error(&key, &path, "string")))
+            }
+        }
+    }
+    Ok(service)
+}
+
+fn parse_channel<IO, F>(path: Path, source: &mut JSON, adapter: &Id<AdapterId>, mechanism: F)
+    -> Result<Channel<IO>, ParseError> where IO: IOMechanism, F: FnOnce(ChannelKind) -> IO
+{
+    let id = try!(path.push("id", |path| Id::take(path, source, "id")));
+    let service = try!(path.push("service", |path| Id::take(path, source, "service")));
+    let kind = try!(path.push("kind", |path| ChannelKind::take(path, source, "kind")));
+    let tags = try!(take_tags(path.clone(), source));
+    let capabilities = match source.as_object_mut().and_then(|obj| obj.remove("capabilities")) {
+        None | Some(JSON::Null) => None,
+        Some(json) => Some(try!(serde_json::value::from_value(json).map_err(ParseError::json)))
+    };
+    Ok(Channel {
+        tags: tags,
+        id: id,
+        service: service,
+        mechanism: mechanism(kind),
+        adapter: adapter.clone(),
+        last_seen: None,
+        capabilities: capabilities
+    })
+}
+
+fn parse_results(path: Path, source: &mut JSON) -> Result<Results, ParseError> {
+    let results = match source.as_object_mut().and_then(|obj| obj.remove("results")) {
+        Some(JSON::Object(results)) => results,
+        Some(_) => return Err(ParseError::type_error("results", &path, "object")),
+        None => return Err(ParseError::missing_field("results", &path))
+    };
+    let mut parsed = HashMap::new();
+    for (id, mut result) in results {
+        let value = try!(path.push(&id, |path| {
+            if let Some(message) = result.find("Err").and_then(|err| err.as_string()) {
+                return Ok(Err(Error::InternalError(InternalError::GenericError(message.to_owned()))));
+            }
+            match result.as_object_mut().and_then(|obj| obj.remove("Ok")) {
+                None => Err(ParseError::type_error("result", &path, "object {Ok} | {Err}")),
+                Some(JSON::Null) => Ok(Ok(None)),
+                Some(mut value) => Ok(Ok(Some(try!(path.push("Ok", |path| Value::parse(path, &mut value))))))
+            }
+        }));
+        parsed.insert(id, value);
+    }
+    Ok(parsed)
+}
+
+impl ChildMessage {
+    fn parse(path: Path, source: &mut JSON, adapter: &Id<AdapterId>) -> Result<Self, ParseError> {
+        let typ = try!(path.push("type", |path| String::take(path, source, "type")));
+        let take_object = |source: &mut JSON, field: &str| {
+            match source.as_object_mut().and_then(|obj| obj.remove(field)) {
+                Some(json) => Ok(json),
+                None => Err(ParseError::missing_field(field, &path))
+            }
+        };
+        match &typ as &str {
+            "ready" => Ok(ChildMessage::Ready),
+            "add_service" => {
+                let mut service = try!(take_object(source, "service"));
+                let service = try!(path.push("service", |path| parse_service(path, &mut service, adapter)));
+                Ok(ChildMessage::AddService(service))
+            }
+            "add_getter" => {
+                let mut getter = try!(take_object(source, "getter"));
+                let getter = try!(path.push("getter", |path| parse_channel(path, &mut getter, adapter, |kind| Getter {
+                    kind: kind,
+                    updated: None
+                })));
+                Ok(ChildMessage::AddGetter(getter))
+            }
+            "add_setter" => {
+                let mut setter = try!(take_object(source, "setter"));
+                let setter = try!(path.push("setter", |path| parse_channel(path, &mut setter, adapter, |kind| Setter {
+                    kind: kind,
+                    updated: None
+                })));
+                Ok(ChildMessage::AddSetter(setter))
+            }
+            "remove_service" => Ok(ChildMessage::RemoveService(try!(path.push("id", |path| Id::take(path, source, "id"))))),
+            "remove_getter" => Ok(ChildMessage::RemoveGetter(try!(path.push("id", |path| Id::take(path, source, "id"))))),
+            "remove_setter" => Ok(ChildMessage::RemoveSetter(try!(path.push("id", |path| Id::take(path, source, "id"))))),
+            "response" => {
+                let request = try!(take_u64(path.clone(), source, "request"));
+                let results = try!(path.push("results", |path| parse_results(path, source)));
+                Ok(ChildMessage::Response {
+                    request: request,
+                    results: results
+                })
+            }
+            "event" => {
+                let watch = try!(take_u64(path.clone(), source, "watch"));
+                let event = try!(path.push("event", |path| String::take(path, source, "event")));
+                let movement = match &event as &str {
+                    "enter" => Movement::Enter,
+                    "exit" => Movement::Exit,
+                    _ => return path.push("event", |path| Err(ParseError::unknown_constant(&event, &path)))
+                };
+                let value = try!(path.push("value", |path| Value::take(path, source, "value")));
+                Ok(ChildMessage::Event {
+                    watch: watch,
+                    movement: movement,
+                    value: value
+                })
+            }
+            _ => path.push("type", |path| Err(ParseError::unknown_constant(&typ, &path)))
+        }
+    }
+}
+
+fn user_to_json(user: &User) -> JSON {
+    match *user {
+        User::None => JSON::Null,
+        User::Id(id) => JSON::I64(id as i64),
+        User::System => "system".to_json()
+    }
+}
+
+fn not_running() -> Error {
+    Error::InternalError(InternalError::GenericError("The adapter process is not running".to_owned()))
+}
+
+/// The connection to the current instance of the process.
+struct Process {
+    /// Incremented whenever the process is restarted, to ignore late messages from
+    /// previous instances.
+    generation: usize,
+
+    /// The stdin of the process, if it is running.
+    stdin: Option<ChildStdin>,
+
+    /// The services registered by the process.
+    services: HashSet<Id<ServiceId>>,
+
+    /// The `fetch` and `send` requests waiting for a response.
+    pending: HashMap<u64, StdSender<Results>>,
+}
+
+impl Process {
+    fn write(&mut self, message: &JSON) -> Result<(), Error> {
+        let stdin = match self.stdin {
+            Some(ref mut stdin) => stdin,
+            None => return Err(not_running())
+        };
+        let mut line = match serde_json::to_string(message) {
+            Ok(line) => line,
+            Err(err) => return Err(Error::InternalError(InternalError::GenericError(format!("{}", err))))
+        };
+        line.push('\n');
+        let io_error = |err: io::Error| Error::InternalError(InternalError::GenericError(format!("{}", err)));
+        try!(stdin.write_all(line.as_bytes()).map_err(&io_error));
+        stdin.flush().map_err(&io_error)
+    }
+}
+
+/// State shared between the adapter and the threads reading from the process.
+struct Shared {
+    id: Id<AdapterId>,
+    /// The manager owns the adapter, so we must not keep it alive.
+    manager: Weak<AdapterManager>,
+    process: Mutex<Process>,
+    watchers: Mutex<HashMap<u64, (Id<Getter>, Box<ExtSender<WatchEvent>>)>>,
+    counter: AtomicUsize,
+}
+
+impl Shared {
+    fn next_key(&self) -> u64 {
+        self.counter.fetch_add(1, Ordering::SeqCst) as u64
+    }
+
+    /// Send a request to the process and wait for its response.
+    ///
+    /// Gives up after the timeout of the `AdapterManager`, as it does not wait any
+    /// longer anyway.
+    fn call(&self, mut message: Vec<(&str, JSON)>) -> Result<Results, Error> {
+        let timeout = match self.manager.upgrade() {
+            Some(manager) => manager.timeout(),
+            None => return Err(not_running())
+        };
+        let (tx, rx) = std_channel();
+        let request = self.next_key();
+        message.push(("request", JSON::U64(request)));
+        {
+            let mut process = self.process.lock().unwrap();
+            try!(process.write(&message.to_json()));
+            process.pending.insert(request, tx);
+        }
+        match rx.recv_timeout(timeout) {
+            Ok(results) => Ok(results),
+            Err(RecvTimeoutError::Timeout) => {
+                // Don't let late responses pile up.
+                self.process.lock().unwrap().pending.remove(&request);
+                Err(Error::Timeout(self.id.clone()))
+            }
+            // The sender is dropped if the process dies before answering.
+            Err(RecvTimeoutError::Disconnected) => Err(not_running())
+        }
+    }
+
+    /// Fail the requests pending on an instance of the process, e.g. because it has
+    /// exited. Does nothing if this instance has already been replaced.
+    fn fail_pending(&self, generation: usize) {
+        let mut process = self.process.lock().unwrap();
+        if process.generation == generation {
+            process.pending.clear();
+        }
+    }
+
+    /// Start talking to a new instance of the process.
+    fn attach(shared: &Arc<Shared>, stdin: ChildStdin, stdout: ChildStdout) {
+        let previous = shared.process.lock().unwrap().generation;
+        shared.detach(previous, None);
+
+        let generation = {
+            let mut process = shared.process.lock().unwrap();
+            process.generation += 1;
+            process.stdin = Some(stdin);
+            process.generation
+        };
+        if let Some(manager) = shared.manager.upgrade() {
+            let _ignored = manager.set_adapter_status(&shared.id, AdapterStatus::Starting);
+        }
+
+        // Registrations are handled on a separate thread, as the `AdapterManager` may
+        // call back into the adapter, e.g. to fetch the initial value of a watched getter,
+        // and wait for a response that only the reader can deliver.
+        let (tx, rx) = std_channel();
+        let worker = shared.clone();
+        thread::spawn(move || {
+            for message in rx.iter() {
+                worker.register(generation, message);
+            }
+            worker.detach(generation, Some(AdapterStatus::Failed("The adapter process has exited".to_owned())));
+        });
+
+        let reader = shared.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => {
+                        warn!("[{}] Could not read from adapter process: {}", reader.id, err);
+                        break;
+                    }
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let message = match serde_json::from_str(&line) {
+                    Err(err) => Err(ParseError::json(err)),
+                    Ok(mut json) => ChildMessage::parse(Path::new(), &mut json, &reader.id)
+                };
+                match message {
+                    Err(err) => warn!("[{}] Invalid message from adapter process {}: {:?}", reader.id, line, err),
+                    Ok(ChildMessage::Response { request, results }) => reader.respond(generation, request, results),
+                    Ok(ChildMessage::Event { watch, movement, value }) => reader.notify(watch, movement, value),
+                    Ok(message) => {
+                        let _ = tx.send(message);
+                    }
+                }
+            }
+            // The process has exited. Its pending requests won't be answered, so fail them
+            // immediately rather than once the worker has handled all registrations.
+            reader.fail_pending(generation);
+            // Dropping `tx` lets the worker clean up once it has handled all registrations.
+        });
+    }
+
+    /// Forget about an instance of the process, removing its services and failing its
+    /// pending requests. Does nothing if this instance has already been replaced.
+    fn detach(&self, generation: usize, status: Option<AdapterStatus>) {
+        let mut services = {
+            let mut process = self.process.lock().unwrap();
+            if process.generation != generation {
+                return;
+            }
+            process.stdin = None;
+            process.pending.clear();
+            process.services.drain().collect::<Vec<_>>()
+        };
+        let manager = match self.manager.upgrade() {
+            Some(manager) => manager,
+            None => return
+        };
+        for id in services.drain(..) {
+            if let Err(err) = manager.remove_service(&id) {
+                debug!("[{}] Could not remove service {}: {}", self.id, id, err);
+            }
+        }
+        if let Some(status) = status {
+            let _ignored = manager.set_adapter_status(&self.id, status);
+        }
+    }
+
+    fn is_current(&self, generation: usize) -> bool {
+        self.process.lock().unwrap().generation == generation
+    }
+
+    fn register(&self, generation: usize, message: ChildMessage) {
+        if !self.is_current(generation) {
+            return;
+        }
+        let manager = match self.manager.upgrade() {
+            Some(manager) => manager,
+            None => return
+        };
+        let result = match message {
+            ChildMessage::Ready => manager.set_adapter_status(&self.id, AdapterStatus::Running),
+            ChildMessage::AddService(service) => {
+                let id = service.id.clone();
+                let result = manager.add_service(service);
+                if result.is_ok() {
+                    self.process.lock().unwrap().services.insert(id);
+                }
+                result
+            }
+            ChildMessage::RemoveService(id) => {
+                self.process.lock().unwrap().services.remove(&id);
+                manager.remove_service(&id)
+            }
+            ChildMessage::AddGetter(getter) => manager.add_getter(getter),
+            ChildMessage::RemoveGetter(id) => manager.remove_getter(&id),
+            ChildMessage::AddSetter(setter) => manager.add_setter(setter),
+            ChildMessage::RemoveSetter(id) => manager.remove_setter(&id),
+            ChildMessage::Response { .. } | ChildMessage::Event { .. } => Ok(())
+        };
+        if let Err(err) = result {
+            warn!("[{}] Request from adapter process failed: {}", self.id, err);
+        }
+    }
+
+    fn respond(&self, generation: usize, request: u64, results: Results) {
+        let mut process = self.process.lock().unwrap();
+        if process.generation != generation {
+            return;
+        }
+        match process.pending.remove(&request) {
+            Some(tx) => { let _ = tx.send(results); },
+            None => warn!("[{}] Response to unknown request {}", self.id, request)
+        }
+    }
+
+    fn notify(&self, watch: u64, movement: Movement, value: Value) {
+        let watchers = self.watchers.lock().unwrap();
+        if let Some(&(ref id, ref tx)) = watchers.get(&watch) {
+            let event = match movement {
+                Movement::Enter => WatchEvent::Enter { id: id.clone(), value: value },
+                Movement::Exit => WatchEvent::Exit { id: id.clone(), value: value }
+            };
+            let _ = tx.send(event);
+        }
+    }
+}
+
+/// A guard used to stop watching a getter.
+struct Guard {
+    shared: Weak<Shared>,
+    watch: u64,
+}
+impl AdapterWatchGuard for Guard {
+}
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.watchers.lock().unwrap().remove(&self.watch);
+            let message = vec![("type", "unwatch".to_json()), ("watch", JSON::U64(self.watch))];
+            let _ignored = shared.process.lock().unwrap().write(&message.to_json());
+        }
+    }
+}
+
+pub struct ExternalAdapter {
+    name: String,
+    command: String,
+    shared: Arc<Shared>,
+    process: Mutex<Option<ManagedProcess>>,
+}
+
+impl ExternalAdapter {
+    pub fn id(name: &str) -> Id<AdapterId> {
+        Id::new(&format!("{}@external.link.mozilla.org", name))
+    }
+
+    /// Register an adapter implemented by the process `command`, and start the process.
+    pub fn init(manager: &Arc<AdapterManager>, name: &str, command: &str) -> Result<Arc<Self>, Error> {
+        let adapter = Arc::new(ExternalAdapter {
+            name: name.to_owned(),
+            command: command.to_owned(),
+            shared: Arc::new(Shared {
+                id: Self::id(name),
+                manager: Arc::downgrade(manager),
+                process: Mutex::new(Process {
+                    generation: 0,
+                    stdin: None,
+                    services: HashSet::new(),
+                    pending: HashMap::new(),
+                }),
+                watchers: Mutex::new(HashMap::new()),
+                counter: AtomicUsize::new(0),
+            }),
+            process: Mutex::new(None),
+        });
+        try!(manager.add_adapter(adapter.clone()));
+        if let Err(err) = adapter.start() {
+            let _ignored = manager.remove_adapter(&adapter.shared.id);
+            return Err(Error::InternalError(InternalError::GenericError(format!("{}", err))));
+        }
+        Ok(adapter)
+    }
+
+    fn start(&self) -> io::Result<()> {
+        let shared = self.shared.clone();
+        let command = self.command.clone();
+        let process = try!(ManagedProcess::start(move || {
+            let mut child = try!(Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn());
+            if let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) {
+                Shared::attach(&shared, stdin, stdout);
+            }
+            Ok(child)
+        }));
+        *self.process.lock().unwrap() = Some(process);
+        Ok(())
+    }
+}
+
+impl Adapter for ExternalAdapter {
+    fn id(&self) -> Id<AdapterId> {
+        self.shared.id.clone()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn vendor(&self) -> &str {
+        ADAPTER_VENDOR
+    }
+
+    fn version(&self) -> &[u32;4] {
+        &ADAPTER_VERSION
+    }
+
+    fn fetch_values(&self, mut set: Vec<Id<Getter>>, user: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        let message = vec![("type", "fetch".to_json()), ("getters", set.to_json()), ("user", user_to_json(&user))];
+        match self.shared.call(message) {
+            Err(err) => set.drain(..).map(|id| (id, Err(err.clone()))).collect(),
+            Ok(mut results) => set.drain(..).map(|id| {
+                let result = results.remove(&id.to_string())
+                    .unwrap_or_else(|| Err(Error::InternalError(InternalError::NoSuchGetter(id.clone()))));
+                (id, result)
+            }).collect()
+        }
+    }
+
+    fn send_values(&self, mut values: HashMap<Id<Setter>, Value>, user: User) -> ResultMap<Id<Setter>, (), Error> {
+        let message = vec![("type", "send".to_json()), ("values", values.to_json()), ("user", user_to_json(&user))];
+        match self.shared.call(message) {
+            Err(err) => values.drain().map(|(id, _)| (id, Err(err.clone()))).collect(),
+            Ok(mut results) => values.drain().map(|(id, _)| {
+                let result = match results.remove(&id.to_string()) {
+                    Some(result) => result.map(|_| ()),
+                    None => Err(Error::InternalError(InternalError::NoSuchSetter(id.clone())))
+                };
+                (id, result)
+            }).collect()
+        }
+    }
+
+    fn register_watch(&self, mut watch: Vec<WatchTarget>) -> WatchResult {
+        watch.drain(..).map(|(id, range, tx): (Id<Getter>, Option<Range>, Box<ExtSender<WatchEvent>>)| {
+            let key = self.shared.next_key();
+            let message = vec![
+                ("type", "watch".to_json()),
+                ("watch", JSON::U64(key)),
+                ("getter", id.to_json()),
+                ("range", range.to_json())
+            ];
+            self.shared.watchers.lock().unwrap().insert(key, (id.clone(), tx));
+            let result = self.shared.process.lock().unwrap().write(&message.to_json());
+            let result = match result {
+                Ok(()) => Ok(Box::new(Guard {
+                    shared: Arc::downgrade(&self.shared),
+                    watch: key
+                }) as Box<AdapterWatchGuard>),
+                Err(err) => {
+                    self.shared.watchers.lock().unwrap().remove(&key);
+                    Err(err)
+                }
+            };
+            (id, result)
+        }).collect()
+    }
+
+    fn stop(&self) {
+        if let Some(process) = self.process.lock().unwrap().take() {
+            if let Err(err) = process.shutdown() {
+                warn!("[{}] Could not stop adapter process: {}", self.shared.id, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+describe! external_adapter {
+    before_each {
+        use foxbox_taxonomy::api::{ API, User };
+        use foxbox_taxonomy::manager::AdapterManager;
+        use foxbox_taxonomy::selector::*;
+        use foxbox_taxonomy::services::*;
+        use foxbox_taxonomy::values::{ OnOff, Value };
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        // A fake adapter: registers a service with a getter and a setter, answers
+        // `fetch` requests with `Off` for user 1 and `On` for anybody else, and exits
+        // whenever it receives a `send` request.
+        let script = r#"
+            echo '{"type": "add_service", "service": {"id": "service:fake@test", "properties": {"model": "fake"}}}'
+            echo '{"type": "add_getter", "getter": {"id": "getter:fake@test", "service": "service:fake@test", "kind": "LightOn"}}'
+            echo '{"type": "add_setter", "setter": {"id": "setter:fake@test", "service": "service:fake@test", "kind": "LightOn"}}'
+            echo '{"type": "ready"}'
+            while read line; do
+                case "$line" in
+                    *'"fetch"'*)
+                        request=$(echo "$line" | sed 's/.*"request":\([0-9]*\).*/\1/')
+                        case "$line" in
+                            *'"user":1'[,}]*) value=Off ;;
+                            *) value=On ;;
+                        esac
+                        echo "{\"type\": \"response\", \"request\": $request, \"results\": {\"getter:fake@test\": {\"Ok\": {\"OnOff\": \"$value\"}}}}"
+                        ;;
+                    *'"send"'*)
+                        exit 0
+                        ;;
+                esac
+            done
+        "#;
+
+        let manager = Arc::new(AdapterManager::new(None));
+        let adapter = ExternalAdapter::init(&manager, "fake", script).unwrap();
+
+        let service_selector = vec![ServiceSelector::new().with_id(Id::new("service:fake@test"))];
+        let wait_for_services = |count: usize| {
+            for _ in 0..100 {
+                if manager.get_services(service_selector.clone()).len() == count {
+                    return true;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            false
+        };
+    }
+
+    it "should register the services of the process" {
+        assert!(wait_for_services(1));
+        let services = manager.get_services(service_selector.clone());
+        assert_eq!(services[0].adapter, ExternalAdapter::id("fake"));
+        assert_eq!(services[0].properties.get("model"), Some(&"fake".to_owned()));
+        assert_eq!(services[0].getters.len(), 1);
+        assert_eq!(services[0].setters.len(), 1);
+    }
+
+    it "should forward fetch requests to the process" {
+        assert!(wait_for_services(1));
+        let getter_id : Id<Getter> = Id::new("getter:fake@test");
+        let mut values = manager.fetch_values(vec![GetterSelector::new().with_id(getter_id.clone())], User::None);
+        match values.remove(&getter_id) {
+            Some(Ok(Some(Value::OnOff(OnOff::On)))) => {},
+            other => panic!("Unexpected result {:?}", other)
+        }
+    }
+
+    it "should forward the user to the process" {
+        assert!(wait_for_services(1));
+        let getter_id : Id<Getter> = Id::new("getter:fake@test");
+        let mut values = manager.fetch_values(vec![GetterSelector::new().with_id(getter_id.clone())], User::Id(1));
+        match values.remove(&getter_id) {
+            Some(Ok(Some(Value::OnOff(OnOff::Off)))) => {},
+            other => panic!("Unexpected result {:?}", other)
+        }
+    }
+
+    it "should remove the services of the process when it dies, then restart it" {
+        assert!(wait_for_services(1));
+        let setter_id : Id<Setter> = Id::new("setter:fake@test");
+        let mut results = manager.send_values(vec![
+            Targetted::new(vec![SetterSelector::new().with_id(setter_id.clone())], Value::OnOff(OnOff::Off))
+        ], User::None);
+        assert!(results.remove(&setter_id).unwrap().is_err());
+
+        assert!(wait_for_services(0));
+        assert!(wait_for_services(1));
+    }
+
+    after_each {
+        adapter.stop();
+        manager.stop();
+    }
+}
@@ -122,14 +122,10 @@ impl IPCameraAdapter {
             adapter: adapter_id.clone(),
             id: getter_image_list_id.clone(),
             last_seen: None,
+            capabilities: None,
             service: service_id.clone(),
             mechanism: Getter {
-                kind: ChannelKind::Extension {
-                    vendor: Id::new("foxlink@mozilla.com"),
-                    adapter: Id::new("IPCam Adapter"),
-                    kind: Id::new("image_list"),
-                    typ: Type::Json,
-                },
+                kind: ChannelKind::ImageList,
                 updated: None,
             },
         }));
@@ -140,14 +136,10 @@ impl IPCameraAdapter {
             adapter: adapter_id.clone(),
             id: getter_image_newest_id.clone(),
             last_seen: None,
+            capabilities: None,
             service: service_id.clone(),
             mechanism: Getter {
-                kind: ChannelKind::Extension {
-                    vendor: Id::new("foxlink@mozilla.com"),
-                    adapter: Id::new("IPCam Adapter"),
-                    kind: Id::new("latest image"),
-                    typ: Type::Binary,
-                },
+                kind: ChannelKind::LatestImage,
                 updated: None,
             },
         }));
@@ -158,6 +150,7 @@ impl IPCameraAdapter {
             adapter: adapter_id.clone(),
             id: setter_snapshot_id.clone(),
             last_seen: None,
+            capabilities: None,
             service: service_id.clone(),
             mechanism: Setter {
                 kind: ChannelKind::TakeSnapshot,
@@ -171,6 +164,7 @@ impl IPCameraAdapter {
             adapter: adapter_id.clone(),
             id: getter_username_id.clone(),
             last_seen: None,
+            capabilities: None,
             service: service_id.clone(),
             mechanism: Getter {
                 kind: ChannelKind::Username,
@@ -184,6 +178,7 @@ impl IPCameraAdapter {
             adapter: adapter_id.clone(),
             id: setter_username_id.clone(),
             last_seen: None,
+            capabilities: None,
             service: service_id.clone(),
             mechanism: Setter {
                 kind: ChannelKind::Username,
@@ -197,6 +192,7 @@ impl IPCameraAdapter {
             adapter: adapter_id.clone(),
             id: getter_password_id.clone(),
             last_seen: None,
+            capabilities: None,
             service: service_id.clone(),
             mechanism: Getter {
                 kind: ChannelKind::Password,
@@ -210,6 +206,7 @@ impl IPCameraAdapter {
             adapter: adapter_id.clone(),
             id: setter_password_id.clone(),
             last_seen: None,
+            capabilities: None,
             service: service_id.clone(),
             mechanism: Setter {
                 kind: ChannelKind::Password,
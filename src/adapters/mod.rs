@@ -12,64 +12,372 @@ pub mod console;
 #[cfg(target_os = "linux")]
 pub mod tts;
 
+/// A proxy for adapters implemented as external processes.
+mod external;
+
 /// An adapter providing access to IP cameras.
 mod ip_camera;
 
 /// An adapter dedicated to the Philips Hue
 mod philips_hue;
 
+/// An adapter exposing scenes, i.e. named sets of values sent to setters at once.
+pub mod scenes;
+
 /// An adapter providing access to Thinkerbell.
 mod thinkerbell;
 
 /// An adapter providing `WebPush` services.
 pub mod webpush;
 
-use foxbox_taxonomy::manager::AdapterManager as TaxoManager;
+use foxbox_taxonomy::groups::Groups;
+use foxbox_taxonomy::manager::{ AdapterManager as TaxoManager, AdapterManagerHandle };
+use foxbox_taxonomy::services::{ AdapterId, Id };
+use foxbox_taxonomy::virtual_channels::VirtualChannels;
 
-use self::thinkerbell::ThinkerbellAdapter;
+pub use self::scenes::Scenes;
+pub use self::thinkerbell::ThinkerbellAdapter;
 use traits::Controller;
 
 use openzwave::Adapter as OpenzwaveAdapter;
 
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{ Arc, Mutex };
+
+/// The adapters built into foxbox, in the order in which they are started.
+static BUILTIN_ADAPTERS: [&'static str; 11] = [
+    "console", "philips_hue", "clock", "webpush", "ip_camera", "scenes", "thinkerbell", "openzwave", "tts",
+    "virtual_channels", "groups"
+];
+
+/// The configuration namespace used to enable or disable adapters.
+///
+/// Each adapter is enabled unless property `<adapter name>` is `"false"`.
+static CONFIG_NAMESPACE: &'static str = "adapters";
+
+/// An adapter known to foxbox, whether or not it is running.
+#[derive(Clone, Debug, Serialize)]
+pub struct AdapterInfo {
+    /// The name of the adapter, as used in the configuration and the admin API.
+    pub name: String,
+
+    /// Whether the adapter should be started along with foxbox.
+    pub enabled: bool,
+
+    /// Whether the adapter is currently running.
+    pub running: bool,
+
+    /// The error reported by the latest attempt to start the adapter, if any.
+    pub error: Option<String>,
+
+    /// The ids of the taxonomy adapters registered by this adapter while it runs.
+    pub ids: Vec<Id<AdapterId>>,
+}
+
+/// An error while starting or stopping an adapter.
+#[derive(Debug, Serialize)]
+pub enum AdminError {
+    NoSuchAdapter(String),
+    InitError(String),
+}
+
+struct Entry {
+    info: AdapterInfo,
+
+    /// The command starting the process, for adapters implemented as external processes.
+    command: Option<String>,
+}
 
 pub struct AdapterManager<T> {
     controller: T,
+
+    manager: Arc<TaxoManager>,
+
+    /// The scenes adapter, while it is running.
+    scenes: Mutex<Option<Arc<Scenes>>>,
+
+    /// The Thinkerbell adapter, while it is running.
+    thinkerbell: Mutex<Option<ThinkerbellAdapter>>,
+
+    /// The virtual channels adapter, while it is running.
+    virtual_channels: Mutex<Option<Arc<VirtualChannels>>>,
+
+    /// The groups adapter, while it is running.
+    groups: Mutex<Option<Arc<Groups>>>,
+
+    /// All the adapters known to foxbox, in the order in which they are started.
+    ///
+    /// This is not locked while adapters start or stop, as they may take a while and
+    /// call back into foxbox.
+    entries: Mutex<Vec<Entry>>,
+
+    /// Held while adapters start or stop, so that an adapter is not started or stopped
+    /// twice at once.
+    transition: Mutex<()>,
 }
 
 impl<T: Controller> AdapterManager<T> {
-    pub fn new(controller: T) -> Self {
+    pub fn new(controller: T, manager: &Arc<TaxoManager>) -> Self {
         debug!("Creating Adapter Manager");
+        let config = controller.get_config();
+        let mut entries : Vec<_> = BUILTIN_ADAPTERS.iter().map(|name| (name.to_string(), None)).collect();
+
+        // Property `names` of the `external_adapters` configuration namespace is a
+        // comma-separated list of adapters implemented as external processes. For each
+        // name, the property with this name is the command starting the adapter.
+        if let Some(names) = config.get("external_adapters", "names") {
+            for name in names.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+                if entries.iter().any(|&(ref known, _)| known == name) {
+                    warn!("Ignoring external adapter {}, as another adapter has the same name", name);
+                    continue;
+                }
+                match config.get("external_adapters", name) {
+                    None => warn!("No command for external adapter {}", name),
+                    Some(command) => entries.push((name.to_owned(), Some(command)))
+                }
+            }
+        }
+
+        let entries = entries.drain(..).map(|(name, command)| {
+            let enabled = config.get(CONFIG_NAMESPACE, &name) != Some("false".to_owned());
+            Entry {
+                info: AdapterInfo {
+                    name: name,
+                    enabled: enabled,
+                    running: false,
+                    error: None,
+                    ids: vec![],
+                },
+                command: command
+            }
+        }).collect();
+
         AdapterManager {
             controller: controller,
+            manager: manager.clone(),
+            scenes: Mutex::new(None),
+            thinkerbell: Mutex::new(None),
+            virtual_channels: Mutex::new(None),
+            groups: Mutex::new(None),
+            entries: Mutex::new(entries),
+            transition: Mutex::new(()),
         }
     }
 
     #[cfg(target_os = "linux")]
-    fn start_tts(&self, manager: &Arc<TaxoManager>) {
-        tts::init(manager).unwrap();
+    fn init_tts(&self) -> (Vec<Id<AdapterId>>, Result<(), String>) {
+        (vec![tts::id()], tts::init(&self.manager).map_err(|err| err.to_string()))
     }
 
     #[cfg(not(target_os = "linux"))]
-    fn start_tts(&self, _: &Arc<TaxoManager>) {
-        info!("No tts support on this platform.");
+    fn init_tts(&self) -> (Vec<Id<AdapterId>>, Result<(), String>) {
+        (vec![], Err("No tts support on this platform.".to_owned()))
+    }
+
+    /// Start an adapter. Returns the ids of the taxonomy adapters that it registered.
+    fn init_adapter(&self, name: &str, command: &Option<String>) -> Result<Vec<Id<AdapterId>>, String> {
+        let manager = &self.manager;
+
+        // The ids of the taxonomy adapters that the adapter registers, along with the
+        // result of its initialization.
+        let (ids, result) = if let Some(ref command) = *command {
+            (vec![external::ExternalAdapter::id(name)],
+             external::ExternalAdapter::init(manager, name, command)
+                .map(|_| ())
+                .map_err(|err| err.to_string()))
+        } else {
+            match name {
+                "console" =>
+                    (vec![console::Console::id()],
+                     console::Console::init(manager).map_err(|err| err.to_string())),
+                "philips_hue" =>
+                    (vec![philips_hue::create_adapter_id()],
+                     philips_hue::PhilipsHueAdapter::init(manager, self.controller.clone())
+                        .map_err(|err| err.to_string())),
+                "clock" =>
+                    (vec![clock::Clock::id()],
+                     clock::Clock::init(manager).map_err(|err| err.to_string())),
+                "webpush" =>
+                    (vec![webpush::WebPush::id()],
+                     webpush::WebPush::init(self.controller.clone(), manager)
+                        .map_err(|err| err.to_string())),
+                "ip_camera" =>
+                    (vec![ip_camera::IPCameraAdapter::id()],
+                     ip_camera::IPCameraAdapter::init(manager, self.controller.clone())
+                        .map_err(|err| err.to_string())),
+                "scenes" => {
+                    let scenes_path = &self.controller.get_profile().path_for("scenes.sqlite");
+                    (vec![Scenes::id()],
+                     Scenes::init(manager, scenes_path)
+                        .map(|scenes| {
+                            *self.scenes.lock().unwrap() = Some(scenes);
+                        })
+                        .map_err(|err| format!("{:?}", err)))
+                }
+                "thinkerbell" => {
+                    let scripts_path = &self.controller.get_profile().path_for("thinkerbell_scripts.sqlite");
+                    (vec![ThinkerbellAdapter::id()],
+                     ThinkerbellAdapter::init(manager, scripts_path)
+                        .map(|thinkerbell| {
+                            *self.thinkerbell.lock().unwrap() = Some(thinkerbell);
+                        })
+                        .map_err(|err| err.to_string()))
+                }
+                "openzwave" => {
+                    let profile_openzwave = &self.controller.get_profile().path_for("openzwave");
+                    let openzwave_device = self.controller.get_config().get("openzwave", "device");
+                    match OpenzwaveAdapter::init(manager, profile_openzwave, openzwave_device) {
+                        // Without a Z-Wave device, the adapter does not register anything.
+                        Ok(id) => return Ok(id.into_iter().collect()),
+                        Err(err) => (vec![OpenzwaveAdapter::id()], Err(format!("{:?}", err)))
+                    }
+                }
+                "tts" => self.init_tts(),
+                "virtual_channels" => {
+                    let db_path = PathBuf::from(self.controller.get_profile().path_for("virtual_channels.sqlite"));
+                    (vec![VirtualChannels::id()],
+                     VirtualChannels::init(manager, &db_path)
+                        .map(|virtual_channels| {
+                            *self.virtual_channels.lock().unwrap() = Some(virtual_channels);
+                        })
+                        .map_err(|err| format!("{:?}", err)))
+                }
+                "groups" => {
+                    let db_path = PathBuf::from(self.controller.get_profile().path_for("groups.sqlite"));
+                    (vec![Groups::id()],
+                     Groups::init(manager, &db_path)
+                        .map(|groups| {
+                            *self.groups.lock().unwrap() = Some(groups);
+                        })
+                        .map_err(|err| format!("{:?}", err)))
+                }
+                _ => return Err(format!("Unknown adapter {}", name))
+            }
+        };
+
+        if let Err(err) = result {
+            // Clean up whatever was registered before the error.
+            for id in ids {
+                let _ignored = manager.remove_adapter(&id);
+            }
+            return Err(err);
+        }
+        Ok(ids)
+    }
+
+    /// Apply `cb` to the entry of adapter `name`.
+    fn with_entry<F, R>(&self, name: &str, cb: F) -> Result<R, AdminError> where F: FnOnce(&mut Entry) -> R {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.iter_mut().find(|entry| entry.info.name == name) {
+            Some(entry) => Ok(cb(entry)),
+            None => Err(AdminError::NoSuchAdapter(name.to_owned()))
+        }
+    }
+
+    /// Start an adapter, unless it is already running, and enable it for the next
+    /// runs of foxbox, once it has started.
+    pub fn start_adapter(&self, name: &str) -> Result<(), AdminError> {
+        let _transition = self.transition.lock().unwrap();
+        let (running, command) = try!(self.with_entry(name, |entry| (entry.info.running, entry.command.clone())));
+        if !running {
+            let result = self.init_adapter(name, &command);
+            try!(try!(self.with_entry(name, |entry| Self::record_start(entry, result))));
+        }
+        self.controller.get_config().set(CONFIG_NAMESPACE, name, "true");
+        self.with_entry(name, |entry| entry.info.enabled = true)
+    }
+
+    /// Record the result of starting the adapter of `entry`.
+    fn record_start(entry: &mut Entry, result: Result<Vec<Id<AdapterId>>, String>) -> Result<(), AdminError> {
+        match result {
+            Ok(ids) => {
+                info!("Started adapter {}", entry.info.name);
+                entry.info.running = true;
+                entry.info.error = None;
+                entry.info.ids = ids;
+                Ok(())
+            }
+            Err(err) => {
+                warn!("Could not start adapter {}: {}", entry.info.name, err);
+                entry.info.error = Some(err.clone());
+                Err(AdminError::InitError(err))
+            }
+        }
+    }
+
+    /// Stop an adapter, if it is running, and disable it for the next runs of foxbox.
+    pub fn stop_adapter(&self, name: &str) -> Result<(), AdminError> {
+        let _transition = self.transition.lock().unwrap();
+        let (running, ids) = try!(self.with_entry(name, |entry| {
+            entry.info.enabled = false;
+            (entry.info.running, entry.info.ids.clone())
+        }));
+        self.controller.get_config().set(CONFIG_NAMESPACE, name, "false");
+        if !running {
+            return Ok(());
+        }
+        for id in ids {
+            if let Err(err) = self.manager.remove_adapter(&id) {
+                warn!("Could not remove adapter {}: {}", id, err);
+            }
+        }
+        if name == "scenes" {
+            *self.scenes.lock().unwrap() = None;
+        }
+        if name == "thinkerbell" {
+            *self.thinkerbell.lock().unwrap() = None;
+        }
+        if name == "virtual_channels" {
+            *self.virtual_channels.lock().unwrap() = None;
+        }
+        if name == "groups" {
+            *self.groups.lock().unwrap() = None;
+        }
+        try!(self.with_entry(name, |entry| {
+            entry.info.running = false;
+            entry.info.ids.clear();
+        }));
+        info!("Stopped adapter {}", name);
+        Ok(())
+    }
+
+    /// Start all the enabled adapters. Adapters that fail to start are reported by
+    /// `adapters()`.
+    pub fn start(&self) {
+        let _transition = self.transition.lock().unwrap();
+        let pending : Vec<_> = self.entries.lock().unwrap().iter()
+            .filter(|entry| entry.info.enabled && !entry.info.running)
+            .map(|entry| (entry.info.name.clone(), entry.command.clone()))
+            .collect();
+        for (name, command) in pending {
+            let result = self.init_adapter(&name, &command);
+            let _ignored = self.with_entry(&name, |entry| Self::record_start(entry, result));
+        }
+    }
+
+    /// The adapters known to foxbox.
+    pub fn adapters(&self) -> Vec<AdapterInfo> {
+        self.entries.lock().unwrap().iter().map(|entry| entry.info.clone()).collect()
+    }
+
+    /// The scenes adapter, if it is running.
+    pub fn scenes(&self) -> Option<Arc<Scenes>> {
+        self.scenes.lock().unwrap().clone()
+    }
+
+    /// The Thinkerbell adapter, if it is running.
+    pub fn thinkerbell(&self) -> Option<ThinkerbellAdapter> {
+        self.thinkerbell.lock().unwrap().clone()
+    }
+
+    /// The virtual channels adapter, if it is running.
+    pub fn virtual_channels(&self) -> Option<Arc<VirtualChannels>> {
+        self.virtual_channels.lock().unwrap().clone()
     }
 
-    /// Start all the adapters.
-    pub fn start(&mut self, manager: &Arc<TaxoManager>) {
-        let c = self.controller.clone(); // extracted here to prevent double-borrow of 'self'
-        console::Console::init(manager).unwrap(); // FIXME: We should have a way to report errors
-        philips_hue::PhilipsHueAdapter::init(manager, c.clone()).unwrap();
-        clock::Clock::init(manager).unwrap(); // FIXME: We should have a way to report errors
-        webpush::WebPush::init(c, manager).unwrap();
-        ip_camera::IPCameraAdapter::init(manager, self.controller.clone()).unwrap();
-        let scripts_path = &self.controller.get_profile().path_for("thinkerbell_scripts.sqlite");
-        ThinkerbellAdapter::init(manager, scripts_path).unwrap(); // FIXME: no unwrap!
-        let profile_openzwave = &self.controller.get_profile().path_for("openzwave");
-        let openzwave_device = self.controller.clone().get_config().get("openzwave", "device");
-        OpenzwaveAdapter::init(manager, profile_openzwave, openzwave_device).unwrap();
-
-        self.start_tts(manager);
+    /// The groups adapter, if it is running.
+    pub fn groups(&self) -> Option<Arc<Groups>> {
+        self.groups.lock().unwrap().clone()
     }
 
     /// Stop all the adapters.
@@ -10,6 +10,8 @@
 //!
 //! The module spawns a management thread for every hub.
 
+use foxbox_taxonomy::api::AdapterStatus;
+use foxbox_taxonomy::manager::AdapterManagerHandle;
 use serde_json;
 use std::sync::{ Arc, Mutex };
 use std::thread;
@@ -63,6 +65,8 @@ impl<C: Controller> Hub<C> {
                 if !api.lock().unwrap().is_paired() {
                     warn!("Philips Hue detected but not paired. Please, push pairing \
                            button on Philips Hue Bridge ID {} to start using it.", id);
+                    let _ = adapter.manager.set_adapter_status(&adapter.adapter_id,
+                        AdapterStatus::Degraded(format!("Philips Hue Bridge {} is not paired", id)));
 
                     // Try pairing for 120 seconds.
                     for _ in 0..120 {
@@ -92,6 +96,8 @@ impl<C: Controller> Hub<C> {
                     }
                     if api.lock().unwrap().is_paired() {
                         info!("Paired with Philips Hue Bridge ID {}", id);
+                        let _ = adapter.manager.set_adapter_status(&adapter.adapter_id,
+                            AdapterStatus::Running);
                         adapter.controller.adapter_notification(
                             json_value!({ adapter: "philips_hue", message: "PairingSuccess",
                                 hub: id }));
@@ -11,7 +11,7 @@
 use foxbox_taxonomy::api::Error;
 use foxbox_taxonomy::manager::*;
 use foxbox_taxonomy::services::*;
-use foxbox_taxonomy::values::{ Type };
+use foxbox_taxonomy::values::{ OnOff, Percentage, Value };
 use super::*;
 use super::hub_api::HubApi;
 use std::collections::HashSet;
@@ -22,6 +22,24 @@ const CUSTOM_PROPERTY_MODEL: &'static str = "model";
 const CUSTOM_PROPERTY_NAME: &'static str = "name";
 const CUSTOM_PROPERTY_TYPE: &'static str = "type";
 
+/// Lights are either on or off, and so is their availability.
+fn on_off_capabilities() -> Option<Capabilities> {
+    Some(Capabilities {
+        choices: Some(vec![Value::OnOff(OnOff::On), Value::OnOff(OnOff::Off)]),
+        .. Capabilities::default()
+    })
+}
+
+/// The Hue API operates on brightness values in [0, 254], which we expose as a ratio.
+fn brightness_capabilities() -> Option<Capabilities> {
+    Some(Capabilities {
+        min: Some(Value::Percentage(Percentage::Ratio(0.))),
+        max: Some(Value::Percentage(Percentage::Ratio(1.))),
+        unit: Some("Ratio".to_owned()),
+        .. Capabilities::default()
+    })
+}
+
 #[derive(Clone)]
 pub struct Light {
     api: Arc<Mutex<HubApi>>,
@@ -33,6 +51,8 @@ pub struct Light {
     pub set_power_id: Id<Setter>,
     pub get_color_id: Id<Getter>,
     pub set_color_id: Id<Setter>,
+    pub get_brightness_id: Id<Getter>,
+    pub set_brightness_id: Id<Setter>,
 }
 
 impl Light {
@@ -49,6 +69,8 @@ impl Light {
             set_power_id: create_setter_id("power", &hub_id, &light_id),
             get_color_id: create_getter_id("color", &hub_id, &light_id),
             set_color_id: create_setter_id("color", &hub_id, &light_id),
+            get_brightness_id: create_getter_id("brightness", &hub_id, &light_id),
+            set_brightness_id: create_setter_id("brightness", &hub_id, &light_id),
         }
     }
     pub fn start(&self) {
@@ -90,14 +112,10 @@ impl Light {
                 adapter: adapter_id.clone(),
                 id: self.get_available_id.clone(),
                 last_seen: None,
+                capabilities: on_off_capabilities(),
                 service: self.service_id.clone(),
                 mechanism: Getter {
-                    kind: ChannelKind::Extension {
-                        vendor: Id::new("foxlink@mozilla.com"),
-                        adapter: Id::new("Philips Hue Adapter"),
-                        kind: Id::new("available"),
-                        typ: Type::OnOff,
-                    },
+                    kind: ChannelKind::Available,
                     updated: None,
                 },
             }));
@@ -107,6 +125,7 @@ impl Light {
                 adapter: adapter_id.clone(),
                 id: self.get_power_id.clone(),
                 last_seen: None,
+                capabilities: on_off_capabilities(),
                 service: self.service_id.clone(),
                 mechanism: Getter {
                     kind: ChannelKind::LightOn,
@@ -119,6 +138,7 @@ impl Light {
                 adapter: adapter_id.clone(),
                 id: self.set_power_id.clone(),
                 last_seen: None,
+                capabilities: on_off_capabilities(),
                 service: self.service_id.clone(),
                 mechanism: Setter {
                     kind: ChannelKind::LightOn,
@@ -131,14 +151,10 @@ impl Light {
                 adapter: adapter_id.clone(),
                 id: self.get_color_id.clone(),
                 last_seen: None,
+                capabilities: None,
                 service: self.service_id.clone(),
                 mechanism: Getter {
-                    kind: ChannelKind::Extension {
-                        vendor: Id::new("foxlink@mozilla.com"),
-                        adapter: Id::new("Philips Hue Adapter"),
-                        kind: Id::new("Color"),
-                        typ: Type::Color,
-                    },
+                    kind: ChannelKind::LightColor,
                     updated: None,
                 },
             }));
@@ -148,14 +164,36 @@ impl Light {
                 adapter: adapter_id.clone(),
                 id: self.set_color_id.clone(),
                 last_seen: None,
+                capabilities: None,
+                service: self.service_id.clone(),
+                mechanism: Setter {
+                    kind: ChannelKind::LightColor,
+                    updated: None,
+                },
+            }));
+
+            try!(manager.add_getter(Channel {
+                tags: HashSet::new(),
+                adapter: adapter_id.clone(),
+                id: self.get_brightness_id.clone(),
+                last_seen: None,
+                capabilities: brightness_capabilities(),
+                service: self.service_id.clone(),
+                mechanism: Getter {
+                    kind: ChannelKind::LightBrightness,
+                    updated: None,
+                },
+            }));
+
+            try!(manager.add_setter(Channel {
+                tags: HashSet::new(),
+                adapter: adapter_id.clone(),
+                id: self.set_brightness_id.clone(),
+                last_seen: None,
+                capabilities: brightness_capabilities(),
                 service: self.service_id.clone(),
                 mechanism: Setter {
-                    kind: ChannelKind::Extension {
-                        vendor: Id::new("foxlink@mozilla.com"),
-                        adapter: Id::new("Philips Hue Adapter"),
-                        kind: Id::new("Color"),
-                        typ: Type::Color,
-                    },
+                    kind: ChannelKind::LightBrightness,
                     updated: None,
                 },
             }));
@@ -164,6 +202,8 @@ impl Light {
             services_lock.getters.insert(self.get_available_id.clone(), self.clone());
             services_lock.getters.insert(self.get_power_id.clone(), self.clone());
             services_lock.setters.insert(self.set_power_id.clone(), self.clone());
+            services_lock.getters.insert(self.get_brightness_id.clone(), self.clone());
+            services_lock.setters.insert(self.set_brightness_id.clone(), self.clone());
             services_lock.getters.insert(self.get_color_id.clone(), self.clone());
             services_lock.setters.insert(self.set_color_id.clone(), self.clone());
 
@@ -188,14 +228,10 @@ impl Light {
                 adapter: adapter_id.clone(),
                 id: self.get_available_id.clone(),
                 last_seen: None,
+                capabilities: on_off_capabilities(),
                 service: self.service_id.clone(),
                 mechanism: Getter {
-                    kind: ChannelKind::Extension {
-                        vendor: Id::new("foxlink@mozilla.com"),
-                        adapter: Id::new("Philips Hue Adapter"),
-                        kind: Id::new("available"),
-                        typ: Type::OnOff,
-                    },
+                    kind: ChannelKind::Available,
                     updated: None,
                 },
             }));
@@ -205,6 +241,7 @@ impl Light {
                 adapter: adapter_id.clone(),
                 id: self.get_power_id.clone(),
                 last_seen: None,
+                capabilities: on_off_capabilities(),
                 service: self.service_id.clone(),
                 mechanism: Getter {
                     kind: ChannelKind::LightOn,
@@ -217,6 +254,7 @@ impl Light {
                 adapter: adapter_id.clone(),
                 id: self.set_power_id.clone(),
                 last_seen: None,
+                capabilities: on_off_capabilities(),
                 service: self.service_id.clone(),
                 mechanism: Setter {
                     kind: ChannelKind::LightOn,
@@ -224,10 +262,38 @@ impl Light {
                 },
             }));
 
+            try!(manager.add_getter(Channel {
+                tags: HashSet::new(),
+                adapter: adapter_id.clone(),
+                id: self.get_brightness_id.clone(),
+                last_seen: None,
+                capabilities: brightness_capabilities(),
+                service: self.service_id.clone(),
+                mechanism: Getter {
+                    kind: ChannelKind::LightBrightness,
+                    updated: None,
+                },
+            }));
+
+            try!(manager.add_setter(Channel {
+                tags: HashSet::new(),
+                adapter: adapter_id.clone(),
+                id: self.set_brightness_id.clone(),
+                last_seen: None,
+                capabilities: brightness_capabilities(),
+                service: self.service_id.clone(),
+                mechanism: Setter {
+                    kind: ChannelKind::LightBrightness,
+                    updated: None,
+                },
+            }));
+
             let mut services_lock = services.lock().unwrap();
             services_lock.getters.insert(self.get_available_id.clone(), self.clone());
             services_lock.getters.insert(self.get_power_id.clone(), self.clone());
             services_lock.setters.insert(self.set_power_id.clone(), self.clone());
+            services_lock.getters.insert(self.get_brightness_id.clone(), self.clone());
+            services_lock.setters.insert(self.set_brightness_id.clone(), self.clone());
 
         } else {
             warn!("Ignoring unsupported Hue light type {}, ID {} on bridge {}",
@@ -250,14 +316,12 @@ impl Light {
         self.api.lock().unwrap().set_light_power(&self.light_id, on);
     }
 
-    #[allow(dead_code)]
     pub fn get_brightness(&self) -> f64 {
         // Hue API gives brightness value in [0, 254]
         let ls = self.api.lock().unwrap().get_light_status(&self.light_id);
         ls.state.bri as f64 / 254f64
     }
 
-    #[allow(dead_code)]
     pub fn set_brightness(&self, bri: f64) {
         // Hue API takes brightness value in [0, 254]
         let bri = bri.max(0f64).min(1f64); // [0,1]
@@ -20,7 +20,7 @@ pub mod structs;
 use foxbox_taxonomy::api::{ Error, InternalError, User };
 use foxbox_taxonomy::manager::*;
 use foxbox_taxonomy::services::*;
-use foxbox_taxonomy::values::{ Color, OnOff, Type, TypeError, Value };
+use foxbox_taxonomy::values::{ Color, OnOff, Percentage, Type, TypeError, Value };
 
 use std::collections::HashMap;
 use std::sync::{ Arc, Mutex };
@@ -157,7 +157,6 @@ impl<C: Controller> PhilipsHueAdapter<C> {
                             warn!("Ignoring request to remove unknown Hue hub");
                         }
                     },
-                    // TODO: Currently unused, but required for teardown
                     HueAction::StopAdapter => {
                         debug!("HueAction::StopAdapter received");
                         break;
@@ -180,15 +179,6 @@ impl<C: Controller> PhilipsHueAdapter<C> {
         Ok(())
     }
 
-    // TODO: This should be called on tear-down by the adapter manager or
-    // the taxonomy manager, but currently it can't be because the adapter
-    // manager is never given a reference and .stop() is not part of a
-    // trait shared amongst adapters.
-    #[allow(dead_code)]
-    pub fn stop(&self) {
-        let _ = self.tx.lock().unwrap().send(HueAction::StopAdapter);
-    }
-
     pub fn send(&self, action: HueAction) {
         let _ = self.tx.lock().unwrap().send(action);
     }
@@ -254,6 +244,9 @@ impl<C: Controller> Adapter for PhilipsHueAdapter<C> {
                 let (h, s, v) = light.get_color();
                 return (id, Ok(Some(Value::Color(Color::HSV(h, s, v)))));
             }
+            if id == light.get_brightness_id {
+                return (id, Ok(Some(Value::Percentage(Percentage::Ratio(light.get_brightness())))));
+            }
 
             (id.clone(), Err(Error::InternalError(InternalError::NoSuchGetter(id))))
         }).collect()
@@ -293,6 +286,18 @@ impl<C: Controller> Adapter for PhilipsHueAdapter<C> {
                 }
                 return (id, Ok(()));
             }
+            if id == light.set_brightness_id {
+                match value {
+                    Value::Percentage(ref brightness) => { light.set_brightness(brightness.as_ratio()); },
+                    _ => {
+                        return (id, Err(Error::TypeError(TypeError {
+                                        got: value.get_type(),
+                                        expected: Type::Percentage
+                                    })));
+                    }
+                }
+                return (id, Ok(()));
+            }
 
             (id.clone(), Err(Error::InternalError(InternalError::NoSuchSetter(id))))
         }).collect()
@@ -303,4 +308,8 @@ impl<C: Controller> Adapter for PhilipsHueAdapter<C> {
             (id.clone(), Err(Error::GetterDoesNotSupportWatching(id)))
         }).collect()
     }
+
+    fn stop(&self) {
+        let _ = self.tx.lock().unwrap().send(HueAction::StopAdapter);
+    }
 }
@@ -0,0 +1,530 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An adapter exposing scenes, i.e. named sets of values to send to setters at once,
+//! e.g. "movie night": dim the living room lights, turn off the other lights.
+//!
+//! The values of a scene are a `TargetMap<SetterSelector, Value>`, as taken by
+//! `API::send_values`. Scenes are stored in the profile. They may also be captured from
+//! the current values of getters, in which case each value is sent back to the setters
+//! of the same service and kind as the getter.
+//!
+//! Each scene is exposed as a service with one `ActivateScene` setter, which sends
+//! all the values of the scene, so scenes can be activated like any other setter,
+//! including from Thinkerbell rules, with a statement such as
+//!
+//! ```ignore
+//! {
+//!   "destination": [{"id": "setter:activate.movie@scenes.link.mozilla.org"}],
+//!   "value": {"Unit": null},
+//!   "kind": "ActivateScene"
+//! }
+//! ```
+
+use foxbox_taxonomy::api::{ API, Error, InternalError, TargetMap, User };
+use foxbox_taxonomy::manager::*;
+use foxbox_taxonomy::parse::*;
+use foxbox_taxonomy::selector::*;
+use foxbox_taxonomy::services::*;
+use foxbox_taxonomy::values::Value;
+
+use rusqlite::{ Connection, Error as SqliteError };
+use serde_json;
+
+use std::collections::{ HashMap, HashSet };
+use std::sync::{ Arc, Mutex, Weak };
+
+static ADAPTER_NAME: &'static str = "Scenes adapter (built-in)";
+static ADAPTER_VENDOR: &'static str = "team@link.mozilla.org";
+static ADAPTER_VERSION: [u32;4] = [0, 0, 0, 0];
+
+/// An error while managing scenes.
+#[derive(Debug, Serialize)]
+pub enum SceneError {
+    /// Scene names may only contain letters, digits, `-` and `_`.
+    InvalidName(String),
+
+    /// The values of a scene could not be parsed.
+    ParseError(ParseError),
+
+    /// There is no scene with this name.
+    NoSuchScene(String),
+
+    /// None of the getters had a value that could be captured.
+    NothingToCapture,
+
+    /// There was an error executing some SQL.
+    SQLError(String),
+
+    /// The scene could not be registered with the taxonomy.
+    TaxonomyError(Error),
+}
+
+impl From<SqliteError> for SceneError {
+    fn from(err: SqliteError) -> Self {
+        SceneError::SQLError(format!("{}", err))
+    }
+}
+
+impl From<ParseError> for SceneError {
+    fn from(err: ParseError) -> Self {
+        SceneError::ParseError(err)
+    }
+}
+
+impl From<Error> for SceneError {
+    fn from(err: Error) -> Self {
+        SceneError::TaxonomyError(err)
+    }
+}
+
+/// A scene, as listed by `Scenes::get_scenes`.
+#[derive(Debug, Serialize)]
+pub struct SceneInfo {
+    pub name: String,
+
+    /// The service exposing the scene.
+    pub service: Id<ServiceId>,
+
+    /// The setter activating the scene.
+    pub setter: Id<Setter>,
+
+    /// The values of the scene, as JSON.
+    pub values: serde_json::Value,
+}
+
+struct ScenesState {
+    db: Connection,
+
+    /// The source of each scene, by name.
+    scenes: HashMap<String, String>,
+
+    /// The scenes being activated, along with the user activating them, to prevent a
+    /// scene from activating itself forever. As a scene sends its values on behalf of
+    /// the user activating it, activating a scene again on behalf of the same user
+    /// before the previous activation completes is considered re-entrant. Other users
+    /// may activate the scene meanwhile.
+    activating: HashSet<(String, User)>,
+}
+
+pub struct Scenes {
+    /// The manager is only used to send values, so we don't need to keep it alive.
+    manager: Weak<AdapterManager>,
+
+    state: Mutex<ScenesState>,
+}
+
+impl Scenes {
+    pub fn id() -> Id<AdapterId> {
+        Id::new("scenes@link.mozilla.org")
+    }
+
+    pub fn service_id(name: &str) -> Id<ServiceId> {
+        Id::new(&format!("service:{}@scenes.link.mozilla.org", name))
+    }
+
+    pub fn setter_activate_id(name: &str) -> Id<Setter> {
+        Id::new(&format!("setter:activate.{}@scenes.link.mozilla.org", name))
+    }
+
+    fn check_name(name: &str) -> Result<(), SceneError> {
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            return Err(SceneError::InvalidName(name.to_owned()));
+        }
+        Ok(())
+    }
+
+    fn manager(&self) -> Result<Arc<AdapterManager>, Error> {
+        self.manager.upgrade().ok_or_else(|| {
+            Error::InternalError(InternalError::GenericError("The adapter manager is gone".to_owned()))
+        })
+    }
+
+    fn add_scene_service(manager: &AdapterManager, name: &str) -> Result<(), Error> {
+        let service_id = Self::service_id(name);
+        let mut service = Service::empty(service_id.clone(), Self::id());
+        service.properties.insert("name".to_owned(), name.to_owned());
+        try!(manager.add_service(service));
+        let result = manager.add_setter(Channel {
+            id: Self::setter_activate_id(name),
+            service: service_id.clone(),
+            adapter: Self::id(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Setter {
+                kind: ChannelKind::ActivateScene,
+                updated: None
+            },
+        });
+        if result.is_err() {
+            let _ = manager.remove_service(&service_id);
+        }
+        result
+    }
+
+    /// Add a scene, replacing any scene with the same name. `source` is the JSON source
+    /// of a `TargetMap<SetterSelector, Value>`.
+    ///
+    /// A new scene is registered with the taxonomy before being stored, so that a scene
+    /// is never stored without being exposed.
+    pub fn put_scene(&self, name: &str, source: &str) -> Result<(), SceneError> {
+        try!(Self::check_name(name));
+        try!(TargetMap::<SetterSelector, Value>::from_str(source));
+        let manager = try!(self.manager());
+        let mut state = self.state.lock().unwrap();
+        let is_new = !state.scenes.contains_key(name);
+        if is_new {
+            try!(Self::add_scene_service(&manager, name));
+        }
+        if let Err(err) = state.db.execute("INSERT OR REPLACE INTO scenes (name, source) VALUES ($1, $2)",
+            &[&name.to_owned(), &source.to_owned()]) {
+            if is_new {
+                let _ = manager.remove_service(&Self::service_id(name));
+            }
+            return Err(SceneError::from(err));
+        }
+        state.scenes.insert(name.to_owned(), source.to_owned());
+        Ok(())
+    }
+
+    /// Add a scene from the current values of `getters`, replacing any scene with the
+    /// same name. Each value is sent back to the setters of the same service and kind
+    /// as its getter.
+    pub fn capture_scene(&self, name: &str, getters: Vec<GetterSelector>, user: User) -> Result<(), SceneError> {
+        try!(Self::check_name(name));
+        let manager = try!(self.manager());
+        let values = manager.fetch_values(getters.clone(), user);
+        let mut targets = vec![];
+        for getter in manager.get_getter_channels(getters) {
+            let value = match values.get(&getter.id) {
+                Some(&Ok(Some(ref value))) => value,
+                _ => continue
+            };
+            let selector = SetterSelector::new()
+                .with_parent(getter.service.clone())
+                .with_kind(getter.mechanism.kind.clone());
+            for setter in manager.get_setter_channels(vec![selector]) {
+                targets.push(vec![
+                    ("select", vec![("id", setter.id.to_json())].to_json()),
+                    ("value", value.to_json()),
+                ].to_json());
+            }
+        }
+        if targets.is_empty() {
+            return Err(SceneError::NothingToCapture);
+        }
+        let source = serde_json::to_string(&targets.to_json()).unwrap();
+        self.put_scene(name, &source)
+    }
+
+    /// Remove a scene.
+    pub fn remove_scene(&self, name: &str) -> Result<(), SceneError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.scenes.contains_key(name) {
+            return Err(SceneError::NoSuchScene(name.to_owned()));
+        }
+        try!(state.db.execute("DELETE FROM scenes WHERE name = $1", &[&name.to_owned()]));
+        state.scenes.remove(name);
+        if let Ok(manager) = self.manager() {
+            try!(manager.remove_service(&Self::service_id(name)));
+        }
+        Ok(())
+    }
+
+    /// Get all the scenes.
+    pub fn get_scenes(&self) -> Vec<SceneInfo> {
+        let state = self.state.lock().unwrap();
+        state.scenes.iter()
+            .map(|(name, source)| SceneInfo {
+                name: name.clone(),
+                service: Self::service_id(name),
+                setter: Self::setter_activate_id(name),
+                // The source has been parsed successfully before being stored.
+                values: serde_json::from_str(source).unwrap_or(serde_json::Value::Null)
+            })
+            .collect()
+    }
+
+    /// Send all the values of a scene, returning the result for each setter.
+    pub fn activate_scene(&self, name: &str, user: User) -> Result<ResultMap<Id<Setter>, (), Error>, SceneError> {
+        let source = {
+            let mut state = self.state.lock().unwrap();
+            let source = match state.scenes.get(name) {
+                None => return Err(SceneError::NoSuchScene(name.to_owned())),
+                Some(source) => source.clone()
+            };
+            if !state.activating.insert((name.to_owned(), user.clone())) {
+                return Err(SceneError::TaxonomyError(Error::InternalError(InternalError::GenericError(
+                    format!("Scene {} is already being activated", name)))));
+            }
+            source
+        };
+        let key = (name.to_owned(), user.clone());
+        let result = TargetMap::<SetterSelector, Value>::from_str(&source)
+            .map_err(SceneError::from)
+            .and_then(|values| {
+                self.manager().map(|manager| manager.send_values(values, user)).map_err(SceneError::from)
+            });
+        self.state.lock().unwrap().activating.remove(&key);
+        result
+    }
+
+    /// Open or create the scenes database at `path`, then register the adapter and
+    /// its scenes.
+    pub fn init(manager: &Arc<AdapterManager>, path: &str) -> Result<Arc<Self>, SceneError> {
+        debug!("Opening scenes database at {}", path);
+        let db = try!(Connection::open(path));
+        try!(db.execute("CREATE TABLE IF NOT EXISTS scenes (
+                    name    TEXT NOT NULL PRIMARY KEY,
+                    source  TEXT NOT NULL
+            )", &[]));
+
+        let mut scenes = HashMap::new();
+        {
+            let mut stmt = try!(db.prepare("SELECT name, source FROM scenes"));
+            let rows = try!(stmt.query(&[]));
+            for result_row in rows {
+                let row = try!(result_row);
+                let name: String = try!(row.get_checked(0));
+                let source: String = try!(row.get_checked(1));
+                scenes.insert(name, source);
+            }
+        }
+        let names : Vec<_> = scenes.keys().cloned().collect();
+
+        let adapter = Arc::new(Scenes {
+            manager: Arc::downgrade(manager),
+            state: Mutex::new(ScenesState {
+                db: db,
+                scenes: scenes,
+                activating: HashSet::new(),
+            })
+        });
+        try!(manager.add_adapter(adapter.clone()));
+        for name in names {
+            try!(Self::add_scene_service(manager, &name));
+        }
+        Ok(adapter)
+    }
+}
+
+impl Adapter for Scenes {
+    fn id(&self) -> Id<AdapterId> {
+        Self::id()
+    }
+
+    fn name(&self) -> &str {
+        ADAPTER_NAME
+    }
+
+    fn vendor(&self) -> &str {
+        ADAPTER_VENDOR
+    }
+
+    fn version(&self) -> &[u32;4] {
+        &ADAPTER_VERSION
+    }
+
+    fn fetch_values(&self, mut set: Vec<Id<Getter>>, _: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        set.drain(..).map(|id| {
+            (id.clone(), Err(Error::InternalError(InternalError::NoSuchGetter(id))))
+        }).collect()
+    }
+
+    fn send_values(&self, mut values: HashMap<Id<Setter>, Value>, user: User) -> ResultMap<Id<Setter>, (), Error> {
+        let names : Vec<_> = self.state.lock().unwrap().scenes.keys().cloned().collect();
+        values.drain()
+            .map(|(id, _)| {
+                let name = match names.iter().find(|name| Self::setter_activate_id(name) == id) {
+                    Some(name) => name,
+                    None => return (id.clone(), Err(Error::InternalError(InternalError::NoSuchSetter(id))))
+                };
+                let result = match self.activate_scene(name, user.clone()) {
+                    Ok(results) => {
+                        let failures = results.values().filter(|result| result.is_err()).count();
+                        if failures == 0 {
+                            Ok(())
+                        } else {
+                            Err(Error::InternalError(InternalError::GenericError(
+                                format!("Scene {}: could not send {} of {} values", name, failures, results.len()))))
+                        }
+                    }
+                    Err(SceneError::TaxonomyError(err)) => Err(err),
+                    Err(err) => Err(Error::InternalError(InternalError::GenericError(format!("{:?}", err))))
+                };
+                (id, result)
+            })
+            .collect()
+    }
+
+    fn register_watch(&self, mut watch: Vec<WatchTarget>) -> WatchResult
+    {
+        watch.drain(..).map(|(id, _, _)| {
+            (id.clone(), Err(Error::GetterDoesNotSupportWatching(id)))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+describe! scenes {
+    before_each {
+        use foxbox_taxonomy::acl::Acl;
+        use foxbox_taxonomy::api::{ API, Targetted, User };
+        use foxbox_taxonomy::fake_adapter::*;
+        use foxbox_taxonomy::manager::AdapterManager;
+        use foxbox_taxonomy::selector::*;
+        use foxbox_taxonomy::services::*;
+        use foxbox_taxonomy::values::{ OnOff, Value };
+        use std::collections::HashSet;
+        use std::sync::Arc;
+        use tempdir::TempDir;
+
+        let tempdir = TempDir::new("scenes").unwrap();
+        let db_path = tempdir.path().join("scenes.sqlite").to_str().unwrap().to_owned();
+
+        // Access control is not enforced until a grant is recorded.
+        let acl = Acl::new(&tempdir.path().join("acl.sqlite")).unwrap();
+        let manager = Arc::new(AdapterManager::new(None).with_acl(acl));
+        let adapter_id = Id::<AdapterId>::new("adapter@test");
+        let service_id = Id::<ServiceId>::new("service@test");
+        let getter_id = Id::<Getter>::new("getter@test");
+        let setter_id = Id::<Setter>::new("setter@test");
+        let fake = FakeAdapter::new(&adapter_id);
+        let tweak = fake.get_tweak();
+        let rx = fake.take_rx();
+        manager.add_adapter(Arc::new(fake)).unwrap();
+        manager.add_service(Service::empty(service_id.clone(), adapter_id.clone())).unwrap();
+        manager.add_getter(Channel {
+            id: getter_id.clone(),
+            service: service_id.clone(),
+            adapter: adapter_id.clone(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Getter {
+                kind: ChannelKind::LightOn,
+                updated: None
+            },
+        }).unwrap();
+        manager.add_setter(Channel {
+            id: setter_id.clone(),
+            service: service_id.clone(),
+            adapter: adapter_id.clone(),
+            last_seen: None,
+            capabilities: None,
+            tags: HashSet::new(),
+            mechanism: Setter {
+                kind: ChannelKind::LightOn,
+                updated: None
+            },
+        }).unwrap();
+
+        let scenes = Scenes::init(&manager, &db_path).unwrap();
+        let activate = |name: &str| {
+            manager.send_values(vec![Targetted::new(vec![SetterSelector::new().with_id(Scenes::setter_activate_id(name))],
+                                                    Value::Unit)], User::None)
+        };
+    }
+
+    it "should expose scenes as services and send their values when activated" {
+        scenes.put_scene("movie", r#"{"select": {"kind": "LightOn"}, "value": {"OnOff": "Off"}}"#).unwrap();
+        let setters = manager.get_setter_channels(vec![SetterSelector::new().with_kind(ChannelKind::ActivateScene)]);
+        assert_eq!(setters.len(), 1);
+        assert_eq!(setters[0].service, Scenes::service_id("movie"));
+
+        let results = activate("movie");
+        assert!(results.get(&Scenes::setter_activate_id("movie")).unwrap().is_ok());
+        match rx.try_recv().unwrap() {
+            Effect::ValueSent(ref id, Value::OnOff(OnOff::Off)) if *id == setter_id => {},
+            effect => panic!("Unexpected effect {:?}", effect)
+        }
+
+        scenes.remove_scene("movie").unwrap();
+        assert!(manager.get_setter_channels(vec![SetterSelector::new().with_kind(ChannelKind::ActivateScene)]).is_empty());
+    }
+
+    it "should send the values of a scene on behalf of the user activating it" {
+        scenes.put_scene("movie", r#"{"select": {"kind": "LightOn"}, "value": {"OnOff": "Off"}}"#).unwrap();
+        let acl = manager.acl().unwrap();
+        acl.put_grant(&Id::new("activate"), r#"{
+            "grantee": {"user": 1},
+            "write": [{"kind": "ActivateScene"}]
+        }"#).unwrap();
+        let activate_as = |user| {
+            manager.send_values(vec![Targetted::new(vec![SetterSelector::new().with_id(Scenes::setter_activate_id("movie"))],
+                                                    Value::Unit)], user)
+        };
+
+        // As with a Thinkerbell rule owned by user 1, the values of the scene are
+        // subject to the permissions of user 1.
+        let results = activate_as(User::Id(1));
+        assert!(results.get(&Scenes::setter_activate_id("movie")).unwrap().is_err());
+        assert!(rx.try_recv().is_err());
+
+        acl.put_grant(&Id::new("lights"), r#"{
+            "grantee": {"user": 1},
+            "write": [{"kind": "LightOn"}]
+        }"#).unwrap();
+        let results = activate_as(User::Id(1));
+        assert!(results.get(&Scenes::setter_activate_id("movie")).unwrap().is_ok());
+        match rx.try_recv().unwrap() {
+            Effect::ValueSent(ref id, Value::OnOff(OnOff::Off)) if *id == setter_id => {},
+            effect => panic!("Unexpected effect {:?}", effect)
+        }
+    }
+
+    it "should let several users activate a scene at once, but not re-enter it" {
+        scenes.put_scene("movie", r#"{"select": {"kind": "LightOn"}, "value": {"OnOff": "Off"}}"#).unwrap();
+
+        // User 2 is still activating the scene.
+        scenes.state.lock().unwrap().activating.insert(("movie".to_owned(), User::Id(2)));
+        assert!(scenes.activate_scene("movie", User::Id(1)).is_ok());
+        match scenes.activate_scene("movie", User::Id(2)) {
+            Err(SceneError::TaxonomyError(_)) => {},
+            other => panic!("Unexpected result {:?}", other)
+        }
+    }
+
+    it "should capture scenes from the current values of getters" {
+        tweak(Tweak::InjectGetterValue(getter_id.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+        scenes.capture_scene("morning", vec![GetterSelector::new().with_id(getter_id.clone())], User::None).unwrap();
+
+        let results = scenes.activate_scene("morning", User::None).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results.get(&setter_id).unwrap().is_ok());
+        match rx.try_recv().unwrap() {
+            Effect::ValueSent(ref id, Value::OnOff(OnOff::On)) if *id == setter_id => {},
+            effect => panic!("Unexpected effect {:?}", effect)
+        }
+    }
+
+    it "should reload scenes from the profile" {
+        scenes.put_scene("night", r#"{"select": {"id": "setter@test"}, "value": {"OnOff": "Off"}}"#).unwrap();
+        manager.remove_adapter(&Scenes::id()).unwrap();
+
+        let scenes = Scenes::init(&manager, &db_path).unwrap();
+        assert_eq!(scenes.get_scenes().len(), 1);
+        assert!(activate("night").get(&Scenes::setter_activate_id("night")).unwrap().is_ok());
+    }
+
+    it "should reject invalid scenes" {
+        match scenes.put_scene("a b", "[]") {
+            Err(SceneError::InvalidName(_)) => {},
+            other => panic!("Unexpected result {:?}", other)
+        }
+        match scenes.put_scene("movie", "{}") {
+            Err(SceneError::ParseError(_)) => {},
+            other => panic!("Unexpected result {:?}", other)
+        }
+        match scenes.remove_scene("unknown") {
+            Err(SceneError::NoSuchScene(_)) => {},
+            other => panic!("Unexpected result {:?}", other)
+        }
+        match scenes.capture_scene("empty", vec![GetterSelector::new().with_id(getter_id.clone())], User::None) {
+            Err(SceneError::NothingToCapture) => {},
+            other => panic!("Unexpected result {:?}", other)
+        }
+    }
+}
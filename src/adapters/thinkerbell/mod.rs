@@ -7,7 +7,7 @@ use foxbox_taxonomy::util::Id;
 use foxbox_taxonomy::values::{ Duration, Type, Value, TypeError, OnOff };
 
 use foxbox_thinkerbell::compile::ExecutableDevEnv;
-use foxbox_thinkerbell::manager::{ ScriptManager, ScriptId, Error as ScriptManagerError };
+use foxbox_thinkerbell::manager::{ Diagnostic, ScriptManager, ScriptId, Error as ScriptManagerError };
 use foxbox_thinkerbell::run::ExecutionEvent;
 
 use timer;
@@ -33,6 +33,8 @@ static ADAPTER_VERSION: [u32;4] = [0, 0, 0, 0];
 /// - Get Enabled (getter) -- returns whether or not the script is enabled
 /// - Remove (setter) -- removes the script
 ///
+/// Scripts can also be validated without being added, see `validate()`.
+///
 /// This adapter performs most actions by delegating channel messages to its main thread.
 #[derive(Clone)]
 pub struct ThinkerbellAdapter {
@@ -148,6 +150,7 @@ enum ThinkAction {
     RemoveRuleService(Id<ScriptId>),
     RespondToGetter(RawSender<Result<Option<Value>, Error>>, Id<Getter>),
     RespondToSetter(RawSender<Result<(), Error>>, Id<Setter>, Value, User),
+    Validate(RawSender<Result<Vec<Diagnostic>, ScriptManagerError>>, String),
 }
 
 /// An internal data structure to track getters and setters.
@@ -161,6 +164,9 @@ struct ThinkerbellRule {
 }
 
 impl ThinkerbellAdapter {
+    pub fn id() -> Id<AdapterId> {
+        Id::new("thinkerbell@link.mozilla.org")
+    }
 
     #[allow(cyclomatic_complexity)]
     fn main(
@@ -231,6 +237,10 @@ impl ThinkerbellAdapter {
                     }
                     let _ = tx.send(Err(Error::InternalError(InternalError::NoSuchGetter(getter_id.clone()))));
                 },
+                // Check a script without adding it.
+                ThinkAction::Validate(tx, source) => {
+                    let _ = tx.send(script_manager.validate(&source));
+                },
                 // Respond to a pending Setter request.
                 ThinkAction::RespondToSetter(tx, setter_id, value, user) => {
                     // Add a new rule (with the given JSON source).
@@ -289,6 +299,17 @@ impl ThinkerbellAdapter {
         }
     }
 
+    /// Check a script without adding it, returning all the problems detected.
+    pub fn validate(&self, source: String) -> Result<Vec<Diagnostic>, Error> {
+        let (tx, rx) = channel();
+        let _ = self.tx.lock().unwrap().send(ThinkAction::Validate(tx, source));
+        match rx.recv() {
+            Ok(result) => result.map_err(sm_error),
+            Err(recv_err) => Err(Error::InternalError(
+                InternalError::GenericError(format!("{:?}", recv_err))))
+        }
+    }
+
     /// Add a new service for a script. (This does not start this script, this just adds a Service.)
     fn add_rule_service(&self, script_id: Id<ScriptId>) -> Result<ThinkerbellRule, Error> {
         let service_id = Id::new(&format!("thinkerbell/{}", script_id.as_atom()));
@@ -309,6 +330,7 @@ impl ThinkerbellAdapter {
             service: service_id.clone(),
             adapter: self.adapter_id.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 kind: ChannelKind::ThinkerbellRuleOn,
@@ -322,6 +344,7 @@ impl ThinkerbellAdapter {
             service: service_id.clone(),
             adapter: self.adapter_id.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Getter {
                 kind: ChannelKind::ThinkerbellRuleSource,
@@ -335,6 +358,7 @@ impl ThinkerbellAdapter {
             service: service_id.clone(),
             adapter: self.adapter_id.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 kind: ChannelKind::ThinkerbellRuleOn,
@@ -348,6 +372,7 @@ impl ThinkerbellAdapter {
             service: service_id.clone(),
             adapter: self.adapter_id.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 kind: ChannelKind::RemoveThinkerbellRule,
@@ -367,8 +392,8 @@ impl ThinkerbellAdapter {
     }
 
     /// Everything is initialized here, but the real work happens in the main() loop.
-    pub fn init(manager: &Arc<AdapterManager>, scripts_path: &str) -> Result<(), Error> {
-        let adapter_id = Id::new("thinkerbell@link.mozilla.org");
+    pub fn init(manager: &Arc<AdapterManager>, scripts_path: &str) -> Result<Self, Error> {
+        let adapter_id = ThinkerbellAdapter::id();
         let setter_add_rule_id = Id::new("thinkerbell-add-rule");
         let root_service_id = Id::new("thinkerbell-root-service");
 
@@ -405,6 +430,7 @@ impl ThinkerbellAdapter {
             service: root_service_id.clone(),
             adapter: adapter_id.clone(),
             last_seen: None,
+            capabilities: None,
             tags: HashSet::new(),
             mechanism: Setter {
                 kind: ChannelKind::AddThinkerbellRule,
@@ -412,9 +438,10 @@ impl ThinkerbellAdapter {
             },
         }));
 
+        let main_adapter = adapter.clone();
         thread::spawn(move || {
             info!("[thinkerbell@link.mozilla.org] Started Thinkerbell main thread.");
-            adapter.main(rx, script_manager)
+            main_adapter.main(rx, script_manager)
         });
 
         // FIXME: We need to consume the events from the execution environment to prevent the
@@ -427,6 +454,6 @@ impl ThinkerbellAdapter {
             }
         });
 
-        Ok(())
+        Ok(adapter)
     }
 }
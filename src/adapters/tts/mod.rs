@@ -11,7 +11,7 @@ use foxbox_taxonomy::adapter::*;
 use foxbox_taxonomy::manager::AdapterManager;
 use foxbox_taxonomy::api::{ Error, InternalError, User };
 use foxbox_taxonomy::services::{ AdapterId, Channel, ChannelKind, Getter, Id, Service, ServiceId, Setter };
-use foxbox_taxonomy::values::{ Type, Value };
+use foxbox_taxonomy::values::Value;
 use std::collections::{ HashMap, HashSet };
 use std::sync::Arc;
 use transformable_channels::mpsc::*;
@@ -78,6 +78,10 @@ impl<T: TtsEngine> Adapter for TtsAdapter<T> {
     }
 }
 
+pub fn id() -> Id<AdapterId> {
+    adapter_id!(ADAPTER_ID)
+}
+
 pub fn init(adapt: &Arc<AdapterManager>) -> Result<(), Error> {
     let engine = EspeakEngine { };
     if !engine.init() {
@@ -98,14 +102,10 @@ pub fn init(adapt: &Arc<AdapterManager>) -> Result<(), Error> {
         adapter: adapter_id.clone(),
         id: talk_setter_id.clone(),
         last_seen: None,
+        capabilities: None,
         service: service_id.clone(),
         mechanism: Setter {
-            kind: ChannelKind::Extension {
-                vendor: Id::new(ADAPTER_VENDOR),
-                adapter: Id::new(ADAPTER_NAME),
-                kind: Id::new("Sentence"),
-                typ: Type::String,
-            },
+            kind: ChannelKind::Sentence,
             updated: None
         }
     }));
@@ -214,11 +214,11 @@ impl<C: Controller> Adapter for WebPush<C> {
         set.drain(..).map(|id| {
             let user_id = if cfg!(feature = "authentication") {
                 match user {
-                    User::None => {
+                    User::Id(id) => id,
+                    _ => {
                         return (id,
                                 Err(Error::InternalError(InternalError::GenericError("Cannot fetch from this channel without a user.".to_owned()))));
-                    },
-                    User::Id(id) => id
+                    }
                 }
             } else {
                 NO_AUTH_USER_ID
@@ -248,11 +248,11 @@ impl<C: Controller> Adapter for WebPush<C> {
         values.drain().map(|(id, value)| {
             let user_id = if cfg!(feature = "authentication") {
                 match user {
-                    User::None => {
+                    User::Id(id) => id,
+                    _ => {
                         return (id,
                             Err(Error::InternalError(InternalError::GenericError("Cannot send to this channel without a user.".to_owned()))));
-                    },
-                    User::Id(id) => id
+                    }
                 }
             } else {
                 NO_AUTH_USER_ID
@@ -328,6 +328,7 @@ impl<C: Controller> WebPush<C> {
                     adapter: id.clone(),
                     id: $id,
                     last_seen: None,
+                    capabilities: None,
                     service: service_id.clone(),
                     mechanism: Getter {
                         kind: ChannelKind::Extension {
@@ -349,6 +350,7 @@ impl<C: Controller> WebPush<C> {
                     adapter: id.clone(),
                     id: $id,
                     last_seen: None,
+                    capabilities: None,
                     service: service_id.clone(),
                     mechanism: Setter {
                         kind: ChannelKind::Extension {
@@ -368,6 +370,7 @@ impl<C: Controller> WebPush<C> {
             adapter: id.clone(),
             id: setter_notify_id,
             last_seen: None,
+            capabilities: None,
             service: service_id.clone(),
             mechanism: Setter {
                 kind: ChannelKind::WebPushNotify,
@@ -0,0 +1,196 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+extern crate serde_json;
+
+use acl_router::is_admin;
+use adapters::{ AdapterManager, AdminError };
+
+use foxbox_users::{ AuthEndpoint, UsersManager };
+
+use iron::{ Handler, IronResult, Request, Response };
+use iron::headers::ContentType;
+use iron::method::Method;
+use iron::prelude::Chain;
+use iron::status::Status;
+
+use serde::ser::Serialize;
+
+use std::sync::Arc;
+use traits::Controller;
+
+/// This is a specialized Router for starting and stopping adapters at runtime.
+/// It handles all the calls under the api/v1/admin/ url space. Only administrators may
+/// use it.
+pub struct AdaptersRouter<T> {
+    adapters: Arc<AdapterManager<T>>,
+    users_manager: Arc<UsersManager>,
+}
+
+impl<T: Controller> AdaptersRouter<T> {
+    pub fn new(adapters: Arc<AdapterManager<T>>, users_manager: Arc<UsersManager>) -> Self {
+        AdaptersRouter {
+            adapters: adapters,
+            users_manager: users_manager
+        }
+    }
+
+    fn build_response<S: Serialize>(&self, obj: &S) -> IronResult<Response> {
+        let serialized = itry!(serde_json::to_string(obj));
+        let mut response = Response::with(serialized);
+        response.status = Some(Status::Ok);
+        response.headers.set(ContentType::json());
+        Ok(response)
+    }
+
+    fn build_result(&self, result: Result<(), AdminError>) -> IronResult<Response> {
+        let err = match result {
+            Ok(()) => return Ok(Response::with(Status::NoContent)),
+            Err(err) => err
+        };
+        let status = match err {
+            AdminError::NoSuchAdapter(_) => Status::NotFound,
+            AdminError::InitError(_) => Status::InternalServerError,
+        };
+        let mut response = try!(self.build_response(&err));
+        response.status = Some(status);
+        Ok(response)
+    }
+}
+
+impl<T: Controller> Handler for AdaptersRouter<T> {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        match is_admin(&self.users_manager, req) {
+            Ok(true) => {},
+            Ok(false) => return Ok(Response::with(Status::Forbidden)),
+            Err(response) => return Ok(response)
+        }
+
+        // We are handling urls relative to the mounter set up in http_server.rs
+        // That means that for a full url like http://localhost/api/v1/admin/adapters
+        // the req.url.path will only contain ["adapters"]
+        let path = req.url.path.clone();
+
+        match (&req.method, path.len(), path.get(0).map(|s| &s[..]), path.get(2).map(|s| &s[..])) {
+            // List adapters, whether or not they are running.
+            (&Method::Get, 1, Some("adapters"), _) =>
+                self.build_response(&self.adapters.adapters()),
+
+            // Start an adapter and enable it for the next runs.
+            (&Method::Post, 3, Some("adapters"), Some("start")) =>
+                self.build_result(self.adapters.start_adapter(&path[1])),
+
+            // Stop an adapter and disable it for the next runs.
+            (&Method::Post, 3, Some("adapters"), Some("stop")) =>
+                self.build_result(self.adapters.stop_adapter(&path[1])),
+
+            // Fallthrough, returning a 404.
+            _ => Ok(Response::with((Status::NotFound,
+                                    format!("Unknown url: {}", req.url))))
+        }
+    }
+}
+
+pub fn create<T>(controller: T, adapters: &Arc<AdapterManager<T>>) -> Chain
+    where T: Controller {
+    let users_manager = controller.get_users_manager();
+    let router = AdaptersRouter::new(adapters.clone(), users_manager.clone());
+
+    let auth_endpoints = if cfg!(feature = "authentication") && !cfg!(test) {
+        // Keep this list in sync with all the (url path, http method) from
+        // the handle() method and with the CORS chain in http_server.rs
+        vec![
+            AuthEndpoint(vec![Method::Get], "adapters".to_owned()),
+            AuthEndpoint(vec![Method::Post], "adapters/:name/start".to_owned()),
+            AuthEndpoint(vec![Method::Post], "adapters/:name/stop".to_owned())
+        ]
+    } else {
+        vec![]
+    };
+
+    let mut chain = Chain::new(router);
+    chain.around(users_manager.get_middleware(auth_endpoints));
+
+    chain
+}
+
+#[cfg(test)]
+describe! adapters_router {
+    before_each {
+        extern crate serde_json;
+
+        use foxbox_taxonomy::api::API;
+        use foxbox_taxonomy::manager::AdapterManager as TaxoManager;
+        use foxbox_taxonomy::selector::ServiceSelector;
+        use iron::Headers;
+        use iron::status::Status;
+        use iron_test::{ request, response };
+        use mount::Mount;
+        use stubs::controller::ControllerStub;
+        use std::sync::Arc;
+
+        let controller = ControllerStub::new();
+        let taxo_manager = Arc::new(TaxoManager::new(None));
+        let adapters = Arc::new(AdapterManager::new(controller.clone(), &taxo_manager));
+
+        let mut mount = Mount::new();
+        mount.mount("/api/v1/admin", create(controller.clone(), &adapters));
+    }
+
+    it "should list adapters" {
+        let response = request::get("http://localhost:3000/api/v1/admin/adapters",
+                                    Headers::new(),
+                                    &mount).unwrap();
+        let body = response::extract_body_to_string(response);
+        let json : serde_json::Value = serde_json::from_str(&body).unwrap();
+        let list = json.as_array().unwrap();
+        assert!(list.iter().any(|adapter| adapter.find("name").unwrap().as_string() == Some("console")));
+    }
+
+    it "should start and stop adapters" {
+        let response = request::post("http://localhost:3000/api/v1/admin/adapters/console/start",
+                                     Headers::new(), "", &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::NoContent);
+        assert_eq!(taxo_manager.get_services(vec![ServiceSelector::new()]).len(), 1);
+        let console = adapters.adapters().into_iter().find(|adapter| adapter.name == "console").unwrap();
+        assert!(console.running);
+        assert!(console.enabled);
+        assert_eq!(controller.config.get("adapters", "console"), Some("true".to_owned()));
+
+        let response = request::post("http://localhost:3000/api/v1/admin/adapters/console/stop",
+                                     Headers::new(), "", &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::NoContent);
+        assert_eq!(taxo_manager.get_services(vec![ServiceSelector::new()]).len(), 0);
+        let console = adapters.adapters().into_iter().find(|adapter| adapter.name == "console").unwrap();
+        assert!(!console.running);
+        assert!(!console.enabled);
+        assert_eq!(controller.config.get("adapters", "console"), Some("false".to_owned()));
+    }
+
+    it "should not enable adapters that fail to start" {
+        use adapters::console::Console;
+        use foxbox_taxonomy::fake_adapter::FakeAdapter;
+
+        let response = request::post("http://localhost:3000/api/v1/admin/adapters/console/stop",
+                                     Headers::new(), "", &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::NoContent);
+
+        // The console cannot register itself while another adapter has its id.
+        taxo_manager.add_adapter(Arc::new(FakeAdapter::new(&Console::id()))).unwrap();
+        let response = request::post("http://localhost:3000/api/v1/admin/adapters/console/start",
+                                     Headers::new(), "", &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::InternalServerError);
+        let console = adapters.adapters().into_iter().find(|adapter| adapter.name == "console").unwrap();
+        assert!(!console.running);
+        assert!(!console.enabled);
+        assert!(console.error.is_some());
+        assert_eq!(controller.config.get("adapters", "console"), Some("false".to_owned()));
+    }
+
+    it "should reject unknown adapters" {
+        let response = request::post("http://localhost:3000/api/v1/admin/adapters/foo/start",
+                                     Headers::new(), "", &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::NotFound);
+    }
+}
@@ -7,7 +7,11 @@ extern crate mio;
 
 use adapters::AdapterManager;
 use config_store::ConfigService;
-use foxbox_taxonomy::manager::AdapterManager as TaxoManager;
+use foxbox_taxonomy::acl::Acl;
+use foxbox_taxonomy::api::AdapterHealth;
+use foxbox_taxonomy::history::{ HistoryStorage, RetentionPolicy };
+use foxbox_taxonomy::manager::{ AdapterManager as TaxoManager, DEFAULT_TIMEOUT_MS };
+use foxbox_taxonomy::parse::ToJSON;
 use foxbox_users::UsersManager;
 use http_server::HttpServer;
 use profile_service::{ ProfilePath, ProfileService };
@@ -18,10 +22,13 @@ use std::net::ToSocketAddrs;
 use std::path::PathBuf;
 use std::sync::{ Arc, Mutex };
 use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+use std::time::Duration;
 use std::vec::IntoIter;
 use upnp::UpnpManager;
 use tls::{ CertificateManager, CertificateRecord, SniSslContextProvider, TlsOption };
-use traits::Controller;
+use transformable_channels::mpsc::*;
+use traits::{ Controller, ControllerError };
 use ws_server::WsServer;
 use ws;
 
@@ -72,9 +79,16 @@ impl FoxBox {
 
 impl Controller for FoxBox {
 
-    fn run(&mut self, shutdown_flag: &AtomicBool) {
+    fn run(&mut self, shutdown_flag: &AtomicBool) -> Result<(), ControllerError> {
 
         debug!("Starting controller");
+        // Open the access control database first, so that we don't start any
+        // service that would be left unprotected.
+        let acl_db_path = PathBuf::from(self.profile_service.path_for("taxonomy_acl.sqlite"));
+        let acl = try!(Acl::new(&acl_db_path));
+        // Without authentication, every request is anonymous and must not be locked out.
+        let acl = if cfg!(feature = "authentication") { acl } else { acl.without_authentication() };
+
         let mut event_loop = mio::EventLoop::new().unwrap();
 
         {
@@ -83,13 +97,41 @@ impl Controller for FoxBox {
 
         // Create the taxonomy based AdapterManager
         let tags_db_path = PathBuf::from(self.profile_service.path_for("taxonomy_tags.sqlite"));
-        let taxo_manager = Arc::new(TaxoManager::new(Some(tags_db_path)));
+        let history_db_path = PathBuf::from(self.profile_service.path_for("taxonomy_history.sqlite"));
+        let history = HistoryStorage::new(&history_db_path, RetentionPolicy::default());
+        // Delay after which a request stops waiting for an unresponsive adapter.
+        let timeout_ms = self.config.get_or_set_default("taxonomy", "adapter_timeout_ms",
+                                                        &DEFAULT_TIMEOUT_MS.to_string())
+            .parse()
+            .unwrap_or(DEFAULT_TIMEOUT_MS);
+
+        // Notify websocket clients whenever an adapter is added or reports a new status,
+        // both for the adapter and for each of its services, which share its status.
+        let (tx_health, rx_health) : (RawSender<AdapterHealth>, _) = channel();
+        let controller = self.clone();
+        thread::spawn(move || {
+            for health in rx_health {
+                for service in &health.services {
+                    let (service, adapter) = (service.to_json(), health.id.to_json());
+                    let (status, since) = (health.status.to_json(), health.since.to_json());
+                    controller.broadcast_to_websockets(json_value!({ type: "core/service/health",
+                        service: service, adapter: adapter, status: status, since: since }));
+                }
+                let health = health.to_json();
+                controller.broadcast_to_websockets(json_value!({ type: "core/adapter/health", health: health }));
+            }
+        });
+
+        let taxo_manager = Arc::new(TaxoManager::with_history(Some(tags_db_path), Some(history))
+            .with_acl(acl)
+            .with_timeout(Duration::from_millis(timeout_ms))
+            .with_health_listener(Box::new(tx_health)));
 
-        let mut adapter_manager = AdapterManager::new(self.clone());
-        adapter_manager.start(&taxo_manager);
+        let adapter_manager = Arc::new(AdapterManager::new(self.clone(), &taxo_manager));
+        adapter_manager.start();
 
-        HttpServer::new(self.clone()).start(&taxo_manager);
-        WsServer::start(self.clone());
+        HttpServer::new(self.clone()).start(&taxo_manager, &adapter_manager);
+        WsServer::start(self.clone(), &taxo_manager);
 
         self.upnp.search(None).unwrap();
 
@@ -101,6 +143,7 @@ impl Controller for FoxBox {
         debug!("Stopping controller");
         adapter_manager.stop();
         taxo_manager.stop();
+        Ok(())
     }
 
     fn adapter_started(&self, adapter: String) {
@@ -0,0 +1,282 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Streaming watch events as Server-Sent Events.
+//!
+//! Each event is sent as
+//!
+//! ```ignore
+//! id: <event id>
+//! data: WatchEvent
+//!
+//! ```
+//!
+//! Recently sent events are kept in an `EventLog`, so that clients reconnecting with a
+//! `Last-Event-ID` header receive the events they may have missed.
+//!
+//! Each stream keeps one of the threads of the http server busy for as long as the
+//! client is connected, so the number of concurrent streams is capped by a `StreamLimit`.
+
+extern crate serde_json;
+
+use foxbox_taxonomy::api::WatchEvent;
+
+use iron::response::{ ResponseBody, WriteBody };
+
+use transformable_channels::mpsc::*;
+
+use std::collections::VecDeque;
+use std::io::{ self, Write };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::thread;
+use std::time::Duration;
+
+/// The number of events kept for clients resuming a stream.
+const LOG_CAPACITY: usize = 1024;
+
+/// The delay between two heartbeats. Heartbeats keep proxies from closing idle
+/// connections and let us notice that the client is gone.
+const HEARTBEAT_SECS: u64 = 15;
+
+pub enum StreamMessage {
+    Event(WatchEvent),
+    Heartbeat,
+}
+
+struct LogState {
+    next_id: u64,
+
+    /// `(event id, stream, data)`, in increasing id order.
+    events: VecDeque<(u64, String, String)>,
+}
+
+/// The events recently sent on all streams.
+pub struct EventLog {
+    state: Mutex<LogState>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        EventLog {
+            state: Mutex::new(LogState {
+                next_id: 0,
+                events: VecDeque::new(),
+            })
+        }
+    }
+
+    /// Record an event sent on `stream`, returning its id.
+    fn record(&self, stream: &str, data: &str) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.events.push_back((id, stream.to_owned(), data.to_owned()));
+        if state.events.len() > LOG_CAPACITY {
+            state.events.pop_front();
+        }
+        id
+    }
+
+    /// The events sent on `stream` after event `last_id`.
+    fn since(&self, stream: &str, last_id: u64) -> Vec<(u64, String)> {
+        let state = self.state.lock().unwrap();
+        state.events.iter()
+            .filter(|&&(id, ref key, _)| id > last_id && key == stream)
+            .map(|&(id, _, ref data)| (id, data.clone()))
+            .collect()
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        EventLog::new()
+    }
+}
+
+/// A cap on the number of concurrent streams.
+pub struct StreamLimit {
+    open: Arc<AtomicUsize>,
+    max: usize,
+}
+
+impl StreamLimit {
+    pub fn new(max: usize) -> Self {
+        StreamLimit {
+            open: Arc::new(AtomicUsize::new(0)),
+            max: max,
+        }
+    }
+
+    /// Reserve a stream, or return `None` if `max` streams are already open. The
+    /// stream is released once the `StreamSlot` is dropped.
+    pub fn acquire(&self) -> Option<StreamSlot> {
+        if self.open.fetch_add(1, Ordering::SeqCst) >= self.max {
+            self.open.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(StreamSlot(self.open.clone()))
+    }
+}
+
+/// A stream reserved with `StreamLimit::acquire`.
+pub struct StreamSlot(Arc<AtomicUsize>);
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Send `StreamMessage::Heartbeat` on `tx` periodically, until the stream is closed.
+pub fn start_heartbeat(tx: RawSender<StreamMessage>) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(Duration::from_secs(HEARTBEAT_SECS));
+            if tx.send(StreamMessage::Heartbeat).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// The body of a response streaming watch events. The stream lasts until the client
+/// disconnects, at which point `guard` is dropped.
+pub struct EventStream<G> {
+    /// Identifies the stream across reconnections.
+    stream: String,
+    log: Arc<EventLog>,
+    replay: Vec<(u64, String)>,
+    rx: Receiver<StreamMessage>,
+    guard: Option<G>,
+}
+
+impl<G> EventStream<G> where G: Send {
+    /// Create a stream. If the client is reconnecting, `last_id` is the id of the last
+    /// event it has received.
+    pub fn new(stream: String, log: &Arc<EventLog>, last_id: Option<u64>,
+               rx: Receiver<StreamMessage>, guard: G) -> Self {
+        let replay = match last_id {
+            Some(last_id) => log.since(&stream, last_id),
+            None => vec![]
+        };
+        EventStream {
+            stream: stream,
+            log: log.clone(),
+            replay: replay,
+            rx: rx,
+            guard: Some(guard),
+        }
+    }
+
+    fn write_event(res: &mut ResponseBody, id: u64, data: &str) -> io::Result<()> {
+        try!(write!(res, "id: {}\ndata: {}\n\n", id, data));
+        res.flush()
+    }
+
+    fn write_events(&mut self, res: &mut ResponseBody) -> io::Result<()> {
+        for (id, data) in self.replay.drain(..) {
+            try!(Self::write_event(res, id, &data));
+        }
+        try!(res.write_all(b": connected\n\n"));
+        try!(res.flush());
+
+        for message in self.rx.iter() {
+            match message {
+                StreamMessage::Event(event) => {
+                    let data = match serde_json::to_string(&event) {
+                        Ok(data) => data,
+                        Err(err) => {
+                            error!("Could not serialize watch event: {}", err);
+                            continue;
+                        }
+                    };
+                    let id = self.log.record(&self.stream, &data);
+                    try!(Self::write_event(res, id, &data));
+                }
+                StreamMessage::Heartbeat => {
+                    try!(res.write_all(b":\n\n"));
+                    try!(res.flush());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<G> WriteBody for EventStream<G> where G: Send {
+    fn write_body(&mut self, res: &mut ResponseBody) -> io::Result<()> {
+        let result = self.write_events(res);
+        // The client is gone, stop watching.
+        self.guard = None;
+        result
+    }
+}
+
+#[cfg(test)]
+describe! event_stream {
+    before_each {
+        extern crate serde_json;
+
+        use foxbox_taxonomy::api::WatchEvent;
+        use foxbox_taxonomy::services::Id;
+        use foxbox_taxonomy::values::{ OnOff, Value };
+        use iron::response::{ ResponseBody, WriteBody };
+        use std::sync::Arc;
+        use transformable_channels::mpsc::*;
+
+        let log = Arc::new(EventLog::new());
+        let watch_event = |value| WatchEvent::EnterRange {
+            from: Id::new("getter:test"),
+            value: Value::OnOff(value)
+        };
+        let event = |value| StreamMessage::Event(watch_event(value));
+        let data = |value| serde_json::to_string(&watch_event(value)).unwrap();
+        let run = |last_id, messages: Vec<StreamMessage>| {
+            let (tx, rx) = channel();
+            for message in messages {
+                tx.send(message).unwrap();
+            }
+            drop(tx);
+            let mut stream = EventStream::new("stream".to_owned(), &log, last_id, rx, ());
+            let mut buf = vec![];
+            stream.write_body(&mut ResponseBody::new(&mut buf)).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+    }
+
+    it "should send events with increasing ids" {
+        let body = run(None, vec![event(OnOff::On), StreamMessage::Heartbeat, event(OnOff::Off)]);
+        assert_eq!(body, format!(": connected\n\nid: 0\ndata: {}\n\n:\n\nid: 1\ndata: {}\n\n",
+                                 data(OnOff::On), data(OnOff::Off)));
+    }
+
+    it "should replay missed events when resuming" {
+        run(None, vec![event(OnOff::On), event(OnOff::Off)]);
+        let body = run(Some(0), vec![]);
+        assert_eq!(body, format!("id: 1\ndata: {}\n\n: connected\n\n", data(OnOff::Off)));
+    }
+
+    it "should cap the number of concurrent streams" {
+        let limit = StreamLimit::new(2);
+        let first = limit.acquire();
+        let second = limit.acquire();
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert!(limit.acquire().is_none());
+
+        // Closing a stream makes room for another one.
+        drop(first);
+        assert!(limit.acquire().is_some());
+    }
+
+    it "should not replay events of other streams" {
+        run(None, vec![event(OnOff::On), event(OnOff::Off)]);
+        let (_, rx) = channel();
+        let mut stream = EventStream::new("other".to_owned(), &log, Some(0), rx, ());
+        let mut buf = vec![];
+        stream.write_body(&mut ResponseBody::new(&mut buf)).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), ": connected\n\n");
+    }
+}
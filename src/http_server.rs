@@ -2,7 +2,11 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use acl_router;
+use adapters::AdapterManager as Adapters;
+use adapters_router;
 use foxbox_taxonomy::manager::*;
+use groups_router;
 use hyper::net::{ NetworkListener };
 use iron::{ AfterMiddleware, Chain, Handler,
             HttpServerFactory, Iron, IronResult, Request,
@@ -18,10 +22,13 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use std::thread;
 use taxonomy_router;
+use scenes_router;
+use thinkerbell_router;
 use tls::SniServerFactory;
 use traits::Controller;
+use virtual_channels_router;
 
-const THREAD_COUNT: usize = 8;
+pub const THREAD_COUNT: usize = 8;
 
 struct Custom404;
 
@@ -64,7 +71,8 @@ impl<T: Controller> HttpServer<T> {
         HttpServer { controller: controller }
     }
 
-    pub fn start(&mut self, adapter_api: &Arc<AdapterManager>) {
+    pub fn start(&mut self, adapter_api: &Arc<AdapterManager>,
+                 adapters: &Arc<Adapters<T>>) {
         let taxonomy_chain = taxonomy_router::create(self.controller.clone(),
                                                       adapter_api);
 
@@ -74,6 +82,14 @@ impl<T: Controller> HttpServer<T> {
              .mount("/ping", Ping)
              .mount("/api/v1", taxonomy_chain)
              .mount("/users", users_manager.get_router_chain());
+        if let Some(acl_chain) = acl_router::create(self.controller.clone(), adapter_api) {
+            mount.mount("/api/v1/acl", acl_chain);
+        }
+        mount.mount("/api/v1/admin", adapters_router::create(self.controller.clone(), adapters));
+        mount.mount("/api/v1/scenes", scenes_router::create(self.controller.clone(), adapters));
+        mount.mount("/api/v1/thinkerbell", thinkerbell_router::create(self.controller.clone(), adapters));
+        mount.mount("/api/v1/virtual", virtual_channels_router::create(self.controller.clone(), adapters));
+        mount.mount("/api/v1/groups", groups_router::create(self.controller.clone(), adapters));
 
         let mut chain = Chain::new(mount);
         chain.link_after(Custom404);
@@ -87,12 +103,46 @@ impl<T: Controller> HttpServer<T> {
             // Taxonomy router paths. Keep in sync with taxonomy_router.rs
             (vec![Method::Get, Method::Post], "api/v1/services".to_owned()),
             (vec![Method::Post, Method::Delete], "api/v1/services/tags".to_owned()),
+            (vec![Method::Get, Method::Post], "api/v1/services/health".to_owned()),
+            (vec![Method::Get], "api/v1/adapters/health".to_owned()),
             (vec![Method::Get, Method::Post], "api/v1/channels/getters".to_owned()),
             (vec![Method::Get, Method::Post], "api/v1/channels/setters".to_owned()),
             (vec![Method::Put], "api/v1/channels/get".to_owned()),
             (vec![Method::Put], "api/v1/channels/set".to_owned()),
+            (vec![Method::Put], "api/v1/channels/set/atomic".to_owned()),
+            (vec![Method::Get, Method::Put], "api/v1/channels/watch".to_owned()),
+            (vec![Method::Put], "api/v1/channels/history".to_owned()),
             (vec![Method::Post, Method::Delete], "api/v1/channel/getters/tags".to_owned()),
-            (vec![Method::Post, Method::Delete], "api/v1/channel/setters/tags".to_owned())
+            (vec![Method::Post, Method::Delete], "api/v1/channel/setters/tags".to_owned()),
+
+            // Access control router paths. Keep in sync with acl_router.rs
+            (vec![Method::Get], "api/v1/acl/grants".to_owned()),
+            (vec![Method::Put, Method::Delete], "api/v1/acl/grants/:id".to_owned()),
+            (vec![Method::Get], "api/v1/acl/groups".to_owned()),
+            (vec![Method::Put, Method::Delete], "api/v1/acl/groups/:id".to_owned()),
+            (vec![Method::Get, Method::Put], "api/v1/acl/enforced".to_owned()),
+
+            // Adapters router paths. Keep in sync with adapters_router.rs
+            (vec![Method::Get], "api/v1/admin/adapters".to_owned()),
+            (vec![Method::Post], "api/v1/admin/adapters/:name/start".to_owned()),
+            (vec![Method::Post], "api/v1/admin/adapters/:name/stop".to_owned()),
+
+            // Scenes router paths. Keep in sync with scenes_router.rs
+            (vec![Method::Get], "api/v1/scenes/list".to_owned()),
+            (vec![Method::Put, Method::Delete], "api/v1/scenes/:name".to_owned()),
+            (vec![Method::Post], "api/v1/scenes/:name/capture".to_owned()),
+            (vec![Method::Post], "api/v1/scenes/:name/activate".to_owned()),
+
+            // Thinkerbell router paths. Keep in sync with thinkerbell_router.rs
+            (vec![Method::Post], "api/v1/thinkerbell/validate".to_owned()),
+
+            // Virtual channels router paths. Keep in sync with virtual_channels_router.rs
+            (vec![Method::Get, Method::Put], "api/v1/virtual/getters".to_owned()),
+            (vec![Method::Delete], "api/v1/virtual/getters/:id".to_owned()),
+
+            // Groups router paths. Keep in sync with groups_router.rs
+            (vec![Method::Get, Method::Put], "api/v1/groups/list".to_owned()),
+            (vec![Method::Delete], "api/v1/groups/:name".to_owned())
         ]);
         chain.link_after(cors);
 
@@ -146,6 +196,7 @@ describe! http_server {
     before_each {
         extern crate hyper;
 
+        use adapters::AdapterManager as Adapters;
         use foxbox_taxonomy::manager::AdapterManager;
         use std::thread;
         use std::sync::Arc;
@@ -153,9 +204,10 @@ describe! http_server {
         use stubs::controller::ControllerStub;
 
         let taxo_manager = Arc::new(AdapterManager::new(None));
+        let adapters = Arc::new(Adapters::new(ControllerStub::new(), &taxo_manager));
 
         let mut http_server = HttpServer::new(ControllerStub::new());
-        http_server.start(&taxo_manager);
+        http_server.start(&taxo_manager, &adapters);
         // HACK: Let some time for the http server to start.
         thread::sleep(Duration::new(3, 0));
     }
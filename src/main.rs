@@ -73,18 +73,25 @@ extern crate tempdir;
 // Need to be declared first so to let the macros be visible from other modules.
 #[macro_use]
 mod utils;
+mod acl_router;
 mod adapters;
+mod adapters_router;
 mod config_store;
 mod controller;
+mod event_stream;
+mod groups_router;
 mod http_server;
 mod managed_process;
 mod profile_service;
 mod registration;
+mod scenes_router;
 mod upnp;
 mod static_router;
 mod taxonomy_router;
+mod thinkerbell_router;
 mod traits;
 mod tunnel_controller;
+mod virtual_channels_router;
 mod ws_server;
 
 #[cfg(test)]
@@ -299,7 +306,9 @@ fn main() {
     registrar.start(args.flag_iface, &tunnel,
                     args.flag_port,  &controller);
 
-    controller.run(&SHUTDOWN_FLAG);
+    if let Err(err) = controller.run(&SHUTDOWN_FLAG) {
+        error!("Could not start the controller: {:?}", err);
+    }
 
     if let Some(mut tunnel) = tunnel {
         tunnel.stop().unwrap();
@@ -0,0 +1,231 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+extern crate serde_json;
+
+use adapters::AdapterManager;
+use adapters::scenes::SceneError;
+
+use foxbox_taxonomy::api::User;
+use foxbox_taxonomy::parse::*;
+use foxbox_taxonomy::selector::GetterSelector;
+
+use foxbox_users::{ AuthEndpoint, SessionToken };
+
+use iron::{ Handler, headers, IronResult, Request, Response };
+use iron::headers::ContentType;
+use iron::method::Method;
+use iron::prelude::Chain;
+use iron::status::Status;
+
+use serde::ser::Serialize;
+
+use std::io::Read;
+use std::sync::Arc;
+use traits::Controller;
+
+/// This is a specialized Router for managing scenes.
+/// It handles all the calls under the api/v1/scenes/ url space. It responds with a 503
+/// while the scenes adapter is stopped.
+pub struct ScenesRouter<T> {
+    adapters: Arc<AdapterManager<T>>,
+}
+
+impl<T: Controller> ScenesRouter<T> {
+    pub fn new(adapters: Arc<AdapterManager<T>>) -> Self {
+        ScenesRouter {
+            adapters: adapters
+        }
+    }
+
+    fn build_response<S: Serialize>(&self, obj: &S) -> IronResult<Response> {
+        let serialized = itry!(serde_json::to_string(obj));
+        let mut response = Response::with(serialized);
+        response.status = Some(Status::Ok);
+        response.headers.set(ContentType::json());
+        Ok(response)
+    }
+
+    fn build_error(&self, err: SceneError) -> IronResult<Response> {
+        let status = match err {
+            SceneError::InvalidName(_) | SceneError::ParseError(_) => Status::BadRequest,
+            SceneError::NoSuchScene(_) => Status::NotFound,
+            SceneError::NothingToCapture => Status::Conflict,
+            SceneError::SQLError(_) | SceneError::TaxonomyError(_) => Status::InternalServerError,
+        };
+        let mut response = try!(self.build_response(&err));
+        response.status = Some(status);
+        Ok(response)
+    }
+
+    fn build_result(&self, result: Result<(), SceneError>) -> IronResult<Response> {
+        match result {
+            Ok(()) => Ok(Response::with(Status::NoContent)),
+            Err(err) => self.build_error(err)
+        }
+    }
+}
+
+impl<T: Controller> Handler for ScenesRouter<T> {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let scenes = match self.adapters.scenes() {
+            Some(scenes) => scenes,
+            None => return Ok(Response::with((Status::ServiceUnavailable,
+                                              "The scenes adapter is not running")))
+        };
+
+        let user: User = match req.headers.clone().get::
+            <headers::Authorization<headers::Bearer>>() {
+            Some(&headers::Authorization(headers::Bearer { ref token })) => {
+                match SessionToken::from_string(token) {
+                    Ok(token) => User::Id(token.claims.id),
+                    Err(_) => return Ok(Response::with(Status::Unauthorized))
+                }
+            },
+            _ => User::None
+        };
+
+        // We are handling urls relative to the mounter set up in http_server.rs
+        // That means that for a full url like http://localhost/api/v1/scenes/list
+        // the req.url.path will only contain ["list"]
+        let path = req.url.path.clone();
+        let mut body = String::new();
+        itry!(req.body.read_to_string(&mut body));
+
+        match (&req.method, path.len(), path.get(1).map(|s| &s[..])) {
+            // List scenes.
+            (&Method::Get, 1, _) if path[0] == "list" =>
+                self.build_response(&scenes.get_scenes()),
+
+            // Add or replace a scene. The body is a `TargetMap<SetterSelector, Value>`,
+            // as for `PUT api/v1/channels/set`.
+            (&Method::Put, 1, _) =>
+                self.build_result(scenes.put_scene(&path[0], &body)),
+
+            // Remove a scene.
+            (&Method::Delete, 1, _) =>
+                self.build_result(scenes.remove_scene(&path[0])),
+
+            // Add or replace a scene with the current values of getters. The body is an
+            // array of `GetterSelector`.
+            (&Method::Post, 2, Some("capture")) => {
+                let getters = match Vec::<GetterSelector>::from_str(&body) {
+                    Ok(getters) => getters,
+                    Err(err) => return self.build_error(SceneError::ParseError(err))
+                };
+                self.build_result(scenes.capture_scene(&path[0], getters, user))
+            },
+
+            // Send the values of a scene. The response is the result for each setter.
+            (&Method::Post, 2, Some("activate")) => {
+                match scenes.activate_scene(&path[0], user) {
+                    Ok(results) => {
+                        let serialized = itry!(serde_json::to_string(&results.to_json()));
+                        let mut response = Response::with(serialized);
+                        response.status = Some(Status::Ok);
+                        response.headers.set(ContentType::json());
+                        Ok(response)
+                    }
+                    Err(err) => self.build_error(err)
+                }
+            },
+
+            // Fallthrough, returning a 404.
+            _ => Ok(Response::with((Status::NotFound,
+                                    format!("Unknown url: {}", req.url))))
+        }
+    }
+}
+
+pub fn create<T>(controller: T, adapters: &Arc<AdapterManager<T>>) -> Chain
+    where T: Controller {
+    let router = ScenesRouter::new(adapters.clone());
+
+    let auth_endpoints = if cfg!(feature = "authentication") && !cfg!(test) {
+        // Keep this list in sync with all the (url path, http method) from
+        // the handle() method and with the CORS chain in http_server.rs
+        vec![
+            AuthEndpoint(vec![Method::Get], "list".to_owned()),
+            AuthEndpoint(vec![Method::Put, Method::Delete], ":name".to_owned()),
+            AuthEndpoint(vec![Method::Post], ":name/capture".to_owned()),
+            AuthEndpoint(vec![Method::Post], ":name/activate".to_owned())
+        ]
+    } else {
+        vec![]
+    };
+
+    let mut chain = Chain::new(router);
+    chain.around(controller.get_users_manager().get_middleware(auth_endpoints));
+
+    chain
+}
+
+#[cfg(test)]
+describe! scenes_router {
+    before_each {
+        extern crate serde_json;
+
+        use adapters::AdapterManager as Adapters;
+        use foxbox_taxonomy::manager::AdapterManager;
+        use iron::Headers;
+        use iron::status::Status;
+        use iron_test::{ request, response };
+        use mount::Mount;
+        use stubs::controller::ControllerStub;
+        use std::sync::Arc;
+
+        let manager = Arc::new(AdapterManager::new(None));
+        let adapters = Arc::new(Adapters::new(ControllerStub::new(), &manager));
+        adapters.start_adapter("scenes").unwrap();
+        let scenes = adapters.scenes().unwrap();
+
+        let mut mount = Mount::new();
+        mount.mount("/api/v1/scenes", create(ControllerStub::new(), &adapters));
+    }
+
+    it "should add, list and remove scenes" {
+        let response = request::put("http://localhost:3000/api/v1/scenes/movie",
+                                    Headers::new(),
+                                    r#"[{"select": {"kind": "LightOn"}, "value": {"OnOff": "Off"}}]"#,
+                                    &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::NoContent);
+
+        let response = request::get("http://localhost:3000/api/v1/scenes/list",
+                                    Headers::new(),
+                                    &mount).unwrap();
+        let body = response::extract_body_to_string(response);
+        let json : serde_json::Value = serde_json::from_str(&body).unwrap();
+        let list = json.as_array().unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].find("name").unwrap().as_string(), Some("movie"));
+
+        let response = request::post("http://localhost:3000/api/v1/scenes/movie/activate",
+                                     Headers::new(), "", &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::Ok);
+
+        let response = request::delete("http://localhost:3000/api/v1/scenes/movie",
+                                       Headers::new(),
+                                       &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::NoContent);
+        assert!(scenes.get_scenes().is_empty());
+    }
+
+    it "should reject malformed and unknown scenes" {
+        let response = request::put("http://localhost:3000/api/v1/scenes/movie",
+                                    Headers::new(), "{}", &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::BadRequest);
+
+        let response = request::post("http://localhost:3000/api/v1/scenes/unknown/activate",
+                                     Headers::new(), "", &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::NotFound);
+    }
+
+    it "should respond with 503 while the adapter is stopped" {
+        adapters.stop_adapter("scenes").unwrap();
+        let response = request::get("http://localhost:3000/api/v1/scenes/list",
+                                    Headers::new(),
+                                    &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::ServiceUnavailable);
+    }
+}
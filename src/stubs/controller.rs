@@ -16,7 +16,7 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 use tls::{ CertificateManager, CertificateRecord, SniSslContextProvider };
-use traits::Controller;
+use traits::{ Controller, ControllerError };
 use upnp::UpnpManager;
 use ws;
 
@@ -46,7 +46,7 @@ impl Default for ControllerStub {
 }
 
 impl Controller for ControllerStub {
-    fn run(&mut self, _: &AtomicBool) {}
+    fn run(&mut self, _: &AtomicBool) -> Result<(), ControllerError> { Ok(()) }
     fn adapter_started(&self, _: String) {}
     fn adapter_notification(&self, _: serde_json::value::Value) {}
     fn http_as_addrs(&self) -> Result<IntoIter<SocketAddr>, io::Error> {
@@ -4,15 +4,20 @@
 
 extern crate serde_json;
 
+use event_stream::{ self, EventLog, EventStream, StreamLimit, StreamMessage };
+
 use foxbox_taxonomy::manager::*;
-use foxbox_taxonomy::api::{ API, Error, TargetMap, User };
-use foxbox_taxonomy::values::{ Binary, Value };
+use foxbox_taxonomy::api::{ API, Error, FetchOptions, TargetMap, User, WatchOptions };
+use foxbox_taxonomy::util::Exactly;
+use foxbox_taxonomy::values::{ Binary, Range, Value };
 use foxbox_taxonomy::selector::*;
 use foxbox_taxonomy::services::*;
 
 use foxbox_users::AuthEndpoint;
 use foxbox_users::SessionToken;
 
+use http_server::THREAD_COUNT;
+
 use iron::{ Handler, headers, IronResult, Request, Response };
 use iron::headers::ContentType;
 use iron::method::Method;
@@ -21,21 +26,37 @@ use iron::request::Body;
 use iron::status::Status;
 
 use std::io::{ Error as IOError, Read };
+use std::str;
 use std::sync::Arc;
 use traits::Controller;
+use transformable_channels::mpsc::*;
+use url::form_urlencoded;
 
 /// This is a specialized Router for the taxonomy API.
 /// It handles all the calls under the api/v1/ url space.
 pub struct TaxonomyRouter {
-    api: Arc<AdapterManager>
+    api: Arc<AdapterManager>,
+
+    /// The events recently sent on `channels/watch` streams.
+    events: Arc<EventLog>,
+
+    /// The `channels/watch` streams currently open.
+    streams: StreamLimit,
 }
 
+/// The maximal number of concurrent `channels/watch` streams. As each stream keeps a
+/// thread of the http server busy, this leaves threads available for the rest of the
+/// REST API.
+const MAX_STREAMS: usize = THREAD_COUNT / 2;
+
 type GetterResultMap = ResultMap<Id<Getter>, Option<Value>, Error>;
 
 impl TaxonomyRouter {
     pub fn new(adapter_api: &Arc<AdapterManager>) -> Self {
         TaxonomyRouter {
-            api: adapter_api.clone()
+            api: adapter_api.clone(),
+            events: Arc::new(EventLog::new()),
+            streams: StreamLimit::new(MAX_STREAMS)
         }
     }
 
@@ -75,6 +96,108 @@ impl TaxonomyRouter {
         Ok(s)
     }
 
+    /// Fetch the values of getters. The body is either an array of `GetterSelector`, or
+    /// `{getters, options}`, where `options` is a `FetchOptions`, to receive the values
+    /// with their timestamp and possibly from the cache.
+    fn fetch(&self, req: &mut Request, user: User) -> IronResult<Response> {
+        let source = itry!(Self::read_body_to_string(&mut req.body));
+        let mut json : serde_json::Value = match serde_json::de::from_str(&source as &str) {
+            Err(err) => return self.build_parse_error(&ParseError::json(err)),
+            Ok(json) => json
+        };
+        if json.is_object() {
+            let getters = match Path::new().push_str("body.getters",
+                |path| Vec::<GetterSelector>::take(path, &mut json, "getters")) {
+                Err(err) => return self.build_parse_error(&err),
+                Ok(getters) => getters
+            };
+            let options = match Path::new().push_str("body.options",
+                |path| FetchOptions::take_opt(path, &mut json, "options")) {
+                None => FetchOptions::default(),
+                Some(Err(err)) => return self.build_parse_error(&err),
+                Some(Ok(options)) => options
+            };
+            return self.build_response(&self.api.fetch_values_with_options(getters, options, user));
+        }
+        let getters = match Path::new().push_str("body", |path| Vec::<GetterSelector>::parse(path, &mut json)) {
+            Err(err) => return self.build_parse_error(&err),
+            Ok(getters) => getters
+        };
+        let res = self.api.fetch_values(getters, user);
+        if let Some(payload) = self.get_binary(&res) {
+            self.build_binary_response(&payload)
+        } else {
+            self.build_response(&res)
+        }
+    }
+
+    /// Stream the events of a watch as Server-Sent Events, until the client disconnects.
+    /// The watch is taken from the body of a PUT request, or from the `watch` query
+    /// parameter of a GET request, since `EventSource` only issues GET requests.
+    ///
+    /// Note that each stream keeps one of the threads of the http server busy, so at most
+    /// `MAX_STREAMS` streams may be open at once.
+    fn watch(&self, req: &mut Request, user: User) -> IronResult<Response> {
+        let source = if req.method == Method::Get {
+            let query = req.url.query.clone().unwrap_or_else(String::new);
+            let watch = form_urlencoded::parse(query.as_bytes()).into_iter()
+                .find(|&(ref key, _)| key == "watch")
+                .map(|(_, value)| value);
+            match watch {
+                Some(watch) => watch,
+                None => return Ok(Response::with((Status::BadRequest,
+                                                  "Missing query parameter: watch")))
+            }
+        } else {
+            itry!(Self::read_body_to_string(&mut req.body))
+        };
+        let watch = match Path::new().push_str("body",
+            |path| TargetMap::<GetterSelector, Exactly<Range>>::from_str_at(path, &source as &str))
+        {
+            Ok(watch) => watch,
+            Err(err) => return self.build_parse_error(&err)
+        };
+
+        // Refuse to stream channels that the user is not allowed to read.
+        let selectors = watch.iter()
+            .flat_map(|targetted| targetted.select.iter().cloned())
+            .collect();
+        if !self.api.unreadable_getters(selectors, &user).is_empty() {
+            return Ok(Response::with(Status::Forbidden));
+        }
+
+        let slot = match self.streams.acquire() {
+            Some(slot) => slot,
+            None => return Ok(Response::with((Status::ServiceUnavailable,
+                                              "Too many open event streams")))
+        };
+
+        let last_id = req.headers.get_raw("Last-Event-ID")
+            .and_then(|raw| raw.get(0))
+            .and_then(|bytes| str::from_utf8(bytes).ok())
+            .and_then(|id| id.trim().parse::<u64>().ok());
+
+        // When resuming, also resend the current values, as the client may have missed
+        // events that are too old to be replayed.
+        let options = WatchOptions {
+            initial: last_id.is_some(),
+            .. WatchOptions::default()
+        };
+
+        let (tx, rx) = channel();
+        event_stream::start_heartbeat(tx.internal_clone());
+        let guard = self.api.watch_values_with_options(watch, options,
+            Box::new(tx.map(StreamMessage::Event)), user.clone());
+        let stream = EventStream::new(format!("{:?} {}", user, source), &self.events,
+                                      last_id, rx, (guard, slot));
+
+        let mut response = Response::with(Status::Ok);
+        response.headers.set(ContentType("text/event-stream".parse().unwrap()));
+        response.headers.set(headers::CacheControl(vec![headers::CacheDirective::NoCache]));
+        response.body = Some(Box::new(stream));
+        Ok(response)
+    }
+
     // Checks if a getter result map is a binary payload.
     fn get_binary(&self, map: &GetterResultMap) -> Option<Binary> {
         // For now, consider as binary a result map with a single element that
@@ -155,17 +278,6 @@ impl Handler for TaxonomyRouter {
             ($api:ident, $arg:ident, $call:ident) => (self.build_response(&$api.$call($arg, user)))
         }
 
-        macro_rules! binary {
-            ($api:ident, $arg:ident, $call:ident) => ({
-                        let res = $api.$call($arg, user);
-                        if let Some(payload) = self.get_binary(&res) {
-                            self.build_binary_response(&payload)
-                        } else {
-                            self.build_response(&res)
-                        }
-                    })
-        }
-
         // Generates the code to process a given HTTP call with a json body.
         macro_rules! payload_api {
             ($call:ident, $param:ty, $path:expr, $method:expr, $action:ident) => (
@@ -186,9 +298,10 @@ impl Handler for TaxonomyRouter {
         }
 
         // Generates the code to process a given HTTP call with a json body.
-        // This version takes 2 parameters for the internal call.
+        // This version takes 2 parameters for the internal call, followed by
+        // any additional arguments, e.g. the user.
         macro_rules! payload_api2 {
-            ($call:ident, $name1:ident => $param1:ty, $name2:ident => $param2:ty, $path:expr, $method:expr) => (
+            ($call:ident, $name1:ident => $param1:ty, $name2:ident => $param2:ty, $path:expr, $method:expr $(, $extra:expr)*) => (
                 if path == $path && req.method == $method {
                     type Param1 = $param1;
                     type Param2 = $param2;
@@ -208,7 +321,7 @@ impl Handler for TaxonomyRouter {
                             Err(err) => return self.build_parse_error(&err),
                             Ok(val) => val
                         };
-                        self.build_response(&self.api.$call(arg_1, arg_2))
+                        self.build_response(&self.api.$call(arg_1, arg_2 $(, $extra)*))
                     }
                 }
             )
@@ -221,39 +334,59 @@ impl Handler for TaxonomyRouter {
         get_post_api!(get_getter_channels, GetterSelector, ["channels", "getters"]);
         get_post_api!(get_setter_channels, SetterSelector, ["channels", "setters"]);
 
+        // Health of services and adapters.
+        get_post_api!(get_services_health, ServiceSelector, ["services", "health"]);
+        if path == ["adapters", "health"] && req.method == Method::Get {
+            return self.build_response(&self.api.get_adapters_health());
+        }
+
         // Fetching and getting values.
         // We can't use a GET http method here because the Fetch() DOM api
         // doesn't allow bodies with GET and HEAD requests.
-        payload_api!(fetch_values, Vec<GetterSelector>, ["channels", "get"], Method::Put, binary);
+        if path == ["channels", "get"] && req.method == Method::Put {
+            return self.fetch(req, user);
+        }
         payload_api!(send_values, TargetMap<SetterSelector, Value>, ["channels", "set"], Method::Put, simple);
+        payload_api!(send_values_atomic, TargetMap<SetterSelector, Value>, ["channels", "set", "atomic"], Method::Put, simple);
+
+        // Watching values, as Server-Sent Events.
+        if path == ["channels", "watch"] && (req.method == Method::Get || req.method == Method::Put) {
+            return self.watch(req, user);
+        }
+
+        // Fetching the history of values.
+        payload_api2!(fetch_history,
+                      getters => Vec<GetterSelector>,
+                      period => Period,
+                      ["channels", "history"], Method::Put, user);
 
         // Adding tags.
         payload_api2!(add_service_tags,
                       services => Vec<ServiceSelector>,
                       tags => Vec<Id<TagId>>,
-                      ["services", "tags"], Method::Post);
+                      ["services", "tags"], Method::Post, user);
         payload_api2!(add_getter_tags,
                       getters => Vec<GetterSelector>,
                       tags => Vec<Id<TagId>>,
-                      ["channels", "getter", "tags"], Method::Post);
+                      ["channels", "getter", "tags"], Method::Post, user);
         payload_api2!(add_setter_tags,
                       setters => Vec<SetterSelector>,
                       tags => Vec<Id<TagId>>,
-                      ["channels", "setter", "tags"], Method::Post);
+                      ["channels", "setter", "tags"], Method::Post, user);
 
         // Removing tags.
         payload_api2!(remove_service_tags,
                       services => Vec<ServiceSelector>,
                       tags => Vec<Id<TagId>>,
-                      ["services", "tags"], Method::Delete);
+                      ["services", "tags"], Method::Delete, user);
         payload_api2!(remove_getter_tags,
                       getters => Vec<GetterSelector>,
                       tags => Vec<Id<TagId>>,
-                      ["channels", "getter", "tags"], Method::Delete);
+                      ["channels", "getter", "tags"], Method::Delete, user);
         payload_api2!(remove_setter_tags,
                       setters => Vec<SetterSelector>,
                       tags => Vec<Id<TagId>>,
-                      ["channels", "setter", "tags"], Method::Delete);
+                      ["channels", "setter", "tags"], Method::Delete, user);
 
         // Fallthrough, returning a 404.
         Ok(Response::with((Status::NotFound,
@@ -271,10 +404,15 @@ pub fn create<T>(controller: T, adapter_api: &Arc<AdapterManager>) -> Chain
         vec![
             AuthEndpoint(vec![Method::Get, Method::Post], "services".to_owned()),
             AuthEndpoint(vec![Method::Post, Method::Delete], "services/tags".to_owned()),
+            AuthEndpoint(vec![Method::Get, Method::Post], "services/health".to_owned()),
+            AuthEndpoint(vec![Method::Get], "adapters/health".to_owned()),
             AuthEndpoint(vec![Method::Get, Method::Post], "channels/getters".to_owned()),
             AuthEndpoint(vec![Method::Get, Method::Post], "channels/setters".to_owned()),
             AuthEndpoint(vec![Method::Get], "channels/get".to_owned()),
             AuthEndpoint(vec![Method::Put], "channels/set".to_owned()),
+            AuthEndpoint(vec![Method::Put], "channels/set/atomic".to_owned()),
+            AuthEndpoint(vec![Method::Get, Method::Put], "channels/watch".to_owned()),
+            AuthEndpoint(vec![Method::Put], "channels/history".to_owned()),
             AuthEndpoint(vec![Method::Post, Method::Delete], "channel/getters/tags".to_owned()),
             AuthEndpoint(vec![Method::Post, Method::Delete], "channel/setters/tags".to_owned())
         ]
@@ -328,6 +466,34 @@ describe! taxonomy_router {
 
         assert_eq!(body, s);
     }
+
+    it "should fetch timestamped values with options" {
+        let response = request::put("http://localhost:3000/api/v1/channels/get",
+                                    Headers::new(),
+                                    r#"{"getters":[{"id":"getter:timestamp.clock@link.mozilla.org"}],"options":{"max_age":60}}"#,
+                                    &mount).unwrap();
+        let body = response::extract_body_to_string(response);
+        let json : serde_json::Value = serde_json::from_str(&body).unwrap();
+        let fetched = json.find("getter:timestamp.clock@link.mozilla.org").unwrap();
+        assert!(fetched.find("value").is_some());
+        assert!(fetched.find("timestamp").is_some());
+        assert_eq!(fetched.find("adapter").unwrap().as_string(), Some("clock@link.mozilla.org"));
+    }
+
+    it "should reject malformed watch streams" {
+        use iron::status::Status;
+
+        let response = request::get("http://localhost:3000/api/v1/channels/watch",
+                                    Headers::new(),
+                                    &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::BadRequest);
+
+        let response = request::put("http://localhost:3000/api/v1/channels/watch",
+                                    Headers::new(),
+                                    r#"{"not": "a watch"}"#,
+                                    &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::BadRequest);
+    }
 }
 
 #[cfg(test)]
@@ -419,6 +585,7 @@ describe! binary_getter {
                     adapter: adapter_id.clone(),
                     id: Id::new("getter:binary@link.mozilla.org"),
                     last_seen: None,
+                    capabilities: None,
                     service: service_id.clone(),
                     mechanism: Getter {
                         kind: ChannelKind::Extension {
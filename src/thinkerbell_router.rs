@@ -0,0 +1,91 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+extern crate serde_json;
+
+use adapters::AdapterManager;
+
+use foxbox_users::AuthEndpoint;
+
+use iron::{ Handler, IronResult, Request, Response };
+use iron::headers::ContentType;
+use iron::method::Method;
+use iron::prelude::Chain;
+use iron::status::Status;
+
+use std::io::Read;
+use std::sync::Arc;
+use traits::Controller;
+
+/// This is a specialized Router for the Thinkerbell rules engine.
+/// It handles all the calls under the api/v1/thinkerbell/ url space. It responds with a
+/// 503 while the Thinkerbell adapter is stopped.
+pub struct ThinkerbellRouter<T> {
+    adapters: Arc<AdapterManager<T>>,
+}
+
+impl<T: Controller> ThinkerbellRouter<T> {
+    pub fn new(adapters: Arc<AdapterManager<T>>) -> Self {
+        ThinkerbellRouter {
+            adapters: adapters
+        }
+    }
+}
+
+impl<T: Controller> Handler for ThinkerbellRouter<T> {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let adapter = match self.adapters.thinkerbell() {
+            Some(adapter) => adapter,
+            None => return Ok(Response::with((Status::ServiceUnavailable,
+                                              "The Thinkerbell adapter is not running")))
+        };
+
+        // We are handling urls relative to the mounter set up in http_server.rs
+        // That means that for a full url like http://localhost/api/v1/thinkerbell/validate
+        // the req.url.path will only contain ["validate"]
+        let path = req.url.path.clone();
+
+        // Check a script without installing it. The body is the source of the
+        // script, the response is the list of problems, which is empty if the
+        // script could be installed as is.
+        if path == ["validate"] && req.method == Method::Post {
+            let mut source = String::new();
+            itry!(req.body.read_to_string(&mut source));
+            let diagnostics = match adapter.validate(source) {
+                Ok(diagnostics) => diagnostics,
+                Err(err) => return Ok(Response::with((Status::InternalServerError,
+                                                      format!("{:?}", err))))
+            };
+            let serialized = itry!(serde_json::to_string(&diagnostics));
+            let mut response = Response::with(serialized);
+            response.status = Some(Status::Ok);
+            response.headers.set(ContentType::json());
+            return Ok(response);
+        }
+
+        // Fallthrough, returning a 404.
+        Ok(Response::with((Status::NotFound,
+                           format!("Unknown url: {}", req.url))))
+    }
+}
+
+pub fn create<T>(controller: T, adapters: &Arc<AdapterManager<T>>) -> Chain
+    where T: Controller {
+    let router = ThinkerbellRouter::new(adapters.clone());
+
+    let auth_endpoints = if cfg!(feature = "authentication") && !cfg!(test) {
+        // Keep this list in sync with all the (url path, http method) from
+        // the handle() method and with the CORS chain in http_server.rs
+        vec![
+            AuthEndpoint(vec![Method::Post], "validate".to_owned())
+        ]
+    } else {
+        vec![]
+    };
+
+    let mut chain = Chain::new(router);
+    chain.around(controller.get_users_manager().get_middleware(auth_endpoints));
+
+    chain
+}
@@ -4,6 +4,7 @@
 
 use config_store::ConfigService;
 use core::marker::Reflect;
+use foxbox_taxonomy::acl::AclError;
 use foxbox_users::UsersManager;
 use profile_service::ProfileService;
 use serde_json;
@@ -16,8 +17,21 @@ use tls::{ CertificateRecord, CertificateManager };
 use upnp::UpnpManager;
 use ws;
 
+/// An error preventing the controller from starting.
+#[derive(Debug)]
+pub enum ControllerError {
+    /// The access control database could not be opened.
+    AclError(AclError),
+}
+
+impl From<AclError> for ControllerError {
+    fn from(err: AclError) -> Self {
+        ControllerError::AclError(err)
+    }
+}
+
 pub trait Controller : Send + Sync + Clone + Reflect + 'static {
-    fn run(&mut self, shutdown_flag: &AtomicBool);
+    fn run(&mut self, shutdown_flag: &AtomicBool) -> Result<(), ControllerError>;
     fn adapter_started(&self, adapter: String);
     fn adapter_notification(&self, notification: serde_json::value::Value);
     fn http_as_addrs(&self) -> Result<IntoIter<SocketAddr>, io::Error>;
@@ -0,0 +1,220 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+extern crate serde_json;
+
+use acl_router::is_admin;
+use adapters::AdapterManager;
+
+use foxbox_taxonomy::util::Id;
+use foxbox_taxonomy::virtual_channels::VirtualError;
+
+use foxbox_users::{ AuthEndpoint, UsersManager };
+
+use iron::{ Handler, IronResult, Request, Response };
+use iron::headers::ContentType;
+use iron::method::Method;
+use iron::prelude::Chain;
+use iron::status::Status;
+
+use serde::ser::Serialize;
+
+use std::io::Read;
+use std::sync::Arc;
+use traits::Controller;
+
+/// This is a specialized Router for managing virtual getters.
+/// It handles all the calls under the api/v1/virtual/ url space. It responds with a 503
+/// while the virtual channels adapter is stopped. Only administrators may add or remove
+/// virtual getters.
+pub struct VirtualChannelsRouter<T> {
+    adapters: Arc<AdapterManager<T>>,
+    users_manager: Arc<UsersManager>,
+}
+
+impl<T: Controller> VirtualChannelsRouter<T> {
+    pub fn new(adapters: Arc<AdapterManager<T>>, users_manager: Arc<UsersManager>) -> Self {
+        VirtualChannelsRouter {
+            adapters: adapters,
+            users_manager: users_manager
+        }
+    }
+
+    fn build_response<S: Serialize>(&self, obj: &S) -> IronResult<Response> {
+        let serialized = itry!(serde_json::to_string(obj));
+        let mut response = Response::with(serialized);
+        response.status = Some(Status::Ok);
+        response.headers.set(ContentType::json());
+        Ok(response)
+    }
+
+    fn build_result<V: Serialize>(&self, result: Result<V, VirtualError>) -> IronResult<Response> {
+        let err = match result {
+            Ok(value) => return self.build_response(&value),
+            Err(err) => err
+        };
+        let status = match err {
+            VirtualError::ParseError(_) => Status::BadRequest,
+            VirtualError::NoSuchGetter(_) => Status::NotFound,
+            VirtualError::TaxonomyError(_) | VirtualError::SQLError(_) => Status::InternalServerError,
+        };
+        let mut response = try!(self.build_response(&err));
+        response.status = Some(status);
+        Ok(response)
+    }
+}
+
+impl<T: Controller> Handler for VirtualChannelsRouter<T> {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let virtual_channels = match self.adapters.virtual_channels() {
+            Some(virtual_channels) => virtual_channels,
+            None => return Ok(Response::with((Status::ServiceUnavailable,
+                                              "The virtual channels adapter is not running")))
+        };
+
+        // We are handling urls relative to the mounter set up in http_server.rs
+        // That means that for a full url like http://localhost/api/v1/virtual/getters
+        // the req.url.path will only contain ["getters"]
+        let path = req.url.path.clone();
+        let mut body = String::new();
+        itry!(req.body.read_to_string(&mut body));
+
+        if req.method == Method::Put || req.method == Method::Delete {
+            match is_admin(&self.users_manager, req) {
+                Ok(true) => {},
+                Ok(false) => return Ok(Response::with(Status::Forbidden)),
+                Err(response) => return Ok(response)
+            }
+        }
+
+        match (&req.method, path.len(), path.get(0).map(|s| &s[..])) {
+            // List the definitions of virtual getters.
+            (&Method::Get, 1, Some("getters")) =>
+                self.build_response(&virtual_channels.get_getters()),
+
+            // Add or replace a virtual getter. The body is the JSON source of the
+            // definition, the response is the id of the virtual getter.
+            (&Method::Put, 1, Some("getters")) =>
+                self.build_result(virtual_channels.put_getter(&body)),
+
+            // Remove a virtual getter.
+            (&Method::Delete, 2, Some("getters")) =>
+                self.build_result(virtual_channels.remove_getter(&Id::new(&path[1]))),
+
+            // Fallthrough, returning a 404.
+            _ => Ok(Response::with((Status::NotFound,
+                                    format!("Unknown url: {}", req.url))))
+        }
+    }
+}
+
+pub fn create<T>(controller: T, adapters: &Arc<AdapterManager<T>>) -> Chain
+    where T: Controller {
+    let users_manager = controller.get_users_manager();
+    let router = VirtualChannelsRouter::new(adapters.clone(), users_manager.clone());
+
+    let auth_endpoints = if cfg!(feature = "authentication") && !cfg!(test) {
+        // Keep this list in sync with all the (url path, http method) from
+        // the handle() method and with the CORS chain in http_server.rs
+        vec![
+            AuthEndpoint(vec![Method::Get, Method::Put], "getters".to_owned()),
+            AuthEndpoint(vec![Method::Delete], "getters/:id".to_owned())
+        ]
+    } else {
+        vec![]
+    };
+
+    let mut chain = Chain::new(router);
+    chain.around(users_manager.get_middleware(auth_endpoints));
+
+    chain
+}
+
+#[cfg(test)]
+describe! virtual_channels_router {
+    before_each {
+        extern crate serde_json;
+
+        use adapters::AdapterManager as Adapters;
+        use foxbox_taxonomy::manager::AdapterManager;
+        use iron::Headers;
+        use iron::status::Status;
+        use iron_test::{ request, response };
+        use mount::Mount;
+        use stubs::controller::ControllerStub;
+        use std::sync::Arc;
+
+        let manager = Arc::new(AdapterManager::new(None));
+        let adapters = Arc::new(Adapters::new(ControllerStub::new(), &manager));
+        adapters.start_adapter("virtual_channels").unwrap();
+        let virtual_channels = adapters.virtual_channels().unwrap();
+
+        let mut mount = Mount::new();
+        mount.mount("/api/v1/virtual", create(ControllerStub::new(), &adapters));
+
+        let definition = r#"{
+            "id": "getter:any-window-open@virtual",
+            "kind": "OpenClosed",
+            "aggregation": "Any",
+            "source": {"kind": "OpenClosed", "tags": ["window"]}
+        }"#;
+    }
+
+    it "should add, list and remove virtual getters" {
+        let response = request::put("http://localhost:3000/api/v1/virtual/getters",
+                                    Headers::new(), definition, &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::Ok);
+        let body = response::extract_body_to_string(response);
+        assert_eq!(body, r#""getter:any-window-open@virtual""#);
+
+        let response = request::get("http://localhost:3000/api/v1/virtual/getters",
+                                    Headers::new(),
+                                    &mount).unwrap();
+        let body = response::extract_body_to_string(response);
+        let json : serde_json::Value = serde_json::from_str(&body).unwrap();
+        let getters = json.as_object().unwrap();
+        assert_eq!(getters.len(), 1);
+        assert!(getters.contains_key("getter:any-window-open@virtual"));
+
+        let response = request::delete("http://localhost:3000/api/v1/virtual/getters/getter:any-window-open@virtual",
+                                       Headers::new(),
+                                       &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::Ok);
+        assert!(virtual_channels.get_getters().is_empty());
+    }
+
+    it "should reject malformed and unknown virtual getters" {
+        let response = request::put("http://localhost:3000/api/v1/virtual/getters",
+                                    Headers::new(), "{}", &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::BadRequest);
+
+        let response = request::delete("http://localhost:3000/api/v1/virtual/getters/getter:unknown@virtual",
+                                       Headers::new(),
+                                       &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::NotFound);
+    }
+
+    it "should only let administrators change virtual getters" {
+        use iron::headers::{ Authorization, Bearer };
+
+        let mut headers = Headers::new();
+        headers.set(Authorization(Bearer { token: "not a session token".to_owned() }));
+        let response = request::put("http://localhost:3000/api/v1/virtual/getters",
+                                    headers.clone(), definition, &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::Unauthorized);
+        assert!(virtual_channels.get_getters().is_empty());
+
+        let response = request::get("http://localhost:3000/api/v1/virtual/getters",
+                                    headers, &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::Ok);
+    }
+
+    it "should respond with 503 while the adapter is stopped" {
+        adapters.stop_adapter("virtual_channels").unwrap();
+        let response = request::get("http://localhost:3000/api/v1/virtual/getters",
+                                    Headers::new(),
+                                    &mount).unwrap();
+        assert_eq!(response.status.unwrap(), Status::ServiceUnavailable);
+    }
+}
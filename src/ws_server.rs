@@ -1,11 +1,120 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The WebSocket server.
+//!
+//! Besides the notifications broadcast by the controller, clients may subscribe to
+//! changes on getter channels and call the taxonomy API.
+//!
+//! # Subscribing
+//!
+//! ```ignore
+//! {
+//!   "type": "channels/watch",
+//!   "subscription": "my subscription id",
+//!   "watch": TargetMap<GetterSelector, Exactly<Range>>,
+//!   "options": WatchOptions // Optional
+//! }
+//! ```
+//!
+//! The `options` may be used to throttle, debounce or filter the events of each channel.
+//! The subscription id is chosen by the client and must be unique for the connection.
+//! From this point, each `WatchEvent` is sent to the client as
+//!
+//! ```ignore
+//! {
+//!   "type": "channels/watch/event",
+//!   "subscription": "my subscription id",
+//!   "event": WatchEvent
+//! }
+//! ```
+//!
+//! # Unsubscribing
+//!
+//! ```ignore
+//! {
+//!   "type": "channels/unwatch",
+//!   "subscription": "my subscription id"
+//! }
+//! ```
+//!
+//! All subscriptions are dropped once the connection is closed.
+//!
+//! # Errors
+//!
+//! Invalid messages are answered with
+//!
+//! ```ignore
+//! {
+//!   "type": "channels/watch/error",
+//!   "subscription": "my subscription id" | null,
+//!   "error": "some human-readable message"
+//! }
+//! ```
+//!
+//! # Calling the taxonomy API
+//!
+//! ```ignore
+//! {
+//!   "type": "api/call",
+//!   "id": "my correlation id",
+//!   "method": "fetch_values",
+//!   "params": Vec<GetterSelector>
+//! }
+//! ```
+//!
+//! The correlation id is chosen by the client. Methods and their `params` are:
+//!
+//! - `get_services`: an array of `ServiceSelector` (if omitted, all services);
+//! - `get_getter_channels`: an array of `GetterSelector` (if omitted, all getters);
+//! - `get_setter_channels`: an array of `SetterSelector` (if omitted, all setters);
+//! - `add_service_tags`, `remove_service_tags`: `{services, tags}`;
+//! - `add_getter_tags`, `remove_getter_tags`: `{getters, tags}`;
+//! - `add_setter_tags`, `remove_setter_tags`: `{setters, tags}`;
+//! - `fetch_values`: an array of `GetterSelector`;
+//! - `fetch_values_with_options`: `{getters, options}`, where `options` is a `FetchOptions`;
+//! - `send_values`, `send_values_atomic`: a `TargetMap<SetterSelector, Value>`;
+//! - `fetch_history`: `{getters, period}`.
+//!
+//! These are the same encodings as the REST API. Calls and subscriptions are executed on
+//! behalf of the user whose token was used to open the connection. The result is sent as
+//!
+//! ```ignore
+//! {
+//!   "type": "api/response",
+//!   "id": "my correlation id",
+//!   "result": ...
+//! }
+//! ```
+//!
+//! or, if the call could not be parsed,
+//!
+//! ```ignore
+//! {
+//!   "type": "api/error",
+//!   "id": "my correlation id",
+//!   "error": "some human-readable message"
+//! }
+//! ```
+
 extern crate url;
 
+use foxbox_taxonomy::api::{ API, FetchOptions, TargetMap, User, WatchEvent, WatchOptions };
+use foxbox_taxonomy::manager::{ AdapterManager, WatchGuard };
+use foxbox_taxonomy::selector::*;
+use foxbox_taxonomy::services::*;
+use foxbox_taxonomy::util::Exactly;
+use foxbox_taxonomy::values::{ Range, Value };
+use foxbox_users::SessionToken;
+
 use self::url::Url;
+use serde_json;
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::thread;
 use traits::Controller;
+use transformable_channels::mpsc::*;
 use ws;
 use ws::{ Handler, Sender, Result, Message, Handshake, CloseCode, Error };
 use ws::listen;
@@ -14,30 +123,284 @@ pub struct WsServer;
 
 pub struct WsHandler<T> {
     pub out: Sender,
-    pub controller: T
+    pub controller: T,
+    pub api: Arc<AdapterManager>,
+
+    /// The user on behalf of whom API calls are made, as authenticated
+    /// when opening the connection.
+    user: User,
+
+    /// The ongoing subscriptions, indexed by their id. Dropping the `WatchGuard`
+    /// stops watching.
+    subscriptions: HashMap<String, WatchGuard>,
+
+    /// Watch events, tagged with their subscription id, waiting to be forwarded
+    /// to the client.
+    tx_watch: RawSender<(String, WatchEvent)>,
 }
 
 impl WsServer {
 
-    pub fn start<T: Controller>(controller: T) {
+    pub fn start<T: Controller>(controller: T, adapter_api: &Arc<AdapterManager>) {
         let addrs: Vec<_> = controller.ws_as_addrs().unwrap().collect();
+        let api = adapter_api.clone();
         thread::Builder::new().name("WsServer".to_owned()).spawn(move || {
 
             listen(addrs[0], |out| {
-                WsHandler {
-                    out: out,
-                    controller: controller.clone(),
-                }
+                WsHandler::new(out, controller.clone(), api.clone())
             }).unwrap();
         }).unwrap();
     }
 }
 
+/// A message sent by a client.
+#[derive(Debug)]
+pub enum ClientMessage {
+    /// Start watching a set of getters.
+    Watch {
+        subscription: String,
+        watch: TargetMap<GetterSelector, Exactly<Range>>,
+        options: WatchOptions
+    },
+
+    /// Stop watching.
+    Unwatch {
+        subscription: String
+    },
+
+    /// Call a method of the taxonomy API.
+    Call {
+        id: String,
+        call: ApiCall
+    },
+}
+
+impl Parser<ClientMessage> for ClientMessage {
+    fn description() -> String {
+        "ClientMessage".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> ::std::result::Result<Self, ParseError> {
+        let typ = try!(path.push("type", |path| String::take(path, source, "type")));
+        match &typ as &str {
+            "channels/watch" => {
+                let subscription = try!(path.push("subscription", |path| String::take(path, source, "subscription")));
+                let watch = try!(path.push("watch", |path| TargetMap::<GetterSelector, Exactly<Range>>::take(path, source, "watch")));
+                let options = match path.push("options", |path| WatchOptions::take_opt(path, source, "options")) {
+                    None => WatchOptions::default(),
+                    Some(result) => try!(result)
+                };
+                Ok(ClientMessage::Watch {
+                    subscription: subscription,
+                    watch: watch,
+                    options: options
+                })
+            }
+            "channels/unwatch" => {
+                let subscription = try!(path.push("subscription", |path| String::take(path, source, "subscription")));
+                Ok(ClientMessage::Unwatch {
+                    subscription: subscription
+                })
+            }
+            "api/call" => {
+                let id = try!(path.push("id", |path| String::take(path, source, "id")));
+                let call = try!(ApiCall::parse(path, source));
+                Ok(ClientMessage::Call {
+                    id: id,
+                    call: call
+                })
+            }
+            _ => path.push("type", |path| Err(ParseError::unknown_constant(&typ, &path)))
+        }
+    }
+}
+
+/// A call to a method of the taxonomy API.
+#[derive(Debug)]
+pub enum ApiCall {
+    GetServices(Vec<ServiceSelector>),
+    AddServiceTags(Vec<ServiceSelector>, Vec<Id<TagId>>),
+    RemoveServiceTags(Vec<ServiceSelector>, Vec<Id<TagId>>),
+    GetGetterChannels(Vec<GetterSelector>),
+    GetSetterChannels(Vec<SetterSelector>),
+    AddGetterTags(Vec<GetterSelector>, Vec<Id<TagId>>),
+    RemoveGetterTags(Vec<GetterSelector>, Vec<Id<TagId>>),
+    AddSetterTags(Vec<SetterSelector>, Vec<Id<TagId>>),
+    RemoveSetterTags(Vec<SetterSelector>, Vec<Id<TagId>>),
+    FetchValues(Vec<GetterSelector>),
+    FetchValuesWithOptions(Vec<GetterSelector>, FetchOptions),
+    SendValues(TargetMap<SetterSelector, Value>),
+    SendValuesAtomic(TargetMap<SetterSelector, Value>),
+    FetchHistory(Vec<GetterSelector>, Period),
+}
+
+impl ApiCall {
+    /// Parse `{selectors, tags}`, as used by the tag-related methods.
+    fn parse_tags<S>(path: Path, params: &mut JSON, field: &str) ->
+        ::std::result::Result<(Vec<S>, Vec<Id<TagId>>), ParseError> where S: Parser<S>
+    {
+        let selectors = try!(path.push(field, |path| Vec::<S>::take(path, params, field)));
+        let tags = try!(path.push("tags", |path| Vec::<Id<TagId>>::take(path, params, "tags")));
+        Ok((selectors, tags))
+    }
+}
+
+impl Parser<ApiCall> for ApiCall {
+    fn description() -> String {
+        "ApiCall".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> ::std::result::Result<Self, ParseError> {
+        let method = try!(path.push("method", |path| String::take(path, source, "method")));
+        let params = match *source {
+            JSON::Object(ref mut obj) => obj.remove("params"),
+            _ => None
+        };
+        path.push("params", |path| {
+            // As with `GET` requests in the REST API, omitting the selectors of
+            // queries returns everything.
+            let mut params = match params {
+                Some(params) => params,
+                None => match &method as &str {
+                    "get_services" => return Ok(ApiCall::GetServices(vec![ServiceSelector::new()])),
+                    "get_getter_channels" => return Ok(ApiCall::GetGetterChannels(vec![GetterSelector::new()])),
+                    "get_setter_channels" => return Ok(ApiCall::GetSetterChannels(vec![SetterSelector::new()])),
+                    _ => JSON::Null
+                }
+            };
+            let params = &mut params;
+            let call = match &method as &str {
+                "get_services" =>
+                    ApiCall::GetServices(try!(Vec::<ServiceSelector>::parse(path, params))),
+                "add_service_tags" => {
+                    let (selectors, tags) = try!(Self::parse_tags(path, params, "services"));
+                    ApiCall::AddServiceTags(selectors, tags)
+                }
+                "remove_service_tags" => {
+                    let (selectors, tags) = try!(Self::parse_tags(path, params, "services"));
+                    ApiCall::RemoveServiceTags(selectors, tags)
+                }
+                "get_getter_channels" =>
+                    ApiCall::GetGetterChannels(try!(Vec::<GetterSelector>::parse(path, params))),
+                "get_setter_channels" =>
+                    ApiCall::GetSetterChannels(try!(Vec::<SetterSelector>::parse(path, params))),
+                "add_getter_tags" => {
+                    let (selectors, tags) = try!(Self::parse_tags(path, params, "getters"));
+                    ApiCall::AddGetterTags(selectors, tags)
+                }
+                "remove_getter_tags" => {
+                    let (selectors, tags) = try!(Self::parse_tags(path, params, "getters"));
+                    ApiCall::RemoveGetterTags(selectors, tags)
+                }
+                "add_setter_tags" => {
+                    let (selectors, tags) = try!(Self::parse_tags(path, params, "setters"));
+                    ApiCall::AddSetterTags(selectors, tags)
+                }
+                "remove_setter_tags" => {
+                    let (selectors, tags) = try!(Self::parse_tags(path, params, "setters"));
+                    ApiCall::RemoveSetterTags(selectors, tags)
+                }
+                "fetch_values" =>
+                    ApiCall::FetchValues(try!(Vec::<GetterSelector>::parse(path, params))),
+                "fetch_values_with_options" => {
+                    let selectors = try!(path.push("getters", |path| Vec::<GetterSelector>::take(path, params, "getters")));
+                    let options = try!(path.push("options", |path| FetchOptions::take(path, params, "options")));
+                    ApiCall::FetchValuesWithOptions(selectors, options)
+                }
+                "send_values" =>
+                    ApiCall::SendValues(try!(TargetMap::<SetterSelector, Value>::parse(path, params))),
+                "send_values_atomic" =>
+                    ApiCall::SendValuesAtomic(try!(TargetMap::<SetterSelector, Value>::parse(path, params))),
+                "fetch_history" => {
+                    let selectors = try!(path.push("getters", |path| Vec::<GetterSelector>::take(path, params, "getters")));
+                    let period = try!(path.push("period", |path| Period::take(path, params, "period")));
+                    ApiCall::FetchHistory(selectors, period)
+                }
+                _ => return Err(ParseError::unknown_constant(&method, &path))
+            };
+            Ok(call)
+        })
+    }
+}
+
 impl<T: Controller> WsHandler<T> {
 
+    pub fn new(out: Sender, controller: T, api: Arc<AdapterManager>) -> Self {
+        // Forward the watch events to the client. The thread stops once the handler
+        // and all its watches have been dropped.
+        let (tx, rx) = channel();
+        let out_watch = out.clone();
+        thread::spawn(move || {
+            for (subscription, event) in rx {
+                let message = json!({ type: "channels/watch/event", subscription: subscription, event: event });
+                if let Err(err) = out_watch.send(message) {
+                    error!("Error sending watch event to socket: {}", err);
+                }
+            }
+        });
+
+        WsHandler {
+            out: out,
+            controller: controller,
+            api: api,
+            user: User::None,
+            subscriptions: HashMap::new(),
+            tx_watch: tx,
+        }
+    }
+
     fn close_with_error(&mut self, reason: &'static str) -> Result<()> {
         self.out.close_with_reason(ws::CloseCode::Error, reason)
     }
+
+    fn send_error(&self, subscription: Option<String>, error: String) -> Result<()> {
+        self.out.send(json!({ type: "channels/watch/error", subscription: subscription, error: error }))
+    }
+
+    fn watch(&mut self, subscription: String, watch: TargetMap<GetterSelector, Exactly<Range>>,
+        options: WatchOptions) -> Result<()> {
+        if self.subscriptions.contains_key(&subscription) {
+            return self.send_error(Some(subscription), "Duplicate subscription".to_owned());
+        }
+        let id = subscription.clone();
+        let on_event = self.tx_watch.map(move |event| (id.clone(), event));
+        let guard = self.api.watch_values_with_options(watch, options, Box::new(on_event), self.user.clone());
+        self.subscriptions.insert(subscription, guard);
+        Ok(())
+    }
+
+    fn call(&self, id: String, call: ApiCall) -> Result<()> {
+        let api = &self.api;
+        let user = self.user.clone();
+        let result = match call {
+            ApiCall::GetServices(selectors) => api.get_services(selectors).to_json(),
+            ApiCall::AddServiceTags(selectors, tags) => api.add_service_tags(selectors, tags, user).to_json(),
+            ApiCall::RemoveServiceTags(selectors, tags) => api.remove_service_tags(selectors, tags, user).to_json(),
+            ApiCall::GetGetterChannels(selectors) => api.get_getter_channels(selectors).to_json(),
+            ApiCall::GetSetterChannels(selectors) => api.get_setter_channels(selectors).to_json(),
+            ApiCall::AddGetterTags(selectors, tags) => api.add_getter_tags(selectors, tags, user).to_json(),
+            ApiCall::RemoveGetterTags(selectors, tags) => api.remove_getter_tags(selectors, tags, user).to_json(),
+            ApiCall::AddSetterTags(selectors, tags) => api.add_setter_tags(selectors, tags, user).to_json(),
+            ApiCall::RemoveSetterTags(selectors, tags) => api.remove_setter_tags(selectors, tags, user).to_json(),
+            ApiCall::FetchValues(selectors) => api.fetch_values(selectors, user).to_json(),
+            ApiCall::FetchValuesWithOptions(selectors, options) =>
+                api.fetch_values_with_options(selectors, options, user).to_json(),
+            ApiCall::SendValues(values) => api.send_values(values, user).to_json(),
+            ApiCall::SendValuesAtomic(values) => api.send_values_atomic(values, user).to_json(),
+            ApiCall::FetchHistory(selectors, period) => api.fetch_history(selectors, period, user).to_json(),
+        };
+        self.out.send(json!({ type: "api/response", id: id, result: result }))
+    }
+
+    fn send_call_error(&self, id: String, error: String) -> Result<()> {
+        self.out.send(json!({ type: "api/error", id: id, error: error }))
+    }
+
+    fn unwatch(&mut self, subscription: String) -> Result<()> {
+        match self.subscriptions.remove(&subscription) {
+            // Dropping the guard stops watching.
+            Some(_) => Ok(()),
+            None => self.send_error(Some(subscription), "Unknown subscription".to_owned())
+        }
+    }
 }
 
 impl<T: Controller> Handler for WsHandler<T> {
@@ -71,6 +434,12 @@ impl<T: Controller> Handler for WsHandler<T> {
             return self.close_with_error("Authorization failed");
         }
 
+        // API calls are made on behalf of the owner of the token.
+        self.user = match SessionToken::from_string(&token) {
+            Ok(token) => User::Id(token.claims.id),
+            Err(_) => return self.close_with_error("Authorization failed")
+        };
+
         self.controller.add_websocket(self.out.clone());
 
         Ok(())
@@ -79,7 +448,26 @@ impl<T: Controller> Handler for WsHandler<T> {
     fn on_message(&mut self, msg: Message) -> Result<()> {
         info!("Message from websocket ({:?}): {}", self.out.token(), msg);
 
-        Ok(())
+        let source = match msg.as_text() {
+            Ok(text) => text.to_owned(),
+            Err(_) => return self.send_error(None, "Binary messages are not supported".to_owned())
+        };
+        let mut json: JSON = match serde_json::from_str(&source) {
+            Ok(json) => json,
+            Err(err) => return self.send_error(None, format!("{}", ParseError::json(err)))
+        };
+
+        // Keep the correlation id of API calls, to be able to report parse errors.
+        let id = json.find("id").and_then(|id| id.as_string()).map(|id| id.to_owned());
+        match ClientMessage::parse(Path::new(), &mut json) {
+            Err(err) => match id {
+                Some(id) => self.send_call_error(id, format!("{}", err)),
+                None => self.send_error(None, format!("{}", err)),
+            },
+            Ok(ClientMessage::Watch { subscription, watch, options }) => self.watch(subscription, watch, options),
+            Ok(ClientMessage::Unwatch { subscription }) => self.unwatch(subscription),
+            Ok(ClientMessage::Call { id, call }) => self.call(id, call),
+        }
     }
 
     fn on_close(&mut self, code: CloseCode, reason: &str) {
@@ -89,6 +477,9 @@ impl<T: Controller> Handler for WsHandler<T> {
             _ => error!("The ws client encountered an error: {}.", reason),
         }
 
+        // Stop watching.
+        self.subscriptions.clear();
+
         self.controller.remove_websocket(self.out.clone());
     }
 
@@ -96,3 +487,115 @@ impl<T: Controller> Handler for WsHandler<T> {
         error!("The ws server encountered an error: {:?}", err);
     }
 }
+
+#[cfg(test)]
+describe! ws_client_message {
+    before_each {
+        use foxbox_taxonomy::parse::*;
+        use foxbox_taxonomy::util::Exactly;
+        use ws_server::{ ApiCall, ClientMessage };
+    }
+
+    it "should parse a watch request" {
+        let source = r#"{
+            "type": "channels/watch",
+            "subscription": "sub 1",
+            "watch": [{"select": {"kind": "LightOn"}, "range": {"Eq": {"OnOff": "On"}}}]
+        }"#;
+        match ClientMessage::from_str(source).unwrap() {
+            ClientMessage::Watch { subscription, watch, options } => {
+                assert_eq!(subscription, "sub 1");
+                assert!(options.min_interval.is_none());
+                assert!(options.debounce.is_none());
+                assert!(options.min_delta.is_none());
+                assert_eq!(watch.len(), 1);
+                match watch[0].payload {
+                    Exactly::Exactly(_) => {},
+                    ref other => panic!("Unexpected range {:?}", other)
+                }
+            },
+            other => panic!("Unexpected message {:?}", other)
+        }
+    }
+
+    it "should parse watch options" {
+        let source = r#"{
+            "type": "channels/watch",
+            "subscription": "sub 1",
+            "watch": [{"select": {"kind": "LightOn"}, "range": {"Eq": {"OnOff": "On"}}}],
+            "options": {"debounce": 0.5, "min_delta": 2}
+        }"#;
+        match ClientMessage::from_str(source).unwrap() {
+            ClientMessage::Watch { options, .. } => {
+                assert!(options.min_interval.is_none());
+                assert!(options.debounce.is_some());
+                assert_eq!(options.min_delta, Some(2.));
+            },
+            other => panic!("Unexpected message {:?}", other)
+        }
+    }
+
+    it "should parse an unwatch request" {
+        let source = r#"{"type": "channels/unwatch", "subscription": "sub 1"}"#;
+        match ClientMessage::from_str(source).unwrap() {
+            ClientMessage::Unwatch { subscription } => assert_eq!(subscription, "sub 1"),
+            other => panic!("Unexpected message {:?}", other)
+        }
+    }
+
+    it "should parse an api call" {
+        let source = r#"{
+            "type": "api/call",
+            "id": "call 1",
+            "method": "add_getter_tags",
+            "params": {"getters": [{"kind": "LightOn"}], "tags": ["tag 1", "tag 2"]}
+        }"#;
+        match ClientMessage::from_str(source).unwrap() {
+            ClientMessage::Call { id, call: ApiCall::AddGetterTags(selectors, tags) } => {
+                assert_eq!(id, "call 1");
+                assert_eq!(selectors.len(), 1);
+                assert_eq!(tags.len(), 2);
+            },
+            other => panic!("Unexpected message {:?}", other)
+        }
+    }
+
+    it "should parse an atomic send" {
+        let source = r#"{
+            "type": "api/call",
+            "id": "call 1",
+            "method": "send_values_atomic",
+            "params": [{"select": {"kind": "LightOn"}, "value": {"OnOff": "Off"}}]
+        }"#;
+        match ClientMessage::from_str(source).unwrap() {
+            ClientMessage::Call { call: ApiCall::SendValuesAtomic(values), .. } =>
+                assert_eq!(values.len(), 1),
+            other => panic!("Unexpected message {:?}", other)
+        }
+    }
+
+    it "should select everything when a query has no params" {
+        let source = r#"{"type": "api/call", "id": "call 1", "method": "get_getter_channels"}"#;
+        match ClientMessage::from_str(source).unwrap() {
+            ClientMessage::Call { call: ApiCall::GetGetterChannels(selectors), .. } =>
+                assert_eq!(selectors.len(), 1),
+            other => panic!("Unexpected message {:?}", other)
+        }
+    }
+
+    it "should reject unknown api methods" {
+        let source = r#"{"type": "api/call", "id": "call 1", "method": "foo", "params": []}"#;
+        match ClientMessage::from_str(source) {
+            Err(ParseError::UnknownConstant { .. }) => {},
+            other => panic!("Unexpected result {:?}", other)
+        }
+    }
+
+    it "should reject unknown messages" {
+        let source = r#"{"type": "channels/foo", "subscription": "sub 1"}"#;
+        match ClientMessage::from_str(source) {
+            Err(ParseError::UnknownConstant { .. }) => {},
+            other => panic!("Unexpected result {:?}", other)
+        }
+    }
+}